@@ -0,0 +1,1179 @@
+//! Semantic analysis.
+//!
+//! Computes the [`Layout`] of every expression of a program, and checks it
+//! against the place where the expression is used, before the program is
+//! compiled into IR. The IR compiler assumes the program has been checked.
+//!
+//! [`Layout`]: ../ir/enum.Layout.html
+use crate::{
+    byteorder::NativeEndian,
+    ir::{
+        charmap::{self, Quoted},
+        const_expr, mangle, Layout, Namespace,
+    },
+    parser::{
+        ast,
+        ast::{Expression, Field, Statement, Type},
+        lex,
+        lex::span::{Span, Spanned},
+        source::Module,
+        Diagnostic,
+    },
+};
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// Type errors.
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum TypeError {
+    #[error("Mismatched types: expected `{expected}`, found `{found}`")]
+    Mismatch {
+        span: Span,
+        expected: Layout,
+        found: Layout,
+    },
+
+    #[error("Expected a value, found an expression without one")]
+    Void { span: Span },
+
+    #[error("Literal out of range for `{layout}`")]
+    LiteralRange { span: Span, layout: Layout },
+
+    #[error("Literal out of range for `bool`")]
+    BoolRange { span: Span },
+
+    #[error("Mismatched types: expected `bool`, found `u8`")]
+    NotBool { span: Span },
+
+    #[error("Mismatched types: expected `u8`, found `bool`")]
+    FromBool { span: Span },
+
+    #[error("Expected a numeric type, found `{found}`")]
+    NotNumeric { span: Span, found: Layout },
+
+    #[error("Expected a pointer, found `{found}`")]
+    NotPointer { span: Span, found: Layout },
+
+    #[error("Expected an unsigned offset, found `{found}`")]
+    NotOffset { span: Span, found: Layout },
+
+    #[error("Expected an array, found `{found}`")]
+    NotArray { span: Span, found: Layout },
+
+    #[error("Invalid left-hand side of assignment")]
+    NotAssignable { span: Span },
+
+    #[error("Can't take the address of this expression")]
+    NotAddressable { span: Span },
+
+    #[error("Expected {expected} argument(s), found {found}")]
+    Arity {
+        span: Span,
+        expected: usize,
+        found: usize,
+    },
+
+    #[error("Undefined symbol: `{name}`")]
+    UndefinedSymbol { span: Span, name: String },
+
+    #[error("Expected a function name")]
+    NotCallable { span: Span },
+
+    #[error("Undefined function: `{name}`")]
+    UndefinedFunction { span: Span, name: String },
+
+    #[error("Missing return value of type `{expected}`")]
+    MissingReturn { span: Span, expected: Layout },
+
+    #[error("Unexpected return value")]
+    UnexpectedReturn { span: Span },
+
+    #[error("Unsupported type")]
+    UnsupportedType { span: Span },
+
+    #[error("Array length is not a constant expression")]
+    ArrayLen { span: Span },
+
+    #[error("Undefined type: `{name}`")]
+    UndefinedType { span: Span, name: String },
+
+    #[error("Expected a struct or union, found `{found}`")]
+    NotStruct { span: Span, found: Layout },
+
+    #[error("No field `{name}` on type `{found}`")]
+    UndefinedField {
+        span: Span,
+        name: String,
+        found: Layout,
+    },
+
+    #[error("Can't infer the type of a struct initializer")]
+    UntypedStruct { span: Span },
+
+    #[error("Missing field `{name}` in struct initializer")]
+    MissingField { span: Span, name: String },
+
+    #[error("Field `{name}` initialized more than once")]
+    DuplicateField { span: Span, name: String },
+
+    #[error("Union initializers must set exactly one field")]
+    UnionFields { span: Span },
+
+    #[error("{message}")]
+    InvalidLiteral { span: Span, message: String },
+
+    #[error("Expected a string literal")]
+    ExpectedString { span: Span },
+}
+
+impl TypeError {
+    /// Location of the error in the source code.
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Mismatch { span, .. }
+            | Self::Void { span }
+            | Self::LiteralRange { span, .. }
+            | Self::BoolRange { span }
+            | Self::NotBool { span }
+            | Self::FromBool { span }
+            | Self::NotNumeric { span, .. }
+            | Self::NotPointer { span, .. }
+            | Self::NotOffset { span, .. }
+            | Self::NotArray { span, .. }
+            | Self::NotAssignable { span }
+            | Self::NotAddressable { span }
+            | Self::Arity { span, .. }
+            | Self::UndefinedSymbol { span, .. }
+            | Self::NotCallable { span }
+            | Self::UndefinedFunction { span, .. }
+            | Self::MissingReturn { span, .. }
+            | Self::UnexpectedReturn { span }
+            | Self::UnsupportedType { span }
+            | Self::ArrayLen { span }
+            | Self::UndefinedType { span, .. }
+            | Self::NotStruct { span, .. }
+            | Self::UndefinedField { span, .. }
+            | Self::UntypedStruct { span }
+            | Self::MissingField { span, .. }
+            | Self::DuplicateField { span, .. }
+            | Self::UnionFields { span }
+            | Self::InvalidLiteral { span, .. }
+            | Self::ExpectedString { span } => *span,
+        }
+    }
+}
+
+impl From<&TypeError> for Diagnostic {
+    fn from(error: &TypeError) -> Self {
+        let diagnostic = Self::new(error.to_string()).span(error.span());
+        match error {
+            TypeError::Mismatch { found, .. } => diagnostic.label(format!("found `{}`", found)),
+            TypeError::LiteralRange { layout, .. } => {
+                diagnostic.label(format!("doesn't fit in a `{}`", layout))
+            }
+            TypeError::BoolRange { .. } => diagnostic.label("expected `0` or `1`"),
+            TypeError::NotBool { .. } => diagnostic.label("found `u8`"),
+            TypeError::FromBool { .. } => diagnostic.label("found `bool`"),
+            TypeError::UndefinedSymbol { .. }
+            | TypeError::UndefinedFunction { .. }
+            | TypeError::UndefinedType { .. } => diagnostic.label("not found in this scope"),
+            TypeError::UndefinedField { .. } => diagnostic.label("unknown field"),
+            TypeError::DuplicateField { .. } => diagnostic.label("already initialized"),
+            _ => diagnostic,
+        }
+    }
+}
+
+/// Type check a program.
+pub fn check(ast: &ast::Ast<'_>) -> Result<(), Vec<TypeError>> {
+    check_all(&[], ast)
+}
+
+/// Type check a program made of multiple source files.
+///
+/// Modules must be sorted in import order, with the entry module last (as
+/// returned by [`Sources::parse`]).
+///
+/// [`Sources::parse`]: ../../parser/source/struct.Sources.html#method.parse
+pub fn check_modules(modules: &[Module<'_>]) -> Result<(), Vec<TypeError>> {
+    let (entry, modules) = modules.split_last().expect("Missing entry module");
+    check_all(modules, &entry.ast)
+}
+
+fn check_all(modules: &[Module<'_>], ast: &ast::Ast<'_>) -> Result<(), Vec<TypeError>> {
+    let mut checker = Checker::default();
+    for module in modules {
+        for name in module.module {
+            checker.namespace.push_mod(name);
+        }
+        checker.statements(&module.ast.inner);
+        for _ in module.module {
+            checker.namespace.pop_mod();
+        }
+    }
+    checker.statements(&ast.inner);
+    if checker.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(checker.errors)
+    }
+}
+
+// Marker for errors that have already been reported.
+struct Reported;
+
+// Layout of an expression (`None` if the expression doesn't evaluate to a value).
+type Ty = Result<Option<Layout>, Reported>;
+
+struct Symbol {
+    // `None` if the layout of the symbol is invalid.
+    layout: Option<Layout>,
+    stack: bool,
+    // `bool` shares the layout of `u8`, so it's tracked separately.
+    bool_: bool,
+}
+
+// Function signature (`None` layouts are invalid types).
+struct Fn {
+    args: Vec<Option<Layout>>,
+    ret: Option<Option<Layout>>,
+}
+
+#[derive(Default)]
+struct Scope {
+    // function scopes hide the stack symbols of the enclosing scopes.
+    fn_: bool,
+    symbols: HashMap<String, Symbol>,
+    // named types (`None` if the layout of the type is invalid).
+    types: HashMap<String, Option<Layout>>,
+}
+
+struct Checker {
+    namespace: Namespace,
+    scopes: Vec<Scope>,
+    fns: HashMap<String, Fn>,
+    // return layout of the function being checked (`Some(None)` if the
+    // return type is invalid).
+    ret: Option<Option<Layout>>,
+    errors: Vec<TypeError>,
+}
+
+impl Default for Checker {
+    fn default() -> Self {
+        Self {
+            namespace: Namespace::default(),
+            scopes: vec![Scope::default()],
+            fns: HashMap::new(),
+            ret: None,
+            errors: Vec::new(),
+        }
+    }
+}
+
+impl Checker {
+    fn error(&mut self, error: TypeError) -> Reported {
+        self.errors.push(error);
+        Reported
+    }
+
+    fn scoped<F: FnOnce(&mut Self)>(&mut self, fn_: bool, fun: F) {
+        self.scopes.push(Scope {
+            fn_,
+            ..Default::default()
+        });
+        fun(self);
+        self.scopes.pop();
+    }
+
+    fn statements(&mut self, statements: &[Statement<'_>]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement<'_>) {
+        match statement {
+            Statement::If(if_) => self.if_(if_),
+            Statement::IfElse(if_else) => {
+                self.if_(&if_else.if_);
+                self.scoped(false, |c| c.statements(&if_else.else_.inner));
+            }
+            Statement::Scope(scope) => self.scoped(false, |c| c.statements(&scope.inner)),
+            Statement::Loop(loop_) => self.scoped(false, |c| c.statements(&loop_.inner)),
+            Statement::While(while_) => {
+                let _ = self.truth(&while_.expression);
+                self.scoped(false, |c| c.statements(&while_.inner));
+            }
+            Statement::Mod(mod_) => {
+                self.namespace.push_mod(&mod_.ident.to_string());
+                self.statements(&mod_.inner);
+                self.namespace.pop_mod();
+            }
+            Statement::Use(use_) => self.namespace.import(&use_.path),
+            Statement::Static(static_) => {
+                let layout = self.layout(&static_.field.type_);
+                self.define(&static_.field, layout, false);
+            }
+            Statement::Const(const_) => {
+                let layout = self.layout(&const_.field.type_);
+                if let Some(layout) = &layout {
+                    let bool_ = matches!(const_.field.type_, Type::Bool(_));
+                    let _ = self
+                        .expect(&const_.expression, layout)
+                        .and_then(|_| self.boolean(bool_, &const_.expression));
+                }
+                self.define(&const_.field, layout, false);
+            }
+            Statement::Include(include) => {
+                if charmap::quoted(&include.path) != Some(Quoted::String) {
+                    self.error(TypeError::ExpectedString {
+                        span: include.path.span(),
+                    });
+                }
+                let layout = self.layout(&include.field.type_);
+                self.define(&include.field, layout, false);
+                if let Some(map) = &include.map {
+                    let layout = self.layout(&map.type_);
+                    self.define(map, layout, false);
+                }
+            }
+            Statement::Let(let_) => {
+                let layout = self.layout(&let_.field.type_);
+                if let Some(layout) = &layout {
+                    let bool_ = matches!(let_.field.type_, Type::Bool(_));
+                    let _ = self
+                        .expect(&let_.expression, layout)
+                        .and_then(|_| self.boolean(bool_, &let_.expression));
+                }
+                self.define(&let_.field, layout, true);
+            }
+            Statement::For(for_) => {
+                let layout = self.layout(&for_.field.type_);
+                if let Some(layout) = &layout {
+                    let _ = self
+                        .expect(&for_.range.left, layout)
+                        .and_then(|_| self.numeric(&for_.range.left, layout));
+                    let _ = self.expect(&for_.range.right, layout);
+                    if let Some(step) = &for_.range.step {
+                        let _ = self.expect(&step.expression, layout);
+                    }
+                }
+                self.scoped(false, |c| {
+                    c.define(&for_.field, layout, true);
+                    c.statements(&for_.inner);
+                });
+            }
+            Statement::Inline(inline) => {
+                let _ = self.expr(&inline.inner, None);
+            }
+            Statement::Fn(fn_) => self.fn_(fn_),
+            Statement::Struct(struct_) => {
+                let layout = self.fields(&struct_.fields).map(Layout::struct_);
+                self.define_type(&struct_.ident, layout);
+            }
+            Statement::Union(union) => {
+                let layout = self.fields(&union.fields).map(Layout::union);
+                self.define_type(&union.ident, layout);
+            }
+            Statement::Enum(enum_) => self.enum_(enum_),
+            Statement::Type(type_) => {
+                let layout = self.layout(&type_.inner);
+                self.define_type(&type_.ident, layout);
+            }
+            Statement::Match(match_) => self.match_(match_),
+            Statement::Return(return_) => match (&self.ret, &return_.expression) {
+                (Some(Some(expected)), Some(expression)) => {
+                    let expected = expected.clone();
+                    let _ = self.expect(expression, &expected);
+                }
+                (Some(Some(expected)), None) => {
+                    let expected = expected.clone();
+                    self.error(TypeError::MissingReturn {
+                        span: return_.span(),
+                        expected,
+                    });
+                }
+                (None, Some(expression)) => {
+                    self.error(TypeError::UnexpectedReturn {
+                        span: expression.span(),
+                    });
+                }
+                _ => {}
+            },
+            Statement::Asm(_)
+            | Statement::Panic(_)
+            | Statement::Continue(_)
+            | Statement::Break(_) => {}
+        }
+    }
+
+    fn if_(&mut self, if_: &ast::If<'_>) {
+        let _ = self.truth(&if_.expression);
+        self.scoped(false, |c| c.statements(&if_.inner));
+    }
+
+    fn match_(&mut self, match_: &ast::Match<'_>) {
+        let layout = self
+            .value(&match_.expression, None)
+            .and_then(|layout| self.numeric(&match_.expression, &layout).map(|_| layout));
+        for arm in &match_.arms {
+            if let Ok(layout) = &layout {
+                for pattern in &arm.patterns {
+                    let _ = self.expect(pattern, layout);
+                }
+            }
+            self.scoped(false, |c| c.statements(&arm.inner));
+        }
+        if let Some(else_) = &match_.else_ {
+            self.scoped(false, |c| c.statements(&else_.inner));
+        }
+    }
+
+    // Enums are `u8`s, and their variants `u8` constants (`Enum::Variant`).
+    fn enum_(&mut self, enum_: &ast::types::EnumDef<'_>) {
+        for variant in &enum_.variants {
+            if let Some(discriminant) = &variant.discriminant {
+                let _ = self.expect(&discriminant.expression, &Layout::U8);
+            }
+            let name = format!("{}::{}", enum_.ident, variant.ident);
+            let name = mangle(self.namespace.module(), &name);
+            let symbol = Symbol {
+                layout: Some(Layout::U8),
+                stack: false,
+                bool_: false,
+            };
+            self.scopes.last_mut().unwrap().symbols.insert(name, symbol);
+        }
+        self.define_type(&enum_.ident, Some(Layout::U8));
+    }
+
+    fn fn_(&mut self, fn_: &ast::Fn<'_>) {
+        let fields: Vec<_> = fn_.fn_arg.iter().flat_map(|a| &a.inner).collect();
+        let args: Vec<_> = fields.iter().map(|f| self.layout(&f.type_)).collect();
+        let ret = fn_.fn_return.as_ref().map(|r| self.layout(&r.type_));
+
+        // defined before the body, so functions can be recursive.
+        let name = mangle(self.namespace.module(), &fn_.ident.to_string());
+        let sig = Fn {
+            args: args.clone(),
+            ret: ret.clone(),
+        };
+        self.fns.insert(name, sig);
+
+        let parent = std::mem::replace(&mut self.ret, ret);
+        self.scoped(true, |c| {
+            for (field, layout) in fields.into_iter().zip(args) {
+                c.define(field, layout, true);
+            }
+            c.statements(&fn_.inner);
+        });
+        self.ret = parent;
+    }
+
+    // Memory layout of a type.
+    fn layout(&mut self, type_: &Type<'_>) -> Option<Layout> {
+        Some(match type_ {
+            Type::U8(_) | Type::Bool(_) => Layout::U8,
+            Type::I8(_) => Layout::I8,
+            Type::U16(_) => Layout::U16,
+            Type::I16(_) => Layout::I16,
+            Type::Pointer(ptr) => Layout::Pointer(Box::new(self.layout(&ptr.type_)?)),
+            Type::Array(array) => {
+                let inner = self.layout(&array.type_);
+                let len = match const_expr::<NativeEndian>(&array.len, None) {
+                    Ok(Some(len)) => len,
+                    _ => {
+                        self.error(TypeError::ArrayLen {
+                            span: array.len.span(),
+                        });
+                        return None;
+                    }
+                };
+                Layout::Array {
+                    inner: Box::new(inner?),
+                    len,
+                }
+            }
+            Type::Struct(struct_) => Layout::struct_(self.fields(&struct_.fields)?),
+            Type::Union(union) => Layout::union(self.fields(&union.fields)?),
+            Type::Path(path) => {
+                let name: Vec<_> = path.iter().map(|i| i.to_string()).collect();
+                let name = name.join("::");
+                let layout = self.namespace.resolve(&name).find_map(|name| {
+                    self.scopes
+                        .iter()
+                        .rev()
+                        .find_map(|scope| scope.types.get(&name))
+                });
+                match layout {
+                    Some(layout) => layout.clone()?,
+                    None => {
+                        self.error(TypeError::UndefinedType {
+                            span: path.span(),
+                            name,
+                        });
+                        return None;
+                    }
+                }
+            }
+        })
+    }
+
+    // Names & layouts of struct or union fields (all fields are checked).
+    fn fields(&mut self, fields: &[Field<'_>]) -> Option<Vec<(String, Layout)>> {
+        let fields: Vec<_> = fields
+            .iter()
+            .map(|f| Some((f.ident.to_string(), self.layout(&f.type_)?)))
+            .collect();
+        fields.into_iter().collect()
+    }
+
+    fn define_type(&mut self, ident: &lex::Ident<'_>, layout: Option<Layout>) {
+        let name = mangle(self.namespace.module(), &ident.to_string());
+        let scope = self.scopes.last_mut().unwrap();
+        scope.types.insert(name, layout);
+    }
+
+    // Define the symbols of a field (and its struct & union fields).
+    fn define(&mut self, field: &Field<'_>, layout: Option<Layout>, stack: bool) {
+        let name = field.ident.to_string();
+        let name = if stack {
+            name
+        } else {
+            mangle(self.namespace.module(), &name)
+        };
+        self.define_field(name, &field.type_, layout, stack);
+    }
+
+    fn define_field(
+        &mut self,
+        name: String,
+        type_: &Type<'_>,
+        layout: Option<Layout>,
+        stack: bool,
+    ) {
+        let fields = match (type_, &layout) {
+            (Type::Struct(ast::types::Struct { fields, .. }), Some(Layout::Struct(layouts)))
+            | (Type::Union(ast::types::Union { fields, .. }), Some(Layout::Union(layouts))) => {
+                fields
+                    .iter()
+                    .zip(layouts.iter().map(|f| Some(f.layout.clone())))
+                    .collect()
+            }
+            (Type::Struct(struct_), None) => struct_.fields.iter().map(|f| (f, None)).collect(),
+            (Type::Union(union), None) => union.fields.iter().map(|f| (f, None)).collect(),
+            _ => Vec::new(),
+        };
+        for (field, layout) in fields {
+            let name = format!("{}::{}", name, field.ident);
+            self.define_field(name, &field.type_, layout, stack);
+        }
+        let scope = self.scopes.last_mut().unwrap();
+        let bool_ = matches!(type_, Type::Bool(_));
+        scope.symbols.insert(
+            name,
+            Symbol {
+                layout,
+                stack,
+                bool_,
+            },
+        );
+    }
+
+    fn symbol(&self, name: &str) -> Option<&Symbol> {
+        let mut stack = true;
+        for scope in self.scopes.iter().rev() {
+            if let Some(symbol) = scope.symbols.get(name).filter(|s| s.stack && stack) {
+                return Some(symbol);
+            }
+            stack &= !scope.fn_;
+        }
+        self.namespace.resolve(name).find_map(|name| {
+            self.scopes
+                .iter()
+                .rev()
+                .filter_map(|scope| scope.symbols.get(&name))
+                .find(|s| !s.stack)
+        })
+    }
+
+    // Checks that the expression evaluates to the expected layout.
+    fn expect(&mut self, expression: &Expression<'_>, expected: &Layout) -> Result<(), Reported> {
+        let found = self.value(expression, Some(expected))?;
+        if &found != expected {
+            return Err(self.error(TypeError::Mismatch {
+                span: expression.span(),
+                expected: expected.clone(),
+                found,
+            }));
+        }
+        Ok(())
+    }
+
+    // Layout of an expression, which must evaluate to a value.
+    fn value(
+        &mut self,
+        expression: &Expression<'_>,
+        expected: Option<&Layout>,
+    ) -> Result<Layout, Reported> {
+        match self.expr(expression, expected)? {
+            Some(layout) => Ok(layout),
+            None => Err(self.error(TypeError::Void {
+                span: expression.span(),
+            })),
+        }
+    }
+
+    // Whether the expression is a `bool` symbol (`None` if it isn't a symbol).
+    fn bool_symbol(&self, expression: &Expression<'_>) -> Option<bool> {
+        match expression {
+            Expression::Path(path) => {
+                let name: Vec<_> = path.iter().map(|i| i.to_string()).collect();
+                self.symbol(&name.join("::")).map(|s| s.bool_)
+            }
+            _ => None,
+        }
+    }
+
+    // Whether the expression evaluates to a `bool` (`None` if unknown, or if
+    // it's a numeric literal, which may be either).
+    fn is_bool(&self, expression: &Expression<'_>) -> Option<bool> {
+        use Expression as E;
+        match expression {
+            _ if is_literal(expression) => None,
+            E::Path(_) => self.bool_symbol(expression),
+            E::Lit(lit) => match lit.to_string().as_str() {
+                "true" | "false" => Some(true),
+                _ => None,
+            },
+            E::LogicalAnd(_)
+            | E::LogicalOr(_)
+            | E::LogicalNot(_)
+            | E::Eq(_)
+            | E::NotEq(_)
+            | E::LessEq(_)
+            | E::GreaterEq(_)
+            | E::Less(_)
+            | E::Greater(_) => Some(true),
+            E::Add(_)
+            | E::Sub(_)
+            | E::Mul(_)
+            | E::Div(_)
+            | E::And(_)
+            | E::Or(_)
+            | E::Xor(_)
+            | E::LeftShift(_)
+            | E::RightShift(_)
+            | E::Minus(_)
+            | E::Not(_) => Some(false),
+            _ => None,
+        }
+    }
+
+    // Checks that a value can be stored in a `bool` (or in a `u8`, if
+    // `bool_` is false). `bool`s and `u8`s share the same layout, so the
+    // literals stored in a `bool` must be `0` or `1`, and any other value
+    // must agree with the destination on being a `bool`.
+    fn boolean(&mut self, bool_: bool, expression: &Expression<'_>) -> Result<(), Reported> {
+        let span = expression.span();
+        match (bool_, self.is_bool(expression)) {
+            (true, Some(false)) => Err(self.error(TypeError::NotBool { span })),
+            (false, Some(true)) => Err(self.error(TypeError::FromBool { span })),
+            (true, None) if is_literal(expression) => {
+                match const_expr::<NativeEndian>(expression, None) {
+                    Ok(Some(0)) | Ok(Some(1)) => Ok(()),
+                    _ => Err(self.error(TypeError::BoolRange { span })),
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Checks that the expression can be used as a boolean condition.
+    fn truth(&mut self, expression: &Expression<'_>) -> Result<(), Reported> {
+        let layout = self.value(expression, None)?;
+        self.numeric(expression, &layout)
+    }
+
+    fn numeric(&mut self, expression: &Expression<'_>, layout: &Layout) -> Result<(), Reported> {
+        match layout {
+            Layout::U8 | Layout::I8 | Layout::U16 | Layout::I16 => Ok(()),
+            _ => Err(self.error(TypeError::NotNumeric {
+                span: expression.span(),
+                found: layout.clone(),
+            })),
+        }
+    }
+
+    // Layout of an expression. The expected layout is only a hint for the layout
+    // of literals, and it's up to the caller to compare it with the result.
+    fn expr(&mut self, expression: &Expression<'_>, expected: Option<&Layout>) -> Ty {
+        use Expression as E;
+
+        macro_rules! arithmetic {
+            ($node:expr) => {{
+                let layout = self.binary(&$node.inner.left, &$node.inner.right, expected)?;
+                Ok(Some(layout))
+            }};
+        }
+        // 16bit values are shifted by an 8bit amount
+        macro_rules! shift {
+            ($node:expr) => {{
+                let left = &$node.inner.left;
+                if is_literal(left) {
+                    arithmetic!($node)
+                } else {
+                    let layout = self.value(left, expected)?;
+                    self.numeric(left, &layout)?;
+                    match layout {
+                        Layout::U16 | Layout::I16 => {
+                            self.expect(&$node.inner.right, &Layout::U8)?
+                        }
+                        _ => self.expect(&$node.inner.right, &layout)?,
+                    }
+                    Ok(Some(layout))
+                }
+            }};
+        }
+        // pointers are offset by an unsigned integer (scaled by the size of
+        // the pointed value)
+        macro_rules! offset {
+            ($node:expr) => {{
+                let left = &$node.inner.left;
+                if is_literal(left) {
+                    arithmetic!($node)
+                } else {
+                    let layout = self.value(left, expected)?;
+                    match layout {
+                        Layout::Pointer(_) => self.offset(&$node.inner.right)?,
+                        _ => {
+                            self.numeric(left, &layout)?;
+                            self.expect(&$node.inner.right, &layout)?;
+                        }
+                    }
+                    Ok(Some(layout))
+                }
+            }};
+        }
+        macro_rules! compare {
+            ($node:expr) => {{
+                self.compare(&$node.inner.left, &$node.inner.right)?;
+                Ok(Some(Layout::U8))
+            }};
+        }
+        // operands are evaluated independently, the result is a boolean.
+        macro_rules! logical {
+            ($node:expr) => {{
+                let left = self.truth(&$node.inner.left);
+                let right = self.truth(&$node.inner.right);
+                left.and(right)?;
+                Ok(Some(Layout::U8))
+            }};
+        }
+        macro_rules! assign {
+            ($node:expr) => {{
+                let layout = self.place(&$node.inner.left)?;
+                self.numeric(&$node.inner.left, &layout)?;
+                self.expect(&$node.inner.right, &layout)?;
+                Ok(None)
+            }};
+        }
+        macro_rules! offset_assign {
+            ($node:expr) => {{
+                let layout = self.place(&$node.inner.left)?;
+                match layout {
+                    Layout::Pointer(_) => self.offset(&$node.inner.right)?,
+                    _ => {
+                        self.numeric(&$node.inner.left, &layout)?;
+                        self.expect(&$node.inner.right, &layout)?;
+                    }
+                }
+                Ok(None)
+            }};
+        }
+
+        match expression {
+            E::Lit(lit) => {
+                if let Some(quoted) = charmap::quoted(lit) {
+                    let text = charmap::unescape(lit).map_err(|error| {
+                        self.error(TypeError::InvalidLiteral {
+                            span: error.span,
+                            message: error.message,
+                        })
+                    })?;
+                    return Ok(Some(match quoted {
+                        Quoted::Char => Layout::U8,
+                        Quoted::String => Layout::Array {
+                            inner: Box::new(Layout::U8),
+                            len: text.len() as u16,
+                        },
+                    }));
+                }
+                let lit = lit.to_string();
+                if lit == "true" || lit == "false" {
+                    return Ok(Some(Layout::U8));
+                }
+                // literals that don't fit in 16 bits fail to evaluate.
+                let value = const_expr::<NativeEndian>(expression, None).ok().flatten();
+                let layout = match expected {
+                    Some(Layout::Pointer(_))
+                    | Some(Layout::I8)
+                    | Some(Layout::U16)
+                    | Some(Layout::I16) => expected.unwrap().clone(),
+                    _ => Layout::U8,
+                };
+                match layout {
+                    _ if value.is_none() => {}
+                    Layout::U8 if value > Some(0xff) => {}
+                    Layout::I8 if value > Some(i8::MAX as u16) => {}
+                    Layout::I16 if value > Some(i16::MAX as u16) => {}
+                    _ => return Ok(Some(layout)),
+                }
+                Err(self.error(TypeError::LiteralRange {
+                    span: expression.span(),
+                    layout,
+                }))
+            }
+            E::Path(path) => {
+                let name: Vec<_> = path.iter().map(|i| i.to_string()).collect();
+                let name = name.join("::");
+                match self.symbol(&name) {
+                    Some(Symbol {
+                        layout: Some(layout),
+                        ..
+                    }) => Ok(Some(layout.clone())),
+                    Some(_) => Err(Reported),
+                    None => Err(self.error(TypeError::UndefinedSymbol {
+                        span: path.span(),
+                        name,
+                    })),
+                }
+            }
+            E::Array(array) => {
+                let inner = match expected {
+                    Some(Layout::Array { inner, .. }) => Some(inner.as_ref().clone()),
+                    _ => None,
+                };
+                let mut items = array.inner.iter();
+                let inner = match (items.next(), inner) {
+                    (Some(first), Some(inner)) => {
+                        self.expect(first, &inner)?;
+                        inner
+                    }
+                    (Some(first), None) => self.value(first, None)?,
+                    (None, inner) => inner.unwrap_or(Layout::U8),
+                };
+                for item in items {
+                    self.expect(item, &inner)?;
+                }
+                let len = array.inner.len() as u16;
+                let inner = Box::new(inner);
+                Ok(Some(Layout::Array { inner, len }))
+            }
+            E::Struct(struct_) => {
+                let layout = match expected {
+                    Some(layout @ Layout::Struct(_)) | Some(layout @ Layout::Union(_)) => layout,
+                    Some(found) => {
+                        return Err(self.error(TypeError::NotStruct {
+                            span: struct_.span(),
+                            found: found.clone(),
+                        }))
+                    }
+                    None => {
+                        return Err(self.error(TypeError::UntypedStruct {
+                            span: struct_.span(),
+                        }))
+                    }
+                };
+                let mut result = Ok(());
+                for (i, init) in struct_.fields.iter().enumerate() {
+                    let name = init.ident.to_string();
+                    if struct_.fields[..i]
+                        .iter()
+                        .any(|f| f.ident.to_string() == name)
+                    {
+                        let span = init.ident.span();
+                        result = Err(self.error(TypeError::DuplicateField { span, name }));
+                        continue;
+                    }
+                    match layout.field(&name) {
+                        Some(field) => {
+                            let field = field.layout.clone();
+                            result = result.and(self.expect(&init.expression, &field));
+                        }
+                        None => {
+                            result = Err(self.error(TypeError::UndefinedField {
+                                span: init.ident.span(),
+                                name,
+                                found: layout.clone(),
+                            }))
+                        }
+                    }
+                }
+                // every struct field is initialized, but only one of a union
+                match layout {
+                    Layout::Struct(fields) => {
+                        for field in fields {
+                            let name = &field.name;
+                            if !struct_.fields.iter().any(|f| f.ident.to_string() == *name) {
+                                result = Err(self.error(TypeError::MissingField {
+                                    span: struct_.span(),
+                                    name: name.clone(),
+                                }));
+                            }
+                        }
+                    }
+                    _ if struct_.fields.len() != 1 => {
+                        result = Err(self.error(TypeError::UnionFields {
+                            span: struct_.span(),
+                        }))
+                    }
+                    _ => {}
+                }
+                result?;
+                Ok(Some(layout.clone()))
+            }
+            E::Minus(minus) => {
+                // the magnitude of the smallest signed integer doesn't fit in it
+                let min = match expected {
+                    Some(Layout::I8) => Some(0x80),
+                    Some(Layout::I16) => Some(0x8000),
+                    _ => None,
+                };
+                if let (E::Lit(_), Some(min)) = (&minus.inner, min) {
+                    if const_expr::<NativeEndian>(&minus.inner, None)
+                        .ok()
+                        .flatten()
+                        == Some(min)
+                    {
+                        return Ok(expected.cloned());
+                    }
+                }
+                let layout = self.value(&minus.inner, expected)?;
+                self.numeric(&minus.inner, &layout)?;
+                Ok(Some(layout))
+            }
+            E::Not(not) => {
+                let layout = self.value(&not.inner, expected)?;
+                self.numeric(&not.inner, &layout)?;
+                Ok(Some(layout))
+            }
+            E::LogicalNot(not) => {
+                self.truth(&not.inner)?;
+                Ok(Some(Layout::U8))
+            }
+            E::AddressOf(address_of) => {
+                if !is_place(&address_of.inner) {
+                    return Err(self.error(TypeError::NotAddressable {
+                        span: address_of.inner.span(),
+                    }));
+                }
+                let layout = self.value(&address_of.inner, None)?;
+                Ok(Some(Layout::Pointer(Box::new(layout))))
+            }
+            E::Deref(deref) => {
+                let expected = expected.map(|l| Layout::Pointer(Box::new(l.clone())));
+                match self.value(&deref.inner, expected.as_ref())? {
+                    Layout::Pointer(inner) => Ok(Some(*inner)),
+                    found => Err(self.error(TypeError::NotPointer {
+                        span: deref.inner.span(),
+                        found,
+                    })),
+                }
+            }
+            E::Add(node) => offset!(node),
+            E::Sub(node) => offset!(node),
+            E::Mul(node) => arithmetic!(node),
+            E::Div(node) => arithmetic!(node),
+            E::And(node) => arithmetic!(node),
+            E::Or(node) => arithmetic!(node),
+            E::Xor(node) => arithmetic!(node),
+            E::LeftShift(node) => shift!(node),
+            E::RightShift(node) => shift!(node),
+            E::LogicalAnd(node) => logical!(node),
+            E::LogicalOr(node) => logical!(node),
+            E::Eq(node) => compare!(node),
+            E::NotEq(node) => compare!(node),
+            E::LessEq(node) => compare!(node),
+            E::GreaterEq(node) => compare!(node),
+            E::Less(node) => compare!(node),
+            E::Greater(node) => compare!(node),
+            E::Assign(node) => {
+                let layout = self.place(&node.inner.left)?;
+                self.expect(&node.inner.right, &layout)?;
+                if let Some(bool_) = self.bool_symbol(&node.inner.left) {
+                    self.boolean(bool_, &node.inner.right)?;
+                }
+                Ok(None)
+            }
+            E::PlusAssign(node) => offset_assign!(node),
+            E::MinusAssign(node) => offset_assign!(node),
+            E::MulAssign(node) => assign!(node),
+            E::DivAssign(node) => assign!(node),
+            E::AndAssign(node) => assign!(node),
+            E::OrAssign(node) => assign!(node),
+            E::XorAssign(node) => assign!(node),
+            E::Index(node) => {
+                let array = &node.inner.right;
+                let inner = match self.value(array, None)? {
+                    Layout::Array { inner, .. } => *inner,
+                    found => {
+                        return Err(self.error(TypeError::NotArray {
+                            span: array.span(),
+                            found,
+                        }))
+                    }
+                };
+                let index = self.value(&node.inner.left, None)?;
+                self.numeric(&node.inner.left, &index)?;
+                Ok(Some(inner))
+            }
+            E::Field(node) => {
+                let found = self.value(&node.inner, None)?;
+                let name = node.ident.to_string();
+                match (found.field(&name), &found) {
+                    (Some(field), _) => Ok(Some(field.layout.clone())),
+                    (None, Layout::Struct(_)) | (None, Layout::Union(_)) => {
+                        Err(self.error(TypeError::UndefinedField {
+                            span: node.ident.span(),
+                            name,
+                            found,
+                        }))
+                    }
+                    (None, _) => Err(self.error(TypeError::NotStruct {
+                        span: node.inner.span(),
+                        found,
+                    })),
+                }
+            }
+            E::Call(node) => {
+                let path = match &node.inner.left {
+                    E::Path(path) => path,
+                    left => return Err(self.error(TypeError::NotCallable { span: left.span() })),
+                };
+                let name: Vec<_> = path.iter().map(|i| i.to_string()).collect();
+                let name = name.join("::");
+                let fn_ = self
+                    .namespace
+                    .resolve(&name)
+                    .find_map(|n| self.fns.get(&n))
+                    .map(|fn_| (fn_.args.clone(), fn_.ret.clone()));
+                let (args, ret) = match fn_ {
+                    Some(fn_) => fn_,
+                    None => {
+                        return Err(self.error(TypeError::UndefinedFunction {
+                            span: path.span(),
+                            name,
+                        }))
+                    }
+                };
+                if args.len() != node.inner.args.len() {
+                    return Err(self.error(TypeError::Arity {
+                        span: node.span(),
+                        expected: args.len(),
+                        found: node.inner.args.len(),
+                    }));
+                }
+                let mut result = Ok(());
+                for (arg, layout) in node.inner.args.iter().zip(args) {
+                    if let Some(layout) = layout {
+                        result = result.and(self.expect(arg, &layout));
+                    }
+                }
+                result?;
+                match ret {
+                    Some(Some(ret)) => Ok(Some(ret)),
+                    Some(None) => Err(Reported),
+                    None => Ok(None),
+                }
+            }
+        }
+    }
+
+    // Layout of the two operands of a binary operation.
+    fn binary(
+        &mut self,
+        left: &Expression<'_>,
+        right: &Expression<'_>,
+        expected: Option<&Layout>,
+    ) -> Result<Layout, Reported> {
+        // infer the layout of literals from the other operand.
+        let (first, second) = if is_literal(left) && !is_literal(right) {
+            (right, left)
+        } else {
+            (left, right)
+        };
+        let layout = self.value(first, expected)?;
+        self.numeric(first, &layout)?;
+        self.expect(second, &layout)?;
+        Ok(layout)
+    }
+
+    // Checks the operands of a comparison, which are either numbers or pointers
+    // of the same type. Literals take the layout of the other operand, so
+    // pointers can be compared against `0` (null).
+    fn compare(&mut self, left: &Expression<'_>, right: &Expression<'_>) -> Result<(), Reported> {
+        let (first, second) = if is_literal(left) && !is_literal(right) {
+            (right, left)
+        } else {
+            (left, right)
+        };
+        let layout = self.value(first, None)?;
+        if !matches!(layout, Layout::Pointer(_)) {
+            self.numeric(first, &layout)?;
+        }
+        self.expect(second, &layout)
+    }
+
+    // Checks the offset of pointer arithmetic.
+    fn offset(&mut self, expression: &Expression<'_>) -> Result<(), Reported> {
+        match self.value(expression, Some(&Layout::U16))? {
+            Layout::U8 | Layout::U16 => Ok(()),
+            found => Err(self.error(TypeError::NotOffset {
+                span: expression.span(),
+                found,
+            })),
+        }
+    }
+
+    // Layout of the left-hand side of an assignment.
+    fn place(&mut self, expression: &Expression<'_>) -> Result<Layout, Reported> {
+        if is_place(expression) {
+            self.value(expression, None)
+        } else {
+            Err(self.error(TypeError::NotAssignable {
+                span: expression.span(),
+            }))
+        }
+    }
+}
+
+// Whether the expression refers to a memory location.
+fn is_place(expression: &Expression<'_>) -> bool {
+    match expression {
+        Expression::Path(_) | Expression::Index(_) | Expression::Deref(_) => true,
+        Expression::Field(field) => is_place(&field.inner),
+        _ => false,
+    }
+}
+
+// Whether the expression is made up of numeric literals only.
+fn is_literal(expression: &Expression<'_>) -> bool {
+    use Expression as E;
+    match expression {
+        E::Lit(lit) => {
+            let lit = lit.to_string();
+            !lit.starts_with('"') && !lit.starts_with('\'') && lit != "true" && lit != "false"
+        }
+        E::Minus(e) => is_literal(&e.inner),
+        E::Not(e) => is_literal(&e.inner),
+        E::Add(e) => is_literal(&e.inner.left) && is_literal(&e.inner.right),
+        E::Sub(e) => is_literal(&e.inner.left) && is_literal(&e.inner.right),
+        E::Mul(e) => is_literal(&e.inner.left) && is_literal(&e.inner.right),
+        E::Div(e) => is_literal(&e.inner.left) && is_literal(&e.inner.right),
+        E::And(e) => is_literal(&e.inner.left) && is_literal(&e.inner.right),
+        E::Or(e) => is_literal(&e.inner.left) && is_literal(&e.inner.right),
+        E::Xor(e) => is_literal(&e.inner.left) && is_literal(&e.inner.right),
+        _ => false,
+    }
+}
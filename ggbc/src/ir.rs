@@ -1,9 +1,28 @@
 //! Intermediate representation language.
-use crate::{byteorder::ByteOrder, parser::ast, Bytes};
+use crate::{
+    byteorder::ByteOrder,
+    parser::{
+        ast,
+        lex::span::Span,
+        source::{AssetProvider, Module},
+    },
+    Bytes,
+};
+pub(crate) use compile::{
+    alloc::{mangle, Namespace},
+    charmap,
+    constant::const_expr,
+};
+pub use compile::{
+    charmap::Charmap,
+    layout::{Field, Layout},
+    SemanticError,
+};
 use compile::{Compile, Context};
 use opcodes::Statement;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
+use std::rc::Rc;
 
 mod compile;
 pub mod opcodes;
@@ -30,35 +49,131 @@ pub struct Ir<B: ByteOrder> {
     _phantom: std::marker::PhantomData<B>,
 }
 
-impl<B: ByteOrder> Ir<B> {
+/// Builder of [`Ir`] programs with custom compilation options.
+///
+/// # Example
+/// ```
+/// use ggbc::{byteorder::NativeEndian, ir::{Charmap, Ir, IrBuilder}};
+///
+/// let (ast, _) = ggbc::parser::parse(r#"const HELLO:[u8 5] = "HELLO""#);
+/// let ir: Ir<NativeEndian> = IrBuilder::default()
+///     .charmap(Charmap::empty().chars("EHLO", 1))
+///     .build(&ast)
+///     .unwrap();
+/// assert_eq!(&[2, 1, 3, 3, 4][..], &ir.const_[..]);
+/// ```
+#[derive(Default, Clone)]
+pub struct IrBuilder {
+    charmap: Charmap,
+    assets: Option<Rc<dyn AssetProvider>>,
+}
+
+impl IrBuilder {
+    /// Charmap used to encode string and character literals.
+    /// Defaults to ASCII.
+    pub fn charmap(mut self, charmap: Charmap) -> Self {
+        self.charmap = charmap;
+        self
+    }
+
+    /// Provider of the files embedded with `include` statements.
+    /// Programs can't include any files unless one is set.
+    pub fn assets<A: AssetProvider + 'static>(mut self, assets: A) -> Self {
+        self.assets = Some(Rc::new(assets));
+        self
+    }
+
     /// Convert AST into IR intermediate code.
-    pub fn new(ast: &ast::Ast<'_>) -> Self {
+    pub fn build<B: ByteOrder>(&self, ast: &ast::Ast<'_>) -> Result<Ir<B>, SemanticError> {
+        self.compile(&[], ast)
+    }
+
+    /// Convert the modules of a program made of multiple source files into IR
+    /// intermediate code.
+    ///
+    /// Modules must be sorted in import order, with the entry module last (as
+    /// returned by [`Sources::parse`]).
+    ///
+    /// [`Sources::parse`]: ../../parser/source/struct.Sources.html#method.parse
+    pub fn build_modules<B: ByteOrder>(
+        &self,
+        modules: &[Module<'_>],
+    ) -> Result<Ir<B>, SemanticError> {
+        match modules.split_last() {
+            Some((entry, modules)) if entry.module.is_empty() => self.compile(modules, &entry.ast),
+            _ => {
+                let span = Span {
+                    min: [0, 0],
+                    max: [0, 0],
+                };
+                Err(SemanticError::new(span, "Missing entry module")
+                    .note("The entry module (with an empty path) must come last"))
+            }
+        }
+    }
+
+    fn compile<B: ByteOrder>(
+        &self,
+        modules: &[Module<'_>],
+        ast: &ast::Ast<'_>,
+    ) -> Result<Ir<B>, SemanticError> {
         let mut context: Context<B> = Context::default();
+        context.symbol_alloc.set_charmap(self.charmap.clone());
+        context.assets = self.assets.clone();
         let mut main = Vec::new();
 
-        ast.compile(&mut context, &mut main);
-
-        // inner ast statements define the entry point (a.k.a. main) routine
+        // inner ast statements define the entry point (a.k.a. main) routine,
+        // which is always stored first.
         let main_handle = context.routines.len();
-        context.routines.push(Routine {
+        context.routines.push(Routine::default());
+
+        // imported modules only contain items, so they don't contribute any
+        // statements to the main routine.
+        for module in modules {
+            context.source = module.module.to_vec();
+            compile::compile_module(module.module, &module.ast.inner, &mut context, &mut main)?;
+        }
+        context.source.clear();
+        ast.compile(&mut context, &mut main)?;
+
+        context.routines[main_handle] = Routine {
             debug_name: Some("main".to_string()),
             stack_size: context.stack_size,
             args_size: 0,
             return_size: 0,
             statements: main,
-        });
+        };
 
-        Self {
+        Ok(Ir {
             static_alloc: context.symbol_alloc.static_usage(),
             const_: context.symbol_alloc.into_const_data().into_boxed_slice(),
             routines: context.routines.into_boxed_slice(),
-            // TODO define syntax for interrupt handlers
             handlers: Handlers {
                 main: main_handle,
-                ..Default::default()
+                ..context.handlers
             },
             _phantom: std::marker::PhantomData,
-        }
+        })
+    }
+}
+
+impl<B: ByteOrder> Ir<B> {
+    /// Convert AST into IR intermediate code.
+    ///
+    /// Use [`IrBuilder`] to change the default compilation options.
+    pub fn new(ast: &ast::Ast<'_>) -> Result<Self, SemanticError> {
+        IrBuilder::default().build(ast)
+    }
+
+    /// Convert the modules of a program made of multiple source files into IR
+    /// intermediate code.
+    ///
+    /// Modules must be sorted in import order, with the entry module last (as
+    /// returned by [`Sources::parse`]).
+    ///
+    /// [`Sources::parse`]: ../../parser/source/struct.Sources.html#method.parse
+    pub fn with_modules(modules: &[Module<'_>]) -> Result<Self, SemanticError> {
+        IrBuilder::default().build_modules(modules)
     }
 
     /// Optimize IR instructions of all routines.
@@ -124,7 +239,7 @@ pub struct Handlers {
 
 /// Data associated with a compiled IR routine.
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Default, Clone, Eq, PartialEq, Hash)]
 pub struct Routine {
     /// Optional routine name (for debugging purposes).
     pub debug_name: Option<String>,
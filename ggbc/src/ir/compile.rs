@@ -3,20 +3,32 @@ use crate::{
     ir::{
         opcodes::{
             Destination, Location, Pointer, Source, Statement,
-            Statement::{Inc, Jmp, JmpCmp, JmpCmpNot, Ld, Nop, Ret, Stop, Sub},
+            Statement::{Inc, Jmp, JmpCmp, JmpCmpNot, Ld, Nop, Ret, Stop},
             StopStatus,
         },
-        Routine,
+        Handlers, Routine,
+    },
+    parser::{
+        ast,
+        lex::span::{Span, Spanned},
+        source::AssetProvider,
+        Diagnostic,
     },
-    parser::ast,
 };
 use alloc::{FnAlloc, RegisterAlloc, SymbolAlloc};
+use charmap::Char;
 use layout::Layout;
+use std::{convert::TryFrom, rc::Rc};
+use thiserror::Error;
 
-mod alloc;
+pub(crate) mod alloc;
+mod asm;
+pub(crate) mod charmap;
+pub(crate) mod constant;
 pub(crate) mod expression;
-mod layout;
+pub(crate) mod layout;
 pub(crate) mod optimize;
+mod tiles;
 
 // placeholder NOPs
 pub(crate) const NOP_PERSIST: usize = 0;
@@ -24,14 +36,59 @@ pub(crate) const NOP_CONTINUE: usize = 1;
 pub(crate) const NOP_BREAK: usize = 2;
 pub(crate) const NOP_UNREACHABLE: usize = 3;
 
-fn compile_scope<B: ByteOrder, F: FnOnce(&mut Context<B>)>(context: &mut Context<B>, fun: F) {
+/// Semantic error, found while compiling a program into IR.
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+#[error("{message}")]
+pub struct SemanticError {
+    /// Location of the error in the source code.
+    pub span: Span,
+
+    /// Error message.
+    pub message: String,
+
+    /// Additional notes on the error.
+    pub notes: Vec<String>,
+}
+
+impl SemanticError {
+    pub(crate) fn new<M: Into<String>>(span: Span, message: M) -> Self {
+        Self {
+            span,
+            message: message.into(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub(crate) fn note<N: Into<String>>(mut self, note: N) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+}
+
+impl From<&SemanticError> for Diagnostic {
+    fn from(error: &SemanticError) -> Self {
+        let diagnostic = Self::new(error.message.clone()).span(error.span);
+        error
+            .notes
+            .iter()
+            .fold(diagnostic, |d, note| d.note(note.clone()))
+    }
+}
+
+pub(crate) type Result<T> = std::result::Result<T, SemanticError>;
+
+fn compile_scope<B, F>(context: &mut Context<B>, fun: F) -> Result<()>
+where
+    B: ByteOrder,
+    F: FnOnce(&mut Context<B>) -> Result<()>,
+{
     // push static symbols from the parent scope (to be restored later)
     // all symbols defined within the child scope will be freed by the end.
     let child = context.symbol_alloc.clone();
     let parent: SymbolAlloc<B> = std::mem::replace(&mut context.symbol_alloc, child);
     //let parent_stack_usage = context.symbol_alloc.stack_usage();
 
-    fun(context);
+    let result = fun(context);
 
     // restore symbols
     let child_static_usage = context.symbol_alloc.static_usage();
@@ -40,6 +97,16 @@ fn compile_scope<B: ByteOrder, F: FnOnce(&mut Context<B>)>(context: &mut Context
     context.stack_size = context.stack_size.max(child_stack_usage);
     context.symbol_alloc.set_static_usage(child_static_usage);
     let _ = context.symbol_alloc.set_const(child_const);
+    result
+}
+
+// Relative jump over `offset` statements, failing if the jump doesn't fit in
+// the 8bit offset (the `span` is of the statement compiling the jump).
+fn relative(offset: isize, span: Span) -> Result<Location> {
+    i8::try_from(offset).map(Location::Relative).map_err(|_| {
+        SemanticError::new(span, "Block too large to jump over")
+            .note("Jumps are limited to 127 statements forward, and 128 backwards")
+    })
 }
 
 /// Ir compilation context.
@@ -48,104 +115,366 @@ pub struct Context<B: ByteOrder> {
     pub(super) routines: Vec<Routine>,
     pub(super) symbol_alloc: SymbolAlloc<B>,
     pub(super) stack_size: u16,
+    pub(super) handlers: Handlers,
+    pub(super) assets: Option<Rc<dyn AssetProvider>>,
+    /// Path of the module of the source file being compiled.
+    pub(super) source: Vec<String>,
     return_: Option<Layout>,
     fn_alloc: FnAlloc,
     register_alloc: RegisterAlloc,
 }
 
 pub trait Compile {
-    fn compile<B: ByteOrder>(&self, context: &mut Context<B>, out: &mut Vec<Statement>);
+    fn compile<B: ByteOrder>(
+        &self,
+        context: &mut Context<B>,
+        out: &mut Vec<Statement>,
+    ) -> Result<()>;
 }
 
-impl Compile for Vec<ast::Statement<'_>> {
-    fn compile<B: ByteOrder>(&self, context: &mut Context<B>, out: &mut Vec<Statement>) {
+impl Compile for [ast::Statement<'_>] {
+    fn compile<B: ByteOrder>(
+        &self,
+        context: &mut Context<B>,
+        out: &mut Vec<Statement>,
+    ) -> Result<()> {
         for statement in self {
             match statement {
-                ast::Statement::If(if_) => if_.compile(context, out),
-                ast::Statement::IfElse(if_else) => if_else.compile(context, out),
-                ast::Statement::Scope(scope) => scope.compile(context, out),
-                ast::Statement::Mod(_) => todo!(),
-                ast::Statement::Static(static_) => static_.compile(context, out),
-                ast::Statement::Const(const_) => const_.compile(context, out),
-                ast::Statement::Let(let_) => let_.compile(context, out),
-                ast::Statement::For(for_) => for_.compile(context, out),
-                ast::Statement::Loop(loop_) => loop_.compile(context, out),
-                ast::Statement::Inline(inline) => inline.compile(context, out),
-                ast::Statement::Fn(fn_) => fn_.compile(context, out),
+                ast::Statement::If(if_) => if_.compile(context, out)?,
+                ast::Statement::IfElse(if_else) => if_else.compile(context, out)?,
+                ast::Statement::Scope(scope) => scope.compile(context, out)?,
+                ast::Statement::Mod(mod_) => mod_.compile(context, out)?,
+                ast::Statement::Use(use_) => use_.compile(context, out)?,
+                ast::Statement::Asm(asm) => asm.compile(context, out)?,
+                ast::Statement::Static(static_) => static_.compile(context, out)?,
+                ast::Statement::Const(const_) => const_.compile(context, out)?,
+                ast::Statement::Include(include) => include.compile(context, out)?,
+                ast::Statement::Let(let_) => let_.compile(context, out)?,
+                ast::Statement::For(for_) => for_.compile(context, out)?,
+                ast::Statement::Loop(loop_) => loop_.compile(context, out)?,
+                ast::Statement::While(while_) => while_.compile(context, out)?,
+                ast::Statement::Inline(inline) => inline.compile(context, out)?,
+                ast::Statement::Fn(fn_) => fn_.compile(context, out)?,
+                ast::Statement::Struct(struct_) => struct_.compile(context, out)?,
+                ast::Statement::Union(union) => union.compile(context, out)?,
+                ast::Statement::Enum(enum_) => enum_.compile(context, out)?,
+                ast::Statement::Type(type_) => type_.compile(context, out)?,
+                ast::Statement::Match(match_) => match_.compile(context, out)?,
                 ast::Statement::Panic(panic) => {
-                    panic.compile(context, out);
+                    panic.compile(context, out)?;
                     break;
                 }
                 ast::Statement::Continue(continue_) => {
-                    continue_.compile(context, out);
+                    continue_.compile(context, out)?;
                     break;
                 }
                 ast::Statement::Break(break_) => {
-                    break_.compile(context, out);
+                    break_.compile(context, out)?;
                     break;
                 }
                 ast::Statement::Return(return_) => {
-                    return_.compile(context, out);
+                    return_.compile(context, out)?;
                     break;
                 }
             }
         }
+        Ok(())
     }
 }
 
 impl Compile for ast::Ast<'_> {
-    fn compile<B: ByteOrder>(&self, context: &mut Context<B>, out: &mut Vec<Statement>) {
+    fn compile<B: ByteOrder>(
+        &self,
+        context: &mut Context<B>,
+        out: &mut Vec<Statement>,
+    ) -> Result<()> {
         out.push(Nop(NOP_PERSIST));
-        self.inner.compile(context, out);
+        self.inner.compile(context, out)?;
         out.push(Stop(StopStatus::Success));
         let stack_size = context.symbol_alloc.stack_usage();
         context.stack_size = context.stack_size.max(stack_size);
+        Ok(())
     }
 }
 
 impl Compile for ast::Panic<'_> {
-    fn compile<B: ByteOrder>(&self, _: &mut Context<B>, out: &mut Vec<Statement>) {
+    fn compile<B: ByteOrder>(&self, _: &mut Context<B>, out: &mut Vec<Statement>) -> Result<()> {
         out.push(Stop(StopStatus::Error));
+        Ok(())
     }
 }
 
 impl Compile for ast::Scope<'_> {
-    fn compile<B: ByteOrder>(&self, context: &mut Context<B>, out: &mut Vec<Statement>) {
-        compile_scope(context, |ctx| self.inner.compile(ctx, out));
+    fn compile<B: ByteOrder>(
+        &self,
+        context: &mut Context<B>,
+        out: &mut Vec<Statement>,
+    ) -> Result<()> {
+        compile_scope(context, |ctx| self.inner.compile(ctx, out))
+    }
+}
+
+/// Compile the items of a module, nested in the module `path` relative to the
+/// current one.
+pub(super) fn compile_module<B: ByteOrder>(
+    path: &[String],
+    inner: &[ast::Statement<'_>],
+    context: &mut Context<B>,
+    out: &mut Vec<Statement>,
+) -> Result<()> {
+    // modules are namespaces, not scopes: the symbols declared within them
+    // remain allocated after the block (under a mangled name).
+    for statement in inner {
+        match statement {
+            ast::Statement::Mod(_)
+            | ast::Statement::Use(_)
+            | ast::Statement::Static(_)
+            | ast::Statement::Const(_)
+            | ast::Statement::Include(_)
+            | ast::Statement::Fn(_)
+            | ast::Statement::Struct(_)
+            | ast::Statement::Union(_)
+            | ast::Statement::Enum(_)
+            | ast::Statement::Type(_) => {}
+            _ => {
+                return Err(
+                    SemanticError::new(statement.span(), "Statement not allowed in a mod").note(
+                        "Only mod, use, static, const, include, fn, struct, union, enum, and \
+                         type items are allowed inside of a mod",
+                    ),
+                )
+            }
+        }
+    }
+    for name in path {
+        context.symbol_alloc.push_mod(name);
+    }
+    let result = inner.compile(context, out);
+    for _ in path {
+        context.symbol_alloc.pop_mod();
+    }
+    result
+}
+
+impl Compile for ast::Mod<'_> {
+    fn compile<B: ByteOrder>(
+        &self,
+        context: &mut Context<B>,
+        out: &mut Vec<Statement>,
+    ) -> Result<()> {
+        compile_module(&[self.ident.to_string()], &self.inner, context, out)
+    }
+}
+
+impl Compile for ast::Use<'_> {
+    fn compile<B: ByteOrder>(
+        &self,
+        context: &mut Context<B>,
+        _: &mut Vec<Statement>,
+    ) -> Result<()> {
+        context.symbol_alloc.import(&self.path);
+        Ok(())
     }
 }
 
 impl Compile for ast::Static<'_> {
-    fn compile<B: ByteOrder>(&self, context: &mut Context<B>, _: &mut Vec<Statement>) {
+    fn compile<B: ByteOrder>(
+        &self,
+        context: &mut Context<B>,
+        _: &mut Vec<Statement>,
+    ) -> Result<()> {
         if let Some(offset) = &self.offset {
             // static memory with explicit offset means the memory is located at the
             // absolute location in memory.
             let symbol_alloc = &context.symbol_alloc;
-            let offset = expression::const_expr(&offset.expression, Some(symbol_alloc))
-                .expect("Not a constant expression offset!");
-            context.symbol_alloc.alloc_absolute(&self.field, offset);
+            let offset =
+                constant::const_expr(&offset.expression, Some(symbol_alloc))?.ok_or_else(|| {
+                    let span = offset.expression.span();
+                    SemanticError::new(span, "Static offset must be a constant expression")
+                })?;
+            context.symbol_alloc.alloc_absolute(&self.field, offset)
         } else {
             // otw the memory is allocated by the compiler in the static virtual memory
             // space.
-            context.symbol_alloc.alloc_static(&self.field);
+            context.symbol_alloc.alloc_static(&self.field)
         }
     }
 }
 
 impl Compile for ast::Const<'_> {
-    fn compile<B: ByteOrder>(&self, context: &mut Context<B>, _: &mut Vec<Statement>) {
+    fn compile<B: ByteOrder>(
+        &self,
+        context: &mut Context<B>,
+        _: &mut Vec<Statement>,
+    ) -> Result<()> {
         context
             .symbol_alloc
-            .alloc_const(&self.field, &self.expression);
+            .alloc_const(&self.field, &self.expression)
+    }
+}
+
+impl Compile for ast::Include<'_> {
+    fn compile<B: ByteOrder>(
+        &self,
+        context: &mut Context<B>,
+        _: &mut Vec<Statement>,
+    ) -> Result<()> {
+        let path: String = charmap::unescape(&self.path)?
+            .into_iter()
+            .map(|c| match c {
+                Char::Char(c) => c,
+                Char::Byte(byte) => byte as char,
+            })
+            .collect();
+        let error = |message: String| SemanticError::new(self.path.span(), message);
+        let is_png = path.to_lowercase().ends_with(".png");
+        if let (false, Some(map)) = (is_png, &self.map) {
+            return Err(SemanticError::new(
+                map.span(),
+                "Tilemaps can only be generated from `.png` images",
+            ));
+        }
+        let assets = context.assets.as_ref().ok_or_else(|| {
+            error(format!("Can't include `{}`", path))
+                .note("Files can only be included when compiling with an asset provider")
+        })?;
+        let data = match assets.asset(&context.source, &path) {
+            Ok(Some(data)) => data,
+            Ok(None) => return Err(error(format!("File not found: `{}`", path))),
+            Err(err) => return Err(error(format!("Failed to read `{}`: {}", path, err))),
+        };
+
+        // PNG images are converted into tile data
+        if !is_png {
+            return alloc_include(context, &self.field, &data, "a file", self.path.span());
+        }
+        let tiles = tiles::from_png(&data, self.map.is_some())
+            .map_err(|message| error(format!("Failed to import `{}`", path)).note(message))?;
+        alloc_include(
+            context,
+            &self.field,
+            &tiles.data,
+            "tile data",
+            self.path.span(),
+        )?;
+        match (&self.map, tiles.map) {
+            (Some(map), Some(indices)) => {
+                alloc_include(context, map, &indices, "a tilemap", self.path.span())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// allocate a const symbol for the (already read) contents of an include
+// statement, which must have the same size as the symbol.
+fn alloc_include<B: ByteOrder>(
+    context: &mut Context<B>,
+    field: &ast::Field<'_>,
+    data: &[u8],
+    what: &str,
+    span: Span,
+) -> Result<()> {
+    let layout = context.symbol_alloc.layout(&field.type_)?;
+    if layout.size() as usize != data.len() {
+        let message = format!(
+            "Expected {} of {} bytes, found {}",
+            what,
+            layout.size(),
+            data.len()
+        );
+        return Err(SemanticError::new(span, message)
+            .note(format!("`{}` has type `{}`", field.ident, layout)));
+    }
+    context.symbol_alloc.alloc_const_data(field, data)
+}
+
+impl Compile for ast::types::StructDef<'_> {
+    fn compile<B: ByteOrder>(
+        &self,
+        context: &mut Context<B>,
+        _: &mut Vec<Statement>,
+    ) -> Result<()> {
+        let fields = self.fields.iter().map(|field| {
+            let layout = context.symbol_alloc.layout(&field.type_)?;
+            Ok((field.ident.to_string(), layout))
+        });
+        let layout = Layout::struct_(fields.collect::<Result<Vec<_>>>()?);
+        context.symbol_alloc.alloc_type(&self.ident, layout)
+    }
+}
+
+impl Compile for ast::types::UnionDef<'_> {
+    fn compile<B: ByteOrder>(
+        &self,
+        context: &mut Context<B>,
+        _: &mut Vec<Statement>,
+    ) -> Result<()> {
+        let fields = self.fields.iter().map(|field| {
+            let layout = context.symbol_alloc.layout(&field.type_)?;
+            Ok((field.ident.to_string(), layout))
+        });
+        let layout = Layout::union(fields.collect::<Result<Vec<_>>>()?);
+        context.symbol_alloc.alloc_type(&self.ident, layout)
+    }
+}
+
+impl Compile for ast::types::TypeDef<'_> {
+    fn compile<B: ByteOrder>(
+        &self,
+        context: &mut Context<B>,
+        _: &mut Vec<Statement>,
+    ) -> Result<()> {
+        let layout = context.symbol_alloc.layout(&self.inner)?;
+        context.symbol_alloc.alloc_type(&self.ident, layout)
+    }
+}
+
+impl Compile for ast::types::EnumDef<'_> {
+    fn compile<B: ByteOrder>(
+        &self,
+        context: &mut Context<B>,
+        _: &mut Vec<Statement>,
+    ) -> Result<()> {
+        // discriminant of the next variant (`None` if the previous one was 255)
+        let mut next = Some(0);
+        for variant in &self.variants {
+            let value = match &variant.discriminant {
+                Some(discriminant) => {
+                    let expression = &discriminant.expression;
+                    let symbol_alloc = Some(&context.symbol_alloc);
+                    match constant::const_value(expression, &Layout::U8, symbol_alloc)? {
+                        Some(constant::Value::U8(value)) => value,
+                        _ => {
+                            let message = "Enum discriminant must be a constant expression";
+                            return Err(SemanticError::new(expression.span(), message));
+                        }
+                    }
+                }
+                None => next.ok_or_else(|| {
+                    SemanticError::new(variant.ident.span(), "Enum discriminant overflow")
+                        .note("The previous variant has a discriminant of 255")
+                })?,
+            };
+            context
+                .symbol_alloc
+                .alloc_variant(&self.ident, &variant.ident, value)?;
+            next = value.checked_add(1);
+        }
+        context.symbol_alloc.alloc_type(&self.ident, Layout::U8)
     }
 }
 
 impl Compile for ast::Let<'_> {
-    fn compile<B: ByteOrder>(&self, context: &mut Context<B>, out: &mut Vec<Statement>) {
+    fn compile<B: ByteOrder>(
+        &self,
+        context: &mut Context<B>,
+        out: &mut Vec<Statement>,
+    ) -> Result<()> {
         // allocate memory on the stack for this field
         // the compiled expression should store the result on the stack
-        let stack_address = context.symbol_alloc.alloc_stack_field(&self.field);
-        let field_layout = Layout::new(&self.field.type_);
+        let stack_address = context.symbol_alloc.alloc_stack_field(&self.field)?;
+        let field_layout = context.symbol_alloc.layout(&self.field.type_)?;
         expression::compile_expression_into_pointer(
             &self.expression,
             &field_layout,
@@ -154,12 +483,16 @@ impl Compile for ast::Let<'_> {
             Pointer::Stack(stack_address),
             &mut context.register_alloc,
             out,
-        );
+        )
     }
 }
 
 impl Compile for ast::Inline<'_> {
-    fn compile<B: ByteOrder>(&self, context: &mut Context<B>, out: &mut Vec<Statement>) {
+    fn compile<B: ByteOrder>(
+        &self,
+        context: &mut Context<B>,
+        out: &mut Vec<Statement>,
+    ) -> Result<()> {
         // compile expression and drop the results.
         // the expression will be evaluated by the result is not stored anywhere.
         expression::compile_expr_void(
@@ -176,20 +509,26 @@ struct IfStatements<'a, 'b> {
     expression: &'a ast::Expression<'b>,
     inner: &'a Vec<ast::Statement<'b>>,
     has_else: bool,
+    span: Span,
 }
 
 impl Compile for ast::If<'_> {
-    fn compile<B: ByteOrder>(&self, context: &mut Context<B>, out: &mut Vec<Statement>) {
-        let const_expr = expression::const_expr(&self.expression, Some(&context.symbol_alloc));
+    fn compile<B: ByteOrder>(
+        &self,
+        context: &mut Context<B>,
+        out: &mut Vec<Statement>,
+    ) -> Result<()> {
+        let const_expr = constant::const_expr(&self.expression, Some(&context.symbol_alloc))?;
 
         match const_expr {
-            Some(0) => {}
+            Some(0) => Ok(()),
             Some(_) => compile_scope(context, |ctx| self.inner.compile(ctx, out)),
             None => compile_scope(context, |ctx| {
                 IfStatements {
                     expression: &self.expression,
                     inner: &self.inner,
                     has_else: false,
+                    span: self.span(),
                 }
                 .compile(ctx, out)
             }),
@@ -198,8 +537,12 @@ impl Compile for ast::If<'_> {
 }
 
 impl Compile for ast::IfElse<'_> {
-    fn compile<B: ByteOrder>(&self, context: &mut Context<B>, out: &mut Vec<Statement>) {
-        let const_expr = expression::const_expr(&self.if_.expression, Some(&context.symbol_alloc));
+    fn compile<B: ByteOrder>(
+        &self,
+        context: &mut Context<B>,
+        out: &mut Vec<Statement>,
+    ) -> Result<()> {
+        let const_expr = constant::const_expr(&self.if_.expression, Some(&context.symbol_alloc))?;
 
         match const_expr {
             Some(0) => compile_scope(context, |ctx| self.else_.inner.compile(ctx, out)),
@@ -208,48 +551,215 @@ impl Compile for ast::IfElse<'_> {
                 // compiled else_ block
                 let mut else_ = Vec::new();
 
-                compile_scope(context, |ctx| self.else_.inner.compile(ctx, &mut else_));
+                compile_scope(context, |ctx| self.else_.inner.compile(ctx, &mut else_))?;
                 compile_scope(context, |ctx| {
                     IfStatements {
                         expression: &self.if_.expression,
                         inner: &self.if_.inner,
                         has_else: true,
+                        span: self.if_.span(),
                     }
                     .compile(ctx, out)
-                });
+                })?;
 
                 out.push(Jmp {
-                    location: Location::Relative(else_.len() as _),
+                    location: relative(else_.len() as isize, self.span())?,
                 });
                 out.extend(else_);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Compile for ast::Match<'_> {
+    fn compile<B: ByteOrder>(
+        &self,
+        context: &mut Context<B>,
+        out: &mut Vec<Statement>,
+    ) -> Result<()> {
+        let layout = expression::numeric_layout(&self.expression, &context.symbol_alloc)?
+            .unwrap_or(Layout::U8);
+
+        // pattern values of each arm
+        let mut patterns = Vec::with_capacity(self.arms.len());
+        for arm in &self.arms {
+            let mut values = Vec::with_capacity(arm.patterns.len());
+            for pattern in &arm.patterns {
+                let value = constant::const_value(pattern, &layout, Some(&context.symbol_alloc))?;
+                match value.as_ref().and_then(constant::Value::int) {
+                    Some(value) => values.push(value as u16),
+                    None => {
+                        let message = "Match patterns must be constant expressions";
+                        return Err(SemanticError::new(pattern.span(), message));
+                    }
+                }
+            }
+            patterns.push(values);
+        }
+
+        // matching a constant expression only compiles the matching arm
+        if let Some(value) = constant::const_expr(&self.expression, Some(&context.symbol_alloc))? {
+            let arm = patterns.iter().position(|values| values.contains(&value));
+            return match (arm, &self.else_) {
+                (Some(arm), _) => {
+                    compile_scope(context, |ctx| self.arms[arm].inner.compile(ctx, out))
+                }
+                (None, Some(else_)) => compile_scope(context, |ctx| else_.inner.compile(ctx, out)),
+                (None, None) => Ok(()),
+            };
+        }
+
+        // The compare chain jumps into the first matching arm. If none does,
+        // it falls through to the else block:
+        // ```no_rust
+        // <compare chain>
+        // <else>
+        // Jmp end
+        // <arm 0>
+        // Jmp end
+        // ...
+        // <arm N>
+        // end:
+        // ```
+        let mut else_ = Vec::new();
+        if let Some(else_block) = &self.else_ {
+            compile_scope(context, |ctx| else_block.inner.compile(ctx, &mut else_))?;
+        }
+        let mut inner = Vec::with_capacity(self.arms.len());
+        for arm in &self.arms {
+            let mut statements = Vec::new();
+            compile_scope(context, |ctx| arm.inner.compile(ctx, &mut statements))?;
+            inner.push(statements);
+        }
+
+        // every arm but the last one is followed by a jump to the end
+        let arms_len = inner.iter().map(Vec::len).sum::<usize>() + inner.len().saturating_sub(1);
+        let mut arms = Vec::with_capacity(arms_len);
+        let mut targets = Vec::with_capacity(inner.len());
+        for (i, statements) in inner.into_iter().enumerate() {
+            if i > 0 {
+                let offset = arms_len - arms.len() - 1;
+                arms.push(Jmp {
+                    location: relative(offset as isize, self.span())?,
+                });
             }
+            targets.push(arms.len());
+            arms.extend(statements);
+        }
+        if !self.arms.is_empty() {
+            else_.push(Jmp {
+                location: relative(arms_len as isize, self.span())?,
+            });
+        }
+
+        // offsets of the arms, relative to the beginning of the compare chain
+        let chain_len = 2 * patterns.iter().map(Vec::len).sum::<usize>();
+        let targets: Vec<_> = targets
+            .into_iter()
+            .map(|target| chain_len + else_.len() + target)
+            .collect();
+
+        let mut chain = Vec::with_capacity(chain_len);
+        let symbol_alloc = &context.symbol_alloc;
+        let fn_alloc = &context.fn_alloc;
+        let register_alloc = &mut context.register_alloc;
+        if expression::is_word_layout(&layout) {
+            #[rustfmt::skip] let source = expression::compile_expr_u16(&self.expression, symbol_alloc, fn_alloc, register_alloc, out)?;
+            compare_chain(
+                &patterns,
+                &targets,
+                self.span(),
+                register_alloc,
+                &mut chain,
+                |n, destination| Statement::EqW {
+                    left: source.clone(),
+                    right: Source::Literal(n),
+                    destination,
+                },
+            )?;
+            expression::free_source_registers(&source, register_alloc);
+        } else {
+            #[rustfmt::skip] let source = expression::compile_expr_u8(&self.expression, symbol_alloc, fn_alloc, register_alloc, out)?;
+            compare_chain(
+                &patterns,
+                &targets,
+                self.span(),
+                register_alloc,
+                &mut chain,
+                |n, destination| Statement::Eq {
+                    left: source.clone(),
+                    right: Source::Literal(n as u8),
+                    destination,
+                },
+            )?;
+            expression::free_source_registers(&source, register_alloc);
+        }
+
+        out.extend(chain);
+        out.extend(else_);
+        out.extend(arms);
+        Ok(())
+    }
+}
+
+// Compare the matched value against the patterns of every arm, in order, and
+// jump to the target of the arm when equal. `compare` returns the statement
+// that compares the value against a pattern.
+fn compare_chain<F>(
+    patterns: &[Vec<u16>],
+    targets: &[usize],
+    span: Span,
+    register_alloc: &mut RegisterAlloc,
+    out: &mut Vec<Statement>,
+    compare: F,
+) -> Result<()>
+where
+    F: Fn(u16, Destination) -> Statement,
+{
+    for (values, target) in patterns.iter().zip(targets) {
+        for value in values {
+            let register = register_alloc.alloc();
+            out.push(compare(*value, Destination::Register(register)));
+            let offset = target - out.len() - 1;
+            out.push(JmpCmp {
+                location: relative(offset as isize, span)?,
+                source: Source::Register(register),
+            });
+            register_alloc.free(register);
         }
     }
+    Ok(())
 }
 
 impl Compile for IfStatements<'_, '_> {
-    fn compile<B: ByteOrder>(&self, context: &mut Context<B>, out: &mut Vec<Statement>) {
+    fn compile<B: ByteOrder>(
+        &self,
+        context: &mut Context<B>,
+        out: &mut Vec<Statement>,
+    ) -> Result<()> {
         // compile expression into an 8bit register
-        let source = expression::compile_expr_u8(
+        let source = expression::compile_truth(
             &self.expression,
             &context.symbol_alloc,
             &context.fn_alloc,
             &mut context.register_alloc,
             out,
-        );
+        )?;
         expression::free_source_registers(&source, &mut context.register_alloc);
 
         // compile the block of statements inside the if block.
         // clone the symbol_alloc to free any symbols defined within the block.
         let mut inner = Vec::new();
-        self.inner.compile(context, &mut inner);
+        self.inner.compile(context, &mut inner)?;
 
         let jmp = inner.len() + if self.has_else { 1 } else { 0 };
         out.push(JmpCmpNot {
-            location: Location::Relative(jmp as _),
+            location: relative(jmp as isize, self.span)?,
             source,
         });
         out.extend(inner);
+        Ok(())
     }
 }
 
@@ -266,21 +776,33 @@ struct LoopInner<'a, 'b> {
     prefix: Vec<Statement>,
     inner: &'a Vec<ast::Statement<'b>>,
     suffix: Vec<Statement>,
+    span: Span,
 }
 
 impl Compile for LoopInner<'_, '_> {
-    fn compile<B: ByteOrder>(&self, context: &mut Context<B>, out: &mut Vec<Statement>) {
+    fn compile<B: ByteOrder>(
+        &self,
+        context: &mut Context<B>,
+        out: &mut Vec<Statement>,
+    ) -> Result<()> {
         // compile statements inside the loop block
         // at the end, jump back to the first statement
         let mut inner = Vec::new();
 
         inner.extend_from_slice(&self.prefix);
-        self.inner.compile(context, &mut inner);
+        self.inner.compile(context, &mut inner)?;
         inner.extend_from_slice(&self.suffix);
 
+        // continue jumps to the suffix (if any) rather than the start of the loop.
+        let continue_target = if self.suffix.is_empty() {
+            0
+        } else {
+            inner.len() - self.suffix.len()
+        };
+
         let loop_statements_signed = inner.len() as isize;
         inner.push(Jmp {
-            location: Location::Relative(-(loop_statements_signed + 1) as i8),
+            location: relative(-(loop_statements_signed + 1), self.span)?,
         });
 
         // replace Nop statements (placeholders for break and continue) with the
@@ -291,234 +813,612 @@ impl Compile for LoopInner<'_, '_> {
             match statement {
                 // break
                 Nop(NOP_BREAK) => {
-                    let relative = statements_len - i - 1;
+                    let offset = statements_len - i - 1;
                     *statement = Jmp {
-                        location: Location::Relative(relative as _),
+                        location: relative(offset as isize, self.span)?,
                     };
                 }
                 // continue
                 Nop(NOP_CONTINUE) => {
-                    let relative = i as isize + 1;
+                    let offset = continue_target as isize - i as isize - 1;
                     *statement = Jmp {
-                        location: Location::Relative(-relative as _),
+                        location: relative(offset, self.span)?,
                     };
                 }
                 _ => {}
             }
         }
         out.extend(inner);
+        Ok(())
     }
 }
 
 impl Compile for ast::Loop<'_> {
-    fn compile<B: ByteOrder>(&self, context: &mut Context<B>, out: &mut Vec<Statement>) {
+    fn compile<B: ByteOrder>(
+        &self,
+        context: &mut Context<B>,
+        out: &mut Vec<Statement>,
+    ) -> Result<()> {
         compile_scope(context, |context| {
             LoopInner {
                 prefix: Vec::new(),
                 inner: &self.inner,
                 suffix: Vec::new(),
+                span: self.span(),
             }
             .compile(context, out)
         })
     }
 }
 
+impl Compile for ast::While<'_> {
+    fn compile<B: ByteOrder>(
+        &self,
+        context: &mut Context<B>,
+        out: &mut Vec<Statement>,
+    ) -> Result<()> {
+        let const_expr = constant::const_expr(&self.expression, Some(&context.symbol_alloc))?;
+
+        match const_expr {
+            Some(0) => Ok(()),
+            Some(_) => compile_scope(context, |context| {
+                LoopInner {
+                    prefix: Vec::new(),
+                    inner: &self.inner,
+                    suffix: Vec::new(),
+                    span: self.span(),
+                }
+                .compile(context, out)
+            }),
+            None => compile_scope(context, |context| {
+                // evaluate the condition at the top of every iteration, and break
+                // out of the loop once it is false.
+                let mut prefix = Vec::new();
+                let source = expression::compile_truth(
+                    &self.expression,
+                    &context.symbol_alloc,
+                    &context.fn_alloc,
+                    &mut context.register_alloc,
+                    &mut prefix,
+                )?;
+                expression::free_source_registers(&source, &mut context.register_alloc);
+                prefix.push(JmpCmp {
+                    location: Location::Relative(1),
+                    source,
+                });
+                prefix.push(Nop(NOP_BREAK));
+
+                LoopInner {
+                    prefix,
+                    inner: &self.inner,
+                    suffix: Vec::new(),
+                    span: self.span(),
+                }
+                .compile(context, out)
+            }),
+        }
+    }
+}
+
+// 8 or 16bit operand of the statements that drive a for loop.
+#[derive(Clone, Copy)]
+enum ForOperand {
+    Literal(u16),
+    Register(usize),
+    Stack(u16),
+}
+
+impl ForOperand {
+    fn u8(self) -> Source<u8> {
+        match self {
+            Self::Literal(n) => Source::Literal(n as u8),
+            Self::Register(r) => Source::Register(r),
+            Self::Stack(address) => Source::Pointer {
+                base: Pointer::Stack(address),
+                offset: None,
+            },
+        }
+    }
+
+    fn u16(self) -> Source<u16> {
+        match self {
+            Self::Literal(n) => Source::Literal(n),
+            Self::Register(r) => Source::Register(r),
+            Self::Stack(address) => Source::Pointer {
+                base: Pointer::Stack(address),
+                offset: None,
+            },
+        }
+    }
+
+    fn destination(self) -> Destination {
+        match self {
+            Self::Register(r) => Destination::Register(r),
+            Self::Stack(address) => Destination::Pointer {
+                base: Pointer::Stack(address),
+                offset: None,
+            },
+            Self::Literal(_) => unreachable!(),
+        }
+    }
+}
+
+// number of iterations of a for loop over a constant range.
+fn for_iterations(left: i64, right: i64, inclusive: bool, step: i64) -> i64 {
+    let distance = if step > 0 { right - left } else { left - right };
+    let distance = if inclusive { distance } else { distance - 1 };
+    if distance < 0 {
+        0
+    } else {
+        distance / step.abs() + 1
+    }
+}
+
 impl Compile for ast::For<'_> {
-    fn compile<B: ByteOrder>(&self, context: &mut Context<B>, out: &mut Vec<Statement>) {
-        compile_scope(context, |context| {
-            let mut for_statements = Vec::new();
-            let stack_address = context.symbol_alloc.alloc_stack_field(&self.field);
+    fn compile<B: ByteOrder>(
+        &self,
+        context: &mut Context<B>,
+        out: &mut Vec<Statement>,
+    ) -> Result<()> {
+        // emit the 8bit or the 16bit variant of a binary statement.
+        macro_rules! binary {
+            ($word:expr, $var:ident, $var_w:ident, $left:expr, $right:expr, $dst:expr) => {
+                if $word {
+                    Statement::$var_w {
+                        left: $left.u16(),
+                        right: $right.u16(),
+                        destination: $dst,
+                    }
+                } else {
+                    Statement::$var {
+                        left: $left.u8(),
+                        right: $right.u8(),
+                        destination: $dst,
+                    }
+                }
+            };
+        }
 
-            // init for variable with the lhs side of the range
-            // TODO non-U8 variables
-            let init = expression::compile_expr_u8(
-                &self.range.left,
-                &context.symbol_alloc,
-                &context.fn_alloc,
-                &mut context.register_alloc,
-                out,
-            );
-            expression::free_source_registers(&init, &mut context.register_alloc);
-            out.push(Ld {
-                source: init,
-                destination: Destination::Pointer {
-                    base: Pointer::Stack(stack_address),
-                    offset: None,
-                },
-            });
+        compile_scope(context, |context| {
+            let layout = context.symbol_alloc.layout(&self.field.type_)?;
+            let (word, signed) = match layout {
+                Layout::U8 => (false, false),
+                Layout::I8 => (false, true),
+                Layout::U16 => (true, false),
+                Layout::I16 => (true, true),
+                _ => {
+                    let message = "For loop variables must be integers";
+                    return Err(SemanticError::new(self.field.type_.span(), message));
+                }
+            };
+            let mask = if word { 0xffff } else { 0xff };
 
-            // if the for loop only performs a single iteration (an this can be determined
-            // statically), compile as a regular block statement.
-            // TODO handle cases where no iterations are performed:
-            //  for i:u8 in n..n
-            //  for i:u8 in n..+0
-            let l = expression::const_expr(&self.range.left, Some(&context.symbol_alloc));
-            let r = expression::const_expr(&self.range.right, Some(&context.symbol_alloc));
-            match (l, r, &self.range.eq, &self.range.plus) {
-                // for _ in n..=n
-                (Some(l), Some(r), Some(_), None) if l == r => {
-                    self.inner.compile(context, out);
-                    return;
+            // the step is constant so that the direction of the loop is known.
+            let step = match &self.range.step {
+                None => 1,
+                Some(step) => {
+                    match constant::const_expr(&step.expression, Some(&context.symbol_alloc))? {
+                        Some(0) => {
+                            let message = "For range step can't be zero";
+                            return Err(SemanticError::new(step.expression.span(), message));
+                        }
+                        Some(n) => n as i16 as i64,
+                        None => {
+                            let message = "For range step must be a constant expression";
+                            return Err(SemanticError::new(step.expression.span(), message));
+                        }
+                    }
                 }
-                // for _ in n..(n+1)
-                (Some(l), Some(r), None, None) if l + 1 == r => {
-                    self.inner.compile(context, out);
-                    return;
+            };
+            let inclusive = self.range.eq.is_some();
+            let descending = step < 0;
+
+            let left =
+                constant::const_value(&self.range.left, &layout, Some(&context.symbol_alloc))?
+                    .and_then(|value| value.int());
+            let right = match &self.range.plus {
+                // `..+` ranges are relative to the left side of the range.
+                Some(_) => constant::const_expr(&self.range.right, Some(&context.symbol_alloc))?
+                    .map(|n| n as i64),
+                None => {
+                    constant::const_value(&self.range.right, &layout, Some(&context.symbol_alloc))?
+                        .and_then(|value| value.int())
                 }
-                // for _ in n..=+0
-                (Some(_), Some(0), Some(_), Some(_)) => {
-                    self.inner.compile(context, out);
-                    return;
+            };
+            let end = match (left, right, &self.range.plus) {
+                (Some(left), Some(right), Some(_)) => {
+                    let bounds = match (word, signed) {
+                        (false, false) => 0..=0xff,
+                        (false, true) => -0x80..=0x7f,
+                        (true, false) => 0..=0xffff,
+                        (true, true) => -0x8000..=0x7fff,
+                    };
+                    let end = left + right;
+                    if !bounds.contains(&end) {
+                        let message = "For range end out of bounds";
+                        return Err(SemanticError::new(self.range.right.span(), message).note(
+                            format!("The end of the range ({}) doesn't fit in `{}`", end, layout),
+                        ));
+                    }
+                    Some(end)
                 }
-                _ => {}
+                (_, right, None) => right,
+                _ => None,
+            };
+
+            // the number of iterations can be determined statically.
+            let iterations = match (left, end) {
+                (Some(left), Some(end)) => Some(for_iterations(left, end, inclusive, step)),
+                _ => None,
+            };
+            if iterations == Some(0) {
+                return Ok(());
             }
 
-            // compute end index of the for loop with the rhs of the range
-            // increment if it's an inclusive range
-            let end = expression::compile_expr_u8(
-                &self.range.right,
-                &context.symbol_alloc,
-                &context.fn_alloc,
-                &mut context.register_alloc,
-                out,
-            );
-            let end_register = context.register_alloc.alloc();
-            out.push(Statement::Ld {
-                source: end.clone(),
-                destination: Destination::Register(end_register),
-            });
-            expression::free_source_registers(&end, &mut context.register_alloc);
-            if self.range.eq.is_some() {
-                out.push(Inc {
-                    source: Source::Register(end_register),
-                    destination: Destination::Register(end_register),
+            // init for variable with the lhs side of the range. the variable is
+            // allocated afterwards so that it isn't visible from the range itself.
+            let mut init = Vec::new();
+            let variable = if word {
+                let source = expression::compile_expr_u16(
+                    &self.range.left,
+                    &context.symbol_alloc,
+                    &context.fn_alloc,
+                    &mut context.register_alloc,
+                    &mut init,
+                )?;
+                expression::free_source_registers(&source, &mut context.register_alloc);
+                let variable =
+                    ForOperand::Stack(context.symbol_alloc.alloc_stack_field(&self.field)?);
+                init.push(Statement::LdW {
+                    source,
+                    destination: variable.destination(),
+                });
+                variable
+            } else {
+                let source = expression::compile_expr_u8(
+                    &self.range.left,
+                    &context.symbol_alloc,
+                    &context.fn_alloc,
+                    &mut context.register_alloc,
+                    &mut init,
+                )?;
+                expression::free_source_registers(&source, &mut context.register_alloc);
+                let variable =
+                    ForOperand::Stack(context.symbol_alloc.alloc_stack_field(&self.field)?);
+                init.push(Ld {
+                    source,
+                    destination: variable.destination(),
                 });
+                variable
+            };
+
+            // if the for loop only performs a single iteration, compile as a
+            // regular block statement.
+            if iterations == Some(1) {
+                let mut inner = Vec::new();
+                self.inner.compile(context, &mut inner)?;
+                let inner_len = inner.len();
+                for (i, statement) in inner.iter_mut().enumerate() {
+                    if let Nop(NOP_BREAK | NOP_CONTINUE) = statement {
+                        *statement = Jmp {
+                            location: relative((inner_len - i - 1) as isize, self.span())?,
+                        };
+                    }
+                }
+                out.extend(init);
+                out.extend(inner);
+                return Ok(());
             }
 
-            // begin compiling the inner for loop statements.
-            // check if for loop variable has reached the limit.
-            let cmp_register = context.register_alloc.alloc();
-            let prefix = vec![
-                Sub {
-                    left: Source::Register(end_register),
-                    right: Source::Pointer {
-                        base: Pointer::Stack(stack_address),
-                        offset: None,
+            // compute end index of the for loop with the rhs of the range.
+            // non-constant ends are held in a register for the whole loop.
+            let end = match end {
+                Some(end) => ForOperand::Literal((end & mask) as u16),
+                None => {
+                    let register = context.register_alloc.alloc();
+                    let end = ForOperand::Register(register);
+                    if word {
+                        let source = expression::compile_expr_u16(
+                            &self.range.right,
+                            &context.symbol_alloc,
+                            &context.fn_alloc,
+                            &mut context.register_alloc,
+                            &mut init,
+                        )?;
+                        expression::free_source_registers(&source, &mut context.register_alloc);
+                        init.push(match &self.range.plus {
+                            Some(_) => Statement::AddW {
+                                left: variable.u16(),
+                                right: source,
+                                destination: end.destination(),
+                            },
+                            None => Statement::LdW {
+                                source,
+                                destination: end.destination(),
+                            },
+                        });
+                    } else {
+                        let source = expression::compile_expr_u8(
+                            &self.range.right,
+                            &context.symbol_alloc,
+                            &context.fn_alloc,
+                            &mut context.register_alloc,
+                            &mut init,
+                        )?;
+                        expression::free_source_registers(&source, &mut context.register_alloc);
+                        init.push(match &self.range.plus {
+                            Some(_) => Statement::Add {
+                                left: variable.u8(),
+                                right: source,
+                                destination: end.destination(),
+                            },
+                            None => Ld {
+                                source,
+                                destination: end.destination(),
+                            },
+                        });
+                    }
+                    end
+                }
+            };
+
+            // skip the loop if the range is empty (unless known statically).
+            let mut guard = Vec::new();
+            if iterations.is_none() {
+                let (mut low, mut high) = if descending {
+                    (end, variable)
+                } else {
+                    (variable, end)
+                };
+                let mut registers = vec![context.register_alloc.alloc()];
+                let cmp = ForOperand::Register(registers[0]);
+                // bias signed 16bit values so they compare as unsigned.
+                if word && signed {
+                    let bias = ForOperand::Literal(0x8000);
+                    for operand in [&mut low, &mut high] {
+                        let register = context.register_alloc.alloc();
+                        let biased = ForOperand::Register(register);
+                        guard.push(binary!(
+                            true,
+                            Xor,
+                            XorW,
+                            *operand,
+                            bias,
+                            biased.destination()
+                        ));
+                        *operand = biased;
+                        registers.push(register);
+                    }
+                }
+                guard.push(match (inclusive, signed && !word) {
+                    (true, true) => Statement::LessEqS {
+                        left: low.u8(),
+                        right: high.u8(),
+                        destination: cmp.destination(),
+                    },
+                    (false, true) => Statement::LessS {
+                        left: low.u8(),
+                        right: high.u8(),
+                        destination: cmp.destination(),
                     },
-                    destination: Destination::Register(cmp_register),
+                    (true, false) => binary!(word, LessEq, LessEqW, low, high, cmp.destination()),
+                    (false, false) => binary!(word, Less, LessW, low, high, cmp.destination()),
+                });
+                for register in registers {
+                    context.register_alloc.free(register);
+                }
+                guard.push(JmpCmpNot {
+                    location: Location::Relative(0),
+                    source: cmp.u8(),
+                });
+            }
+
+            // at the end of every iteration, break out of the loop if stepping the
+            // variable would go past the end of the range. Comparing the distance
+            // to the end (rather than the variable itself) avoids overflows.
+            let distance = ForOperand::Register(context.register_alloc.alloc());
+            let step_abs = ForOperand::Literal(step.unsigned_abs() as u16);
+            let mut suffix = Vec::new();
+            suffix.push(if descending {
+                binary!(word, Sub, SubW, variable, end, distance.destination())
+            } else {
+                binary!(word, Sub, SubW, end, variable, distance.destination())
+            });
+            suffix.push(if inclusive {
+                binary!(
+                    word,
+                    GreaterEq,
+                    GreaterEqW,
+                    distance,
+                    step_abs,
+                    distance.destination()
+                )
+            } else {
+                binary!(
+                    word,
+                    Greater,
+                    GreaterW,
+                    distance,
+                    step_abs,
+                    distance.destination()
+                )
+            });
+            suffix.push(JmpCmp {
+                location: Location::Relative(1),
+                source: distance.u8(),
+            });
+            suffix.push(Nop(NOP_BREAK));
+            if let ForOperand::Register(register) = distance {
+                context.register_alloc.free(register);
+            }
+            // step the for loop variable
+            suffix.push(match (step.unsigned_abs(), descending, word) {
+                (1, false, false) => Inc {
+                    source: variable.u8(),
+                    destination: variable.destination(),
                 },
-                // TODO optimize away, as this is equivalent to: if foo { break }
-                JmpCmp {
-                    location: Location::Relative(1),
-                    source: Source::Register(cmp_register),
+                (1, true, false) => Statement::Dec {
+                    source: variable.u8(),
+                    destination: variable.destination(),
                 },
-                Nop(NOP_BREAK),
-            ];
-            context.register_alloc.free(cmp_register);
-            // increment the for loop variable
-            let suffix = vec![Inc {
-                source: Source::Pointer {
-                    base: Pointer::Stack(stack_address),
-                    offset: None,
+                (1, false, true) => Statement::IncW {
+                    source: variable.u16(),
+                    destination: variable.destination(),
                 },
-                destination: Destination::Pointer {
-                    base: Pointer::Stack(stack_address),
-                    offset: None,
+                (1, true, true) => Statement::DecW {
+                    source: variable.u16(),
+                    destination: variable.destination(),
                 },
-            }];
+                (_, false, _) => {
+                    binary!(word, Add, AddW, variable, step_abs, variable.destination())
+                }
+                (_, true, _) => {
+                    binary!(word, Sub, SubW, variable, step_abs, variable.destination())
+                }
+            });
 
             // parse inner loop statements
+            let mut for_statements = Vec::new();
             LoopInner {
-                prefix,
+                prefix: Vec::new(),
                 inner: &self.inner,
                 suffix,
+                span: self.span(),
             }
-            .compile(context, &mut for_statements);
+            .compile(context, &mut for_statements)?;
 
+            if let Some(JmpCmpNot { location, .. }) = guard.last_mut() {
+                *location = relative(for_statements.len() as isize, self.span())?;
+            }
+            out.extend(init);
+            out.extend(guard);
             out.extend(for_statements);
 
             // free register holding the last index of the for loop
-            context.register_alloc.free(end_register);
-        });
+            if let ForOperand::Register(register) = end {
+                context.register_alloc.free(register);
+            }
+            Ok(())
+        })
     }
 }
 
 impl Compile for ast::Break<'_> {
-    fn compile<B: ByteOrder>(&self, _: &mut Context<B>, out: &mut Vec<Statement>) {
+    fn compile<B: ByteOrder>(&self, _: &mut Context<B>, out: &mut Vec<Statement>) -> Result<()> {
         // in order to compile the Break statement, the compiler needs to know how many
         // instructions there are ahead of it. add placeholder Nop statement, which
         // should be replaced inside the compile_loop compile_for functions.
         out.push(Nop(NOP_BREAK));
+        Ok(())
     }
 }
 
 impl Compile for ast::Continue<'_> {
-    fn compile<B: ByteOrder>(&self, _: &mut Context<B>, out: &mut Vec<Statement>) {
+    fn compile<B: ByteOrder>(&self, _: &mut Context<B>, out: &mut Vec<Statement>) -> Result<()> {
         // same deal as with the break statement.
         // use a different Nop to differentiate it.
         out.push(Nop(NOP_CONTINUE));
+        Ok(())
     }
 }
 
 #[rustfmt::skip]
 impl Compile for ast::Fn<'_> {
-    fn compile<B: ByteOrder>(&self, context: &mut Context<B>, _: &mut Vec<Statement>) {
+    fn compile<B: ByteOrder>(
+        &self,
+        context: &mut Context<B>,
+        _: &mut Vec<Statement>,
+    ) -> Result<()> {
+        // allocate a new routine index/handle (used by the Call statement).
+        // this is the index where the routine must be stored in Ir::routines, so
+        // reserve the slot now, in case the body declares more functions.
+        let handle = context.routines.len();
+        context.routines.push(Routine::default());
+        context.fn_alloc.alloc(self, handle, &context.symbol_alloc)?;
+
+        if let Some(handler) = &self.handler {
+            let slot = match handler.interrupt {
+                ast::Interrupt::VBlank => &mut context.handlers.vblank,
+                ast::Interrupt::LcdStat => &mut context.handlers.lcd_stat,
+                ast::Interrupt::Timer => &mut context.handlers.timer,
+                ast::Interrupt::Serial => &mut context.handlers.serial,
+                ast::Interrupt::Joypad => &mut context.handlers.joypad,
+            };
+            if slot.replace(handle).is_some() {
+                let message = "Interrupt handler already defined";
+                return Err(SemanticError::new(handler.ident.span(), message)
+                    .note("Each interrupt can only be handled by one function"));
+            }
+        }
+
+        // the stack frame of the function is independent of the parent's
+        let parent_stack_size = std::mem::replace(&mut context.stack_size, 0);
+
         compile_scope(context, |context| {
             // this is a function so only const and static symbols are visible
             context.symbol_alloc.clear_stack();
 
-            // allocate a new routine index/handle (used by the Call statement).
-            // this is the index where the routine must be stored in Ir::routines.
-            let _handle = context.fn_alloc.alloc(self);
-
             // allocate function parameters in the new stack frame.
             if let Some(args) = &self.fn_arg {
                 for field in &args.inner {
-                    context.symbol_alloc.alloc_stack_field(field);
+                    context.symbol_alloc.alloc_stack_field(field)?;
                 }
             }
 
             let args_size = context.symbol_alloc.stack_usage();
-            //context.stack_size = args_size;
 
             // like with main, start the routine with a Nop instruction
             let mut out = vec![Nop(NOP_PERSIST)];
-            let return_layout = self.fn_return.as_ref().map(|r| Layout::new(&r.type_));
+            let return_layout = self.fn_return.as_ref().map(|r| context.symbol_alloc.layout(&r.type_)).transpose()?;
 
             let return_size = return_layout.as_ref().map(|l| l.size()).unwrap_or(0);
 
             context.return_ = return_layout;
-            self.inner.compile(context, &mut out);
+            let result = self.inner.compile(context, &mut out);
             context.return_ = None;
+            result?;
 
             out.push(Ret);
 
-            let name = Some(self.ident.to_string());
-            context.routines.push(Routine {
+            let name = Some(alloc::mangle(context.symbol_alloc.module(), &self.ident.to_string()));
+            context.routines[handle] = Routine {
                 debug_name: name,
-                stack_size: context.stack_size,
+                stack_size: context.stack_size.max(context.symbol_alloc.stack_usage()),
                 args_size,
                 return_size,
                 statements: out,
-            });
-        });
+            };
+            Ok(())
+        })?;
+
+        context.stack_size = parent_stack_size;
+        Ok(())
     }
 }
 
 impl Compile for ast::Return<'_> {
-    fn compile<B: ByteOrder>(&self, context: &mut Context<B>, out: &mut Vec<Statement>) {
+    fn compile<B: ByteOrder>(
+        &self,
+        context: &mut Context<B>,
+        out: &mut Vec<Statement>,
+    ) -> Result<()> {
         if let Some(return_layout) = &context.return_ {
+            let value = self
+                .expression
+                .as_ref()
+                .ok_or_else(|| SemanticError::new(self.span(), "Missing return value"))?;
             expression::compile_expression_into_pointer::<B>(
-                self.expression.as_ref().unwrap(),
+                value,
                 return_layout,
                 &context.symbol_alloc,
                 &context.fn_alloc,
                 Pointer::Return(0),
                 &mut context.register_alloc,
                 out,
-            );
+            )?;
         }
         out.push(Statement::Ret);
+        Ok(())
     }
 }
 
@@ -531,7 +1431,7 @@ mod test {
 
     #[test]
     fn test_if_const_expr() {
-        let ast = crate::parser::parse(
+        let (ast, errors) = crate::parser::parse(
             r#"
         const F:u8 = 0
         static RESULT1:[u8 13]
@@ -569,11 +1469,11 @@ mod test {
             }
         }
         "#,
-        )
-        .unwrap();
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
         let mut context = Context::<crate::byteorder::NativeEndian>::default();
         let mut statements = Vec::new();
-        ast.inner.compile(&mut context, &mut statements);
+        ast.inner.compile(&mut context, &mut statements).unwrap();
         let gt: Vec<Statement> = vec![];
         assert_eq!(gt, statements); // no code must be generated
     }
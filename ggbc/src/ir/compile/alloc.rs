@@ -1,54 +1,159 @@
 use crate::{
     byteorder::ByteOrder,
     ir::{
-        compile::{expression::const_expr, layout::Layout},
+        compile::{charmap::Charmap, constant::const_value, layout::Layout, Result, SemanticError},
         opcodes::Pointer,
     },
     parser::{
         ast,
-        ast::{Expression, Field, Type},
-        lex::Ident,
+        ast::{Expression, Field, Path, Type},
+        lex::{span::Spanned, Ident},
     },
 };
-use std::{collections::HashMap, marker::PhantomData};
+use std::{collections::HashMap, marker::PhantomData, rc::Rc};
 
 pub struct Fn {
     pub arg_layout: Vec<Layout>,
     pub ret_layout: Option<Layout>,
 }
 
-/// Infallible function allocator.
-///
-/// Panics instead of returning Optionals or Results, therefore a panic means a
-/// bug somewhere in the compiler (likely in the frontend).
+/// Function allocator.
 #[derive(Default)]
 pub struct FnAlloc {
     fns: HashMap<String, (Fn, usize)>,
 }
 
 impl FnAlloc {
-    /// Allocated a function from it's statement.
-    /// Panics if a function of the same name is already allocated.
-    pub fn alloc(&mut self, fn_: &ast::Fn<'_>) -> usize {
-        let id = self.fns.len();
-        let name = fn_.ident.to_string();
+    /// Allocated a function from it's statement, which routine is stored at
+    /// index `id` of the IR routines. The function name is mangled with the
+    /// path of the module it is declared in.
+    /// Fails if a function of the same name is already allocated.
+    pub fn alloc<B: ByteOrder>(
+        &mut self,
+        fn_: &ast::Fn<'_>,
+        id: usize,
+        symbol_alloc: &SymbolAlloc<B>,
+    ) -> Result<()> {
+        let name = mangle(symbol_alloc.module(), &fn_.ident.to_string());
+        if self.fns.contains_key(&name) {
+            let message = format!("Function already defined: `{}`", name);
+            return Err(SemanticError::new(fn_.ident.span(), message));
+        }
+        let arg_layout = fn_
+            .fn_arg
+            .iter()
+            .flat_map(|a| &a.inner)
+            .map(|field| symbol_alloc.layout(&field.type_))
+            .collect::<Result<_>>()?;
+        let ret_layout = fn_
+            .fn_return
+            .as_ref()
+            .map(|r| symbol_alloc.layout(&r.type_))
+            .transpose()?;
         let fn_ = Fn {
-            arg_layout: fn_
-                .fn_arg
-                .iter()
-                .flat_map(|a| &a.inner)
-                .map(|field| Layout::new(&field.type_))
-                .collect(),
-            ret_layout: fn_.fn_return.as_ref().map(|r| Layout::new(&r.type_)),
+            arg_layout,
+            ret_layout,
         };
-        assert!(self.fns.insert(name, (fn_, id)).is_none());
-        id
+        self.fns.insert(name, (fn_, id));
+        Ok(())
     }
 
-    /// Returns the function with the given name.
-    /// Panics if it's not defined.
-    pub fn get(&self, name: &str) -> (&Fn, usize) {
-        self.fns.get(name).map(|(fn_, id)| (fn_, *id)).unwrap()
+    /// Returns the function with the given path, as seen from within the given
+    /// namespace.
+    /// Fails if it's not defined.
+    pub fn get(&self, path: &Path<'_>, namespace: &Namespace) -> Result<(&Fn, usize)> {
+        let name = path_name(path);
+        let fn_ = namespace
+            .resolve(&name)
+            .find_map(|name| self.fns.get(&name))
+            .map(|(fn_, id)| (fn_, *id));
+        fn_.ok_or_else(|| {
+            let message = format!("Undefined function: `{}`", name);
+            SemanticError::new(path.span(), message)
+        })
+    }
+}
+
+/// Name of a path (`a::b::name`).
+pub fn path_name(path: &Path<'_>) -> String {
+    let name: Vec<_> = path.iter().map(|i| i.to_string()).collect();
+    name.join("::")
+}
+
+/// Prefix `name` with the given module path (`a::b::name`).
+pub fn mangle(module: &[String], name: &str) -> String {
+    let mut mangled = String::new();
+    for m in module {
+        mangled.push_str(m);
+        mangled.push_str("::");
+    }
+    mangled.push_str(name);
+    mangled
+}
+
+/// Current module path, and the paths imported into it with `use`.
+#[derive(Debug, Default, Clone)]
+pub struct Namespace {
+    module: Vec<String>,
+
+    /// Imported `(name, path)` pairs, along with the depth of the module they
+    /// were imported from.
+    imports: Vec<(usize, String, String)>,
+}
+
+impl Namespace {
+    /// Path of the current module.
+    pub fn module(&self) -> &[String] {
+        &self.module
+    }
+
+    /// Enter a child module.
+    pub fn push_mod(&mut self, name: &str) {
+        self.module.push(name.to_string());
+    }
+
+    /// Return to the parent module.
+    /// Imports made from within the child module are dropped.
+    pub fn pop_mod(&mut self) {
+        assert!(self.module.pop().is_some());
+        let depth = self.module.len();
+        self.imports.retain(|(d, _, _)| *d <= depth);
+    }
+
+    /// Import a path into the current module, so it can be referred to by its
+    /// last identifier. Import paths are always absolute.
+    pub fn import(&mut self, path: &ast::Path<'_>) {
+        let alias = path.iter().last().unwrap().to_string();
+        let path: Vec<_> = path.iter().map(|i| i.to_string()).collect();
+        let depth = self.module.len();
+        self.imports.push((depth, alias, path.join("::")));
+    }
+
+    /// Returns the candidate mangled names a (possibly qualified) `name` may
+    /// refer to, in order of precedence.
+    ///
+    /// If the head of the path has been imported, the imported path comes
+    /// first. Then paths are relative to the current module, then to each of
+    /// its ancestors, all the way up to the root module.
+    pub fn resolve<'a>(&'a self, name: &'a str) -> impl Iterator<Item = String> + 'a {
+        let mut split = name.splitn(2, "::");
+        let head = split.next().unwrap();
+        let tail = split.next();
+        let import = self
+            .imports
+            .iter()
+            .rev()
+            .find(|(_, alias, _)| alias == head)
+            .map(move |(_, _, path)| match tail {
+                Some(tail) => format!("{}::{}", path, tail),
+                None => path.clone(),
+            });
+        let module = &self.module;
+        import.into_iter().chain(
+            (0..=module.len())
+                .rev()
+                .map(move |len| mangle(&module[..len], name)),
+        )
     }
 }
 
@@ -106,6 +211,9 @@ pub struct SymbolAlloc<B: ByteOrder> {
     absolute_symbols_alloc: u16,
     static_symbols_alloc: u16,
     stack_symbols_alloc: u16,
+    types: HashMap<String, Layout>,
+    namespace: Namespace,
+    charmap: Rc<Charmap>,
     _phantom: PhantomData<B>,
 }
 
@@ -140,6 +248,72 @@ impl<B: ByteOrder> SymbolAlloc<B> {
         std::mem::replace(&mut self.const_, const_)
     }
 
+    /// Charmap used to encode string and character literals.
+    pub fn charmap(&self) -> &Charmap {
+        &self.charmap
+    }
+
+    pub fn set_charmap(&mut self, charmap: Charmap) {
+        self.charmap = Rc::new(charmap);
+    }
+
+    /// Path of the module where symbols are currently being declared.
+    pub fn module(&self) -> &[String] {
+        self.namespace.module()
+    }
+
+    /// Names visible from the current module.
+    pub fn namespace(&self) -> &Namespace {
+        &self.namespace
+    }
+
+    /// Enter a child module. Static and const symbols declared from now on
+    /// will be prefixed with the module path.
+    pub fn push_mod(&mut self, name: &str) {
+        self.namespace.push_mod(name);
+    }
+
+    /// Return to the parent module.
+    pub fn pop_mod(&mut self) {
+        self.namespace.pop_mod();
+    }
+
+    /// Import a path into the current module.
+    pub fn import(&mut self, path: &ast::Path<'_>) {
+        self.namespace.import(path);
+    }
+
+    /// Define a named type in the current module.
+    /// Fails if a type of the same name is already defined.
+    pub fn alloc_type(&mut self, ident: &Ident<'_>, layout: Layout) -> Result<()> {
+        let name = mangle(self.module(), &ident.to_string());
+        if self.types.contains_key(&name) {
+            let message = format!("Type already defined: `{}`", name);
+            return Err(SemanticError::new(ident.span(), message));
+        }
+        self.types.insert(name, layout);
+        Ok(())
+    }
+
+    /// Returns the layout of a named type, resolved like symbol paths.
+    /// Fails if it's not defined.
+    pub fn get_type(&self, path: &Path<'_>) -> Result<&Layout> {
+        let name = path_name(path);
+        let layout = self
+            .namespace
+            .resolve(&name)
+            .find_map(|name| self.types.get(&name));
+        layout.ok_or_else(|| {
+            let message = format!("Undefined type: `{}`", name);
+            SemanticError::new(path.span(), message)
+        })
+    }
+
+    /// Memory layout of a type, which may refer to named types.
+    pub fn layout(&self, type_: &Type<'_>) -> Result<Layout> {
+        Layout::with_types(type_, &|path| self.get_type(path).cloned())
+    }
+
     /// Clear stack symbols
     pub fn clear_stack(&mut self) {
         self.stack_symbols.clear();
@@ -147,54 +321,105 @@ impl<B: ByteOrder> SymbolAlloc<B> {
     }
 
     /// Allocate const address.
-    pub fn alloc_const(&mut self, field: &Field<'_>, expression: &Expression<'_>) {
-        assert!(self.is_undefined(&field.ident));
+    pub fn alloc_const(&mut self, field: &Field<'_>, expression: &Expression<'_>) -> Result<()> {
+        let name = mangle(self.module(), &field.ident.to_string());
+        self.check_undefined(&field.ident, &name)?;
 
+        let layout = self.layout(&field.type_)?;
         Self::compute_all_symbols(
-            &String::new(),
+            name,
             self.const_.len() as _,
-            field,
+            &field.type_,
+            &layout,
             SymbolMemorySpace::Const,
             &mut self.const_symbols,
         );
 
         // compute constant expression value
-        let symbol_alloc = self.clone();
-        compute_const_expr_into_vec::<B>(
-            &Layout::new(&field.type_),
-            expression,
-            &symbol_alloc,
-            &mut self.const_,
+        let value = const_value(expression, &layout, Some(&*self))?.ok_or_else(|| {
+            SemanticError::new(expression.span(), "Not a constant expression")
+                .note("const symbols must be initialized with constant expressions")
+        })?;
+        value.write::<B>(&mut self.const_);
+        Ok(())
+    }
+
+    /// Allocate const address, initialized with raw data (of the same size as
+    /// the symbol).
+    pub fn alloc_const_data(&mut self, field: &Field<'_>, data: &[u8]) -> Result<()> {
+        let name = mangle(self.module(), &field.ident.to_string());
+        self.check_undefined(&field.ident, &name)?;
+
+        let layout = self.layout(&field.type_)?;
+        assert_eq!(layout.size() as usize, data.len());
+        Self::compute_all_symbols(
+            name,
+            self.const_.len() as _,
+            &field.type_,
+            &layout,
+            SymbolMemorySpace::Const,
+            &mut self.const_symbols,
         );
+        self.const_.extend_from_slice(data);
+        Ok(())
+    }
+
+    /// Allocate a const `u8` symbol (`Enum::Variant`) holding the
+    /// discriminant of an enum variant.
+    pub fn alloc_variant(
+        &mut self,
+        enum_: &Ident<'_>,
+        variant: &Ident<'_>,
+        value: u8,
+    ) -> Result<()> {
+        let name = mangle(self.module(), &format!("{}::{}", enum_, variant));
+        self.check_undefined(variant, &name)?;
+        self.const_symbols.push(Symbol {
+            name,
+            offset: self.const_.len() as _,
+            size: 1,
+            layout: Layout::U8,
+            memory_space: SymbolMemorySpace::Const,
+        });
+        self.const_.push(value);
+        Ok(())
     }
 
     /// Allocate static address.
-    pub fn alloc_static(&mut self, field: &Field<'_>) {
-        assert!(self.is_undefined(&field.ident));
+    pub fn alloc_static(&mut self, field: &Field<'_>) -> Result<()> {
+        let name = mangle(self.module(), &field.ident.to_string());
+        self.check_undefined(&field.ident, &name)?;
 
+        let layout = self.layout(&field.type_)?;
         let size = Self::compute_all_symbols(
-            &String::new(),
+            name,
             self.static_symbols_alloc,
-            field,
+            &field.type_,
+            &layout,
             SymbolMemorySpace::Static,
             &mut self.static_symbols,
         );
         self.static_symbols_alloc += size;
+        Ok(())
     }
 
     /// Declares a symbol located at the given offset.
     /// Note that it is possible to overlap two symbols, as long as the language
     /// frontend allows it... (the IR doesn't really care about memory aliasing)
-    pub fn alloc_absolute(&mut self, field: &Field<'_>, offset: u16) {
-        assert!(self.is_undefined(&field.ident));
+    pub fn alloc_absolute(&mut self, field: &Field<'_>, offset: u16) -> Result<()> {
+        let name = mangle(self.module(), &field.ident.to_string());
+        self.check_undefined(&field.ident, &name)?;
 
+        let layout = self.layout(&field.type_)?;
         Self::compute_all_symbols(
-            &String::new(),
+            name,
             offset,
-            field,
+            &field.type_,
+            &layout,
             SymbolMemorySpace::Absolute,
             &mut self.absolute_symbols,
         );
+        Ok(())
     }
 
     pub fn stack_address(&self) -> u16 {
@@ -203,129 +428,110 @@ impl<B: ByteOrder> SymbolAlloc<B> {
 
     /// Allocate stack address, associated to the given field.
     /// Returns the first allocated address.
-    pub fn alloc_stack_field(&mut self, field: &Field<'_>) -> u16 {
-        assert!(self.is_undefined(&field.ident));
+    pub fn alloc_stack_field(&mut self, field: &Field<'_>) -> Result<u16> {
+        let name = field.ident.to_string();
+        self.check_undefined(&field.ident, &name)?;
 
+        let layout = self.layout(&field.type_)?;
         let size = Self::compute_all_symbols(
-            &String::new(),
+            name,
             self.stack_symbols_alloc,
-            field,
+            &field.type_,
+            &layout,
             SymbolMemorySpace::Stack,
             &mut self.stack_symbols,
         );
 
         let alloc = self.stack_symbols_alloc;
         self.stack_symbols_alloc += size;
-        alloc
+        Ok(alloc)
     }
 
-    /// Locates a symbol by name.
-    /// Panics if the symbol is not defined.
-    pub fn get(&self, name: &str) -> &Symbol {
+    /// Locates a symbol by path.
+    ///
+    /// Stack symbols are looked up first. Otherwise the name is resolved
+    /// through the imports of the current module, then relative to the
+    /// current module (and then to each of its ancestors).
+    /// Fails if the symbol is not defined.
+    pub fn get(&self, path: &Path<'_>) -> Result<&Symbol> {
+        let name = path_name(path);
         self.stack_symbols
             .iter()
-            .chain(self.static_symbols.iter())
-            .chain(self.const_symbols.iter())
-            .chain(self.absolute_symbols.iter())
             .find(|s| s.name == name)
-            .expect(&format!("Undefined symbol: {}", name))
+            .or_else(|| {
+                self.namespace.resolve(&name).find_map(|name| {
+                    self.static_symbols
+                        .iter()
+                        .chain(self.const_symbols.iter())
+                        .chain(self.absolute_symbols.iter())
+                        .find(|s| s.name == name)
+                })
+            })
+            .ok_or_else(|| {
+                let message = format!("Undefined symbol: `{}`", name);
+                SemanticError::new(path.span(), message)
+            })
     }
 
-    fn is_undefined(&self, ident: &Ident<'_>) -> bool {
-        !(Self::_is_undefined(ident, &self.absolute_symbols)
-            || Self::_is_undefined(ident, &self.static_symbols)
-            || Self::_is_undefined(ident, &self.const_symbols)
-            || Self::_is_undefined(ident, &self.stack_symbols))
+    fn check_undefined(&self, ident: &Ident<'_>, name: &str) -> Result<()> {
+        if self.is_undefined(name) {
+            Ok(())
+        } else {
+            let message = format!("Symbol already defined: `{}`", name);
+            Err(SemanticError::new(ident.span(), message))
+        }
+    }
+
+    fn is_undefined(&self, name: &str) -> bool {
+        !(Self::_is_undefined(name, &self.absolute_symbols)
+            || Self::_is_undefined(name, &self.static_symbols)
+            || Self::_is_undefined(name, &self.const_symbols)
+            || Self::_is_undefined(name, &self.stack_symbols))
     }
 
-    fn _is_undefined(ident: &Ident<'_>, symbols: &[Symbol]) -> bool {
-        symbols.iter().any(|s| s.name == ident.to_string())
+    fn _is_undefined(name: &str, symbols: &[Symbol]) -> bool {
+        symbols.iter().any(|s| s.name == name)
     }
 
-    // TODO optimize because I'm far too sleepy to do this now.
-    //  No need to be calling size_of all over the place here.
+    // Define the symbol of a field, followed by the symbols of its inline
+    // struct & union fields (`name::field`). Returns the size of the symbol.
     fn compute_all_symbols(
-        prefix: &str,
+        name: String,
         offset: u16,
-        field: &Field<'_>,
+        type_: &Type<'_>,
+        layout: &Layout,
         memory_space: SymbolMemorySpace,
         symbols: &mut Vec<Symbol>,
     ) -> u16 {
-        // append field identifier to the queried field.
-        let name = if prefix.is_empty() {
-            field.ident.to_string()
-        } else {
-            let mut prefix = prefix.to_string();
-            prefix.push_str(&format!("::{}", field.ident));
-            prefix
-        };
-
-        let size = Layout::new(&field.type_).size();
-        match &field.type_ {
-            Type::U8(_) | Type::I8(_) | Type::Array(_) | Type::Pointer(_) => {
-                let layout = Layout::new(&field.type_);
-                symbols.push(Symbol {
-                    name,
-                    offset,
-                    size,
-                    layout,
-                    memory_space,
-                });
-            }
-            Type::Struct(struct_) => {
-                let mut offset = offset;
-                for field in struct_.fields.iter() {
-                    offset +=
-                        Self::compute_all_symbols(&name, offset, field, memory_space, symbols);
-                }
+        let fields = match (type_, layout) {
+            (Type::Struct(ast::types::Struct { fields, .. }), Layout::Struct(layouts))
+            | (Type::Union(ast::types::Union { fields, .. }), Layout::Union(layouts)) => {
+                fields.iter().zip(layouts).collect()
             }
-            Type::Union(union) => {
-                for field in union.fields.iter() {
-                    Self::compute_all_symbols(&name, offset, field, memory_space, symbols);
-                }
-            }
-            _ => unreachable!(),
+            _ => Vec::new(),
+        };
+        let size = layout.size();
+        symbols.push(Symbol {
+            name: name.clone(),
+            offset,
+            size,
+            layout: layout.clone(),
+            memory_space,
+        });
+        for (field, layout) in fields {
+            Self::compute_all_symbols(
+                format!("{}::{}", name, field.ident),
+                offset + layout.offset,
+                &field.type_,
+                &layout.layout,
+                memory_space,
+                symbols,
+            );
         }
         size
     }
 }
 
-#[deprecated]
-pub fn compute_const_expr_into_vec<B: ByteOrder>(
-    layout: &Layout,
-    expression: &Expression<'_>,
-    symbol_alloc: &SymbolAlloc<B>,
-    out: &mut Vec<u8>,
-) {
-    match (layout, expression) {
-        (Layout::U8, expression) => {
-            let lit = const_expr(expression, Some(symbol_alloc)).unwrap();
-            assert!(lit <= 0xff);
-            out.push(lit as u8);
-        }
-        (Layout::I8, expression) => {
-            let lit = const_expr(expression, Some(symbol_alloc)).unwrap();
-            assert!(lit <= i8::max_value() as u16 && lit >= i8::min_value() as u16);
-            out.push(unsafe { std::mem::transmute(lit as i8) });
-        }
-        (Layout::Pointer(_), expr @ Expression::Lit(_)) => {
-            let lit = const_expr(expr, Some(symbol_alloc)).unwrap();
-            let offset = out.len();
-            // append value with the correct endianness
-            out.push(0);
-            out.push(0);
-            B::write_u16(&mut out[offset..], lit);
-        }
-        (Layout::Array { inner, len }, Expression::Array(array)) => {
-            assert_eq!(*len as usize, array.inner.len());
-            for item in &array.inner {
-                compute_const_expr_into_vec::<B>(inner, item, symbol_alloc, out);
-            }
-        }
-        _ => panic!(),
-    }
-}
-
 /// Virtual register allocator.
 #[derive(Default)]
 pub struct RegisterAlloc {
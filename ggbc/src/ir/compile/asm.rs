@@ -0,0 +1,242 @@
+//! Compilation of inline assembly blocks.
+use crate::{
+    byteorder::ByteOrder,
+    ir::{
+        compile::{
+            alloc::{SymbolAlloc, SymbolMemorySpace},
+            constant::const_expr,
+            layout::Layout,
+            Compile, Context, Result, SemanticError,
+        },
+        opcodes::{
+            asm::{Cond, Immediate, Indirect, Item, Mnemonic, Operand, R16, R8},
+            Statement,
+        },
+    },
+    parser::{
+        ast,
+        ast::{
+            asm::{Address, AsmStatement, Condition, Register, Sign},
+            Expression,
+        },
+        lex::span::Spanned,
+    },
+};
+
+impl Compile for ast::Asm<'_> {
+    fn compile<B: ByteOrder>(
+        &self,
+        context: &mut Context<B>,
+        out: &mut Vec<Statement>,
+    ) -> Result<()> {
+        // labels can be referenced before they are defined
+        let mut labels: Vec<String> = Vec::new();
+        for statement in &self.inner {
+            if let AsmStatement::Label(label) = statement {
+                let name = label.ident.to_string();
+                if labels.contains(&name) {
+                    let message = format!("Label already defined: `{}`", name);
+                    return Err(SemanticError::new(label.ident.span(), message));
+                }
+                labels.push(name);
+            }
+        }
+
+        let mut items = Vec::with_capacity(self.inner.len());
+        let mut label = 0;
+        for statement in &self.inner {
+            match statement {
+                AsmStatement::Label(_) => {
+                    items.push(Item::Label(label));
+                    label += 1;
+                }
+                AsmStatement::Instruction(instruction) => {
+                    let mnemonic = mnemonic(&instruction.mnemonic);
+                    let mut operands: Vec<_> = instruction
+                        .operands
+                        .iter()
+                        .map(|o| operand(o, &labels, &context.symbol_alloc))
+                        .collect::<Result<_>>()?;
+
+                    // `%c` is the carry condition when it comes first in a conditional
+                    // instruction (the parser doesn't know about this).
+                    let conditional = match mnemonic {
+                        Mnemonic::Jp | Mnemonic::Jr | Mnemonic::Call => operands.len() == 2,
+                        Mnemonic::Ret => operands.len() == 1,
+                        _ => false,
+                    };
+                    if conditional && operands[0] == Operand::R8(R8::C) {
+                        operands[0] = Operand::Cond(Cond::C);
+                    }
+
+                    items.push(Item::Instruction { mnemonic, operands });
+                }
+            }
+        }
+
+        out.push(Statement::Asm(items));
+        Ok(())
+    }
+}
+
+fn mnemonic(mnemonic: &ast::asm::Mnemonic<'_>) -> Mnemonic {
+    use ast::asm::Mnemonic as M;
+    match mnemonic {
+        M::Nop(_) => Mnemonic::Nop,
+        M::Stop(_) => Mnemonic::Stop,
+        M::Halt(_) => Mnemonic::Halt,
+        M::Di(_) => Mnemonic::Di,
+        M::Ei(_) => Mnemonic::Ei,
+        M::Ld(_) => Mnemonic::Ld,
+        M::Ldh(_) => Mnemonic::Ldh,
+        M::Push(_) => Mnemonic::Push,
+        M::Pop(_) => Mnemonic::Pop,
+        M::Inc(_) => Mnemonic::Inc,
+        M::Dec(_) => Mnemonic::Dec,
+        M::Daa(_) => Mnemonic::Daa,
+        M::Scf(_) => Mnemonic::Scf,
+        M::Cpl(_) => Mnemonic::Cpl,
+        M::Ccf(_) => Mnemonic::Ccf,
+        M::Add(_) => Mnemonic::Add,
+        M::Adc(_) => Mnemonic::Adc,
+        M::Sub(_) => Mnemonic::Sub,
+        M::Sbc(_) => Mnemonic::Sbc,
+        M::And(_) => Mnemonic::And,
+        M::Xor(_) => Mnemonic::Xor,
+        M::Or(_) => Mnemonic::Or,
+        M::Cp(_) => Mnemonic::Cp,
+        M::Jp(_) => Mnemonic::Jp,
+        M::Jr(_) => Mnemonic::Jr,
+        M::Call(_) => Mnemonic::Call,
+        M::Ret(_) => Mnemonic::Ret,
+        M::Reti(_) => Mnemonic::Reti,
+        M::Rst(_) => Mnemonic::Rst,
+        M::Rlca(_) => Mnemonic::Rlca,
+        M::Rla(_) => Mnemonic::Rla,
+        M::Rrca(_) => Mnemonic::Rrca,
+        M::Rra(_) => Mnemonic::Rra,
+        M::Rlc(_) => Mnemonic::Rlc,
+        M::Rrc(_) => Mnemonic::Rrc,
+        M::Rl(_) => Mnemonic::Rl,
+        M::Rr(_) => Mnemonic::Rr,
+        M::Sla(_) => Mnemonic::Sla,
+        M::Sra(_) => Mnemonic::Sra,
+        M::Srl(_) => Mnemonic::Srl,
+        M::Swap(_) => Mnemonic::Swap,
+        M::Bit(_) => Mnemonic::Bit,
+        M::Res(_) => Mnemonic::Res,
+        M::Set(_) => Mnemonic::Set,
+    }
+}
+
+fn operand<B: ByteOrder>(
+    operand: &ast::asm::Operand<'_>,
+    labels: &[String],
+    symbol_alloc: &SymbolAlloc<B>,
+) -> Result<Operand> {
+    use ast::asm::Operand as O;
+    Ok(match operand {
+        O::Register(register) => match register {
+            Register::A(_) => Operand::R8(R8::A),
+            Register::B(_) => Operand::R8(R8::B),
+            Register::C(_) => Operand::R8(R8::C),
+            Register::D(_) => Operand::R8(R8::D),
+            Register::E(_) => Operand::R8(R8::E),
+            Register::H(_) => Operand::R8(R8::H),
+            Register::L(_) => Operand::R8(R8::L),
+            Register::AF(_) => Operand::R16(R16::AF),
+            Register::BC(_) => Operand::R16(R16::BC),
+            Register::DE(_) => Operand::R16(R16::DE),
+            Register::HL(_) => Operand::R16(R16::HL),
+            Register::SP(_) => Operand::R16(R16::SP),
+            Register::F(_) | Register::PC(_) => {
+                let message = format!("Register can't be used as an operand: {}", register);
+                return Err(SemanticError::new(operand.span(), message));
+            }
+        },
+        O::Condition(condition) => Operand::Cond(match condition {
+            Condition::NZ(_) => Cond::NZ,
+            Condition::Z(_) => Cond::Z,
+            Condition::NC(_) => Cond::NC,
+        }),
+        O::SpOffset(offset) => {
+            let value = const_expr(&offset.expression, Some(symbol_alloc))?.ok_or_else(|| {
+                let message = "Stack pointer offset must be a constant expression";
+                SemanticError::new(offset.expression.span(), message)
+            })?;
+            let value = match offset.sign {
+                Sign::Plus(_) => value as i16,
+                Sign::Minus(_) => -(value as i16),
+            };
+            if value < i8::MIN as i16 || value > i8::MAX as i16 {
+                let message = "Stack pointer offset out of range";
+                return Err(SemanticError::new(offset.span(), message)
+                    .note("Offsets must be in the range -128..=127"));
+            }
+            Operand::SpOffset(value as i8)
+        }
+        O::Indirect(indirect) => Operand::Indirect(match &indirect.address {
+            Address::Register(Register::BC(_)) => Indirect::BC,
+            Address::Register(Register::DE(_)) => Indirect::DE,
+            Address::Register(Register::HL(_)) => Indirect::HL,
+            Address::Register(Register::C(_)) => Indirect::C,
+            Address::Register(register) => {
+                let message = format!("Register can't be used as an address: {}", register);
+                return Err(SemanticError::new(operand.span(), message));
+            }
+            Address::HlOffset(_, Sign::Plus(_)) => Indirect::HLInc,
+            Address::HlOffset(_, Sign::Minus(_)) => Indirect::HLDec,
+            Address::Expression(expression) => match immediate(expression, labels, symbol_alloc)? {
+                Operand::Immediate(immediate) => Indirect::Immediate(immediate),
+                _ => {
+                    let message = "Labels can't be used as a memory operand";
+                    return Err(SemanticError::new(expression.span(), message));
+                }
+            },
+        }),
+        O::Expression(expression) => immediate(expression, labels, symbol_alloc)?,
+    })
+}
+
+// Paths are resolved to (in order of precedence):
+// - a label defined in the same block.
+// - the value of a u8 or i8 const symbol.
+// - the address of any other static, const, or absolute symbol.
+// Any other expression must be a constant expression.
+fn immediate<B: ByteOrder>(
+    expression: &Expression<'_>,
+    labels: &[String],
+    symbol_alloc: &SymbolAlloc<B>,
+) -> Result<Operand> {
+    Ok(match expression {
+        Expression::Path(path) => {
+            let name: Vec<_> = path.iter().map(|i| i.to_string()).collect();
+            let name = name.join("::");
+            if let Some(label) = labels.iter().position(|l| *l == name) {
+                return Ok(Operand::Label(label));
+            }
+            let symbol = symbol_alloc.get(path)?;
+            let immediate = match (symbol.memory_space, &symbol.layout) {
+                (SymbolMemorySpace::Stack, _) => {
+                    let message = format!(
+                        "Stack symbols can't be referenced from inline assembly: `{}`",
+                        name
+                    );
+                    return Err(SemanticError::new(path.span(), message));
+                }
+                (SymbolMemorySpace::Const, Layout::U8) | (SymbolMemorySpace::Const, Layout::I8) => {
+                    Immediate::Literal(symbol_alloc.const_data()[symbol.offset as usize] as _)
+                }
+                _ => Immediate::Pointer(symbol.pointer()),
+            };
+            Operand::Immediate(immediate)
+        }
+        expression => {
+            let value = const_expr(expression, Some(symbol_alloc))?.ok_or_else(|| {
+                let message = "Operand must be a constant expression";
+                SemanticError::new(expression.span(), message)
+            })?;
+            Operand::Immediate(Immediate::Literal(value))
+        }
+    })
+}
@@ -0,0 +1,214 @@
+//! Text encoding of string & character literals.
+use crate::{
+    ir::compile::{Result, SemanticError},
+    parser::lex::{span::Spanned, Lit},
+};
+use std::collections::HashMap;
+
+/// Character map, used to encode string and character literals.
+///
+/// Maps characters to bytes (usually the indices of the tiles of a font
+/// tileset). The default charmap encodes ASCII characters as themselves.
+///
+/// # Example
+/// ```
+/// use ggbc::ir::Charmap;
+///
+/// // font tileset with a blank tile, followed by the digits and the alphabet
+/// let charmap = Charmap::empty()
+///     .char(' ', 0)
+///     .chars("0123456789", 1)
+///     .chars("ABCDEFGHIJKLMNOPQRSTUVWXYZ", 11);
+/// assert_eq!(Some(13), charmap.get('C'));
+/// assert_eq!(None, charmap.get('c'));
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Charmap {
+    map: HashMap<char, u8>,
+}
+
+impl Default for Charmap {
+    fn default() -> Self {
+        Self::empty().chars(&(0..=0x7f).map(char::from).collect::<String>(), 0)
+    }
+}
+
+impl Charmap {
+    /// Charmap without any characters.
+    pub fn empty() -> Self {
+        Self {
+            map: HashMap::new(),
+        }
+    }
+
+    /// Map a character to a byte.
+    pub fn char(mut self, c: char, byte: u8) -> Self {
+        self.map.insert(c, byte);
+        self
+    }
+
+    /// Map the characters of `chars` to consecutive bytes, starting at
+    /// `first`.
+    ///
+    /// # Panics
+    /// Panics if the bytes overflow.
+    pub fn chars(mut self, chars: &str, first: u8) -> Self {
+        for (i, c) in chars.chars().enumerate() {
+            let byte = first as usize + i;
+            assert!(byte <= 0xff, "Charmap byte overflow");
+            self.map.insert(c, byte as u8);
+        }
+        self
+    }
+
+    /// Byte of a character, if it has been mapped.
+    pub fn get(&self, c: char) -> Option<u8> {
+        self.map.get(&c).copied()
+    }
+
+    /// Encode the text of a literal.
+    /// Fails if any of the characters hasn't been mapped.
+    pub(crate) fn encode(&self, lit: &Lit<'_>, text: &[Char]) -> Result<Vec<u8>> {
+        text.iter()
+            .map(|c| match c {
+                Char::Char(c) => self.get(*c).ok_or_else(|| {
+                    let message = format!("Character {:?} is not in the charmap", c);
+                    SemanticError::new(lit.span(), message)
+                }),
+                Char::Byte(byte) => Ok(*byte),
+            })
+            .collect()
+    }
+}
+
+/// Decoded character of a literal.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum Char {
+    /// Character, to be encoded with the charmap.
+    Char(char),
+
+    /// Raw byte (`\xNN` escape sequence).
+    Byte(u8),
+}
+
+/// Kind of a quoted literal.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum Quoted {
+    /// `"string"` literal.
+    String,
+
+    /// `'c'` character literal.
+    Char,
+}
+
+/// Returns the kind of a quoted literal, or `None` if it isn't one.
+pub(crate) fn quoted(lit: &Lit<'_>) -> Option<Quoted> {
+    match lit.to_string().as_bytes().first() {
+        Some(b'"') => Some(Quoted::String),
+        Some(b'\'') => Some(Quoted::Char),
+        _ => None,
+    }
+}
+
+/// Decode the escape sequences of a quoted literal (`\n`, `\t`, `\r`, `\0`,
+/// `\\`, `\"`, `\'` and `\xNN`).
+/// Character literals must contain exactly one character.
+pub(crate) fn unescape(lit: &Lit<'_>) -> Result<Vec<Char>> {
+    let raw = lit.to_string();
+    let error = |message: &str| SemanticError::new(lit.span(), message);
+    let mut chars = raw[1..raw.len() - 1].chars();
+    let mut text = Vec::new();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(Char::Char(c));
+            continue;
+        }
+        let c = match chars.next() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some(c @ '\\') | Some(c @ '"') | Some(c @ '\'') => c,
+            Some('x') => {
+                let digits: String = chars.by_ref().take(2).collect();
+                let hex = digits.len() == 2 && digits.chars().all(|c| c.is_ascii_hexdigit());
+                let byte = match u8::from_str_radix(&digits, 16) {
+                    Ok(byte) if hex => byte,
+                    _ => {
+                        return Err(error("Invalid escape sequence")
+                            .note("Byte escapes take two hex digits (`\\x00` to `\\xff`)"))
+                    }
+                };
+                text.push(Char::Byte(byte));
+                continue;
+            }
+            _ => return Err(error("Invalid escape sequence")),
+        };
+        text.push(Char::Char(c));
+    }
+    if quoted(lit) == Some(Quoted::Char) && text.len() != 1 {
+        return Err(error(
+            "Character literals must contain exactly one character",
+        ));
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{unescape, Char, Charmap};
+    use crate::parser::{ast::Expression, lex::Lit};
+
+    fn lit(input: &str) -> Lit<'_> {
+        let (mut ast, errors) = crate::parser::parse(input);
+        assert!(errors.is_empty(), "{:?}", errors);
+        match ast.inner.pop() {
+            Some(crate::parser::ast::Statement::Inline(inline)) => match inline.inner {
+                Expression::Lit(lit) => lit,
+                _ => panic!(),
+            },
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn escape() {
+        let text = unescape(&lit(r#""a\n\t\"\\\x41\x00""#)).unwrap();
+        assert_eq!(
+            vec![
+                Char::Char('a'),
+                Char::Char('\n'),
+                Char::Char('\t'),
+                Char::Char('"'),
+                Char::Char('\\'),
+                Char::Byte(0x41),
+                Char::Byte(0),
+            ],
+            text
+        );
+        assert_eq!(vec![Char::Char('\'')], unescape(&lit(r"'\''")).unwrap());
+        assert!(unescape(&lit(r#""\q""#)).is_err());
+        assert!(unescape(&lit(r#""\x4""#)).is_err());
+        assert!(unescape(&lit(r#""\x+1""#)).is_err());
+        assert!(unescape(&lit(r#""\x-1""#)).is_err());
+        assert!(unescape(&lit("'ab'")).is_err());
+        assert!(unescape(&lit("''")).is_err());
+    }
+
+    #[test]
+    fn encode() {
+        let charmap = Charmap::empty().chars("ABC", 1).char('!', 0x20);
+        let lit = lit(r#""CAB!\xff""#);
+        let text = unescape(&lit).unwrap();
+        assert_eq!(
+            vec![3, 1, 2, 0x20, 0xff],
+            charmap.encode(&lit, &text).unwrap()
+        );
+        let text = unescape(&lit).unwrap();
+        assert_eq!(
+            vec![b'C', b'A', b'B', b'!', 0xff],
+            Charmap::default().encode(&lit, &text).unwrap()
+        );
+        assert!(Charmap::empty().encode(&lit, &text).is_err());
+    }
+}
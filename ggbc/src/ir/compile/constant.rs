@@ -0,0 +1,675 @@
+//! Evaluation of constant expressions.
+use crate::{
+    byteorder::ByteOrder,
+    ir::compile::{
+        alloc::{SymbolAlloc, SymbolMemorySpace},
+        charmap::{self, Charmap, Quoted},
+        layout::Layout,
+        Result, SemanticError,
+    },
+    parser::{
+        ast::Expression,
+        lex::{span::Spanned, Lit},
+    },
+};
+use std::ops::RangeInclusive;
+
+// range of values of an untyped constant expression.
+// Negative values are allowed so they can be sign-extended by the caller.
+const UNTYPED_RANGE: RangeInclusive<i64> = -0x8000..=0xffff;
+
+/// Value of a constant expression.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Value {
+    /// Unsigned 8bit value.
+    U8(u8),
+
+    /// Signed 8bit value.
+    I8(i8),
+
+    /// Unsigned 16bit value (also used for pointers).
+    U16(u16),
+
+    /// Signed 16bit value.
+    I16(i16),
+
+    /// Array of values.
+    Array(Vec<Self>),
+
+    /// Struct field values, in order of declaration.
+    Struct(Vec<Self>),
+
+    /// Raw bytes of a union.
+    Union(Vec<u8>),
+}
+
+impl Value {
+    /// Read a value of the given layout from the beginning of `data`.
+    pub fn read<B: ByteOrder>(layout: &Layout, data: &[u8]) -> Self {
+        match layout {
+            Layout::U8 => Self::U8(data[0]),
+            Layout::I8 => Self::I8(data[0] as i8),
+            Layout::U16 | Layout::Pointer(_) => Self::U16(B::read_u16(data)),
+            Layout::I16 => Self::I16(B::read_i16(data)),
+            Layout::Array { inner, len } => {
+                let size = inner.size() as usize;
+                let items = (0..*len as usize).map(|i| Self::read::<B>(inner, &data[size * i..]));
+                Self::Array(items.collect())
+            }
+            Layout::Struct(fields) => {
+                let values = fields
+                    .iter()
+                    .map(|f| Self::read::<B>(&f.layout, &data[f.offset as usize..]));
+                Self::Struct(values.collect())
+            }
+            Layout::Union(_) => Self::Union(data[..layout.size() as usize].to_vec()),
+        }
+    }
+
+    /// Append the value to `out`, using the byte order `B`.
+    pub fn write<B: ByteOrder>(&self, out: &mut Vec<u8>) {
+        match self {
+            Self::U8(n) => out.push(*n),
+            Self::I8(n) => out.push(*n as u8),
+            Self::U16(n) => {
+                let offset = out.len();
+                out.extend_from_slice(&[0, 0]);
+                B::write_u16(&mut out[offset..], *n);
+            }
+            Self::I16(n) => {
+                let offset = out.len();
+                out.extend_from_slice(&[0, 0]);
+                B::write_i16(&mut out[offset..], *n);
+            }
+            Self::Array(values) | Self::Struct(values) => {
+                for value in values {
+                    value.write::<B>(out);
+                }
+            }
+            Self::Union(bytes) => out.extend_from_slice(bytes),
+        }
+    }
+
+    // numeric value, if the value is an integer.
+    pub(crate) fn int(&self) -> Option<i64> {
+        match self {
+            Self::U8(n) => Some(*n as _),
+            Self::I8(n) => Some(*n as _),
+            Self::U16(n) => Some(*n as _),
+            Self::I16(n) => Some(*n as _),
+            _ => None,
+        }
+    }
+
+    // numeric value of the given layout. The value must be within its range.
+    fn from_int(layout: &Layout, n: i64) -> Self {
+        match layout {
+            Layout::U8 => Self::U8(n as _),
+            Layout::I8 => Self::I8(n as _),
+            Layout::U16 | Layout::Pointer(_) => Self::U16(n as _),
+            Layout::I16 => Self::I16(n as _),
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Bytes of a string literal, encoded with the charmap of the symbol allocator
+/// (or the default charmap), or `None` if the literal isn't a string.
+pub fn string<B: ByteOrder>(
+    lit: &Lit<'_>,
+    symbol_alloc: Option<&SymbolAlloc<B>>,
+) -> Result<Option<Vec<u8>>> {
+    if charmap::quoted(lit) != Some(Quoted::String) {
+        return Ok(None);
+    }
+    let text = charmap::unescape(lit)?;
+    match symbol_alloc {
+        Some(symbol_alloc) => symbol_alloc.charmap().encode(lit, &text),
+        None => Charmap::default().encode(lit, &text),
+    }
+    .map(Some)
+}
+
+// range of values of a numeric layout.
+fn range(layout: &Layout) -> Option<RangeInclusive<i64>> {
+    match layout {
+        Layout::U8 => Some(0..=0xff),
+        Layout::I8 => Some(-0x80..=0x7f),
+        Layout::U16 | Layout::Pointer(_) => Some(0..=0xffff),
+        Layout::I16 => Some(-0x8000..=0x7fff),
+        _ => None,
+    }
+}
+
+/// Evaluate and return the result of a constant expression.
+/// If the passed expression is not a constant expression, returns `None`.
+///
+/// The expression is untyped: negative results are returned in two's
+/// complement, to be truncated to the layout of the value by the caller.
+pub fn const_expr<B: ByteOrder>(
+    expression: &Expression<'_>,
+    symbol_alloc: Option<&SymbolAlloc<B>>,
+) -> Result<Option<u16>> {
+    let int = const_int(expression, None, symbol_alloc)?;
+    Ok(int.map(|n| n as u16))
+}
+
+/// Evaluate a constant expression of the given layout.
+/// If the passed expression is not a constant expression, returns `None`.
+///
+/// Fails if the value (or any intermediate result) doesn't fit in the layout.
+pub fn const_value<B: ByteOrder>(
+    expression: &Expression<'_>,
+    layout: &Layout,
+    symbol_alloc: Option<&SymbolAlloc<B>>,
+) -> Result<Option<Value>> {
+    if range(layout).is_some() {
+        let int = const_int(expression, Some(layout), symbol_alloc)?;
+        return Ok(int.map(|n| Value::from_int(layout, n)));
+    }
+    match (layout, expression) {
+        (Layout::Array { inner, len }, Expression::Array(array)) => {
+            if *len as usize != array.inner.len() {
+                let message = format!(
+                    "Expected an array of {} items, found {}",
+                    len,
+                    array.inner.len()
+                );
+                return Err(SemanticError::new(array.span(), message));
+            }
+            let mut values = Vec::with_capacity(array.inner.len());
+            for item in &array.inner {
+                match const_value(item, inner, symbol_alloc)? {
+                    Some(value) => values.push(value),
+                    None => return Ok(None),
+                }
+            }
+            Ok(Some(Value::Array(values)))
+        }
+        (Layout::Array { inner, len }, Expression::Lit(lit)) if **inner == Layout::U8 => {
+            let bytes = match string(lit, symbol_alloc)? {
+                Some(bytes) => bytes,
+                None => return Err(unsupported(layout, expression)),
+            };
+            if *len as usize != bytes.len() {
+                let message = format!("Expected a string of {} bytes, found {}", len, bytes.len());
+                return Err(SemanticError::new(lit.span(), message));
+            }
+            Ok(Some(Value::Array(
+                bytes.into_iter().map(Value::U8).collect(),
+            )))
+        }
+        (Layout::Struct(fields), Expression::Struct(struct_)) => {
+            if let Some(init) = struct_
+                .fields
+                .iter()
+                .find(|f| layout.field(&f.ident.to_string()).is_none())
+            {
+                let message = format!("No field `{}` on type `{}`", init.ident, layout);
+                return Err(SemanticError::new(init.ident.span(), message));
+            }
+            let mut values = Vec::with_capacity(fields.len());
+            for field in fields {
+                let init = struct_
+                    .fields
+                    .iter()
+                    .find(|f| f.ident.to_string() == field.name);
+                let init = init.ok_or_else(|| {
+                    let message = format!("Missing field `{}` in struct initializer", field.name);
+                    SemanticError::new(struct_.span(), message)
+                })?;
+                match const_value(&init.expression, &field.layout, symbol_alloc)? {
+                    Some(value) => values.push(value),
+                    None => return Ok(None),
+                }
+            }
+            Ok(Some(Value::Struct(values)))
+        }
+        // the bytes not covered by the initialized field are zeroed
+        (Layout::Union(_), Expression::Struct(struct_)) => {
+            let init = match struct_.fields.as_slice() {
+                [init] => init,
+                _ => {
+                    let message = "Union initializers must set exactly one field";
+                    return Err(SemanticError::new(struct_.span(), message));
+                }
+            };
+            let field = layout.field(&init.ident.to_string()).ok_or_else(|| {
+                let message = format!("No field `{}` on type `{}`", init.ident, layout);
+                SemanticError::new(init.ident.span(), message)
+            })?;
+            let value = match const_value(&init.expression, &field.layout, symbol_alloc)? {
+                Some(value) => value,
+                None => return Ok(None),
+            };
+            let mut bytes = Vec::with_capacity(layout.size() as usize);
+            value.write::<B>(&mut bytes);
+            bytes.resize(layout.size() as usize, 0);
+            Ok(Some(Value::Union(bytes)))
+        }
+        (layout, Expression::Path(path)) => {
+            let symbol_alloc = match symbol_alloc {
+                Some(symbol_alloc) => symbol_alloc,
+                None => return Ok(None),
+            };
+            let symbol = symbol_alloc.get(path)?;
+            if !matches!(symbol.memory_space, SymbolMemorySpace::Const) {
+                return Ok(None);
+            }
+            if symbol.layout != *layout {
+                let message = format!("Expected a constant of type `{}`", layout);
+                return Err(SemanticError::new(path.span(), message)
+                    .note(format!("`{}` is of type `{}`", symbol.name, symbol.layout)));
+            }
+            let data = &symbol_alloc.const_data()[symbol.offset as usize..];
+            Ok(Some(Value::read::<B>(layout, data)))
+        }
+        (layout, Expression::Index(_)) | (layout, Expression::Field(_)) => {
+            let symbol_alloc = match symbol_alloc {
+                Some(symbol_alloc) => symbol_alloc,
+                None => return Ok(None),
+            };
+            let (offset, found) = match const_place(expression, symbol_alloc)? {
+                Some(place) => place,
+                None => return Ok(None),
+            };
+            if found != *layout {
+                let message = format!("Expected a constant of type `{}`", layout);
+                return Err(SemanticError::new(expression.span(), message)
+                    .note(format!("found a constant of type `{}`", found)));
+            }
+            let data = &symbol_alloc.const_data()[offset as usize..];
+            Ok(Some(Value::read::<B>(layout, data)))
+        }
+        (layout, expression) => Err(unsupported(layout, expression)),
+    }
+}
+
+// Offset & layout of a const symbol, or of an item (with a constant index) or
+// field of one. Returns `None` if the expression doesn't refer to const memory.
+fn const_place<B: ByteOrder>(
+    expression: &Expression<'_>,
+    symbol_alloc: &SymbolAlloc<B>,
+) -> Result<Option<(u16, Layout)>> {
+    Ok(Some(match expression {
+        Expression::Path(path) => {
+            let symbol = symbol_alloc.get(path)?;
+            if !matches!(symbol.memory_space, SymbolMemorySpace::Const) {
+                return Ok(None);
+            }
+            (symbol.offset, symbol.layout.clone())
+        }
+        Expression::Index(node) => {
+            let (offset, inner, len) = match const_place(&node.inner.right, symbol_alloc)? {
+                Some((offset, Layout::Array { inner, len })) => (offset, *inner, len),
+                _ => return Ok(None),
+            };
+            let index = match const_expr(&node.inner.left, Some(symbol_alloc))? {
+                Some(index) => index,
+                None => return Ok(None),
+            };
+            if index >= len {
+                return Err(
+                    SemanticError::new(node.inner.left.span(), "Index out of bounds")
+                        .note(format!("The length is {} but the index is {}", len, index)),
+                );
+            }
+            (offset + index * inner.size(), inner)
+        }
+        Expression::Field(node) => {
+            let (offset, layout) = match const_place(&node.inner, symbol_alloc)? {
+                Some(place) => place,
+                None => return Ok(None),
+            };
+            match layout.field(&node.ident.to_string()) {
+                Some(field) => (offset + field.offset, field.layout.clone()),
+                None => return Ok(None),
+            }
+        }
+        _ => return Ok(None),
+    }))
+}
+
+fn unsupported(layout: &Layout, expression: &Expression<'_>) -> SemanticError {
+    let message = format!("Unsupported initializer for a const of type `{}`", layout);
+    SemanticError::new(expression.span(), message)
+}
+
+// Evaluate a numeric constant expression.
+// Every intermediate result must fit in the range of `layout` (if any).
+fn const_int<B: ByteOrder>(
+    expression: &Expression<'_>,
+    layout: Option<&Layout>,
+    symbol_alloc: Option<&SymbolAlloc<B>>,
+) -> Result<Option<i64>> {
+    let range = layout.and_then(range).unwrap_or(UNTYPED_RANGE);
+    let type_name = || match layout {
+        Some(layout) => format!("`{}`", layout),
+        None => "16 bits".to_string(),
+    };
+    // check that the value fits in the layout
+    let overflow = |note: String| {
+        let message = "Overflow evaluating constant expression";
+        SemanticError::new(expression.span(), message).note(note)
+    };
+    let check = |n: i64| -> Result<i64> {
+        if range.contains(&n) {
+            Ok(n)
+        } else {
+            Err(overflow(format!(
+                "The result ({}) doesn't fit in {}",
+                n,
+                type_name()
+            )))
+        }
+    };
+    // evaluate a sub-expression, returning early if it's not constant.
+    macro_rules! eval {
+        ($expr:expr, $layout:expr) => {
+            match const_int($expr, $layout, symbol_alloc)? {
+                Some(value) => value,
+                None => return Ok(None),
+            }
+        };
+        ($expr:expr) => {
+            eval!($expr, layout)
+        };
+    }
+    macro_rules! binary {
+        ($e:expr, $op:tt) => {
+            check(eval!(&$e.inner.left) $op eval!(&$e.inner.right))?
+        };
+    }
+    // operands of a comparison are untyped, the result is a boolean.
+    macro_rules! compare {
+        ($e:expr, $op:tt) => {{
+            let l = eval!(&$e.inner.left, None);
+            let r = eval!(&$e.inner.right, None);
+            if l $op r { 1 } else { 0 }
+        }};
+    }
+    macro_rules! shift {
+        ($e:expr, $fn:ident) => {{
+            let l = eval!(&$e.inner.left);
+            let r = eval!(&$e.inner.right, None);
+            if !(0..32).contains(&r) {
+                return Err(overflow(format!("Can't shift by {} bits", r)));
+            }
+            check(l.$fn(r as u32).unwrap())?
+        }};
+    }
+
+    use Expression as E;
+    Ok(Some(match (symbol_alloc, expression) {
+        (Some(symbol_alloc), E::Path(path)) => {
+            let symbol = symbol_alloc.get(path)?;
+            if !matches!(symbol.memory_space, SymbolMemorySpace::Const) {
+                return Ok(None);
+            }
+            let data = &symbol_alloc.const_data()[symbol.offset as usize..];
+            match Value::read::<B>(&symbol.layout, data).int() {
+                Some(n) if range.contains(&n) => n,
+                Some(n) => {
+                    let message = format!("Constant out of range for {}", type_name());
+                    return Err(SemanticError::new(path.span(), message)
+                        .note(format!("`{}` has a value of {}", symbol.name, n)));
+                }
+                None => return Ok(None),
+            }
+        }
+        (Some(symbol_alloc), E::Index(_)) | (Some(symbol_alloc), E::Field(_)) => {
+            let (offset, layout) = match const_place(expression, symbol_alloc)? {
+                Some(place) => place,
+                None => return Ok(None),
+            };
+            let data = &symbol_alloc.const_data()[offset as usize..];
+            match Value::read::<B>(&layout, data).int() {
+                Some(n) if range.contains(&n) => n,
+                Some(n) => {
+                    let message = format!("Constant out of range for {}", type_name());
+                    return Err(SemanticError::new(expression.span(), message)
+                        .note(format!("The constant has a value of {}", n)));
+                }
+                None => return Ok(None),
+            }
+        }
+        (_, E::Lit(lit)) if lit.to_string() == "true" => 1,
+        (_, E::Lit(lit)) if lit.to_string() == "false" => 0,
+        (_, E::Lit(lit)) if charmap::quoted(lit) == Some(Quoted::Char) => {
+            let text = charmap::unescape(lit)?;
+            let n = match symbol_alloc {
+                Some(symbol_alloc) => symbol_alloc.charmap().encode(lit, &text)?,
+                None => Charmap::default().encode(lit, &text)?,
+            }[0] as i64;
+            if !range.contains(&n) {
+                let message = format!("Literal out of range for {}", type_name());
+                return Err(SemanticError::new(lit.span(), message));
+            }
+            n
+        }
+        (_, E::Lit(lit)) => {
+            let num = lit.to_string();
+            let (digits, radix) = if let Some(hex) = num.strip_prefix("0x") {
+                (hex, 16)
+            } else if let Some(bin) = num.strip_prefix("0b") {
+                (bin, 2)
+            } else if let Some(oct) = num.strip_prefix('0').filter(|oct| !oct.is_empty()) {
+                (oct, 8)
+            } else {
+                (&num[..], 10)
+            };
+            let n = u16::from_str_radix(digits, radix).map_err(|_| {
+                SemanticError::new(lit.span(), "Invalid numeric literal")
+                    .note("Numeric literals must fit in 16 bits")
+            })? as i64;
+            if !range.contains(&n) {
+                let message = format!("Literal out of range for {}", type_name());
+                return Err(SemanticError::new(lit.span(), message));
+            }
+            n
+        }
+        // the operand is untyped, so that `-128` is a valid i8
+        (_, E::Minus(e)) => check(-eval!(&e.inner, None))?,
+        (_, E::Not(e)) => {
+            let n = eval!(&e.inner);
+            if n < 0 || *range.start() < 0 {
+                !n
+            } else {
+                // flip the bits within the width of the (unsigned) value
+                n ^ range.end()
+            }
+        }
+        (_, E::Add(e)) => binary!(e, +),
+        (_, E::Sub(e)) => binary!(e, -),
+        (_, E::Mul(e)) => binary!(e, *),
+        (_, E::Div(e)) => {
+            let l = eval!(&e.inner.left);
+            let r = eval!(&e.inner.right);
+            if r == 0 {
+                return Err(SemanticError::new(
+                    e.inner.right.span(),
+                    "Division by zero in constant expression",
+                ));
+            }
+            check(l / r)?
+        }
+        (_, E::And(e)) => binary!(e, &),
+        (_, E::Or(e)) => binary!(e, |),
+        (_, E::Xor(e)) => binary!(e, ^),
+        // the rhs doesn't need to be constant if the lhs determines the result.
+        (_, E::LogicalAnd(e)) => match eval!(&e.inner.left, None) {
+            0 => 0,
+            _ => (eval!(&e.inner.right, None) != 0) as i64,
+        },
+        (_, E::LogicalOr(e)) => match eval!(&e.inner.left, None) {
+            0 => (eval!(&e.inner.right, None) != 0) as i64,
+            _ => 1,
+        },
+        (_, E::LogicalNot(e)) => (eval!(&e.inner, None) == 0) as i64,
+        (_, E::LeftShift(e)) => shift!(e, checked_shl),
+        (_, E::RightShift(e)) => shift!(e, checked_shr),
+        (_, E::Eq(e)) => compare!(e, ==),
+        (_, E::NotEq(e)) => compare!(e, !=),
+        (_, E::Greater(e)) => compare!(e, >),
+        (_, E::GreaterEq(e)) => compare!(e, >=),
+        (_, E::Less(e)) => compare!(e, <),
+        (_, E::LessEq(e)) => compare!(e, <=),
+        _ => return Ok(None),
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::Value;
+    use crate::{
+        byteorder::{BigEndian, LittleEndian, NativeEndian},
+        ir::compile::layout::Layout,
+        parser::ast,
+    };
+
+    fn ast(input: &str) -> ast::Expression<'_> {
+        let mut ctx = crate::parser::ContextBuilder::default().build();
+        let mut tokens = crate::parser::lex::Tokens::new(input).peekable();
+        crate::parser::ast::Grammar::parse(&mut ctx, &mut tokens)
+            .expect("Error parsing constant expression")
+    }
+
+    fn eval(input: &str) -> Option<u16> {
+        super::const_expr::<NativeEndian>(&ast(input), None).unwrap()
+    }
+
+    fn eval_typed(input: &str, layout: &Layout) -> super::Result<Option<Value>> {
+        super::const_value::<NativeEndian>(&ast(input), layout, None)
+    }
+
+    #[test]
+    fn const_expr() {
+        assert_eq!(Some(0x42), eval("(+ 0x40 2)"));
+        assert_eq!(Some(0xffff), eval("-1"));
+        assert_eq!(Some(0xff80), eval("-(+ 0x7f 1)"));
+        assert_eq!(Some(0xffff), eval("(- 1 2)"));
+        assert_eq!(Some(0xfff0), eval("~0xf"));
+    }
+
+    #[test]
+    fn compare() {
+        assert_eq!(Some(0), eval("(== 1 2)"));
+        assert_eq!(Some(1), eval("(~= 1 2)"));
+        assert_eq!(Some(1), eval("(< 1 2)"));
+        assert_eq!(Some(0), eval("(>= 1 2)"));
+        assert_eq!(Some(1), eval("(< -1 0)"));
+    }
+
+    #[test]
+    fn logical() {
+        assert_eq!(Some(1), eval("(and true 2)"));
+        assert_eq!(Some(0), eval("(and 1 false)"));
+        assert_eq!(Some(1), eval("(or 0 0x100)"));
+        assert_eq!(Some(0), eval("(or false 0)"));
+        assert_eq!(Some(1), eval("not 0"));
+        assert_eq!(Some(0), eval("not (< 1 2)"));
+    }
+
+    #[test]
+    fn errors() {
+        let error = |input| super::const_expr::<NativeEndian>(&ast(input), None).unwrap_err();
+
+        assert_eq!(
+            "Division by zero in constant expression",
+            error("(/ 1 (- 2 2))").message
+        );
+        assert_eq!(
+            "Overflow evaluating constant expression",
+            error("(* 0x100 0x100)").message
+        );
+        assert_eq!(
+            "Overflow evaluating constant expression",
+            error("(<< 1 16)").message
+        );
+        assert_eq!("Invalid numeric literal", error("0x10000").message);
+
+        let error = eval_typed("(+ 0xff 1)", &Layout::U8).unwrap_err();
+        assert_eq!("Overflow evaluating constant expression", error.message);
+        assert_eq!(vec!["The result (256) doesn't fit in `u8`"], error.notes);
+        let error = eval_typed("128", &Layout::I8).unwrap_err();
+        assert_eq!("Literal out of range for `i8`", error.message);
+    }
+
+    #[test]
+    fn typed() {
+        assert_eq!(
+            Some(Value::U8(0xf0)),
+            eval_typed("~0xf", &Layout::U8).unwrap()
+        );
+        assert_eq!(
+            Some(Value::I8(-0x10)),
+            eval_typed("~0xf", &Layout::I8).unwrap()
+        );
+        assert_eq!(
+            Some(Value::I8(-128)),
+            eval_typed("-128", &Layout::I8).unwrap()
+        );
+        assert_eq!(
+            Some(Value::I16(-2)),
+            eval_typed("(- 1 3)", &Layout::I16).unwrap()
+        );
+        assert!(eval_typed("(- 1 3)", &Layout::U16).is_err());
+
+        let layout = Layout::Array {
+            inner: Box::new(Layout::U16),
+            len: 2,
+        };
+        assert_eq!(
+            Some(Value::Array(vec![Value::U16(1), Value::U16(0x1234)])),
+            eval_typed("[1 0x1234]", &layout).unwrap()
+        );
+        assert!(eval_typed("[1 2 3]", &layout).is_err());
+    }
+
+    #[test]
+    fn composite() {
+        let point = Layout::struct_(vec![("x", Layout::U8), ("y", Layout::I8)]);
+        assert_eq!(
+            Some(Value::Struct(vec![Value::U8(1), Value::I8(-1)])),
+            eval_typed("{ y:-1 x:1 }", &point).unwrap()
+        );
+        assert!(eval_typed("{ x:1 }", &point).is_err());
+        assert!(eval_typed("{ x:1 y:2 z:3 }", &point).is_err());
+
+        let word = Layout::union(vec![("a", Layout::U8), ("b", Layout::U16)]);
+        assert_eq!(
+            Some(Value::Union(vec![0x42, 0])),
+            eval_typed("{ a:0x42 }", &word).unwrap()
+        );
+        assert!(eval_typed("{ a:1 b:2 }", &word).is_err());
+
+        let string = Layout::Array {
+            inner: Box::new(Layout::U8),
+            len: 2,
+        };
+        assert_eq!(
+            Some(Value::Array(vec![Value::U8(b'h'), Value::U8(b'i')])),
+            eval_typed("\"hi\"", &string).unwrap()
+        );
+        assert!(eval_typed("\"hi!\"", &string).is_err());
+    }
+
+    #[test]
+    fn byte_order() {
+        let layout = Layout::struct_(vec![
+            ("a", Layout::U8),
+            ("b", Layout::I16),
+            ("c", Layout::U16),
+        ]);
+        let value = Value::Struct(vec![Value::U8(1), Value::I16(-2), Value::U16(0x1234)]);
+
+        let mut little = Vec::new();
+        let mut big = Vec::new();
+        value.write::<LittleEndian>(&mut little);
+        value.write::<BigEndian>(&mut big);
+        assert_eq!(vec![1, 0xfe, 0xff, 0x34, 0x12], little);
+        assert_eq!(vec![1, 0xff, 0xfe, 0x12, 0x34], big);
+        assert_eq!(value, Value::read::<LittleEndian>(&layout, &little));
+        assert_eq!(value, Value::read::<BigEndian>(&layout, &big));
+    }
+}
@@ -3,11 +3,18 @@ use crate::{
     ir::{
         compile::{
             alloc::{FnAlloc, RegisterAlloc, SymbolAlloc, SymbolMemorySpace},
+            charmap::{self, Quoted},
+            constant,
+            constant::const_expr,
             layout::Layout,
+            Result, SemanticError,
         },
-        opcodes::{Destination, Pointer, Source, Statement},
+        opcodes::{Destination, Location, Pointer, Source, Statement},
+    },
+    parser::{
+        ast::Expression,
+        lex::span::{union, Spanned},
     },
-    parser::ast::{Expression, Path},
 };
 
 // match to a particular `Expression` enum variant.
@@ -30,13 +37,10 @@ macro_rules! match_expr {
 }
 
 /// Utility function to free all registers referenced inside a Source.
-pub fn free_source_registers(source: &Source<u8>, register_alloc: &mut RegisterAlloc) {
+pub fn free_source_registers<T>(source: &Source<T>, register_alloc: &mut RegisterAlloc) {
     match source {
         Source::Register(r) => register_alloc.free(*r),
-        Source::Pointer {
-            offset: Some(offset),
-            ..
-        } => free_source_registers(offset, register_alloc),
+        Source::Pointer { base, offset } => free_pointer_registers(base, offset, register_alloc),
         _ => {}
     }
 }
@@ -46,15 +50,28 @@ pub fn free_source_registers(source: &Source<u8>, register_alloc: &mut RegisterA
 pub fn free_destination_registers(destination: &Destination, register_alloc: &mut RegisterAlloc) {
     match destination {
         Destination::Register(r) => register_alloc.free(*r),
-        Destination::Pointer {
-            offset: Some(offset),
-            ..
-        } => free_source_registers(offset, register_alloc),
-        _ => {}
+        Destination::Pointer { base, offset } => {
+            free_pointer_registers(base, offset, register_alloc)
+        }
     }
 }
 
-fn destination_to_source(destination: &Destination) -> Source<u8> {
+// free the register holding the address of an indirect pointer, and the ones
+// referenced by its offset.
+fn free_pointer_registers(
+    base: &Pointer,
+    offset: &Option<Box<Source<u8>>>,
+    register_alloc: &mut RegisterAlloc,
+) {
+    if let Pointer::Indirect(r, _) = base {
+        register_alloc.free(*r);
+    }
+    if let Some(offset) = offset {
+        free_source_registers(offset, register_alloc);
+    }
+}
+
+fn destination_to_source<T>(destination: &Destination) -> Source<T> {
     use Destination::*;
     match destination {
         Pointer { base, offset } => Source::Pointer {
@@ -65,96 +82,116 @@ fn destination_to_source(destination: &Destination) -> Source<u8> {
     }
 }
 
-/// Evaluate and return the result of a constant expression.
-/// If the passed expression is not a constant expression, returns `None`.
-pub fn const_expr<B: ByteOrder>(
+// Whether values of the given layout are 16bit words (pointers included).
+pub(crate) fn is_word_layout(layout: &Layout) -> bool {
+    matches!(layout, Layout::U16 | Layout::I16 | Layout::Pointer(_))
+}
+
+// Layout of the value a pointer expression points to, or `None` if the
+// expression isn't a pointer.
+fn pointee<B: ByteOrder>(
     expression: &Expression<'_>,
-    symbol_alloc: Option<&SymbolAlloc<B>>,
-) -> Option<u16> {
-    use Expression as E;
-    match (symbol_alloc, expression) {
-        (Some(symbol_alloc), E::Path(path)) => {
-            let name = path_to_symbol_name(path);
-            let symbol = symbol_alloc.get(&name);
-            match symbol.memory_space {
-                SymbolMemorySpace::Const => {
-                    Some(symbol_alloc.const_data()[symbol.offset as usize] as _)
-                }
-                _ => None,
+    symbol_alloc: &SymbolAlloc<B>,
+) -> Result<Option<Layout>> {
+    Ok(match numeric_layout(expression, symbol_alloc)? {
+        Some(Layout::Pointer(inner)) => Some(*inner),
+        _ => None,
+    })
+}
+
+// Layout of the numeric value an expression evaluates to, or `None` if it is
+// made up of literals only (these take the layout of the other operand).
+pub(crate) fn numeric_layout<B: ByteOrder>(
+    expression: &Expression<'_>,
+    symbol_alloc: &SymbolAlloc<B>,
+) -> Result<Option<Layout>> {
+    macro_rules! binary {
+        ($node:expr) => {
+            match numeric_layout(&$node.inner.left, symbol_alloc)? {
+                Some(layout) => Some(layout),
+                None => numeric_layout(&$node.inner.right, symbol_alloc)?,
             }
+        };
+    }
+
+    use Expression as E;
+    Ok(match expression {
+        E::Path(_) | E::Index(_) | E::Field(_) | E::Deref(_) => {
+            place_layout(expression, symbol_alloc)?
         }
-        (_, E::Lit(lit)) => {
-            let num = lit.to_string();
-            Some(if num.starts_with("0x") {
-                u16::from_str_radix(&num[2..], 16).expect("Not a hex number")
-            } else if num.starts_with('0') && num.len() > 1 {
-                u16::from_str_radix(&num[1..], 8).expect("Not an octal number")
-            } else if num.starts_with("0b") {
-                u16::from_str_radix(&num[2..], 2).expect("Not a bin number")
-            } else {
-                u16::from_str_radix(&num[..], 10).expect("Not a decimal number")
-            })
+        E::AddressOf(node) => {
+            place_layout(&node.inner, symbol_alloc)?.map(|layout| Layout::Pointer(Box::new(layout)))
         }
-        (_, E::Minus(_e)) => todo!(),
-        (_, E::Not(e)) => Some(!const_expr(&e.inner, symbol_alloc)?),
-        (_, E::Add(e)) => Some(
-            const_expr(&e.inner.left, symbol_alloc)? + const_expr(&e.inner.right, symbol_alloc)?,
-        ),
-        (_, E::Sub(e)) => Some(
-            const_expr(&e.inner.left, symbol_alloc)? - const_expr(&e.inner.right, symbol_alloc)?,
-        ),
-        (_, E::Mul(e)) => Some(
-            const_expr(&e.inner.left, symbol_alloc)? * const_expr(&e.inner.right, symbol_alloc)?,
-        ),
-        (_, E::Div(e)) => Some(
-            const_expr(&e.inner.left, symbol_alloc)? / const_expr(&e.inner.right, symbol_alloc)?,
-        ),
-        (_, E::And(e)) => Some(
-            const_expr(&e.inner.left, symbol_alloc)? & const_expr(&e.inner.right, symbol_alloc)?,
-        ),
-        (_, E::Or(e)) => Some(
-            const_expr(&e.inner.left, symbol_alloc)? | const_expr(&e.inner.right, symbol_alloc)?,
-        ),
-        (_, E::Xor(e)) => Some(
-            const_expr(&e.inner.left, symbol_alloc)? ^ const_expr(&e.inner.right, symbol_alloc)?,
-        ),
-        (_, E::LeftShift(e)) => Some(
-            const_expr(&e.inner.left, symbol_alloc)? << const_expr(&e.inner.right, symbol_alloc)?,
-        ),
-        (_, E::RightShift(e)) => Some(
-            const_expr(&e.inner.left, symbol_alloc)? >> const_expr(&e.inner.right, symbol_alloc)?,
-        ),
-        (_, E::Eq(e)) => {
-            let l = const_expr(&e.inner.left, symbol_alloc)?;
-            let r = const_expr(&e.inner.left, symbol_alloc)?;
-            Some(if l == r { 1 } else { 0 })
-        }
-        (_, E::NotEq(e)) => {
-            let l = const_expr(&e.inner.left, symbol_alloc)?;
-            let r = const_expr(&e.inner.left, symbol_alloc)?;
-            Some(if l != r { 1 } else { 0 })
-        }
-        (_, E::Greater(e)) => {
-            let l = const_expr(&e.inner.left, symbol_alloc)?;
-            let r = const_expr(&e.inner.left, symbol_alloc)?;
-            Some(if l > r { 1 } else { 0 })
-        }
-        (_, E::GreaterEq(e)) => {
-            let l = const_expr(&e.inner.left, symbol_alloc)?;
-            let r = const_expr(&e.inner.left, symbol_alloc)?;
-            Some(if l >= r { 1 } else { 0 })
-        }
-        (_, E::Less(e)) => {
-            let l = const_expr(&e.inner.left, symbol_alloc)?;
-            let r = const_expr(&e.inner.left, symbol_alloc)?;
-            Some(if l < r { 1 } else { 0 })
-        }
-        (_, E::LessEq(e)) => {
-            let l = const_expr(&e.inner.left, symbol_alloc)?;
-            let r = const_expr(&e.inner.left, symbol_alloc)?;
-            Some(if l <= r { 1 } else { 0 })
+        E::Minus(node) => numeric_layout(&node.inner, symbol_alloc)?,
+        E::Not(node) => numeric_layout(&node.inner, symbol_alloc)?,
+        E::Add(node) => binary!(node),
+        E::Sub(node) => binary!(node),
+        E::Mul(node) => binary!(node),
+        E::Div(node) => binary!(node),
+        E::And(node) => binary!(node),
+        E::Or(node) => binary!(node),
+        E::Xor(node) => binary!(node),
+        E::LeftShift(node) => numeric_layout(&node.inner.left, symbol_alloc)?,
+        E::RightShift(node) => numeric_layout(&node.inner.left, symbol_alloc)?,
+        E::Eq(_) | E::NotEq(_) | E::Greater(_) | E::GreaterEq(_) | E::Less(_) | E::LessEq(_) => {
+            Some(Layout::U8)
         }
+        E::LogicalAnd(_) | E::LogicalOr(_) | E::LogicalNot(_) => Some(Layout::U8),
         _ => None,
+    })
+}
+
+// Whether the expression evaluates to a 16bit word.
+fn is_word<B: ByteOrder>(
+    expression: &Expression<'_>,
+    symbol_alloc: &SymbolAlloc<B>,
+) -> Result<bool> {
+    let layout = numeric_layout(expression, symbol_alloc)?;
+    Ok(layout.map(|l| is_word_layout(&l)).unwrap_or(false))
+}
+
+// Whether the expression evaluates to a signed byte or word.
+fn is_signed<B: ByteOrder>(
+    expression: &Expression<'_>,
+    symbol_alloc: &SymbolAlloc<B>,
+) -> Result<bool> {
+    let layout = numeric_layout(expression, symbol_alloc)?;
+    Ok(matches!(layout, Some(Layout::I8 | Layout::I16)))
+}
+
+// Scale the offset of an array index by the size of the array items.
+// Dynamic offsets are bytes, so the scaled offset must fit in 8 bits.
+fn scale_offset(
+    expression: &Expression<'_>,
+    offset: Source<u8>,
+    size: u16,
+    register_alloc: &mut RegisterAlloc,
+    statements: &mut Vec<Statement>,
+) -> Result<Source<u8>> {
+    let too_large = || {
+        SemanticError::new(expression.span(), "Array index offset out of range")
+            .note("Non-constant indices can't offset the array by more than 255 bytes")
+    };
+    match offset {
+        _ if size == 1 => Ok(offset),
+        Source::Literal(n) => match n as u16 * size {
+            offset if offset > u8::MAX as u16 => Err(too_large()),
+            offset => Ok(Source::Literal(offset as u8)),
+        },
+        offset if size > u8::MAX as u16 => {
+            free_source_registers(&offset, register_alloc);
+            Err(too_large())
+        }
+        offset => {
+            free_source_registers(&offset, register_alloc);
+            let store_register = register_alloc.alloc();
+            statements.push(Statement::Mul {
+                left: offset,
+                right: Source::Literal(size as u8),
+                destination: Destination::Register(store_register),
+            });
+            Ok(Source::Register(store_register))
+        }
     }
 }
 
@@ -166,116 +203,295 @@ pub fn compile_assign<B: ByteOrder>(
     fn_alloc: &FnAlloc,
     register_alloc: &mut RegisterAlloc,
     statements: &mut Vec<Statement>,
-) {
+) -> Result<()> {
     macro_rules! arithmetic_branch {
-        ($var:ident, $node:expr) => {{
-            let destination = assign_destination(
+        ($var:ident, $var_s:ident, $var_w:ident, $var_sw:ident, $node:expr) => {{
+            let (destination, layout) = compile_place(
                 &$node.inner.left,
                 symbol_alloc,
                 fn_alloc,
                 register_alloc,
                 statements,
-            );
-            let left = destination_to_source(&destination);
-            let right = compile_expr_u8(
-                &$node.inner.right,
-                symbol_alloc,
-                fn_alloc,
-                register_alloc,
-                statements,
-            );
-            // free left and destination only (right is a copy of the former)
-            free_source_registers(&right, register_alloc);
-            free_destination_registers(&destination, register_alloc);
-            statements.push(Statement::$var {
-                left,
-                right,
-                destination,
-            });
+            )?;
+            if is_word_layout(&layout) {
+                let left = destination_to_source(&destination);
+                let right = compile_expr_u16(
+                    &$node.inner.right,
+                    symbol_alloc,
+                    fn_alloc,
+                    register_alloc,
+                    statements,
+                )?;
+                // free left and destination only (right is a copy of the former)
+                free_source_registers(&right, register_alloc);
+                free_destination_registers(&destination, register_alloc);
+                if layout == Layout::I16 {
+                    statements.push(Statement::$var_sw {
+                        left,
+                        right,
+                        destination,
+                    });
+                } else {
+                    statements.push(Statement::$var_w {
+                        left,
+                        right,
+                        destination,
+                    });
+                }
+            } else {
+                let left = destination_to_source(&destination);
+                let right = compile_expr_u8(
+                    &$node.inner.right,
+                    symbol_alloc,
+                    fn_alloc,
+                    register_alloc,
+                    statements,
+                )?;
+                free_source_registers(&right, register_alloc);
+                free_destination_registers(&destination, register_alloc);
+                if layout == Layout::I8 {
+                    statements.push(Statement::$var_s {
+                        left,
+                        right,
+                        destination,
+                    });
+                } else {
+                    statements.push(Statement::$var {
+                        left,
+                        right,
+                        destination,
+                    });
+                }
+            }
         }};
     }
 
     use Expression as E;
     match expression {
-        // FIXME assuming array inner type is u8 :/
+        // FIXME assuming array inner type is u8 or u16 :/
         // TODO generalize to any type composition!!
         E::Assign(node) if matches!(node.inner.right, E::Array(_)) => {
             let array = match_expr!(&node.inner.right, E::Array);
-            #[rustfmt::skip] let destination = assign_destination(&node.inner.left, symbol_alloc, fn_alloc, register_alloc, statements);
+            #[rustfmt::skip] let (destination, layout) = compile_place(&node.inner.left, symbol_alloc, fn_alloc, register_alloc, statements)?;
+            let inner = match layout {
+                Layout::Array { inner, .. } => inner,
+                layout => return Err(expected(&node.inner.right, &layout)),
+            };
             let mut offset = 0;
             for expression in &array.inner {
-                #[rustfmt::skip] let source = compile_expr_u8(expression, symbol_alloc, fn_alloc, register_alloc, statements);
                 let mut destination = destination.clone();
                 let base = match_expr!(destination, Destination::Pointer, base).offset(offset);
                 *match_expr!(&mut destination, Destination::Pointer, base) = base;
+                if is_word_layout(&inner) {
+                    #[rustfmt::skip] let source = compile_expr_u16(expression, symbol_alloc, fn_alloc, register_alloc, statements)?;
+                    free_source_registers(&source, register_alloc);
+                    statements.push(Statement::LdW {
+                        source,
+                        destination,
+                    });
+                } else {
+                    #[rustfmt::skip] let source = compile_expr_u8(expression, symbol_alloc, fn_alloc, register_alloc, statements)?;
+                    free_source_registers(&source, register_alloc);
+                    statements.push(Statement::Ld {
+                        source,
+                        destination,
+                    });
+                }
+                offset += inner.size();
+            }
+            free_destination_registers(&destination, register_alloc);
+        }
+        E::Assign(node) => {
+            #[rustfmt::skip] let (destination, layout) = compile_place(&node.inner.left, symbol_alloc, fn_alloc, register_alloc, statements)?;
+            if is_word_layout(&layout) {
+                #[rustfmt::skip] let source = compile_expr_u16(&node.inner.right, symbol_alloc, fn_alloc, register_alloc, statements)?;
+                free_source_registers(&source, register_alloc);
+                free_destination_registers(&destination, register_alloc);
+                statements.push(Statement::LdW {
+                    source,
+                    destination,
+                });
+            } else {
+                #[rustfmt::skip] let source = compile_expr_u8(&node.inner.right, symbol_alloc, fn_alloc, register_alloc, statements)?;
                 free_source_registers(&source, register_alloc);
                 free_destination_registers(&destination, register_alloc);
                 statements.push(Statement::Ld {
                     source,
                     destination,
                 });
-                offset += 1;
             }
         }
-        E::Assign(node) => {
-            #[rustfmt::skip] let destination = assign_destination(&node.inner.left, symbol_alloc, fn_alloc, register_alloc, statements);
-            #[rustfmt::skip] let source = compile_expr_u8(&node.inner.right, symbol_alloc, fn_alloc, register_alloc, statements);
-            free_source_registers(&source, register_alloc);
-            free_destination_registers(&destination, register_alloc);
-            statements.push(Statement::Ld {
-                source,
-                destination,
-            });
-        }
-        E::PlusAssign(node) => arithmetic_branch!(Add, node),
-        E::MinusAssign(node) => arithmetic_branch!(Sub, node),
-        E::MulAssign(node) => arithmetic_branch!(Mul, node),
-        E::DivAssign(node) => arithmetic_branch!(Div, node),
-        E::AndAssign(node) => arithmetic_branch!(And, node),
-        E::OrAssign(node) => arithmetic_branch!(Or, node),
-        E::XorAssign(node) => arithmetic_branch!(Xor, node),
+        // pointers are offset by a multiple of the size of the pointed value
+        #[rustfmt::skip]
+        E::PlusAssign(node) if pointee(&node.inner.left, symbol_alloc)?.is_some() => compile_pointer_assign(&node.inner.left, &node.inner.right, false, symbol_alloc, fn_alloc, register_alloc, statements)?,
+        #[rustfmt::skip]
+        E::MinusAssign(node) if pointee(&node.inner.left, symbol_alloc)?.is_some() => compile_pointer_assign(&node.inner.left, &node.inner.right, true, symbol_alloc, fn_alloc, register_alloc, statements)?,
+        E::PlusAssign(node) => arithmetic_branch!(Add, Add, AddW, AddW, node),
+        E::MinusAssign(node) => arithmetic_branch!(Sub, Sub, SubW, SubW, node),
+        E::MulAssign(node) => arithmetic_branch!(Mul, Mul, MulW, MulW, node),
+        E::DivAssign(node) => arithmetic_branch!(Div, DivS, DivW, DivSW, node),
+        E::AndAssign(node) => arithmetic_branch!(And, And, AndW, AndW, node),
+        E::OrAssign(node) => arithmetic_branch!(Or, Or, OrW, OrW, node),
+        E::XorAssign(node) => arithmetic_branch!(Xor, Xor, XorW, XorW, node),
         _ => unreachable!(),
     }
+    Ok(())
+}
+
+// compile `pointer += offset` (or `pointer -= offset`).
+fn compile_pointer_assign<B: ByteOrder>(
+    pointer: &Expression<'_>,
+    offset: &Expression<'_>,
+    sub: bool,
+    symbol_alloc: &SymbolAlloc<B>,
+    fn_alloc: &FnAlloc,
+    register_alloc: &mut RegisterAlloc,
+    statements: &mut Vec<Statement>,
+) -> Result<()> {
+    #[rustfmt::skip] let source = compile_pointer_offset(pointer, offset, sub, symbol_alloc, fn_alloc, register_alloc, statements)?;
+    #[rustfmt::skip] let (destination, _) = compile_place(pointer, symbol_alloc, fn_alloc, register_alloc, statements)?;
+    free_source_registers(&source, register_alloc);
+    free_destination_registers(&destination, register_alloc);
+    statements.push(Statement::LdW {
+        source,
+        destination,
+    });
+    Ok(())
 }
 
-// compute the destination of an assignment expression
-fn assign_destination<B: ByteOrder>(
+// compute the memory location an expression refers to (the destination of an
+// assignment, or the source of a load), and the layout of the value stored in it.
+fn compile_place<B: ByteOrder>(
     expression: &Expression<'_>,
     symbol_alloc: &SymbolAlloc<B>,
     fn_alloc: &FnAlloc,
     register_alloc: &mut RegisterAlloc,
     statements: &mut Vec<Statement>,
-) -> Destination {
+) -> Result<(Destination, Layout)> {
     use Expression as E;
     match expression {
         E::Path(path) => {
-            let name = path_to_symbol_name(path);
-            let symbol = symbol_alloc.get(&name);
-            Destination::Pointer {
+            let symbol = symbol_alloc.get(path)?;
+            let destination = Destination::Pointer {
                 base: symbol.pointer(),
                 offset: None,
-            }
+            };
+            Ok((destination, symbol.layout.clone()))
         }
         E::Index(index) => {
-            #[rustfmt::skip] let offset = compile_expr_u8(&index.inner.left, symbol_alloc, fn_alloc, register_alloc, statements);
-            #[rustfmt::skip] let mut destination = assign_destination(&index.inner.right, symbol_alloc, fn_alloc, register_alloc, statements);
-            match_expr!(&mut destination, Destination::Pointer, offset).replace(Box::new(offset));
-            destination
+            #[rustfmt::skip] let offset = compile_expr_u8(&index.inner.left, symbol_alloc, fn_alloc, register_alloc, statements)?;
+            #[rustfmt::skip] let (mut destination, layout) = compile_place(&index.inner.right, symbol_alloc, fn_alloc, register_alloc, statements)?;
+            let inner = match layout {
+                Layout::Array { inner, .. } => *inner,
+                layout => {
+                    let message = format!("Expected an array, found `{}`", layout);
+                    return Err(SemanticError::new(index.inner.right.span(), message));
+                }
+            };
+            // constant indices are folded into the base pointer
+            if let Source::Literal(n) = offset {
+                let base = match_expr!(&mut destination, Destination::Pointer, base);
+                *base = base.offset(n as u16 * inner.size());
+                return Ok((destination, inner));
+            }
+            let dynamic = match_expr!(&mut destination, Destination::Pointer, offset);
+            if dynamic.is_some() {
+                free_source_registers(&offset, register_alloc);
+                free_destination_registers(&destination, register_alloc);
+                return Err(unsupported(
+                    expression,
+                    "Nested indexing with non-constant indices",
+                ));
+            }
+            let offset = scale_offset(expression, offset, inner.size(), register_alloc, statements);
+            let offset = match offset {
+                Ok(offset) => offset,
+                Err(error) => {
+                    free_destination_registers(&destination, register_alloc);
+                    return Err(error);
+                }
+            };
+            dynamic.replace(Box::new(offset));
+            Ok((destination, inner))
         }
-        _ => unreachable!(),
+        E::Field(field) => {
+            #[rustfmt::skip] let (mut destination, layout) = compile_place(&field.inner, symbol_alloc, fn_alloc, register_alloc, statements)?;
+            let name = field.ident.to_string();
+            let field = match layout.field(&name) {
+                Some(field) => field,
+                None => {
+                    free_destination_registers(&destination, register_alloc);
+                    let message = format!("No field `{}` on type `{}`", name, layout);
+                    return Err(SemanticError::new(field.ident.span(), message));
+                }
+            };
+            let base = match_expr!(&mut destination, Destination::Pointer, base);
+            *base = base.offset(field.offset);
+            Ok((destination, field.layout.clone()))
+        }
+        E::Deref(deref) => {
+            let inner = match pointee(&deref.inner, symbol_alloc)? {
+                Some(inner) => inner,
+                None => {
+                    let message = "Expected a pointer";
+                    return Err(SemanticError::new(deref.inner.span(), message));
+                }
+            };
+            #[rustfmt::skip] let address = compile_expr_u16(&deref.inner, symbol_alloc, fn_alloc, register_alloc, statements)?;
+            let base = match address {
+                // constant addresses are dereferenced directly
+                Source::Literal(address) => Pointer::Absolute(address),
+                Source::Register(register) => Pointer::Indirect(register, 0),
+                address => {
+                    free_source_registers(&address, register_alloc);
+                    let register = register_alloc.alloc();
+                    statements.push(Statement::LdW {
+                        source: address,
+                        destination: Destination::Register(register),
+                    });
+                    Pointer::Indirect(register, 0)
+                }
+            };
+            let destination = Destination::Pointer { base, offset: None };
+            Ok((destination, inner))
+        }
+        _ => Err(SemanticError::new(
+            expression.span(),
+            "Invalid left-hand side of assignment",
+        )),
     }
 }
 
+// Layout of the value stored at the memory location an expression refers to.
+fn place_layout<B: ByteOrder>(
+    expression: &Expression<'_>,
+    symbol_alloc: &SymbolAlloc<B>,
+) -> Result<Option<Layout>> {
+    use Expression as E;
+    Ok(match expression {
+        E::Path(path) => Some(symbol_alloc.get(path)?.layout.clone()),
+        E::Index(node) => match place_layout(&node.inner.right, symbol_alloc)? {
+            Some(Layout::Array { inner, .. }) => Some(*inner),
+            _ => None,
+        },
+        E::Field(node) => place_layout(&node.inner, symbol_alloc)?
+            .and_then(|layout| layout.field(&node.ident.to_string()).cloned())
+            .map(|field| field.layout),
+        E::Deref(node) => pointee(&node.inner, symbol_alloc)?,
+        _ => None,
+    })
+}
+
 pub fn compile_expr_u8<B: ByteOrder>(
     expression: &Expression<'_>,
     symbol_alloc: &SymbolAlloc<B>,
     fn_alloc: &FnAlloc,
     register_alloc: &mut RegisterAlloc,
     statements: &mut Vec<Statement>,
-) -> Source<u8> {
-    #[rustfmt::skip] let source = compile_expr(expression, symbol_alloc, fn_alloc, register_alloc, statements);
+) -> Result<Source<u8>> {
+    #[rustfmt::skip] let source = compile_expr(expression, symbol_alloc, fn_alloc, register_alloc, statements)?;
     assert_eq!(1, source.len());
-    source[0].clone()
+    Ok(source[0].clone())
 }
 
 /// compile a `Layout::U8` expression, and store the result in a `Source<u8>`
@@ -292,7 +508,7 @@ pub fn compile_expr<B: ByteOrder>(
     fn_alloc: &FnAlloc,
     register_alloc: &mut RegisterAlloc,
     statements: &mut Vec<Statement>,
-) -> Vec<Source<u8>> {
+) -> Result<Vec<Source<u8>>> {
     macro_rules! arithmetic_branch {
         ($var:ident, $node:expr) => {{
             let left = compile_expr_u8(
@@ -301,14 +517,14 @@ pub fn compile_expr<B: ByteOrder>(
                 fn_alloc,
                 register_alloc,
                 statements,
-            );
+            )?;
             let right = compile_expr_u8(
                 &$node.inner.right,
                 symbol_alloc,
                 fn_alloc,
                 register_alloc,
                 statements,
-            );
+            )?;
             free_source_registers(&left, register_alloc);
             free_source_registers(&right, register_alloc);
             // TODO for now, put it in a register, but it shpuld be possible to instruct the
@@ -323,77 +539,529 @@ pub fn compile_expr<B: ByteOrder>(
         }};
     }
 
+    // use the signed variant of the operation if any of the operands is an i8
+    macro_rules! signed_branch {
+        ($var:ident, $var_s:ident, $node:expr) => {{
+            if is_signed(&$node.inner.left, symbol_alloc)?
+                || is_signed(&$node.inner.right, symbol_alloc)?
+            {
+                arithmetic_branch!($var_s, $node)
+            } else {
+                arithmetic_branch!($var, $node)
+            }
+        }};
+    }
+
+    // signed words are biased so they compare as unsigned (`$bias` is only
+    // needed for ordered comparisons).
+    macro_rules! compare_branch {
+        ($var:ident, $var_s:ident, $var_w:ident, $bias:literal, $node:expr) => {{
+            if is_word(&$node.inner.left, symbol_alloc)?
+                || is_word(&$node.inner.right, symbol_alloc)?
+            {
+                let left = compile_expr_u16(
+                    &$node.inner.left,
+                    symbol_alloc,
+                    fn_alloc,
+                    register_alloc,
+                    statements,
+                )?;
+                let right = compile_expr_u16(
+                    &$node.inner.right,
+                    symbol_alloc,
+                    fn_alloc,
+                    register_alloc,
+                    statements,
+                )?;
+                let (left, right) = if $bias
+                    && (is_signed(&$node.inner.left, symbol_alloc)?
+                        || is_signed(&$node.inner.right, symbol_alloc)?)
+                {
+                    let left = bias(left, register_alloc, statements);
+                    (left, bias(right, register_alloc, statements))
+                } else {
+                    (left, right)
+                };
+                free_source_registers(&left, register_alloc);
+                free_source_registers(&right, register_alloc);
+                let store_register = register_alloc.alloc();
+                statements.push(Statement::$var_w {
+                    left,
+                    right,
+                    destination: Destination::Register(store_register),
+                });
+                vec![Source::Register(store_register)]
+            } else {
+                signed_branch!($var, $var_s, $node)
+            }
+        }};
+    }
+
     use Expression as E;
 
     // if the expression is a constant expression, return it as a literal.
-    if let Some(n) = const_expr(expression, Some(symbol_alloc)) {
-        assert!(n <= 0xff); // TODO wrap?
-        return vec![Source::Literal(n as u8)];
+    // negative constants are sign-extended to 16bits, so truncate those.
+    if let Some(n) = const_expr(expression, Some(symbol_alloc))? {
+        if n > 0xff && n < 0xff80 {
+            let message = "Constant expression out of range for `u8`";
+            return Err(SemanticError::new(expression.span(), message));
+        }
+        return Ok(vec![Source::Literal(n as u8)]);
     }
 
-    match expression {
+    Ok(match expression {
         // TODO numeric const expressions are handled by the above statement, but what if expression
         //  is a string literal?
-        E::Lit(_) => todo!(),
+        E::Lit(_) => return Err(unsupported(expression, "String literals in expressions")),
 
         // symbol name
         E::Path(path) => {
-            let symbol_name = path_to_symbol_name(path);
-            let symbol = symbol_alloc.get(&symbol_name);
-            assert!(matches!(&symbol.layout, Layout::U8));
+            let symbol = symbol_alloc.get(path)?;
+            if symbol.layout != Layout::U8 && symbol.layout != Layout::I8 {
+                let message = format!("Expected a `u8` or `i8` symbol, found `{}`", symbol.layout);
+                return Err(SemanticError::new(path.span(), message));
+            }
             vec![Source::Pointer {
                 base: symbol.pointer(),
                 offset: None,
             }]
         }
 
+        // negation (subtract from zero)
+        E::Minus(node) => {
+            let right = compile_expr_u8(
+                &node.inner,
+                symbol_alloc,
+                fn_alloc,
+                register_alloc,
+                statements,
+            )?;
+            free_source_registers(&right, register_alloc);
+            let store_register = register_alloc.alloc();
+            statements.push(Statement::Sub {
+                left: Source::Literal(0),
+                right,
+                destination: Destination::Register(store_register),
+            });
+            vec![Source::Register(store_register)]
+        }
+
         // 8bit arithmetic
         E::Add(node) => arithmetic_branch!(Add, node),
         E::Sub(node) => arithmetic_branch!(Sub, node),
         E::Mul(node) => arithmetic_branch!(Mul, node),
-        E::Div(node) => arithmetic_branch!(Div, node),
+        E::Div(node) => signed_branch!(Div, DivS, node),
         // TODO modulo
         E::And(node) => arithmetic_branch!(And, node),
         E::Or(node) => arithmetic_branch!(Or, node),
         E::Xor(node) => arithmetic_branch!(Xor, node),
         E::LeftShift(node) => arithmetic_branch!(LeftShift, node),
-        E::RightShift(node) => arithmetic_branch!(RightShift, node),
+        E::RightShift(node) => signed_branch!(RightShift, RightShiftS, node),
 
         // boolean
-        E::Eq(node) => arithmetic_branch!(Eq, node),
-        E::NotEq(node) => arithmetic_branch!(NotEq, node),
-        E::Greater(node) => arithmetic_branch!(Greater, node),
-        E::GreaterEq(node) => arithmetic_branch!(GreaterEq, node),
-        E::Less(node) => arithmetic_branch!(Less, node),
-        E::LessEq(node) => arithmetic_branch!(LessEq, node),
-
-        // array indexing
-        // TODO assuming u8 array. Generalize to any array type!!!
-        E::Index(node) => {
-            let right = match_expr!(&node.inner.right, E::Path);
-            let name = path_to_symbol_name(&right);
-            let symbol = symbol_alloc.get(&name);
-            let offset = compile_expr_u8(
-                &node.inner.left,
+        E::Eq(node) => compare_branch!(Eq, Eq, EqW, false, node),
+        E::NotEq(node) => compare_branch!(NotEq, NotEq, NotEqW, false, node),
+        E::Greater(node) => compare_branch!(Greater, GreaterS, GreaterW, true, node),
+        E::GreaterEq(node) => compare_branch!(GreaterEq, GreaterEqS, GreaterEqW, true, node),
+        E::Less(node) => compare_branch!(Less, LessS, LessW, true, node),
+        E::LessEq(node) => compare_branch!(LessEq, LessEqS, LessEqW, true, node),
+
+        // logic
+        #[rustfmt::skip]
+        E::LogicalAnd(node) => vec![compile_logical(&node.inner.left, &node.inner.right, false, symbol_alloc, fn_alloc, register_alloc, statements)?],
+        #[rustfmt::skip]
+        E::LogicalOr(node) => vec![compile_logical(&node.inner.left, &node.inner.right, true, symbol_alloc, fn_alloc, register_alloc, statements)?],
+        E::LogicalNot(node) => {
+            #[rustfmt::skip] let source = compile_truth(&node.inner, symbol_alloc, fn_alloc, register_alloc, statements)?;
+            free_source_registers(&source, register_alloc);
+            let store_register = register_alloc.alloc();
+            statements.push(Statement::Eq {
+                left: source,
+                right: Source::Literal(0),
+                destination: Destination::Register(store_register),
+            });
+            vec![Source::Register(store_register)]
+        }
+
+        // array indexing, struct fields & pointer dereference
+        E::Index(_) | E::Field(_) | E::Deref(_) => {
+            #[rustfmt::skip] let (destination, layout) = compile_place(expression, symbol_alloc, fn_alloc, register_alloc, statements)?;
+            if layout != Layout::U8 && layout != Layout::I8 {
+                free_destination_registers(&destination, register_alloc);
+                let message = format!("Expected a `u8` or `i8` value, found `{}`", layout);
+                return Err(SemanticError::new(expression.span(), message));
+            }
+            vec![destination_to_source(&destination)]
+        }
+
+        // array
+        E::Array(_array) => return Err(unsupported(expression, "Array expressions")),
+
+        // functions
+        E::Call(_) => {
+            return Err(unsupported(expression, "Function calls in expressions")
+                .note("Calls can only be made from let initializers, or as statements"))
+        }
+        _ => return Err(unsupported(expression, "Expression")),
+    })
+}
+
+/// compile a condition into a byte that is nonzero if the condition holds.
+/// 16bit values are compared against zero.
+pub fn compile_truth<B: ByteOrder>(
+    expression: &Expression<'_>,
+    symbol_alloc: &SymbolAlloc<B>,
+    fn_alloc: &FnAlloc,
+    register_alloc: &mut RegisterAlloc,
+    statements: &mut Vec<Statement>,
+) -> Result<Source<u8>> {
+    if !is_word(expression, symbol_alloc)? {
+        return compile_expr_u8(
+            expression,
+            symbol_alloc,
+            fn_alloc,
+            register_alloc,
+            statements,
+        );
+    }
+    #[rustfmt::skip] let source = compile_expr_u16(expression, symbol_alloc, fn_alloc, register_alloc, statements)?;
+    free_source_registers(&source, register_alloc);
+    let store_register = register_alloc.alloc();
+    statements.push(Statement::NotEqW {
+        left: source,
+        right: Source::Literal(0),
+        destination: Destination::Register(store_register),
+    });
+    Ok(Source::Register(store_register))
+}
+
+// compile a short-circuiting `and` (or `or`) into a boolean. The rhs is skipped
+// when the lhs alone determines the result:
+//
+// ```no_rust
+//     <left>
+//     JmpCmpNot left, short   ; JmpCmp for `or`
+//     <right>
+//     JmpCmpNot right, short  ; JmpCmp for `or`
+//     Ld 1, result            ; 0 for `or`
+//     Jmp end
+// short:
+//     Ld 0, result            ; 1 for `or`
+// end:
+// ```
+fn compile_logical<B: ByteOrder>(
+    left: &Expression<'_>,
+    right: &Expression<'_>,
+    or: bool,
+    symbol_alloc: &SymbolAlloc<B>,
+    fn_alloc: &FnAlloc,
+    register_alloc: &mut RegisterAlloc,
+    statements: &mut Vec<Statement>,
+) -> Result<Source<u8>> {
+    // jump to the short-circuit result if the operand allows it. Constant
+    // operands either always or never short-circuit.
+    let span = union(&left.span(), &right.span());
+    let jump = |location, source| match source {
+        Source::Literal(n) if (n != 0) == or => Some(Statement::Jmp { location }),
+        Source::Literal(_) => None,
+        source if or => Some(Statement::JmpCmp { location, source }),
+        source => Some(Statement::JmpCmpNot { location, source }),
+    };
+
+    // the lhs is consumed by the jump, so its registers can be reused by the rhs
+    #[rustfmt::skip] let left = compile_truth(left, symbol_alloc, fn_alloc, register_alloc, statements)?;
+    free_source_registers(&left, register_alloc);
+    let mut rhs = Vec::new();
+    #[rustfmt::skip] let right = compile_truth(right, symbol_alloc, fn_alloc, register_alloc, &mut rhs)?;
+    free_source_registers(&right, register_alloc);
+    rhs.extend(jump(Location::Relative(2), right));
+
+    let store_register = register_alloc.alloc();
+    let location = super::relative(rhs.len() as isize + 2, span)?;
+    statements.extend(jump(location, left));
+    statements.extend(rhs);
+    statements.push(Statement::Ld {
+        source: Source::Literal(or as u8 ^ 1),
+        destination: Destination::Register(store_register),
+    });
+    statements.push(Statement::Jmp {
+        location: Location::Relative(1),
+    });
+    statements.push(Statement::Ld {
+        source: Source::Literal(or as u8),
+        destination: Destination::Register(store_register),
+    });
+    Ok(Source::Register(store_register))
+}
+
+/// compile a `Layout::U16` or `Layout::I16` expression, and store the result
+/// in a `Source<u16>` return this source.
+///
+/// # Note
+/// Same as with `compile_expr`, the callee of the function is responsible for
+/// freeing any register referenced by the returned `Source`.
+pub fn compile_expr_u16<B: ByteOrder>(
+    expression: &Expression<'_>,
+    symbol_alloc: &SymbolAlloc<B>,
+    fn_alloc: &FnAlloc,
+    register_alloc: &mut RegisterAlloc,
+    statements: &mut Vec<Statement>,
+) -> Result<Source<u16>> {
+    macro_rules! arithmetic_branch {
+        ($var:ident, $node:expr) => {{
+            let left = compile_expr_u16(
+                &$node.inner.left,
                 symbol_alloc,
                 fn_alloc,
                 register_alloc,
                 statements,
-            );
-            vec![Source::Pointer {
+            )?;
+            let right = compile_expr_u16(
+                &$node.inner.right,
+                symbol_alloc,
+                fn_alloc,
+                register_alloc,
+                statements,
+            )?;
+            free_source_registers(&left, register_alloc);
+            free_source_registers(&right, register_alloc);
+            let store_register = register_alloc.alloc();
+            statements.push(Statement::$var {
+                left,
+                right,
+                destination: Destination::Register(store_register),
+            });
+            Source::Register(store_register)
+        }};
+    }
+    // the shift amount is always a byte
+    macro_rules! shift_branch {
+        ($var:ident, $node:expr) => {{
+            let left = compile_expr_u16(
+                &$node.inner.left,
+                symbol_alloc,
+                fn_alloc,
+                register_alloc,
+                statements,
+            )?;
+            let right = compile_expr_u8(
+                &$node.inner.right,
+                symbol_alloc,
+                fn_alloc,
+                register_alloc,
+                statements,
+            )?;
+            free_source_registers(&left, register_alloc);
+            free_source_registers(&right, register_alloc);
+            let store_register = register_alloc.alloc();
+            statements.push(Statement::$var {
+                left,
+                right,
+                destination: Destination::Register(store_register),
+            });
+            Source::Register(store_register)
+        }};
+    }
+
+    use Expression as E;
+
+    // pointer arithmetic is scaled by the size of the pointed value, so it
+    // can't be folded like the rest of constant expressions.
+    match expression {
+        #[rustfmt::skip]
+        E::Add(node) if pointee(&node.inner.left, symbol_alloc)?.is_some() => return compile_pointer_offset(&node.inner.left, &node.inner.right, false, symbol_alloc, fn_alloc, register_alloc, statements),
+        #[rustfmt::skip]
+        E::Sub(node) if pointee(&node.inner.left, symbol_alloc)?.is_some() => return compile_pointer_offset(&node.inner.left, &node.inner.right, true, symbol_alloc, fn_alloc, register_alloc, statements),
+        _ => {}
+    }
+
+    if let Some(n) = const_expr(expression, Some(symbol_alloc))? {
+        return Ok(Source::Literal(n));
+    }
+
+    Ok(match expression {
+        E::Lit(_) => return Err(unsupported(expression, "String literals in expressions")),
+
+        // symbol name
+        E::Path(path) => {
+            let symbol = symbol_alloc.get(path)?;
+            if !is_word_layout(&symbol.layout) {
+                let message = format!("Expected a `u16` symbol, found `{}`", symbol.layout);
+                return Err(SemanticError::new(path.span(), message));
+            }
+            Source::Pointer {
                 base: symbol.pointer(),
-                offset: Some(Box::new(offset)),
-            }]
+                offset: None,
+            }
         }
 
-        // array
-        E::Array(_array) => todo!(),
+        // negation (subtract from zero)
+        E::Minus(node) => {
+            let right = compile_expr_u16(
+                &node.inner,
+                symbol_alloc,
+                fn_alloc,
+                register_alloc,
+                statements,
+            )?;
+            free_source_registers(&right, register_alloc);
+            let store_register = register_alloc.alloc();
+            statements.push(Statement::SubW {
+                left: Source::Literal(0),
+                right,
+                destination: Destination::Register(store_register),
+            });
+            Source::Register(store_register)
+        }
 
-        // functions
-        #[warn(unused)]
-        E::Call(node) => todo!(),
-        _ => unreachable!(),
+        // 16bit arithmetic
+        E::Add(node) => arithmetic_branch!(AddW, node),
+        E::Sub(node) => arithmetic_branch!(SubW, node),
+        E::Mul(node) => arithmetic_branch!(MulW, node),
+        E::Div(node)
+            if is_signed(&node.inner.left, symbol_alloc)?
+                || is_signed(&node.inner.right, symbol_alloc)? =>
+        {
+            arithmetic_branch!(DivSW, node)
+        }
+        E::Div(node) => arithmetic_branch!(DivW, node),
+        E::And(node) => arithmetic_branch!(AndW, node),
+        E::Or(node) => arithmetic_branch!(OrW, node),
+        E::Xor(node) => arithmetic_branch!(XorW, node),
+        E::LeftShift(node) => shift_branch!(LeftShiftW, node),
+        E::RightShift(node) if is_signed(&node.inner.left, symbol_alloc)? => {
+            shift_branch!(RightShiftSW, node)
+        }
+        E::RightShift(node) => shift_branch!(RightShiftW, node),
+
+        // array indexing, struct fields & pointer dereference
+        E::Index(_) | E::Field(_) | E::Deref(_) => {
+            #[rustfmt::skip] let (destination, layout) = compile_place(expression, symbol_alloc, fn_alloc, register_alloc, statements)?;
+            if !is_word_layout(&layout) {
+                free_destination_registers(&destination, register_alloc);
+                let message = format!("Expected a `u16` value, found `{}`", layout);
+                return Err(SemanticError::new(expression.span(), message));
+            }
+            destination_to_source(&destination)
+        }
+
+        // address of a memory location
+        E::AddressOf(node) => {
+            #[rustfmt::skip] let (destination, _) = compile_place(&node.inner, symbol_alloc, fn_alloc, register_alloc, statements)?;
+            let source = destination_to_source(&destination);
+            free_source_registers(&source, register_alloc);
+            let store_register = register_alloc.alloc();
+            statements.push(Statement::LdAddr {
+                source,
+                destination: Destination::Register(store_register),
+            });
+            Source::Register(store_register)
+        }
+
+        E::Call(_) => {
+            return Err(unsupported(expression, "Function calls in expressions")
+                .note("Calls can only be made from let initializers, or as statements"))
+        }
+        _ => return Err(unsupported(expression, "Expression")),
+    })
+}
+
+// compile `pointer + offset` (or `pointer - offset`), where the offset is
+// scaled by the size of the pointed value.
+fn compile_pointer_offset<B: ByteOrder>(
+    pointer: &Expression<'_>,
+    offset: &Expression<'_>,
+    sub: bool,
+    symbol_alloc: &SymbolAlloc<B>,
+    fn_alloc: &FnAlloc,
+    register_alloc: &mut RegisterAlloc,
+    statements: &mut Vec<Statement>,
+) -> Result<Source<u16>> {
+    let size = match pointee(pointer, symbol_alloc)? {
+        Some(inner) => inner.size(),
+        None => unreachable!(),
+    };
+    #[rustfmt::skip] let left = compile_expr_u16(pointer, symbol_alloc, fn_alloc, register_alloc, statements)?;
+    let right = match numeric_layout(offset, symbol_alloc)? {
+        Some(layout) if !is_word_layout(&layout) => {
+            #[rustfmt::skip] let offset = compile_expr_u8(offset, symbol_alloc, fn_alloc, register_alloc, statements)?;
+            widen(offset, register_alloc, statements)
+        }
+        _ => compile_expr_u16(offset, symbol_alloc, fn_alloc, register_alloc, statements)?,
+    };
+    let right = match right {
+        _ if size == 1 => right,
+        Source::Literal(n) => Source::Literal(n.wrapping_mul(size)),
+        right => {
+            free_source_registers(&right, register_alloc);
+            let store_register = register_alloc.alloc();
+            statements.push(Statement::MulW {
+                left: right,
+                right: Source::Literal(size),
+                destination: Destination::Register(store_register),
+            });
+            Source::Register(store_register)
+        }
+    };
+    free_source_registers(&left, register_alloc);
+    free_source_registers(&right, register_alloc);
+    let store_register = register_alloc.alloc();
+    let destination = Destination::Register(store_register);
+    statements.push(if sub {
+        Statement::SubW {
+            left,
+            right,
+            destination,
+        }
+    } else {
+        Statement::AddW {
+            left,
+            right,
+            destination,
+        }
+    });
+    Ok(Source::Register(store_register))
+}
+
+// flip the sign bit of a 16bit word, so that signed words compare as unsigned.
+fn bias(
+    source: Source<u16>,
+    register_alloc: &mut RegisterAlloc,
+    statements: &mut Vec<Statement>,
+) -> Source<u16> {
+    if let Source::Literal(n) = source {
+        return Source::Literal(n ^ 0x8000);
+    }
+    free_source_registers(&source, register_alloc);
+    let store_register = register_alloc.alloc();
+    statements.push(Statement::XorW {
+        left: source,
+        right: Source::Literal(0x8000),
+        destination: Destination::Register(store_register),
+    });
+    Source::Register(store_register)
+}
+
+// zero-extend a byte into a 16bit word (the address of `Absolute(0)` offset by
+// the byte is the byte itself).
+fn widen(
+    source: Source<u8>,
+    register_alloc: &mut RegisterAlloc,
+    statements: &mut Vec<Statement>,
+) -> Source<u16> {
+    if let Source::Literal(n) = source {
+        return Source::Literal(n as u16);
     }
+    free_source_registers(&source, register_alloc);
+    let store_register = register_alloc.alloc();
+    statements.push(Statement::LdAddr {
+        source: Source::Pointer {
+            base: Pointer::Absolute(0),
+            offset: Some(Box::new(source)),
+        },
+        destination: Destination::Register(store_register),
+    });
+    Source::Register(store_register)
 }
 
 /// compiles the evaluation of an expression, but the result is not stored
@@ -405,11 +1073,11 @@ pub fn compile_expr_void<B: ByteOrder>(
     fn_alloc: &FnAlloc,
     register_alloc: &mut RegisterAlloc,
     statements: &mut Vec<Statement>,
-) {
+) -> Result<()> {
     use Expression as E;
     match expression {
         // superfluous expressions
-        E::Lit(_) | E::Path(_) => { /* Nop */ }
+        E::Lit(_) | E::Path(_) | E::Field(_) => Ok(()),
 
         // assignments
         expression @ E::PlusAssign(_)
@@ -445,23 +1113,18 @@ pub fn compile_expr_void<B: ByteOrder>(
                 dst_base,
                 register_alloc,
                 statements,
-            );
+            )
         }
-        _ => todo!(),
+        _ => Err(unsupported(expression, "Expression statement")),
     }
 }
 
 // TODO remove/replace code below
 
-#[deprecated]
-fn path_to_symbol_name(path: &Path<'_>) -> String {
-    let mut items = path.iter();
-    let name = items.next().unwrap().to_string();
-    items.fold(name, |mut o, ident| {
-        o.push_str("::");
-        o.push_str(&ident.to_string());
-        o
-    })
+// Error for a valid construct the compiler doesn't support yet.
+fn unsupported(expression: &Expression<'_>, what: &str) -> SemanticError {
+    let message = format!("{} not yet supported", what);
+    SemanticError::new(expression.span(), message)
 }
 
 // compile computation of the given expression and store the result in the given
@@ -475,50 +1138,99 @@ pub fn compile_expression_into_pointer<B: ByteOrder>(
     dst_base: Pointer,
     register_alloc: &mut RegisterAlloc,
     statements: &mut Vec<Statement>,
-) {
+) -> Result<()> {
     macro_rules! arithmetic_match_branch {
-        ($node:expr, $var:ident) => {{
+        ($node:expr, $var:ident) => {
+            arithmetic_match_branch!($node, $var, $var)
+        };
+        ($node:expr, $var:ident, $var_s:ident) => {{
             let left = compile_expr_u8(
                 &$node.inner.left,
                 symbol_alloc,
                 fn_alloc,
                 register_alloc,
                 statements,
-            );
+            )?;
             let right = compile_expr_u8(
                 &$node.inner.right,
                 symbol_alloc,
                 fn_alloc,
                 register_alloc,
                 statements,
-            );
+            )?;
             free_source_registers(&left, register_alloc);
             free_source_registers(&right, register_alloc);
-            statements.push($var {
-                left,
-                right,
-                destination: Destination::Pointer {
-                    base: dst_base,
-                    offset: None,
-                },
-            });
+            let destination = Destination::Pointer {
+                base: dst_base,
+                offset: None,
+            };
+            if layout == &Layout::I8 {
+                statements.push($var_s {
+                    left,
+                    right,
+                    destination,
+                });
+            } else {
+                statements.push($var {
+                    left,
+                    right,
+                    destination,
+                });
+            }
         }};
     }
 
     use super::Statement::{
-        Add, And, Div, Eq, Greater, GreaterEq, Ld, LdAddr, LdW, LeftShift, Less, LessEq, Mul,
-        NotEq, Or, RightShift, Sub, Xor,
+        Add, And, Div, DivS, Ld, LdAddr, LdW, LeftShift, Mul, Or, RightShift, RightShiftS, Sub, Xor,
     };
 
+    // 16bit values are computed in a single LdW (addresses are loaded directly)
+    if is_word_layout(layout)
+        && !matches!(expression, Expression::Call(_) | Expression::AddressOf(_))
+    {
+        #[rustfmt::skip] let source = compile_expr_u16(expression, symbol_alloc, fn_alloc, register_alloc, statements)?;
+        free_source_registers(&source, register_alloc);
+        statements.push(LdW {
+            source,
+            destination: Destination::Pointer {
+                base: dst_base,
+                offset: None,
+            },
+        });
+        return Ok(());
+    }
+
     match expression {
+        // strings are copied byte by byte
+        Expression::Lit(lit) if charmap::quoted(lit) == Some(Quoted::String) => {
+            let bytes = constant::string(lit, Some(symbol_alloc))?.unwrap();
+            let found = Layout::Array {
+                inner: Box::new(Layout::U8),
+                len: bytes.len() as u16,
+            };
+            if layout != &found {
+                return Err(mismatch(expression, layout, &found));
+            }
+            for (offset, byte) in bytes.into_iter().enumerate() {
+                statements.push(Ld {
+                    source: Source::Literal(byte),
+                    destination: Destination::Pointer {
+                        base: dst_base.offset(offset as u16),
+                        offset: None,
+                    },
+                });
+            }
+        }
         // compile literal expression by simply move a literal value unto the stack address.
-        // the size must be either a u8 or a u16 at this point. Any other value is wrong and the
-        // compiler frontend should've caught it by now, hence the panic.
+        // the size must be either a u8 or a u16 at this point.
         expr @ Expression::Lit(_) => {
-            let lit = const_expr(expr, Some(symbol_alloc)).unwrap();
+            let lit = const_expr(expr, Some(symbol_alloc))?.unwrap();
             match layout {
-                Layout::U8 => {
-                    assert!(lit <= 0xff);
+                Layout::U8 | Layout::I8 => {
+                    if lit > 0xff {
+                        let message = format!("Literal out of range for `{}`", layout);
+                        return Err(SemanticError::new(expr.span(), message));
+                    }
                     statements.push(Ld {
                         source: Source::Literal(lit as u8),
                         destination: Destination::Pointer {
@@ -527,23 +1239,14 @@ pub fn compile_expression_into_pointer<B: ByteOrder>(
                         },
                     });
                 }
-                Layout::I8 => unimplemented!("TODO i8"),
-                Layout::Pointer(_) => statements.push(LdW {
-                    source: Source::Literal(lit),
-                    destination: Destination::Pointer {
-                        base: dst_base,
-                        offset: None,
-                    },
-                }),
-                _ => panic!(),
+                _ => return Err(expected(expr, layout)),
             }
         }
         Expression::Path(path) => {
-            let name = path_to_symbol_name(path);
-            let symbol = symbol_alloc.get(&name);
-            // fallibility should be implemented in the frontend. If it panics here, it has
-            // to be a bug.
-            assert_eq!(layout, &symbol.layout);
+            let symbol = symbol_alloc.get(path)?;
+            if layout != &symbol.layout {
+                return Err(mismatch(expression, layout, &symbol.layout));
+            }
 
             // byte by byte copy
             // TODO consider using a loop if the type is too large later on if
@@ -571,7 +1274,14 @@ pub fn compile_expression_into_pointer<B: ByteOrder>(
         }
         Expression::Array(value) => match layout {
             Layout::Array { inner, len } => {
-                assert_eq!(*len as usize, value.inner.len());
+                if *len as usize != value.inner.len() {
+                    let message = format!(
+                        "Expected an array of {} items, found {}",
+                        len,
+                        value.inner.len()
+                    );
+                    return Err(SemanticError::new(value.span(), message));
+                }
 
                 let array_type_size = inner.size();
 
@@ -585,21 +1295,65 @@ pub fn compile_expression_into_pointer<B: ByteOrder>(
                         dst_base.offset(offset),
                         register_alloc,
                         statements,
-                    );
+                    )?;
                 }
             }
-            _ => panic!(),
+            _ => return Err(expected(expression, layout)),
+        },
+        Expression::Struct(struct_) => match layout {
+            Layout::Struct(_) | Layout::Union(_) => {
+                if let Layout::Struct(fields) = layout {
+                    if let Some(field) = fields.iter().find(|field| {
+                        !struct_
+                            .fields
+                            .iter()
+                            .any(|f| f.ident.to_string() == field.name)
+                    }) {
+                        let message =
+                            format!("Missing field `{}` in struct initializer", field.name);
+                        return Err(SemanticError::new(struct_.span(), message));
+                    }
+                }
+                for init in &struct_.fields {
+                    let field = layout.field(&init.ident.to_string()).ok_or_else(|| {
+                        let message = format!("No field `{}` on type `{}`", init.ident, layout);
+                        SemanticError::new(init.ident.span(), message)
+                    })?;
+                    compile_expression_into_pointer(
+                        &init.expression,
+                        &field.layout,
+                        symbol_alloc,
+                        fn_alloc,
+                        dst_base.offset(field.offset),
+                        register_alloc,
+                        statements,
+                    )?;
+                }
+            }
+            _ => return Err(expected(expression, layout)),
         },
-        Expression::Minus(_) => {}
+        Expression::Minus(_) => {
+            #[rustfmt::skip] let source = compile_expr_u8(expression, symbol_alloc, fn_alloc, register_alloc, statements)?;
+            free_source_registers(&source, register_alloc);
+            statements.push(Ld {
+                source,
+                destination: Destination::Pointer {
+                    base: dst_base,
+                    offset: None,
+                },
+            });
+        }
         Expression::AddressOf(address_of) => match layout {
             Layout::Pointer(ptr) => {
                 match &address_of.inner {
                     Expression::Path(path) => {
-                        let name = path_to_symbol_name(path);
-                        let symbol = symbol_alloc.get(&name);
+                        let symbol = symbol_alloc.get(path)?;
 
                         // check layouts
-                        assert_eq!(ptr.as_ref(), &symbol.layout);
+                        if ptr.as_ref() != &symbol.layout {
+                            let found = Layout::Pointer(Box::new(symbol.layout.clone()));
+                            return Err(mismatch(expression, layout, &found));
+                        }
 
                         let source_ptr = match symbol.memory_space {
                             SymbolMemorySpace::Stack => Pointer::Stack(symbol.offset),
@@ -618,76 +1372,64 @@ pub fn compile_expression_into_pointer<B: ByteOrder>(
                             },
                         });
                     }
-                    Expression::Index(index) => {
-                        match &index.inner.right {
-                            Expression::Path(path) => {
-                                let name = path_to_symbol_name(path);
-                                let symbol = symbol_alloc.get(&name);
-
-                                // TODO fix lint
-                                #[allow(unused)]
-                                if let Layout::Array { inner, len } = &symbol.layout {
-                                    assert_eq!(ptr, inner);
-
-                                    // TODO extend to non-constant expression indices
-                                    let type_size = inner.size();
-                                    let offset_const_expr =
-                                        const_expr(&index.inner.left, Some(symbol_alloc)).unwrap();
-                                    let offset = symbol.offset + type_size * offset_const_expr;
-
-                                    let source_ptr = match symbol.memory_space {
-                                        SymbolMemorySpace::Static => Pointer::Stack(offset),
-                                        SymbolMemorySpace::Const => Pointer::Const(offset),
-                                        SymbolMemorySpace::Stack => Pointer::Stack(offset),
-                                        SymbolMemorySpace::Absolute => Pointer::Absolute(offset),
-                                    };
-                                    statements.push(LdAddr {
-                                        source: Source::Pointer {
-                                            base: source_ptr,
-                                            offset: None,
-                                        },
-                                        destination: Destination::Pointer {
-                                            base: dst_base,
-                                            offset: None,
-                                        },
-                                    });
-                                } else {
-                                    panic!()
-                                }
-                            }
-                            _ => unimplemented!(),
+                    inner
+                    @ (Expression::Index(_) | Expression::Field(_) | Expression::Deref(_)) => {
+                        #[rustfmt::skip] let (destination, place_layout) = compile_place(inner, symbol_alloc, fn_alloc, register_alloc, statements)?;
+                        free_destination_registers(&destination, register_alloc);
+                        if ptr.as_ref() != &place_layout {
+                            let found = Layout::Pointer(Box::new(place_layout));
+                            return Err(mismatch(expression, layout, &found));
                         }
+                        statements.push(LdAddr {
+                            source: destination_to_source(&destination),
+                            destination: Destination::Pointer {
+                                base: dst_base,
+                                offset: None,
+                            },
+                        });
                     }
                     // TODO generalise (allow taking a pointer of something other than just a
                     // symbol)
-                    _ => unimplemented!(),
+                    inner => return Err(unsupported(inner, "Taking the address of an expression")),
                 }
             }
-            _ => panic!(),
+            _ => return Err(expected(expression, layout)),
         },
-        Expression::Deref(_) => {}
-        Expression::Not(_) => {}
+        Expression::Not(_) => return Err(unsupported(expression, "Bitwise not")),
 
         // binary expressions
         Expression::Add(node) => arithmetic_match_branch!(node, Add),
         Expression::Sub(node) => arithmetic_match_branch!(node, Sub),
         Expression::Mul(node) => arithmetic_match_branch!(node, Mul),
-        Expression::Div(node) => arithmetic_match_branch!(node, Div),
+        Expression::Div(node) => arithmetic_match_branch!(node, Div, DivS),
         Expression::And(node) => arithmetic_match_branch!(node, And),
         Expression::Or(node) => arithmetic_match_branch!(node, Or),
         Expression::Xor(node) => arithmetic_match_branch!(node, Xor),
         Expression::LeftShift(node) => arithmetic_match_branch!(node, LeftShift),
-        Expression::RightShift(node) => arithmetic_match_branch!(node, RightShift),
+        Expression::RightShift(node) => arithmetic_match_branch!(node, RightShift, RightShiftS),
 
-        // boolean
-        Expression::Eq(node) => arithmetic_match_branch!(node, Eq),
-        Expression::NotEq(node) => arithmetic_match_branch!(node, NotEq),
-        Expression::Greater(node) => arithmetic_match_branch!(node, Greater),
-        Expression::GreaterEq(node) => arithmetic_match_branch!(node, GreaterEq),
-        Expression::Less(node) => arithmetic_match_branch!(node, Less),
-        Expression::LessEq(node) => arithmetic_match_branch!(node, LessEq),
-
-        // assignment (these return void, so panic)
+        // boolean (the operands may have a different layout than the result)
+        Expression::Eq(_)
+        | Expression::NotEq(_)
+        | Expression::Greater(_)
+        | Expression::GreaterEq(_)
+        | Expression::Less(_)
+        | Expression::LessEq(_)
+        | Expression::LogicalAnd(_)
+        | Expression::LogicalOr(_)
+        | Expression::LogicalNot(_) => {
+            #[rustfmt::skip] let source = compile_expr_u8(expression, symbol_alloc, fn_alloc, register_alloc, statements)?;
+            free_source_registers(&source, register_alloc);
+            statements.push(Ld {
+                source,
+                destination: Destination::Pointer {
+                    base: dst_base,
+                    offset: None,
+                },
+            });
+        }
+
+        // assignment (these return void)
         Expression::Assign(_)
         | Expression::PlusAssign(_)
         | Expression::MinusAssign(_)
@@ -695,52 +1437,53 @@ pub fn compile_expression_into_pointer<B: ByteOrder>(
         | Expression::DivAssign(_)
         | Expression::AndAssign(_)
         | Expression::OrAssign(_)
-        | Expression::XorAssign(_) => panic!(),
-
-        // TODO reimplement (only works for [u8 N] atm)
-        Expression::Index(index) => {
-            match &index.inner.right {
-                Expression::Path(path) => {
-                    let name = path_to_symbol_name(path);
-                    let symbol = symbol_alloc.get(&name);
-
-                    //assert_eq!(&Layout::Array {}, &symbol.layout);
+        | Expression::XorAssign(_) => {
+            let message = "Assignments don't evaluate to a value";
+            return Err(SemanticError::new(expression.span(), message));
+        }
 
-                    // compute offset
-                    let offset = compile_expr_u8(
-                        &index.inner.left,
-                        symbol_alloc,
-                        fn_alloc,
-                        register_alloc,
-                        statements,
-                    );
-                    free_source_registers(&offset, register_alloc);
-                    let base = match symbol.memory_space {
-                        SymbolMemorySpace::Static => Pointer::Static(symbol.offset),
-                        SymbolMemorySpace::Const => Pointer::Const(symbol.offset),
-                        SymbolMemorySpace::Stack => Pointer::Stack(symbol.offset),
-                        SymbolMemorySpace::Absolute => Pointer::Absolute(symbol.offset),
-                    };
-                    statements.push(Ld {
-                        source: Source::Pointer {
-                            base,
-                            offset: Some(Box::new(offset)),
-                        },
-                        destination: Destination::Pointer {
-                            base: dst_base,
-                            offset: None,
-                        },
-                    });
-                }
-                _ => unimplemented!(),
+        // array indexing, struct fields & pointer dereference
+        Expression::Index(_) | Expression::Field(_) | Expression::Deref(_) => {
+            #[rustfmt::skip] let (destination, place_layout) = compile_place(expression, symbol_alloc, fn_alloc, register_alloc, statements)?;
+            free_destination_registers(&destination, register_alloc);
+            if layout != &place_layout {
+                return Err(mismatch(expression, layout, &place_layout));
+            }
+            let (src_base, src_offset) = match destination {
+                Destination::Pointer { base, offset } => (base, offset),
+                Destination::Register(_) => unreachable!(),
+            };
+            // byte by byte copy
+            for offset in 0..layout.size() {
+                let source = Source::Pointer {
+                    base: src_base.offset(offset),
+                    offset: src_offset.clone(),
+                };
+                let destination = Destination::Pointer {
+                    base: dst_base.offset(offset),
+                    offset: None,
+                };
+                statements.push(Ld {
+                    source,
+                    destination,
+                });
             }
         }
         Expression::Call(call) => match &call.inner.left {
             Expression::Path(ident) => {
-                let (fn_, routine) = fn_alloc.get(&path_to_symbol_name(ident));
+                let (fn_, routine) = fn_alloc.get(ident, symbol_alloc.namespace())?;
 
                 // check that the function returns the type we're trying to compile!
-                //assert_eq!(fn_.ret_layout.as_ref(), Some(layout));
+                // (calls made as statements discard the value into an empty array)
+                match &fn_.ret_layout {
+                    _ if layout.size() == 0 => {}
+                    Some(ret_layout) if ret_layout == layout => {}
+                    Some(ret_layout) => return Err(mismatch(expression, layout, ret_layout)),
+                    None => {
+                        return Err(expected(expression, layout)
+                            .note("The function doesn't return a value"))
+                    }
+                }
 
                 let args_call = &call.inner.args;
                 let args_layout = &fn_.arg_layout;
@@ -752,7 +1495,14 @@ pub fn compile_expression_into_pointer<B: ByteOrder>(
                     offset: None,
                 });
 
-                assert_eq!(args_call.len(), args_layout.len());
+                if args_call.len() != args_layout.len() {
+                    let message = format!(
+                        "Expected {} argument(s), found {}",
+                        args_layout.len(),
+                        args_call.len()
+                    );
+                    return Err(SemanticError::new(call.span(), message));
+                }
 
                 let mut offset = 0;
                 let start = symbol_alloc.stack_address()
@@ -767,7 +1517,7 @@ pub fn compile_expression_into_pointer<B: ByteOrder>(
                         dst_base.offset(offset),
                         register_alloc,
                         statements,
-                    );
+                    )?;
                     offset += arg_layout.size();
                 }
 
@@ -791,27 +1541,26 @@ pub fn compile_expression_into_pointer<B: ByteOrder>(
                     });
                 }
             }
-            _ => panic!(),
+            left => {
+                let message = "Expected a function name";
+                return Err(SemanticError::new(left.span(), message));
+            }
         },
     }
+    Ok(())
 }
 
-#[cfg(test)]
-mod test {
-    use crate::{byteorder::NativeEndian, parser::ast};
-
-    #[test]
-    fn const_expr() {
-        fn ast(input: &str) -> ast::Expression<'_> {
-            let mut ctx = crate::parser::ContextBuilder::default().build();
-            let mut tokens = crate::parser::lex::Tokens::new(input).peekable();
-            crate::parser::ast::Grammar::parse(&mut ctx, &mut tokens)
-                .expect("Error parsing constant expression")
-        }
+// Error for an expression that doesn't evaluate to the expected layout.
+fn mismatch(expression: &Expression<'_>, expected: &Layout, found: &Layout) -> SemanticError {
+    let message = format!(
+        "Mismatched types: expected `{}`, found `{}`",
+        expected, found
+    );
+    SemanticError::new(expression.span(), message)
+}
 
-        assert_eq!(
-            Some(0x42),
-            super::const_expr::<NativeEndian>(&ast("(+ 0x40 2)"), None)
-        );
-    }
+// Same as `mismatch`, for expressions with an unknown layout.
+fn expected(expression: &Expression<'_>, expected: &Layout) -> SemanticError {
+    let message = format!("Expected an expression of type `{}`", expected);
+    SemanticError::new(expression.span(), message)
 }
@@ -1,8 +1,13 @@
 use crate::{
-    ir::compile::expression::const_expr,
-    parser::{ast, ast::Type},
+    ir::compile::{constant::const_expr, Result, SemanticError},
+    parser::{
+        ast,
+        ast::{Path, Type},
+        lex::span::Spanned,
+    },
 };
 use byteorder::NativeEndian;
+use std::fmt;
 
 const BYTE_SIZE: u16 = 1;
 const WORD_SIZE: u16 = 2;
@@ -16,6 +21,12 @@ pub enum Layout {
     /// Signed 8bit byte layout.
     I8,
 
+    /// Unsigned 16bit word layout.
+    U16,
+
+    /// Signed 16bit word layout.
+    I16,
+
     /// Array layout.
     Array {
         /// Array inner type layout.
@@ -29,47 +40,141 @@ pub enum Layout {
     Pointer(Box<Layout>),
 
     /// Struct memory layout.
-    Struct(Vec<Layout>),
+    Struct(Vec<Field>),
+
+    /// Union memory layout.
+    Union(Vec<Field>),
+}
+
+/// Struct & union field.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Field {
+    /// Field name.
+    pub name: String,
+
+    /// Offset from the beginning of the struct (always 0 for union fields).
+    pub offset: u16,
 
-    /// Enum memory layout.
-    Union(Vec<Layout>),
+    /// Field layout.
+    pub layout: Layout,
 }
 
 impl Layout {
     /// Create type layout from a type from the AST.
-    pub fn new(ty: &ast::Type<'_>) -> Self {
-        match ty {
-            Type::U8(_) => Self::U8,
+    /// Fails if the type refers to a named type (see [`with_types`]).
+    ///
+    /// [`with_types`]: #method.with_types
+    pub fn new(ty: &ast::Type<'_>) -> Result<Self> {
+        Self::with_types(ty, &|path| {
+            let message = "Named types not supported in this context";
+            Err(SemanticError::new(path.span(), message))
+        })
+    }
+
+    /// Create type layout from a type from the AST, resolving named types
+    /// with the given function.
+    pub fn with_types<F>(ty: &ast::Type<'_>, types: &F) -> Result<Self>
+    where
+        F: Fn(&Path<'_>) -> Result<Self>,
+    {
+        let fields = |fields: &[ast::Field<'_>]| {
+            fields
+                .iter()
+                .map(|f| Ok((f.ident.to_string(), Self::with_types(&f.type_, types)?)))
+                .collect::<Result<Vec<_>>>()
+        };
+        Ok(match ty {
+            Type::U8(_) | Type::Bool(_) => Self::U8,
             Type::I8(_) => Self::I8,
+            Type::U16(_) => Self::U16,
+            Type::I16(_) => Self::I16,
             Type::Array(array) => {
-                let inner = Box::new(Self::new(&array.type_));
-                let len = const_expr::<NativeEndian>(&array.len, None).unwrap();
+                let inner = Box::new(Self::with_types(&array.type_, types)?);
+                let len = const_expr::<NativeEndian>(&array.len, None)?.ok_or_else(|| {
+                    SemanticError::new(
+                        array.len.span(),
+                        "Array length must be a constant expression",
+                    )
+                })?;
                 Self::Array { inner, len }
             }
             Type::Pointer(ptr) => {
-                let ptr = Box::new(Self::new(&ptr.type_));
+                let ptr = Box::new(Self::with_types(&ptr.type_, types)?);
                 Self::Pointer(ptr)
             }
-            Type::Struct(struct_) => {
-                let struct_ = struct_.fields.iter().map(|f| Self::new(&f.type_)).collect();
-                Self::Struct(struct_)
-            }
-            Type::Union(union) => {
-                let union = union.fields.iter().map(|f| Self::new(&f.type_)).collect();
-                Self::Union(union)
-            }
-            _ => panic!("Type noy yet supported!"),
+            Type::Struct(struct_) => Self::struct_(fields(&struct_.fields)?),
+            Type::Union(union) => Self::union(fields(&union.fields)?),
+            Type::Path(path) => types(path)?,
+        })
+    }
+
+    /// Create struct layout, with the fields laid out in order.
+    pub fn struct_<N: Into<String>>(fields: impl IntoIterator<Item = (N, Self)>) -> Self {
+        let mut offset = 0;
+        let fields = fields.into_iter().map(|(name, layout)| {
+            let field = Field {
+                name: name.into(),
+                offset,
+                layout,
+            };
+            offset += field.layout.size();
+            field
+        });
+        Self::Struct(fields.collect())
+    }
+
+    /// Create union layout, with all the fields overlapping.
+    pub fn union<N: Into<String>>(fields: impl IntoIterator<Item = (N, Self)>) -> Self {
+        let fields = fields.into_iter().map(|(name, layout)| Field {
+            name: name.into(),
+            offset: 0,
+            layout,
+        });
+        Self::Union(fields.collect())
+    }
+
+    /// Returns the field of a struct or union layout by name.
+    pub fn field(&self, name: &str) -> Option<&Field> {
+        match self {
+            Self::Struct(fields) | Self::Union(fields) => fields.iter().find(|f| f.name == name),
+            _ => None,
         }
     }
 
     /// Compute size of the type layout.
     pub fn size(&self) -> u16 {
         match self {
-            Layout::U8 | Layout::I8 => BYTE_SIZE,
-            Layout::Pointer(_) => WORD_SIZE,
-            Layout::Array { inner, len } => len * inner.size(),
-            Layout::Struct(inner) => inner.iter().fold(0, |o, l| o + l.size()),
-            Layout::Union(inner) => inner.iter().fold(0, |o, l| l.size().max(o)),
+            Self::U8 | Self::I8 => BYTE_SIZE,
+            Self::U16 | Self::I16 | Self::Pointer(_) => WORD_SIZE,
+            Self::Array { inner, len } => len * inner.size(),
+            Self::Struct(fields) => fields.iter().fold(0, |o, f| o + f.layout.size()),
+            Self::Union(fields) => fields.iter().fold(0, |o, f| f.layout.size().max(o)),
+        }
+    }
+}
+
+/// Formatted using the type syntax.
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::U8 => write!(f, "u8"),
+            Self::I8 => write!(f, "i8"),
+            Self::U16 => write!(f, "u16"),
+            Self::I16 => write!(f, "i16"),
+            Self::Array { inner, len } => write!(f, "[{} {}]", inner, len),
+            Self::Pointer(inner) => write!(f, "&{}", inner),
+            Self::Struct(fields) | Self::Union(fields) => {
+                let kind = if let Self::Struct(_) = self {
+                    "struct"
+                } else {
+                    "union"
+                };
+                write!(f, "{} {{", kind)?;
+                for field in fields {
+                    write!(f, " {}:{}", field.name, field.layout)?;
+                }
+                write!(f, " }}")
+            }
         }
     }
 }
@@ -90,10 +195,26 @@ mod test {
                 inner: Box::new(Layout::Pointer(Box::new(Layout::U8))),
                 len: 4
             },
-            Layout::new(&type_)
+            Layout::new(&type_).unwrap()
         );
     }
 
+    #[test]
+    fn display() {
+        let layout = Layout::struct_(vec![
+            ("a", Layout::Pointer(Box::new(Layout::I8))),
+            (
+                "b",
+                Layout::Array {
+                    inner: Box::new(Layout::U8),
+                    len: 4,
+                },
+            ),
+            ("c", Layout::Union(vec![])),
+        ]);
+        assert_eq!("struct { a:&i8 b:[u8 4] c:union { } }", layout.to_string());
+    }
+
     #[test]
     fn zero_size_types() {
         assert_eq!(
@@ -118,16 +239,34 @@ mod test {
         assert_eq!(1, Layout::I8.size());
     }
 
+    #[test]
+    fn size_u16() {
+        assert_eq!(2, Layout::U16.size());
+    }
+
+    #[test]
+    fn size_i16() {
+        assert_eq!(2, Layout::I16.size());
+    }
+
     #[test]
     fn test_pointer() {
         assert_eq!(2, Layout::Pointer(Box::new(Layout::U8)).size());
         assert_eq!(
             2,
-            Layout::Pointer(Box::new(Layout::Struct(vec![Layout::U8, Layout::I8]))).size()
+            Layout::Pointer(Box::new(Layout::struct_(vec![
+                ("a", Layout::U8),
+                ("b", Layout::I8)
+            ])))
+            .size()
         );
         assert_eq!(
             2,
-            Layout::Pointer(Box::new(Layout::Struct(vec![Layout::Struct(vec![])]))).size()
+            Layout::Pointer(Box::new(Layout::struct_(vec![(
+                "a",
+                Layout::Struct(vec![])
+            )])))
+            .size()
         );
         assert_eq!(
             2,
@@ -218,17 +357,23 @@ mod test {
     #[test]
     fn test_struct() {
         assert_eq!(0, Layout::Struct(vec![]).size());
-        assert_eq!(1, Layout::Struct(vec![Layout::U8]).size());
-        assert_eq!(2, Layout::Struct(vec![Layout::U8, Layout::I8]).size());
+        assert_eq!(1, Layout::struct_(vec![("a", Layout::U8)]).size());
+        assert_eq!(
+            2,
+            Layout::struct_(vec![("a", Layout::U8), ("b", Layout::I8)]).size()
+        );
         assert_eq!(
             6,
-            Layout::Struct(vec![
-                Layout::U8,
-                Layout::I8,
-                Layout::Array {
-                    inner: Box::new(Layout::U8),
-                    len: 4,
-                }
+            Layout::struct_(vec![
+                ("a", Layout::U8),
+                ("b", Layout::I8),
+                (
+                    "c",
+                    Layout::Array {
+                        inner: Box::new(Layout::U8),
+                        len: 4,
+                    }
+                )
             ])
             .size()
         );
@@ -237,19 +382,54 @@ mod test {
     #[test]
     fn test_union() {
         assert_eq!(0, Layout::Union(vec![]).size());
-        assert_eq!(1, Layout::Union(vec![Layout::U8]).size());
-        assert_eq!(1, Layout::Union(vec![Layout::U8, Layout::I8]).size());
+        assert_eq!(1, Layout::union(vec![("a", Layout::U8)]).size());
+        assert_eq!(
+            1,
+            Layout::union(vec![("a", Layout::U8), ("b", Layout::I8)]).size()
+        );
         assert_eq!(
             4,
-            Layout::Union(vec![
-                Layout::U8,
-                Layout::I8,
-                Layout::Array {
-                    inner: Box::new(Layout::U8),
-                    len: 4,
-                }
+            Layout::union(vec![
+                ("a", Layout::U8),
+                ("b", Layout::I8),
+                (
+                    "c",
+                    Layout::Array {
+                        inner: Box::new(Layout::U8),
+                        len: 4,
+                    }
+                )
             ])
             .size()
         );
     }
+
+    #[test]
+    fn fields() {
+        let layout = Layout::struct_(vec![
+            ("x", Layout::U8),
+            ("y", Layout::U16),
+            (
+                "z",
+                Layout::union(vec![("a", Layout::U8), ("b", Layout::I16)]),
+            ),
+        ]);
+        let offsets: Vec<_> = ["x", "y", "z"]
+            .iter()
+            .map(|name| layout.field(name).unwrap().offset)
+            .collect();
+        assert_eq!(vec![0, 1, 3], offsets);
+        assert_eq!(5, layout.size());
+        assert_eq!(
+            Some(0),
+            layout
+                .field("z")
+                .unwrap()
+                .layout
+                .field("b")
+                .map(|f| f.offset)
+        );
+        assert!(layout.field("w").is_none());
+        assert!(Layout::U8.field("x").is_none());
+    }
 }
@@ -0,0 +1,158 @@
+//! Conversion of images into Game Boy tile data.
+//!
+//! Images are split into 8x8 tiles (left to right, top to bottom), which are
+//! encoded in the 2 bits per pixel format of the Game Boy: 16 bytes per tile,
+//! 2 bytes per row of pixels. The first byte holds the low bit of the color
+//! of every pixel in the row (leftmost pixel in the most significant bit), and
+//! the second byte holds the high bit.
+
+/// Tile data of an image.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct Tiles {
+    /// 2bpp tile data.
+    pub data: Vec<u8>,
+
+    /// Tile index of each 8x8 block of the image (left to right, top to
+    /// bottom), if requested.
+    pub map: Option<Vec<u8>>,
+}
+
+/// Decode a 4-color PNG image into tile data.
+///
+/// Indexed images use the palette index of a pixel as its color (so the
+/// palette can't have more than 4 colors). Grayscale images are mapped to the
+/// 4 shades of the Game Boy, where color 0 is white and color 3 is black.
+///
+/// If `map` is set, repeated tiles are stored only once, and the tilemap of the
+/// image is returned along with the tile data.
+#[cfg(feature = "png")]
+pub(crate) fn from_png(png: &[u8], map: bool) -> Result<Tiles, String> {
+    use png::{BitDepth, ColorType, Decoder, Transformations};
+
+    let mut decoder = Decoder::new(png);
+    decoder.set_transformations(Transformations::IDENTITY);
+    let mut reader = decoder.read_info().map_err(|err| err.to_string())?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut buf).map_err(|err| err.to_string())?;
+
+    let bits = match frame.bit_depth {
+        BitDepth::One => 1,
+        BitDepth::Two => 2,
+        BitDepth::Four => 4,
+        BitDepth::Eight => 8,
+        BitDepth::Sixteen => return Err("16 bit images are not supported".to_string()),
+    };
+    let (width, height) = (frame.width as usize, frame.height as usize);
+    let mut pixels = Vec::with_capacity(width * height);
+    for line in buf.chunks(frame.line_size).take(height) {
+        for x in 0..width {
+            let bit = x * bits;
+            let value = (line[bit / 8] >> (8 - bits - bit % 8)) & (0xff >> (8 - bits));
+            let color = match frame.color_type {
+                ColorType::Indexed if value > 3 => {
+                    return Err(format!("Palette index {} out of range (max is 3)", value));
+                }
+                ColorType::Indexed => value,
+                ColorType::Grayscale if bits == 1 => 3 * (1 - value),
+                ColorType::Grayscale => 3 - (value >> (bits - 2)),
+                _ => return Err("Expected an indexed or grayscale image".to_string()),
+            };
+            pixels.push(color);
+        }
+    }
+    tiles(width, height, &pixels, map)
+}
+
+#[cfg(not(feature = "png"))]
+pub(crate) fn from_png(_: &[u8], _: bool) -> Result<Tiles, String> {
+    Err("PNG images are not supported (missing `png` feature)".to_string())
+}
+
+/// Encode an image (one color from 0 to 3 per pixel, row by row) into tile
+/// data.
+fn tiles(width: usize, height: usize, pixels: &[u8], map: bool) -> Result<Tiles, String> {
+    if width & 7 != 0 || height & 7 != 0 {
+        let message = format!("Image size ({}x{}) is not a multiple of 8", width, height);
+        return Err(message);
+    }
+    let mut tiles: Vec<[u8; 16]> = Vec::new();
+    let mut indices = Vec::new();
+    for tile_y in (0..height).step_by(8) {
+        for tile_x in (0..width).step_by(8) {
+            let mut tile = [0; 16];
+            for y in 0..8 {
+                for x in 0..8 {
+                    let color = pixels[(tile_y + y) * width + tile_x + x];
+                    tile[2 * y] |= (color & 1) << (7 - x);
+                    tile[2 * y + 1] |= (color >> 1) << (7 - x);
+                }
+            }
+            if !map {
+                tiles.push(tile);
+                continue;
+            }
+            let index = match tiles.iter().position(|t| *t == tile) {
+                Some(index) => index,
+                None => {
+                    tiles.push(tile);
+                    tiles.len() - 1
+                }
+            };
+            if index > 0xff {
+                return Err("Tilemaps can't index more than 256 tiles".to_string());
+            }
+            indices.push(index as u8);
+        }
+    }
+    Ok(Tiles {
+        data: tiles.concat(),
+        map: if map { Some(indices) } else { None },
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::tiles;
+
+    #[test]
+    fn encode() {
+        // tile with rows of every color, followed by a tile with a single black
+        // pixel in the last row
+        let mut pixels = vec![0; 16 * 8];
+        for (y, row) in pixels.chunks_mut(16).enumerate() {
+            row[..8].copy_from_slice(&[(y % 4) as u8; 8]);
+        }
+        pixels[16 * 7 + 8] = 3;
+        let tiles = tiles(16, 8, &pixels, false).unwrap();
+        assert_eq!(
+            vec![
+                0x00, 0x00, 0xff, 0x00, 0x00, 0xff, 0xff, 0xff, //
+                0x00, 0x00, 0xff, 0x00, 0x00, 0xff, 0xff, 0xff, //
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x80, //
+            ],
+            tiles.data
+        );
+        assert_eq!(None, tiles.map);
+    }
+
+    #[test]
+    fn dedup() {
+        // 8x24 image: blank, black, blank tiles
+        let mut pixels = vec![0; 8 * 24];
+        pixels[64..128].copy_from_slice(&[3; 64]);
+        let tiles = tiles(8, 24, &pixels, true).unwrap();
+        assert_eq!(32, tiles.data.len());
+        assert_eq!(&[0xff; 16], &tiles.data[16..]);
+        assert_eq!(Some(vec![0, 1, 0]), tiles.map);
+
+        let tiles = super::tiles(8, 24, &pixels, false).unwrap();
+        assert_eq!(48, tiles.data.len());
+        assert_eq!(None, tiles.map);
+    }
+
+    #[test]
+    fn size() {
+        assert!(tiles(8, 12, &[0; 8 * 12], false).is_err());
+    }
+}
@@ -3,6 +3,8 @@ use serde::{Deserialize, Serialize};
 use serde::{Deserializer, Serializer};
 use std::ops::{Range, RangeFrom};
 
+pub mod asm;
+
 /// Virtual memory address type.
 pub type Address = u16;
 
@@ -27,17 +29,22 @@ pub enum Pointer {
 
     /// Function return space.
     Return(Address),
+
+    /// Pointer to the address held in a 16bit virtual register, plus a
+    /// constant offset.
+    Indirect(Register, Address),
 }
 
 impl Pointer {
     pub(crate) fn offset(self, offset: Address) -> Self {
-        use Pointer::{Absolute, Const, Return, Stack, Static};
+        use Pointer::{Absolute, Const, Indirect, Return, Stack, Static};
         match self {
             Absolute(a) => Absolute(a + offset),
             Static(a) => Static(a + offset),
             Const(a) => Const(a + offset),
             Stack(a) => Stack(a + offset),
             Return(a) => Return(a + offset),
+            Indirect(r, a) => Indirect(r, a + offset),
         }
     }
 }
@@ -216,6 +223,27 @@ pub enum Statement {
         destination: Destination,
     },
 
+    /// 8bit signed divide.
+    DivS {
+        left: Source<u8>,
+        right: Source<u8>,
+        destination: Destination,
+    },
+
+    /// 8bit signed remainder.
+    RemS {
+        left: Source<u8>,
+        right: Source<u8>,
+        destination: Destination,
+    },
+
+    /// 8bit signed arithmetic right shift.
+    RightShiftS {
+        left: Source<u8>,
+        right: Source<u8>,
+        destination: Destination,
+    },
+
     /// 16bit add.
     AddW {
         left: Source<u16>,
@@ -286,6 +314,27 @@ pub enum Statement {
         destination: Destination,
     },
 
+    /// 16bit signed divide.
+    DivSW {
+        left: Source<u16>,
+        right: Source<u16>,
+        destination: Destination,
+    },
+
+    /// 16bit signed remainder.
+    RemSW {
+        left: Source<u16>,
+        right: Source<u16>,
+        destination: Destination,
+    },
+
+    /// 16bit signed arithmetic right shift.
+    RightShiftSW {
+        left: Source<u16>,
+        right: Source<u8>,
+        destination: Destination,
+    },
+
     /// 8bit boolean equals.
     Eq {
         left: Source<u8>,
@@ -328,6 +377,76 @@ pub enum Statement {
         destination: Destination,
     },
 
+    /// 8bit signed boolean greater-than.
+    GreaterS {
+        left: Source<u8>,
+        right: Source<u8>,
+        destination: Destination,
+    },
+
+    /// 8bit signed boolean greater-or-equal-than.
+    GreaterEqS {
+        left: Source<u8>,
+        right: Source<u8>,
+        destination: Destination,
+    },
+
+    /// 8bit signed boolean less-than.
+    LessS {
+        left: Source<u8>,
+        right: Source<u8>,
+        destination: Destination,
+    },
+
+    /// 8bit signed boolean less-or-equal-than.
+    LessEqS {
+        left: Source<u8>,
+        right: Source<u8>,
+        destination: Destination,
+    },
+
+    /// 16bit boolean equals.
+    EqW {
+        left: Source<u16>,
+        right: Source<u16>,
+        destination: Destination,
+    },
+
+    /// 16bit boolean not-equals.
+    NotEqW {
+        left: Source<u16>,
+        right: Source<u16>,
+        destination: Destination,
+    },
+
+    /// 16bit boolean greater-than.
+    GreaterW {
+        left: Source<u16>,
+        right: Source<u16>,
+        destination: Destination,
+    },
+
+    /// 16bit boolean greater-or-equal-than.
+    GreaterEqW {
+        left: Source<u16>,
+        right: Source<u16>,
+        destination: Destination,
+    },
+
+    /// 16bit boolean less-than.
+    LessW {
+        left: Source<u16>,
+        right: Source<u16>,
+        destination: Destination,
+    },
+
+    /// 16bit boolean less-or-equal-than.
+    LessEqW {
+        left: Source<u16>,
+        right: Source<u16>,
+        destination: Destination,
+    },
+
     /// Jump to location.
     Jmp { location: Location },
 
@@ -359,6 +478,9 @@ pub enum Statement {
 
     /// Return from routine.
     Ret,
+
+    /// Inline assembly block.
+    Asm(Vec<asm::Item>),
 }
 
 fn ser_range_from<S: Serializer>(range_from: &RangeFrom<u16>, ser: S) -> Result<S::Ok, S::Error> {
@@ -0,0 +1,162 @@
+//! Inline assembly instructions.
+//!
+//! Inline assembly is not interpreted by the IR. Instructions are passed along
+//! to the compilation target mostly as written in the source code, except for
+//! symbol & label references, which have been resolved.
+use crate::ir::opcodes::Pointer;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// 8bit CPU registers.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum R8 {
+    A,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+}
+
+/// 16bit CPU registers.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum R16 {
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+}
+
+/// Jump conditions.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Cond {
+    NZ,
+    Z,
+    NC,
+    C,
+}
+
+/// Instruction mnemonics.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Mnemonic {
+    Nop,
+    Stop,
+    Halt,
+    Di,
+    Ei,
+    Ld,
+    Ldh,
+    Push,
+    Pop,
+    Inc,
+    Dec,
+    Daa,
+    Scf,
+    Cpl,
+    Ccf,
+    Add,
+    Adc,
+    Sub,
+    Sbc,
+    And,
+    Xor,
+    Or,
+    Cp,
+    Jp,
+    Jr,
+    Call,
+    Ret,
+    Reti,
+    Rst,
+    Rlca,
+    Rla,
+    Rrca,
+    Rra,
+    Rlc,
+    Rrc,
+    Rl,
+    Rr,
+    Sla,
+    Sra,
+    Srl,
+    Swap,
+    Bit,
+    Res,
+    Set,
+}
+
+/// Immediate values.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Immediate {
+    /// Literal value.
+    Literal(u16),
+
+    /// Address of a symbol. The actual value is only known by the target.
+    Pointer(Pointer),
+}
+
+/// Memory operands.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Indirect {
+    /// `[%bc]`
+    BC,
+
+    /// `[%de]`
+    DE,
+
+    /// `[%hl]`
+    HL,
+
+    /// `[%hl +]`
+    HLInc,
+
+    /// `[%hl -]`
+    HLDec,
+
+    /// `[%c]` (`0xff00 + C`)
+    C,
+
+    /// `[<immediate>]`
+    Immediate(Immediate),
+}
+
+/// Instruction operands.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Operand {
+    R8(R8),
+    R16(R16),
+    Cond(Cond),
+
+    /// `%sp + e`
+    SpOffset(i8),
+
+    Indirect(Indirect),
+    Immediate(Immediate),
+
+    /// Label defined within the same block.
+    Label(usize),
+}
+
+/// Items of an inline assembly block.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Item {
+    /// Label definition. Labels are numbered in order of definition, starting
+    /// from zero, and are local to the block.
+    Label(usize),
+
+    /// Instruction.
+    Instruction {
+        mnemonic: Mnemonic,
+        operands: Vec<Operand>,
+    },
+}
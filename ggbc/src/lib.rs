@@ -10,9 +10,8 @@
     clippy::doc_markdown,
     clippy::dbg_macro,
     clippy::todo,
-    clippy::empty_enum,
+    clippy::empty_enums,
     clippy::enum_glob_use,
-    clippy::pub_enum_variant_names,
     clippy::mem_forget,
     clippy::use_self,
     clippy::filter_map_next,
@@ -26,9 +25,11 @@
 
 pub use byteorder;
 pub use parser;
+use parser::{source::AssetProvider, Diagnostic};
 use target::Target;
 use thiserror::Error;
 
+pub mod check;
 pub mod ir;
 pub mod target;
 
@@ -38,15 +39,71 @@ pub type Bytes = Box<[u8]>;
 #[derive(Error, Debug)]
 pub enum Error<'a, T: Target> {
     #[error("Parsing error")]
-    Parser(parser::Error<'a>),
+    Parser(Vec<parser::Error<'a>>),
+
+    #[error("Type error")]
+    Type(Vec<check::TypeError>),
+
+    #[error("{message}")]
+    Semantic {
+        /// Location of the error in the source code.
+        span: parser::lex::span::Span,
+
+        /// Error message.
+        message: String,
+
+        /// Additional notes on the error.
+        notes: Vec<String>,
+    },
 
     #[error("Codegen error")]
     Codegen(T::Error),
 }
 
-impl<'a, T: Target> From<parser::Error<'a>> for Error<'a, T> {
-    fn from(error: parser::Error<'a>) -> Self {
-        Self::Parser(error)
+impl<T: Target> Error<'_, T> {
+    /// Returns the diagnostics of the error, to be rendered against the
+    /// source code of the program.
+    ///
+    /// # Example
+    /// ```
+    /// use ggbc::target::LR35902;
+    ///
+    /// let input = "let a:u8 = (+ 1 b)";
+    /// let error = ggbc::compile::<LR35902>(input).unwrap_err();
+    /// for diagnostic in error.diagnostics() {
+    ///     eprint!("{}", diagnostic.render(input));
+    /// }
+    /// ```
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            Self::Parser(errors) => errors.iter().map(Diagnostic::from).collect(),
+            Self::Type(errors) => errors.iter().map(Diagnostic::from).collect(),
+            Self::Semantic {
+                span,
+                message,
+                notes,
+            } => {
+                let diagnostic = Diagnostic::new(message.clone()).span(*span);
+                vec![notes.iter().fold(diagnostic, |d, n| d.note(n.clone()))]
+            }
+            Self::Codegen(error) => vec![Diagnostic::new(error.to_string())],
+        }
+    }
+}
+
+impl<'a, T: Target> From<Vec<parser::Error<'a>>> for Error<'a, T> {
+    fn from(errors: Vec<parser::Error<'a>>) -> Self {
+        Self::Parser(errors)
+    }
+}
+
+impl<T: Target> From<ir::SemanticError> for Error<'_, T> {
+    fn from(error: ir::SemanticError) -> Self {
+        Self::Semantic {
+            span: error.span,
+            message: error.message,
+            notes: error.notes,
+        }
     }
 }
 
@@ -60,8 +117,60 @@ impl<'a, T: Target> From<parser::Error<'a>> for Error<'a, T> {
 /// let program = ggbc::compile::<LR35902>(include_str!("program.ggb")).unwrap();
 /// ```
 pub fn compile<T: Target>(input: &str) -> Result<T::Output, Error<'_, T>> {
-    let ast = parser::parse(input)?;
-    let mut ir = ir::Ir::new(&ast);
+    let (ast, errors) = parser::parse(input);
+    if !errors.is_empty() {
+        return Err(Error::Parser(errors));
+    }
+    check::check(&ast).map_err(Error::Type)?;
+    let mut ir = ir::Ir::new(&ast)?;
+    ir.optimize();
+    T::codegen(&ir).map_err(Error::Codegen)
+}
+
+/// Compile a program made of multiple source files.
+/// # Example
+/// ```
+/// use ggbc::{parser::source::FileSystem, target::LR35902};
+///
+/// // compile GB rom
+/// # #[cfg(well_actually_no)]
+/// let sources = ggbc::parser::source::load(&FileSystem::new("src/main.ggb")).unwrap();
+/// # #[cfg(well_actually_no)]
+/// let program = ggbc::compile_sources::<LR35902>(&sources).unwrap();
+/// ```
+pub fn compile_sources<T: Target>(
+    sources: &parser::source::Sources,
+) -> Result<T::Output, Error<'_, T>> {
+    compile_sources_with(sources, ir::IrBuilder::default())
+}
+
+/// Compile a program made of multiple source files, which may embed other
+/// files with `include` statements.
+/// # Example
+/// ```
+/// use ggbc::{parser::source::FileSystem, target::LR35902};
+///
+/// // compile GB rom
+/// let fs = FileSystem::new("src/main.ggb");
+/// # #[cfg(well_actually_no)]
+/// let sources = ggbc::parser::source::load(&fs).unwrap();
+/// # #[cfg(well_actually_no)]
+/// let program = ggbc::compile_sources_with_assets::<LR35902, _>(&sources, fs).unwrap();
+/// ```
+pub fn compile_sources_with_assets<T: Target, A: AssetProvider + 'static>(
+    sources: &parser::source::Sources,
+    assets: A,
+) -> Result<T::Output, Error<'_, T>> {
+    compile_sources_with(sources, ir::IrBuilder::default().assets(assets))
+}
+
+fn compile_sources_with<T: Target>(
+    sources: &parser::source::Sources,
+    builder: ir::IrBuilder,
+) -> Result<T::Output, Error<'_, T>> {
+    let modules = sources.parse()?;
+    check::check_modules(&modules).map_err(Error::Type)?;
+    let mut ir = builder.build_modules(&modules)?;
     ir.optimize();
     T::codegen(&ir).map_err(Error::Codegen)
 }
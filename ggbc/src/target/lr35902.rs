@@ -0,0 +1,131 @@
+//! LR35902 (Game Boy) CPU compilation target.
+//!
+//! # Memory map
+//! The virtual memory spaces of the IR are mapped into the Game Boy address
+//! space like so:
+//!
+//! - `Pointer::Const` is stored in ROM0, right after the cartridge header.
+//! - `Pointer::Static` is mapped to the beginning of WRAM (`0xc000`).
+//! - `Pointer::Stack` is relative to a frame pointer stored in HRAM. Stack
+//!   frames are allocated in WRAM right after the static memory, growing
+//!   upwards. The hardware stack (`SP`) grows downwards from the end of WRAM.
+//!   Interrupt handlers use a separate region of `INTERRUPT_STACK_SIZE` bytes,
+//!   located between the static memory and the rest of the stack frames.
+//! - `Pointer::Return` and the virtual registers are mapped to HRAM.
+//! - `Pointer::Absolute` pointers are left untouched.
+use crate::{
+    byteorder::LittleEndian,
+    ir::{
+        opcodes::{
+            asm::{Mnemonic, Operand},
+            Register,
+        },
+        Ir,
+    },
+    target::Target,
+    Bytes,
+};
+use asm::Label;
+pub use rom::{CartridgeType, Cgb, RamSize, Region, RomBuilder, CONST, ENTRY_POINT, ROM0_END};
+use thiserror::Error;
+
+mod asm;
+mod codegen;
+mod inline;
+mod rom;
+mod runtime;
+
+/// Upper bound (exclusive) of the cartridge ROM.
+pub const ROM_END: u16 = 0x8000;
+
+/// Beginning of the static memory space (WRAM).
+pub const STATIC: u16 = 0xc000;
+
+/// Upper bound (exclusive) of WRAM.
+pub const WRAM_END: u16 = 0xe000;
+
+/// Minimum amount of WRAM left for stack frames and the hardware stack.
+pub const MIN_STACK_SIZE: u16 = 0x100;
+
+/// Size of the WRAM region reserved for the stack frames of interrupt handlers.
+pub const INTERRUPT_STACK_SIZE: u16 = 0x80;
+
+/// Location of the stack frame pointer (HRAM, relative to `0xff00`).
+pub const FRAME_POINTER: u8 = 0x80;
+
+/// Location of the 8bit virtual registers (HRAM, relative to `0xff00`).
+pub const REGISTERS: u8 = 0x82;
+
+/// Location of the 16bit virtual registers (HRAM, relative to `0xff00`).
+pub const REGISTERS_W: u8 = REGISTERS + NUM_REGISTERS as u8;
+
+/// Location of the return memory space (HRAM, relative to `0xff00`).
+pub const RETURN: u8 = REGISTERS_W + 2 * NUM_REGISTERS as u8;
+
+/// Scratch location used by the interrupt handlers (HRAM, relative to
+/// `0xff00`).
+pub const INTERRUPT_TARGET: u8 = RETURN + RETURN_SIZE as u8;
+
+/// Number of virtual registers of each size mapped to HRAM.
+pub const NUM_REGISTERS: usize = 16;
+
+/// Size of the return memory space.
+pub const RETURN_SIZE: u16 = 16;
+
+/// LR35902 (Game Boy) CPU compilation target.
+#[derive(Debug)]
+#[warn(clippy::empty_enums)]
+pub enum LR35902 {}
+
+/// LR35902-codegen-related errors.
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Virtual register out of range: {0}")]
+    Register(Register),
+
+    #[error("Return memory out of range: {0}")]
+    Return(u16),
+
+    #[error("Jump out of the bounds of the routine")]
+    Jump,
+
+    #[error("Relative jump out of range: {0:?}")]
+    RelativeJump(Label),
+
+    #[error("Undefined label: {0:?}")]
+    UndefinedLabel(Label),
+
+    #[error("Static memory doesn't fit in WRAM ({0} bytes)")]
+    StaticOverflow(u16),
+
+    #[error("Stack frames of interrupt handler {0} don't fit in the interrupt stack")]
+    InterruptStackOverflow(usize),
+
+    #[error("Program doesn't fit in ROM ({0} bytes)")]
+    RomOverflow(usize),
+
+    #[error("Const memory doesn't fit in ROM0 ({0} bytes)")]
+    ConstOverflow(usize),
+
+    #[error("Invalid cartridge title: {0:?}")]
+    Title(String),
+
+    #[error("Attempted to write to const memory")]
+    ConstWrite,
+
+    #[error("Invalid inline assembly instruction: {mnemonic:?} {operands:?}")]
+    InlineAsm {
+        mnemonic: Mnemonic,
+        operands: Vec<Operand>,
+    },
+}
+
+impl Target for LR35902 {
+    type ByteOrder = LittleEndian;
+    type Output = Bytes;
+    type Error = Error;
+
+    fn codegen(ir: &Ir<Self::ByteOrder>) -> Result<Self::Output, Self::Error> {
+        RomBuilder::default().build(ir)
+    }
+}
@@ -0,0 +1,466 @@
+//! LR35902 instruction encoding.
+//!
+//! Only the subset of the instruction set used by the codegen is implemented.
+//! Addresses that are not known during the lowering of the IR (routines, jump
+//! targets, const memory, ...) are referenced through a [`Label`] and resolved
+//! once all the code has been emitted.
+use crate::target::lr35902::{runtime::Runtime, Error};
+use std::collections::HashMap;
+
+/// 8bit register operands, in the order of the opcode encoding.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum R8 {
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    /// Memory pointed by the `HL` register (`(HL)`).
+    AtHl,
+    A,
+}
+
+/// 16bit register operands.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum R16 {
+    BC,
+    DE,
+    HL,
+    /// `SP` for `ld`, `inc`, `dec` & `add`. `AF` for `push` & `pop`.
+    SPorAF,
+}
+
+/// Jump conditions.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Cond {
+    NZ,
+    Z,
+    NC,
+    C,
+}
+
+/// 8bit arithmetic and logic operations performed on the `A` register.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Alu {
+    Add,
+    Adc,
+    Sub,
+    Sbc,
+    And,
+    Xor,
+    Or,
+    Cp,
+}
+
+/// Symbolic code location.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Label {
+    /// First instruction of an IR routine.
+    Routine(usize),
+
+    /// First instruction of an IR statement.
+    Statement { routine: usize, statement: usize },
+
+    /// Beginning of the const memory space.
+    Const,
+
+    /// Runtime support routine.
+    Runtime(Runtime),
+
+    /// Label local to a block of code (see `Asm::local`).
+    Local(usize),
+}
+
+#[derive(Debug)]
+enum FixupKind {
+    Absolute,
+    Relative,
+}
+
+#[derive(Debug)]
+struct Fixup {
+    offset: usize,
+    label: Label,
+    addend: u16,
+    kind: FixupKind,
+}
+
+/// Machine code buffer.
+#[derive(Debug)]
+pub struct Asm {
+    origin: u16,
+    bytes: Vec<u8>,
+    labels: HashMap<Label, u16>,
+    fixups: Vec<Fixup>,
+    locals: usize,
+}
+
+impl Asm {
+    /// Create an empty buffer which code will be located at `origin`.
+    pub fn new(origin: u16) -> Self {
+        Self {
+            origin,
+            bytes: Vec::new(),
+            labels: HashMap::new(),
+            fixups: Vec::new(),
+            locals: 0,
+        }
+    }
+
+    /// Address of the next emitted byte.
+    pub fn address(&self) -> u16 {
+        self.origin.wrapping_add(self.bytes.len() as u16)
+    }
+
+    /// Number of emitted bytes.
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Allocate a new local label.
+    pub fn local(&mut self) -> Label {
+        self.locals += 1;
+        Label::Local(self.locals)
+    }
+
+    /// Bind label to the current address.
+    pub fn label(&mut self, label: Label) {
+        let address = self.address();
+        assert!(self.labels.insert(label, address).is_none());
+    }
+
+    /// Emit raw bytes.
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    /// Pad with `0xff` bytes up to the given address.
+    pub fn org(&mut self, address: u16) {
+        assert!(address >= self.address());
+        let len = (address - self.origin) as usize;
+        self.bytes.resize(len, 0xff);
+    }
+
+    /// Emit a single byte.
+    pub fn byte(&mut self, byte: u8) {
+        self.bytes.push(byte);
+    }
+
+    /// Emit a little-endian 16bit word.
+    pub fn word(&mut self, word: u16) {
+        self.bytes.extend_from_slice(&word.to_le_bytes());
+    }
+
+    /// Emit the address of `label + addend`.
+    pub fn word_label(&mut self, label: Label, addend: u16) {
+        self.fixups.push(Fixup {
+            offset: self.bytes.len(),
+            label,
+            addend,
+            kind: FixupKind::Absolute,
+        });
+        self.word(0);
+    }
+
+    /// Resolve all the label references and return the machine code.
+    pub fn finish(mut self) -> Result<Vec<u8>, Error> {
+        for fixup in &self.fixups {
+            let address = *self
+                .labels
+                .get(&fixup.label)
+                .ok_or(Error::UndefinedLabel(fixup.label))?;
+            match fixup.kind {
+                FixupKind::Absolute => {
+                    let word = address.wrapping_add(fixup.addend).to_le_bytes();
+                    self.bytes[fixup.offset..fixup.offset + 2].copy_from_slice(&word);
+                }
+                FixupKind::Relative => {
+                    let next = self.origin as isize + fixup.offset as isize + 1;
+                    let rel = address as isize - next;
+                    if rel < i8::MIN as isize || rel > i8::MAX as isize {
+                        return Err(Error::RelativeJump(fixup.label));
+                    }
+                    self.bytes[fixup.offset] = rel as i8 as u8;
+                }
+            }
+        }
+        Ok(self.bytes)
+    }
+
+    // instructions
+
+    /// `nop`
+    pub fn nop(&mut self) {
+        self.byte(0x00);
+    }
+
+    /// `halt`
+    pub fn halt(&mut self) {
+        self.byte(0x76);
+    }
+
+    /// `di`
+    pub fn di(&mut self) {
+        self.byte(0xf3);
+    }
+
+    /// `ei`
+    pub fn ei(&mut self) {
+        self.byte(0xfb);
+    }
+
+    /// `ld <dst>, <src>`
+    pub fn ld(&mut self, dst: R8, src: R8) {
+        assert!(
+            !(dst == R8::AtHl && src == R8::AtHl),
+            "ld (hl), (hl) is halt"
+        );
+        self.byte(0x40 | (dst as u8) << 3 | src as u8);
+    }
+
+    /// `ld <dst>, n`
+    pub fn ld_n(&mut self, dst: R8, n: u8) {
+        self.bytes(&[0x06 | (dst as u8) << 3, n]);
+    }
+
+    /// `ld <dst>, nn`
+    pub fn ld_nn(&mut self, dst: R16, nn: u16) {
+        self.byte(0x01 | (dst as u8) << 4);
+        self.word(nn);
+    }
+
+    /// `ld <dst>, <label> + addend`
+    pub fn ld_nn_label(&mut self, dst: R16, label: Label, addend: u16) {
+        self.byte(0x01 | (dst as u8) << 4);
+        self.word_label(label, addend);
+    }
+
+    /// `ld a, (nn)`
+    pub fn ld_a_at(&mut self, nn: u16) {
+        self.byte(0xfa);
+        self.word(nn);
+    }
+
+    /// `ld (nn), a`
+    pub fn ld_at_a(&mut self, nn: u16) {
+        self.byte(0xea);
+        self.word(nn);
+    }
+
+    /// `ld a, (<label> + addend)`
+    pub fn ld_a_at_label(&mut self, label: Label, addend: u16) {
+        self.byte(0xfa);
+        self.word_label(label, addend);
+    }
+
+    /// `ld a, (hl+)`
+    pub fn ld_a_hli(&mut self) {
+        self.byte(0x2a);
+    }
+
+    /// `ld (hl+), a`
+    pub fn ld_hli_a(&mut self) {
+        self.byte(0x22);
+    }
+
+    /// `ld (hl-), a`
+    pub fn ld_hld_a(&mut self) {
+        self.byte(0x32);
+    }
+
+    /// `ldh a, (0xff00 + n)`
+    pub fn ldh_a(&mut self, n: u8) {
+        self.bytes(&[0xf0, n]);
+    }
+
+    /// `ldh (0xff00 + n), a`
+    pub fn ldh_at_a(&mut self, n: u8) {
+        self.bytes(&[0xe0, n]);
+    }
+
+    /// `<op> a, <src>`
+    pub fn alu(&mut self, op: Alu, src: R8) {
+        self.byte(0x80 | (op as u8) << 3 | src as u8);
+    }
+
+    /// `<op> a, n`
+    pub fn alu_n(&mut self, op: Alu, n: u8) {
+        self.bytes(&[0xc6 | (op as u8) << 3, n]);
+    }
+
+    /// `cpl`
+    pub fn cpl(&mut self) {
+        self.byte(0x2f);
+    }
+
+    /// `inc <reg>`
+    pub fn inc(&mut self, reg: R8) {
+        self.byte(0x04 | (reg as u8) << 3);
+    }
+
+    /// `dec <reg>`
+    pub fn dec(&mut self, reg: R8) {
+        self.byte(0x05 | (reg as u8) << 3);
+    }
+
+    /// `inc <reg>` (16bit)
+    pub fn inc16(&mut self, reg: R16) {
+        self.byte(0x03 | (reg as u8) << 4);
+    }
+
+    /// `dec <reg>` (16bit)
+    pub fn dec16(&mut self, reg: R16) {
+        self.byte(0x0b | (reg as u8) << 4);
+    }
+
+    /// `add hl, <reg>`
+    pub fn add_hl(&mut self, reg: R16) {
+        self.byte(0x09 | (reg as u8) << 4);
+    }
+
+    /// `push <reg>`
+    pub fn push(&mut self, reg: R16) {
+        self.byte(0xc5 | (reg as u8) << 4);
+    }
+
+    /// `pop <reg>`
+    pub fn pop(&mut self, reg: R16) {
+        self.byte(0xc1 | (reg as u8) << 4);
+    }
+
+    /// `sla <reg>`
+    pub fn sla(&mut self, reg: R8) {
+        self.bytes(&[0xcb, 0x20 | reg as u8]);
+    }
+
+    /// `srl <reg>`
+    pub fn srl(&mut self, reg: R8) {
+        self.bytes(&[0xcb, 0x38 | reg as u8]);
+    }
+
+    /// `sra <reg>`
+    pub fn sra(&mut self, reg: R8) {
+        self.bytes(&[0xcb, 0x28 | reg as u8]);
+    }
+
+    /// `bit <bit>, <reg>`
+    pub fn bit(&mut self, bit: u8, reg: R8) {
+        assert!(bit < 8);
+        self.bytes(&[0xcb, 0x40 | bit << 3 | reg as u8]);
+    }
+
+    /// `rl <reg>`
+    pub fn rl(&mut self, reg: R8) {
+        self.bytes(&[0xcb, 0x10 | reg as u8]);
+    }
+
+    /// `rr <reg>`
+    pub fn rr(&mut self, reg: R8) {
+        self.bytes(&[0xcb, 0x18 | reg as u8]);
+    }
+
+    /// `jp <label>`
+    pub fn jp(&mut self, label: Label) {
+        self.byte(0xc3);
+        self.word_label(label, 0);
+    }
+
+    /// `jp hl`
+    pub fn jp_hl(&mut self) {
+        self.byte(0xe9);
+    }
+
+    /// `jp <cond>, <label>`
+    pub fn jp_cond(&mut self, cond: Cond, label: Label) {
+        self.byte(0xc2 | (cond as u8) << 3);
+        self.word_label(label, 0);
+    }
+
+    /// `jr <label>`
+    pub fn jr(&mut self, label: Label) {
+        self.byte(0x18);
+        self.rel_label(label);
+    }
+
+    /// `jr <cond>, <label>`
+    pub fn jr_cond(&mut self, cond: Cond, label: Label) {
+        self.byte(0x20 | (cond as u8) << 3);
+        self.rel_label(label);
+    }
+
+    /// Emit the 8bit offset to `label`, relative to the next byte.
+    pub fn rel_label(&mut self, label: Label) {
+        self.fixups.push(Fixup {
+            offset: self.bytes.len(),
+            label,
+            addend: 0,
+            kind: FixupKind::Relative,
+        });
+        self.byte(0);
+    }
+
+    /// `call <label>`
+    pub fn call(&mut self, label: Label) {
+        self.byte(0xcd);
+        self.word_label(label, 0);
+    }
+
+    /// `ret`
+    pub fn ret(&mut self) {
+        self.byte(0xc9);
+    }
+
+    /// `reti`
+    pub fn reti(&mut self) {
+        self.byte(0xd9);
+    }
+
+    /// `ret <cond>`
+    pub fn ret_cond(&mut self, cond: Cond) {
+        self.byte(0xc0 | (cond as u8) << 3);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Alu, Asm, Cond, Label, R16, R8};
+
+    #[test]
+    fn encoding() {
+        let mut asm = Asm::new(0x150);
+        asm.ld(R8::B, R8::A);
+        asm.ld(R8::A, R8::AtHl);
+        asm.ld_n(R8::C, 0x42);
+        asm.ld_nn(R16::HL, 0xc000);
+        asm.ld_nn(R16::SPorAF, 0xe000);
+        asm.alu(Alu::Sub, R8::C);
+        asm.alu_n(Alu::Cp, 0x10);
+        asm.push(R16::SPorAF);
+        asm.pop(R16::DE);
+        asm.srl(R8::A);
+        asm.ldh_a(0x80);
+        assert_eq!(
+            vec![
+                0x47, 0x7e, 0x0e, 0x42, 0x21, 0x00, 0xc0, 0x31, 0x00, 0xe0, 0x91, 0xfe, 0x10, 0xf5,
+                0xd1, 0xcb, 0x3f, 0xf0, 0x80
+            ],
+            asm.finish().unwrap()
+        );
+    }
+
+    #[test]
+    fn labels() {
+        let mut asm = Asm::new(0x150);
+        let label = asm.local();
+        asm.jp(Label::Routine(0));
+        asm.label(label);
+        asm.jr_cond(Cond::NZ, label);
+        asm.label(Label::Routine(0));
+        asm.call(label);
+        assert_eq!(
+            vec![0xc3, 0x55, 0x01, 0x20, 0xfe, 0xcd, 0x53, 0x01],
+            asm.finish().unwrap()
+        );
+    }
+}
@@ -0,0 +1,1184 @@
+//! Lowering of IR statements into LR35902 instructions.
+//!
+//! Every IR statement is lowered independently of the others. The values are
+//! computed in the `A` register (8bit) or the `HL` register (16bit), before
+//! being stored in the statement's destination. Virtual registers are mapped to
+//! HRAM so they can be accessed with the shorter `ldh` instructions.
+use crate::{
+    byteorder::LittleEndian,
+    ir::{
+        opcodes::{
+            Address, Destination, Location, Pointer, Register, Source, Statement, StopStatus,
+        },
+        Ir, Routine,
+    },
+    target::lr35902::{
+        asm::{Alu, Asm, Cond, Label, R16, R8},
+        inline,
+        runtime::{self, Runtime},
+        Error, FRAME_POINTER, INTERRUPT_STACK_SIZE, MIN_STACK_SIZE, NUM_REGISTERS, REGISTERS,
+        REGISTERS_W, RETURN, RETURN_SIZE, STATIC, WRAM_END,
+    },
+};
+use std::collections::BTreeSet;
+
+/// Lower the IR routines, as well as the runtime support routines, into `asm`.
+///
+/// Program execution begins at `Label::Runtime(Runtime::Entry)`. Const memory
+/// is referenced through `Label::Const`, which must be defined by the caller.
+pub fn codegen(asm: &mut Asm, ir: &Ir<LittleEndian>) -> Result<(), Error> {
+    let interrupt_frame = STATIC + ir.static_alloc;
+    let handlers = [
+        ir.handlers.vblank,
+        ir.handlers.lcd_stat,
+        ir.handlers.timer,
+        ir.handlers.serial,
+        ir.handlers.joypad,
+    ];
+    // interrupt enable mask (IE register) of the defined handlers
+    let interrupts = handlers
+        .iter()
+        .enumerate()
+        .filter(|(_, handler)| handler.is_some())
+        .fold(0u8, |mask, (bit, _)| mask | 1 << bit);
+    let frame = if interrupts != 0 {
+        interrupt_frame + INTERRUPT_STACK_SIZE
+    } else {
+        interrupt_frame
+    };
+    if frame as u32 + MIN_STACK_SIZE as u32 > WRAM_END as u32 {
+        return Err(Error::StaticOverflow(ir.static_alloc));
+    }
+    // handlers (and the routines they call) must not reach the frames of main
+    for handler in handlers.iter().flatten() {
+        match stack_depth(ir, *handler, &mut Vec::new()) {
+            Some(depth) if depth <= INTERRUPT_STACK_SIZE as u32 => {}
+            _ => return Err(Error::InterruptStackOverflow(*handler)),
+        }
+    }
+    runtime::emit(asm, frame, interrupt_frame, interrupts, ir.handlers.main);
+    for (index, routine) in ir.routines.iter().enumerate() {
+        Lower::new(asm, index, routine).routine()?;
+    }
+    Ok(())
+}
+
+/// Worst case size of the stack frames of `routine` and the routines it calls
+/// (`None` if the routine is recursive).
+fn stack_depth(ir: &Ir<LittleEndian>, routine: usize, calls: &mut Vec<usize>) -> Option<u32> {
+    if calls.contains(&routine) {
+        return None;
+    }
+    calls.push(routine);
+    let mut depth = ir.routines[routine].stack_size as u32;
+    for statement in &ir.routines[routine].statements {
+        if let Statement::Call { routine, range } = statement {
+            let callee = stack_depth(ir, *routine, calls)?;
+            depth = depth.max(range.start as u32 + callee);
+        }
+    }
+    calls.pop();
+    Some(depth)
+}
+
+/// Address of a memory location known at compile time.
+enum Direct {
+    /// Regular 16bit address.
+    Absolute(u16),
+    /// Address relative to the beginning of const memory.
+    Const(u16),
+    /// HRAM address (relative to `0xff00`).
+    High(u8),
+}
+
+struct Lower<'a> {
+    asm: &'a mut Asm,
+    index: usize,
+    routine: &'a Routine,
+    reg8: BTreeSet<Register>,
+    reg16: BTreeSet<Register>,
+}
+
+impl<'a> Lower<'a> {
+    fn new(asm: &'a mut Asm, index: usize, routine: &'a Routine) -> Self {
+        let mut lower = Self {
+            asm,
+            index,
+            routine,
+            reg8: BTreeSet::new(),
+            reg16: BTreeSet::new(),
+        };
+        lower.collect_registers();
+        lower
+    }
+
+    fn routine(mut self) -> Result<(), Error> {
+        self.asm.label(Label::Routine(self.index));
+        for (i, statement) in self.routine.statements.iter().enumerate() {
+            self.asm.label(self.statement_label(i));
+            self.statement(i, statement)?;
+        }
+        // end of the routine, in case a jump lands right after the last
+        // statement
+        let end = self.routine.statements.len();
+        self.asm.label(self.statement_label(end));
+        self.asm.ret();
+        Ok(())
+    }
+
+    fn statement_label(&self, statement: usize) -> Label {
+        Label::Statement {
+            routine: self.index,
+            statement,
+        }
+    }
+
+    fn statement(&mut self, i: usize, statement: &Statement) -> Result<(), Error> {
+        use Statement::{
+            Add, AddW, And, AndW, Asm, Call, Dec, DecW, Div, DivS, DivSW, DivW, Eq, EqW, Greater,
+            GreaterEq, GreaterEqS, GreaterEqW, GreaterS, GreaterW, Inc, IncW, Jmp, JmpCmp,
+            JmpCmpNot, Ld, LdAddr, LdW, LeftShift, LeftShiftW, Less, LessEq, LessEqS, LessEqW,
+            LessS, LessW, Mul, MulW, Nop, NotEq, NotEqW, Or, OrW, Rem, RemS, RemSW, RemW, Ret,
+            RightShift, RightShiftS, RightShiftSW, RightShiftW, Stop, Sub, SubW, Xor, XorW,
+        };
+        match statement {
+            Nop(_) => {}
+            Stop(StopStatus::Success) => self.asm.jp(Label::Runtime(Runtime::Stop)),
+            Stop(StopStatus::Error) => self.asm.jp(Label::Runtime(Runtime::Panic)),
+            Ld {
+                source,
+                destination,
+            } => {
+                self.load(source)?;
+                self.store(destination)?;
+            }
+            LdW {
+                source,
+                destination,
+            } => {
+                self.load_w(source)?;
+                self.store_w(destination)?;
+            }
+            LdAddr {
+                source,
+                destination,
+            } => {
+                self.load_addr(source)?;
+                self.store_w(destination)?;
+            }
+            Inc {
+                source,
+                destination,
+            } => {
+                self.load(source)?;
+                self.asm.inc(R8::A);
+                self.store(destination)?;
+            }
+            Dec {
+                source,
+                destination,
+            } => {
+                self.load(source)?;
+                self.asm.dec(R8::A);
+                self.store(destination)?;
+            }
+            IncW {
+                source,
+                destination,
+            } => {
+                self.load_w(source)?;
+                self.asm.inc16(R16::HL);
+                self.store_w(destination)?;
+            }
+            DecW {
+                source,
+                destination,
+            } => {
+                self.load_w(source)?;
+                self.asm.dec16(R16::HL);
+                self.store_w(destination)?;
+            }
+            Add {
+                left,
+                right,
+                destination,
+            } => self.alu(Alu::Add, left, right, destination)?,
+            Sub {
+                left,
+                right,
+                destination,
+            } => self.alu(Alu::Sub, left, right, destination)?,
+            And {
+                left,
+                right,
+                destination,
+            } => self.alu(Alu::And, left, right, destination)?,
+            Xor {
+                left,
+                right,
+                destination,
+            } => self.alu(Alu::Xor, left, right, destination)?,
+            Or {
+                left,
+                right,
+                destination,
+            } => self.alu(Alu::Or, left, right, destination)?,
+            LeftShift {
+                left,
+                right,
+                destination,
+            } => self.runtime(Runtime::LeftShift, left, right, destination)?,
+            RightShift {
+                left,
+                right,
+                destination,
+            } => self.runtime(Runtime::RightShift, left, right, destination)?,
+            Mul {
+                left,
+                right,
+                destination,
+            } => self.runtime(Runtime::Mul, left, right, destination)?,
+            Div {
+                left,
+                right,
+                destination,
+            } => self.runtime(Runtime::Div, left, right, destination)?,
+            Rem {
+                left,
+                right,
+                destination,
+            } => self.runtime(Runtime::Rem, left, right, destination)?,
+            DivS {
+                left,
+                right,
+                destination,
+            } => self.runtime(Runtime::DivS, left, right, destination)?,
+            RemS {
+                left,
+                right,
+                destination,
+            } => self.runtime(Runtime::RemS, left, right, destination)?,
+            RightShiftS {
+                left,
+                right,
+                destination,
+            } => self.runtime(Runtime::RightShiftS, left, right, destination)?,
+            AddW {
+                left,
+                right,
+                destination,
+            } => {
+                self.operands_w(left, right)?;
+                self.asm.add_hl(R16::DE);
+                self.store_w(destination)?;
+            }
+            SubW {
+                left,
+                right,
+                destination,
+            } => self.alu_w(Alu::Sub, Alu::Sbc, left, right, destination)?,
+            AndW {
+                left,
+                right,
+                destination,
+            } => self.alu_w(Alu::And, Alu::And, left, right, destination)?,
+            XorW {
+                left,
+                right,
+                destination,
+            } => self.alu_w(Alu::Xor, Alu::Xor, left, right, destination)?,
+            OrW {
+                left,
+                right,
+                destination,
+            } => self.alu_w(Alu::Or, Alu::Or, left, right, destination)?,
+            LeftShiftW {
+                left,
+                right,
+                destination,
+            } => self.shift_w(Runtime::LeftShiftW, left, right, destination)?,
+            RightShiftW {
+                left,
+                right,
+                destination,
+            } => self.shift_w(Runtime::RightShiftW, left, right, destination)?,
+            MulW {
+                left,
+                right,
+                destination,
+            } => self.runtime_w(Runtime::MulW, left, right, destination)?,
+            DivW {
+                left,
+                right,
+                destination,
+            } => self.runtime_w(Runtime::DivW, left, right, destination)?,
+            RemW {
+                left,
+                right,
+                destination,
+            } => self.runtime_w(Runtime::RemW, left, right, destination)?,
+            DivSW {
+                left,
+                right,
+                destination,
+            } => self.runtime_w(Runtime::DivSW, left, right, destination)?,
+            RemSW {
+                left,
+                right,
+                destination,
+            } => self.runtime_w(Runtime::RemSW, left, right, destination)?,
+            RightShiftSW {
+                left,
+                right,
+                destination,
+            } => self.shift_w(Runtime::RightShiftSW, left, right, destination)?,
+            Eq {
+                left,
+                right,
+                destination,
+            } => self.compare(Cond::Z, left, right, destination)?,
+            NotEq {
+                left,
+                right,
+                destination,
+            } => self.compare(Cond::NZ, left, right, destination)?,
+            Less {
+                left,
+                right,
+                destination,
+            } => self.compare(Cond::C, left, right, destination)?,
+            GreaterEq {
+                left,
+                right,
+                destination,
+            } => self.compare(Cond::NC, left, right, destination)?,
+            // a > b <=> b < a
+            Greater {
+                left,
+                right,
+                destination,
+            } => self.compare(Cond::C, right, left, destination)?,
+            // a <= b <=> b >= a
+            LessEq {
+                left,
+                right,
+                destination,
+            } => self.compare(Cond::NC, right, left, destination)?,
+            LessS {
+                left,
+                right,
+                destination,
+            } => self.compare_s(Cond::C, left, right, destination)?,
+            GreaterEqS {
+                left,
+                right,
+                destination,
+            } => self.compare_s(Cond::NC, left, right, destination)?,
+            GreaterS {
+                left,
+                right,
+                destination,
+            } => self.compare_s(Cond::C, right, left, destination)?,
+            LessEqS {
+                left,
+                right,
+                destination,
+            } => self.compare_s(Cond::NC, right, left, destination)?,
+            EqW {
+                left,
+                right,
+                destination,
+            } => self.compare_w(Cond::Z, left, right, destination)?,
+            NotEqW {
+                left,
+                right,
+                destination,
+            } => self.compare_w(Cond::NZ, left, right, destination)?,
+            LessW {
+                left,
+                right,
+                destination,
+            } => self.compare_w(Cond::C, left, right, destination)?,
+            GreaterEqW {
+                left,
+                right,
+                destination,
+            } => self.compare_w(Cond::NC, left, right, destination)?,
+            GreaterW {
+                left,
+                right,
+                destination,
+            } => self.compare_w(Cond::C, right, left, destination)?,
+            LessEqW {
+                left,
+                right,
+                destination,
+            } => self.compare_w(Cond::NC, right, left, destination)?,
+            Jmp { location } => {
+                let label = self.jump_label(i, location)?;
+                self.asm.jp(label);
+            }
+            JmpCmp { location, source } => self.jump_cond(i, Cond::NZ, location, source)?,
+            JmpCmpNot { location, source } => self.jump_cond(i, Cond::Z, location, source)?,
+            Call { routine, range } => self.call(*routine, range.start)?,
+            Ret => self.asm.ret(),
+            Asm(items) => inline::emit(self.asm, items)?,
+        }
+        Ok(())
+    }
+
+    // load & store
+
+    /// Load 8bit source into `A`.
+    fn load(&mut self, source: &Source<u8>) -> Result<(), Error> {
+        match source {
+            Source::Literal(n) => self.asm.ld_n(R8::A, *n),
+            Source::Register(r) => {
+                let r = self.register(*r)?;
+                self.asm.ldh_a(r);
+            }
+            Source::Pointer { base, offset: None } => match self.direct(base)? {
+                Some(Direct::Absolute(nn)) => self.asm.ld_a_at(nn),
+                Some(Direct::Const(a)) => self.asm.ld_a_at_label(Label::Const, a),
+                Some(Direct::High(n)) => self.asm.ldh_a(n),
+                None => {
+                    self.address(base, &None)?;
+                    self.asm.ld(R8::A, R8::AtHl);
+                }
+            },
+            Source::Pointer { base, offset } => {
+                self.address(base, offset)?;
+                self.asm.ld(R8::A, R8::AtHl);
+            }
+        }
+        Ok(())
+    }
+
+    /// Store the contents of `A` into the destination.
+    fn store(&mut self, destination: &Destination) -> Result<(), Error> {
+        match destination {
+            Destination::Register(r) => {
+                let r = self.register(*r)?;
+                self.asm.ldh_at_a(r);
+            }
+            Destination::Pointer { base, offset } => match (offset, self.direct(base)?) {
+                (_, Some(Direct::Const(_))) => return Err(Error::ConstWrite),
+                (None, Some(Direct::Absolute(nn))) => self.asm.ld_at_a(nn),
+                (None, Some(Direct::High(n))) => self.asm.ldh_at_a(n),
+                _ => {
+                    self.asm.ld(R8::B, R8::A);
+                    self.address(base, offset)?;
+                    self.asm.ld(R8::AtHl, R8::B);
+                }
+            },
+        }
+        Ok(())
+    }
+
+    /// Load 16bit source into `HL`.
+    fn load_w(&mut self, source: &Source<u16>) -> Result<(), Error> {
+        match source {
+            Source::Literal(nn) => self.asm.ld_nn(R16::HL, *nn),
+            Source::Register(r) => self.load_register_w(*r)?,
+            Source::Pointer { base, offset } => {
+                self.address(base, offset)?;
+                self.asm.ld_a_hli();
+                self.asm.ld(R8::H, R8::AtHl);
+                self.asm.ld(R8::L, R8::A);
+            }
+        }
+        Ok(())
+    }
+
+    /// Load the (target) address of an address source into `HL`.
+    fn load_addr(&mut self, source: &Source<Address>) -> Result<(), Error> {
+        match source {
+            Source::Literal(nn) => self.asm.ld_nn(R16::HL, *nn),
+            Source::Register(r) => self.load_register_w(*r)?,
+            Source::Pointer { base, offset } => self.address(base, offset)?,
+        }
+        Ok(())
+    }
+
+    fn load_register_w(&mut self, register: Register) -> Result<(), Error> {
+        let r = self.register_w(register)?;
+        self.asm.ldh_a(r);
+        self.asm.ld(R8::L, R8::A);
+        self.asm.ldh_a(r + 1);
+        self.asm.ld(R8::H, R8::A);
+        Ok(())
+    }
+
+    /// Store the contents of `HL` into the destination.
+    fn store_w(&mut self, destination: &Destination) -> Result<(), Error> {
+        match destination {
+            Destination::Register(r) => {
+                let r = self.register_w(*r)?;
+                self.asm.ld(R8::A, R8::L);
+                self.asm.ldh_at_a(r);
+                self.asm.ld(R8::A, R8::H);
+                self.asm.ldh_at_a(r + 1);
+            }
+            Destination::Pointer { base, offset } => {
+                if let Some(Direct::Const(_)) = self.direct(base)? {
+                    return Err(Error::ConstWrite);
+                }
+                self.asm.push(R16::HL);
+                self.address(base, offset)?;
+                self.asm.pop(R16::DE);
+                self.asm.ld(R8::AtHl, R8::E);
+                self.asm.inc16(R16::HL);
+                self.asm.ld(R8::AtHl, R8::D);
+            }
+        }
+        Ok(())
+    }
+
+    /// Compute the address of a pointer into `HL`.
+    ///
+    /// Clobbers `A`, but preserves `BC` and `DE`.
+    fn address(&mut self, base: &Pointer, offset: &Option<Box<Source<u8>>>) -> Result<(), Error> {
+        match offset {
+            None => self.base(base)?,
+            Some(offset) => {
+                self.load(offset)?;
+                self.asm.push(R16::SPorAF);
+                self.base(base)?;
+                self.asm.pop(R16::SPorAF);
+                // HL += A
+                self.asm.alu(Alu::Add, R8::L);
+                self.asm.ld(R8::L, R8::A);
+                self.asm.alu(Alu::Adc, R8::H);
+                self.asm.alu(Alu::Sub, R8::L);
+                self.asm.ld(R8::H, R8::A);
+            }
+        }
+        Ok(())
+    }
+
+    fn base(&mut self, base: &Pointer) -> Result<(), Error> {
+        match base {
+            Pointer::Stack(a) => {
+                let [lo, hi] = a.to_le_bytes();
+                self.asm.ldh_a(FRAME_POINTER);
+                self.asm.alu_n(Alu::Add, lo);
+                self.asm.ld(R8::L, R8::A);
+                self.asm.ldh_a(FRAME_POINTER + 1);
+                self.asm.alu_n(Alu::Adc, hi);
+                self.asm.ld(R8::H, R8::A);
+            }
+            Pointer::Indirect(r, a) => {
+                self.load_register_w(*r)?;
+                if *a != 0 {
+                    let [lo, hi] = a.to_le_bytes();
+                    self.asm.ld(R8::A, R8::L);
+                    self.asm.alu_n(Alu::Add, lo);
+                    self.asm.ld(R8::L, R8::A);
+                    self.asm.ld(R8::A, R8::H);
+                    self.asm.alu_n(Alu::Adc, hi);
+                    self.asm.ld(R8::H, R8::A);
+                }
+            }
+            _ => match self.direct(base)? {
+                Some(Direct::Absolute(nn)) => self.asm.ld_nn(R16::HL, nn),
+                Some(Direct::Const(a)) => self.asm.ld_nn_label(R16::HL, Label::Const, a),
+                Some(Direct::High(n)) => self.asm.ld_nn(R16::HL, 0xff00 | n as u16),
+                None => unreachable!(),
+            },
+        }
+        Ok(())
+    }
+
+    fn direct(&self, pointer: &Pointer) -> Result<Option<Direct>, Error> {
+        Ok(match pointer {
+            Pointer::Absolute(a) if *a >= 0xff00 => Some(Direct::High(*a as u8)),
+            Pointer::Absolute(a) => Some(Direct::Absolute(*a)),
+            Pointer::Static(a) => Some(Direct::Absolute(STATIC + *a)),
+            Pointer::Const(a) => Some(Direct::Const(*a)),
+            Pointer::Return(a) if *a < RETURN_SIZE => Some(Direct::High(RETURN + *a as u8)),
+            Pointer::Return(a) => return Err(Error::Return(*a)),
+            Pointer::Stack(_) | Pointer::Indirect(..) => None,
+        })
+    }
+
+    fn register(&self, register: Register) -> Result<u8, Error> {
+        if register < NUM_REGISTERS {
+            Ok(REGISTERS + register as u8)
+        } else {
+            Err(Error::Register(register))
+        }
+    }
+
+    fn register_w(&self, register: Register) -> Result<u8, Error> {
+        if register < NUM_REGISTERS {
+            Ok(REGISTERS_W + 2 * register as u8)
+        } else {
+            Err(Error::Register(register))
+        }
+    }
+
+    // arithmetic
+
+    /// Load right operand into `C` and left operand into `A`.
+    fn operands(&mut self, left: &Source<u8>, right: &Source<u8>) -> Result<(), Error> {
+        self.load(right)?;
+        self.asm.ld(R8::C, R8::A);
+        self.load(left)
+    }
+
+    /// Load right operand into `DE` and left operand into `HL`.
+    fn operands_w(&mut self, left: &Source<u16>, right: &Source<u16>) -> Result<(), Error> {
+        self.load_w(right)?;
+        self.asm.push(R16::HL);
+        self.load_w(left)?;
+        self.asm.pop(R16::DE);
+        Ok(())
+    }
+
+    fn alu(
+        &mut self,
+        op: Alu,
+        left: &Source<u8>,
+        right: &Source<u8>,
+        destination: &Destination,
+    ) -> Result<(), Error> {
+        self.operands(left, right)?;
+        self.asm.alu(op, R8::C);
+        self.store(destination)
+    }
+
+    fn alu_w(
+        &mut self,
+        lo: Alu,
+        hi: Alu,
+        left: &Source<u16>,
+        right: &Source<u16>,
+        destination: &Destination,
+    ) -> Result<(), Error> {
+        self.operands_w(left, right)?;
+        self.asm.ld(R8::A, R8::L);
+        self.asm.alu(lo, R8::E);
+        self.asm.ld(R8::L, R8::A);
+        self.asm.ld(R8::A, R8::H);
+        self.asm.alu(hi, R8::D);
+        self.asm.ld(R8::H, R8::A);
+        self.store_w(destination)
+    }
+
+    fn runtime(
+        &mut self,
+        runtime: Runtime,
+        left: &Source<u8>,
+        right: &Source<u8>,
+        destination: &Destination,
+    ) -> Result<(), Error> {
+        self.operands(left, right)?;
+        self.asm.call(Label::Runtime(runtime));
+        self.store(destination)
+    }
+
+    fn runtime_w(
+        &mut self,
+        runtime: Runtime,
+        left: &Source<u16>,
+        right: &Source<u16>,
+        destination: &Destination,
+    ) -> Result<(), Error> {
+        self.operands_w(left, right)?;
+        self.asm.call(Label::Runtime(runtime));
+        self.store_w(destination)
+    }
+
+    fn shift_w(
+        &mut self,
+        runtime: Runtime,
+        left: &Source<u16>,
+        right: &Source<u8>,
+        destination: &Destination,
+    ) -> Result<(), Error> {
+        self.load(right)?;
+        self.asm.push(R16::SPorAF);
+        self.load_w(left)?;
+        self.asm.pop(R16::SPorAF);
+        self.asm.ld(R8::C, R8::A);
+        self.asm.call(Label::Runtime(runtime));
+        self.store_w(destination)
+    }
+
+    /// Store 1 if `cond` holds after comparing `left` to `right`, 0 otherwise.
+    fn compare(
+        &mut self,
+        cond: Cond,
+        left: &Source<u8>,
+        right: &Source<u8>,
+        destination: &Destination,
+    ) -> Result<(), Error> {
+        self.operands(left, right)?;
+        self.asm.alu(Alu::Cp, R8::C);
+        let done = self.asm.local();
+        self.asm.ld_n(R8::A, 1);
+        self.asm.jr_cond(cond, done);
+        self.asm.dec(R8::A);
+        self.asm.label(done);
+        self.store(destination)
+    }
+
+    /// Same as `compare`, with both operands interpreted as signed. Flipping
+    /// the sign bits maps the signed range onto the unsigned one.
+    fn compare_s(
+        &mut self,
+        cond: Cond,
+        left: &Source<u8>,
+        right: &Source<u8>,
+        destination: &Destination,
+    ) -> Result<(), Error> {
+        self.operands(left, right)?;
+        self.asm.alu_n(Alu::Xor, 0x80);
+        self.asm.ld(R8::B, R8::A);
+        self.asm.ld(R8::A, R8::C);
+        self.asm.alu_n(Alu::Xor, 0x80);
+        self.asm.ld(R8::C, R8::A);
+        self.asm.ld(R8::A, R8::B);
+        self.asm.alu(Alu::Cp, R8::C);
+        let done = self.asm.local();
+        self.asm.ld_n(R8::A, 1);
+        self.asm.jr_cond(cond, done);
+        self.asm.dec(R8::A);
+        self.asm.label(done);
+        self.store(destination)
+    }
+
+    fn compare_w(
+        &mut self,
+        cond: Cond,
+        left: &Source<u16>,
+        right: &Source<u16>,
+        destination: &Destination,
+    ) -> Result<(), Error> {
+        // HL - DE, leaving the carry of the high byte
+        self.operands_w(left, right)?;
+        self.asm.ld(R8::A, R8::L);
+        self.asm.alu(Alu::Sub, R8::E);
+        self.asm.ld(R8::C, R8::A);
+        self.asm.ld(R8::A, R8::H);
+        self.asm.alu(Alu::Sbc, R8::D);
+        if let Cond::Z | Cond::NZ = cond {
+            self.asm.alu(Alu::Or, R8::C);
+        }
+        let done = self.asm.local();
+        self.asm.ld_n(R8::A, 1);
+        self.asm.jr_cond(cond, done);
+        self.asm.dec(R8::A);
+        self.asm.label(done);
+        self.store(destination)
+    }
+
+    // control flow
+
+    fn jump_label(&self, i: usize, location: &Location) -> Result<Label, Error> {
+        let Location::Relative(rel) = location;
+        let target = i as isize + *rel as isize + 1;
+        if target < 0 || target as usize > self.routine.statements.len() {
+            return Err(Error::Jump);
+        }
+        Ok(self.statement_label(target as usize))
+    }
+
+    fn jump_cond(
+        &mut self,
+        i: usize,
+        cond: Cond,
+        location: &Location,
+        source: &Source<u8>,
+    ) -> Result<(), Error> {
+        let label = self.jump_label(i, location)?;
+        match (cond, source) {
+            (Cond::NZ, Source::Literal(0)) => {}
+            (Cond::NZ, Source::Literal(_)) | (Cond::Z, Source::Literal(0)) => self.asm.jp(label),
+            (_, Source::Literal(_)) => {}
+            _ => {
+                self.load(source)?;
+                self.asm.alu(Alu::Or, R8::A);
+                self.asm.jp_cond(cond, label);
+            }
+        }
+        Ok(())
+    }
+
+    /// Call a routine which stack frame begins `start` bytes after the current
+    /// frame. Virtual registers in use by the caller are preserved across the
+    /// call.
+    fn call(&mut self, routine: usize, start: u16) -> Result<(), Error> {
+        let reg8: Vec<_> = self.reg8.iter().copied().collect();
+        let reg16: Vec<_> = self.reg16.iter().copied().collect();
+
+        for r in &reg8 {
+            let r = self.register(*r)?;
+            self.asm.ldh_a(r);
+            self.asm.push(R16::SPorAF);
+        }
+        for r in &reg16 {
+            self.load_register_w(*r)?;
+            self.asm.push(R16::HL);
+        }
+
+        if start != 0 {
+            self.frame_pointer_add(start);
+        }
+        self.asm.call(Label::Routine(routine));
+        if start != 0 {
+            self.frame_pointer_add(start.wrapping_neg());
+        }
+
+        for r in reg16.iter().rev() {
+            let r = self.register_w(*r)?;
+            self.asm.pop(R16::HL);
+            self.asm.ld(R8::A, R8::L);
+            self.asm.ldh_at_a(r);
+            self.asm.ld(R8::A, R8::H);
+            self.asm.ldh_at_a(r + 1);
+        }
+        for r in reg8.iter().rev() {
+            let r = self.register(*r)?;
+            self.asm.pop(R16::SPorAF);
+            self.asm.ldh_at_a(r);
+        }
+        Ok(())
+    }
+
+    fn frame_pointer_add(&mut self, n: u16) {
+        let [lo, hi] = n.to_le_bytes();
+        self.asm.ldh_a(FRAME_POINTER);
+        self.asm.alu_n(Alu::Add, lo);
+        self.asm.ldh_at_a(FRAME_POINTER);
+        self.asm.ldh_a(FRAME_POINTER + 1);
+        self.asm.alu_n(Alu::Adc, hi);
+        self.asm.ldh_at_a(FRAME_POINTER + 1);
+    }
+
+    // virtual registers
+
+    fn collect_registers(&mut self) {
+        for statement in self.routine.statements.iter() {
+            self.collect_statement(statement);
+        }
+    }
+
+    fn collect_statement(&mut self, statement: &Statement) {
+        use Statement::{
+            Add, AddW, And, AndW, Dec, DecW, Div, DivS, DivSW, DivW, Eq, EqW, Greater, GreaterEq,
+            GreaterEqS, GreaterEqW, GreaterS, GreaterW, Inc, IncW, JmpCmp, JmpCmpNot, Ld, LdAddr,
+            LdW, LeftShift, LeftShiftW, Less, LessEq, LessEqS, LessEqW, LessS, LessW, Mul, MulW,
+            NotEq, NotEqW, Or, OrW, Rem, RemS, RemSW, RemW, RightShift, RightShiftS, RightShiftSW,
+            RightShiftW, Sub, SubW, Xor, XorW,
+        };
+        match statement {
+            Ld {
+                source,
+                destination,
+            }
+            | Inc {
+                source,
+                destination,
+            }
+            | Dec {
+                source,
+                destination,
+            } => {
+                self.collect_source(source);
+                self.collect_destination(destination, false);
+            }
+            LdW {
+                source,
+                destination,
+            }
+            | IncW {
+                source,
+                destination,
+            }
+            | DecW {
+                source,
+                destination,
+            } => {
+                self.collect_source_w(source);
+                self.collect_destination(destination, true);
+            }
+            LdAddr {
+                source,
+                destination,
+            } => {
+                self.collect_source_w(source);
+                self.collect_destination(destination, true);
+            }
+            Add {
+                left,
+                right,
+                destination,
+            }
+            | Sub {
+                left,
+                right,
+                destination,
+            }
+            | And {
+                left,
+                right,
+                destination,
+            }
+            | Xor {
+                left,
+                right,
+                destination,
+            }
+            | Or {
+                left,
+                right,
+                destination,
+            }
+            | LeftShift {
+                left,
+                right,
+                destination,
+            }
+            | RightShift {
+                left,
+                right,
+                destination,
+            }
+            | Mul {
+                left,
+                right,
+                destination,
+            }
+            | Div {
+                left,
+                right,
+                destination,
+            }
+            | Rem {
+                left,
+                right,
+                destination,
+            }
+            | Eq {
+                left,
+                right,
+                destination,
+            }
+            | NotEq {
+                left,
+                right,
+                destination,
+            }
+            | Greater {
+                left,
+                right,
+                destination,
+            }
+            | GreaterEq {
+                left,
+                right,
+                destination,
+            }
+            | Less {
+                left,
+                right,
+                destination,
+            }
+            | DivS {
+                left,
+                right,
+                destination,
+            }
+            | RemS {
+                left,
+                right,
+                destination,
+            }
+            | RightShiftS {
+                left,
+                right,
+                destination,
+            }
+            | GreaterS {
+                left,
+                right,
+                destination,
+            }
+            | GreaterEqS {
+                left,
+                right,
+                destination,
+            }
+            | LessS {
+                left,
+                right,
+                destination,
+            }
+            | LessEqS {
+                left,
+                right,
+                destination,
+            }
+            | LessEq {
+                left,
+                right,
+                destination,
+            } => {
+                self.collect_source(left);
+                self.collect_source(right);
+                self.collect_destination(destination, false);
+            }
+            AddW {
+                left,
+                right,
+                destination,
+            }
+            | SubW {
+                left,
+                right,
+                destination,
+            }
+            | AndW {
+                left,
+                right,
+                destination,
+            }
+            | XorW {
+                left,
+                right,
+                destination,
+            }
+            | OrW {
+                left,
+                right,
+                destination,
+            }
+            | MulW {
+                left,
+                right,
+                destination,
+            }
+            | DivW {
+                left,
+                right,
+                destination,
+            }
+            | RemW {
+                left,
+                right,
+                destination,
+            }
+            | DivSW {
+                left,
+                right,
+                destination,
+            }
+            | RemSW {
+                left,
+                right,
+                destination,
+            } => {
+                self.collect_source_w(left);
+                self.collect_source_w(right);
+                self.collect_destination(destination, true);
+            }
+            LeftShiftW {
+                left,
+                right,
+                destination,
+            }
+            | RightShiftW {
+                left,
+                right,
+                destination,
+            }
+            | RightShiftSW {
+                left,
+                right,
+                destination,
+            } => {
+                self.collect_source_w(left);
+                self.collect_source(right);
+                self.collect_destination(destination, true);
+            }
+            EqW {
+                left,
+                right,
+                destination,
+            }
+            | NotEqW {
+                left,
+                right,
+                destination,
+            }
+            | GreaterW {
+                left,
+                right,
+                destination,
+            }
+            | GreaterEqW {
+                left,
+                right,
+                destination,
+            }
+            | LessW {
+                left,
+                right,
+                destination,
+            }
+            | LessEqW {
+                left,
+                right,
+                destination,
+            } => {
+                self.collect_source_w(left);
+                self.collect_source_w(right);
+                self.collect_destination(destination, false);
+            }
+            JmpCmp { source, .. } | JmpCmpNot { source, .. } => self.collect_source(source),
+            _ => {}
+        }
+    }
+
+    fn collect_source(&mut self, source: &Source<u8>) {
+        match source {
+            Source::Register(r) => {
+                self.reg8.insert(*r);
+            }
+            Source::Pointer { base, offset } => self.collect_pointer(base, offset),
+            _ => {}
+        }
+    }
+
+    fn collect_source_w<T>(&mut self, source: &Source<T>) {
+        match source {
+            Source::Register(r) => {
+                self.reg16.insert(*r);
+            }
+            Source::Pointer { base, offset } => self.collect_pointer(base, offset),
+            _ => {}
+        }
+    }
+
+    fn collect_destination(&mut self, destination: &Destination, word: bool) {
+        match destination {
+            Destination::Register(r) if word => {
+                self.reg16.insert(*r);
+            }
+            Destination::Register(r) => {
+                self.reg8.insert(*r);
+            }
+            Destination::Pointer { base, offset } => self.collect_pointer(base, offset),
+        }
+    }
+
+    fn collect_pointer(&mut self, base: &Pointer, offset: &Option<Box<Source<u8>>>) {
+        if let Pointer::Indirect(r, _) = base {
+            self.reg16.insert(*r);
+        }
+        if let Some(offset) = offset {
+            self.collect_source(offset);
+        }
+    }
+}
@@ -0,0 +1,391 @@
+//! Encoding of inline assembly blocks.
+//!
+//! Inline assembly runs in between the lowering of two IR statements, where
+//! the CPU registers hold no values. Therefore, the instructions are free to
+//! clobber any register except for `SP`, which must be left balanced.
+use crate::{
+    ir::opcodes::{
+        asm::{
+            Cond as IrCond, Immediate, Indirect, Item, Mnemonic, Operand, R16 as IrR16, R8 as IrR8,
+        },
+        Pointer,
+    },
+    target::lr35902::{
+        asm::{Alu, Asm, Cond, Label, R16, R8},
+        Error, STATIC,
+    },
+};
+
+/// Address or value known either at compile time, or once all the code has
+/// been emitted.
+enum Word {
+    Value(u16),
+    Label(Label, u16),
+}
+
+/// Encode an inline assembly block.
+pub fn emit(asm: &mut Asm, items: &[Item]) -> Result<(), Error> {
+    let labels: Vec<Label> = items
+        .iter()
+        .filter(|item| matches!(item, Item::Label(_)))
+        .map(|_| asm.local())
+        .collect();
+    for item in items {
+        match item {
+            Item::Label(label) => asm.label(labels[*label]),
+            Item::Instruction { mnemonic, operands } => {
+                instruction(asm, &labels, *mnemonic, operands).ok_or_else(|| Error::InlineAsm {
+                    mnemonic: *mnemonic,
+                    operands: operands.clone(),
+                })?
+            }
+        }
+    }
+    Ok(())
+}
+
+// Returns None if the instruction can't be encoded.
+#[rustfmt::skip]
+fn instruction(
+    asm: &mut Asm,
+    labels: &[Label],
+    mnemonic: Mnemonic,
+    operands: &[Operand],
+) -> Option<()> {
+    use Operand::{Cond as C, Immediate as I, Indirect as M, R16 as W, R8 as B};
+    match (mnemonic, operands) {
+        (Mnemonic::Nop, []) => asm.nop(),
+        (Mnemonic::Stop, []) => asm.bytes(&[0x10, 0x00]),
+        (Mnemonic::Halt, []) => asm.halt(),
+        (Mnemonic::Di, []) => asm.di(),
+        (Mnemonic::Ei, []) => asm.byte(0xfb),
+        (Mnemonic::Daa, []) => asm.byte(0x27),
+        (Mnemonic::Scf, []) => asm.byte(0x37),
+        (Mnemonic::Cpl, []) => asm.byte(0x2f),
+        (Mnemonic::Ccf, []) => asm.byte(0x3f),
+        (Mnemonic::Rlca, []) => asm.byte(0x07),
+        (Mnemonic::Rla, []) => asm.byte(0x17),
+        (Mnemonic::Rrca, []) => asm.byte(0x0f),
+        (Mnemonic::Rra, []) => asm.byte(0x1f),
+        (Mnemonic::Reti, []) => asm.reti(),
+
+        (Mnemonic::Ld, [dst, src]) => ld(asm, dst, src)?,
+        (Mnemonic::Ldh, [M(Indirect::C), B(IrR8::A)]) => asm.byte(0xe2),
+        (Mnemonic::Ldh, [B(IrR8::A), M(Indirect::C)]) => asm.byte(0xf2),
+        (Mnemonic::Ldh, [M(Indirect::Immediate(i)), B(IrR8::A)]) => asm.ldh_at_a(high(i)?),
+        (Mnemonic::Ldh, [B(IrR8::A), M(Indirect::Immediate(i))]) => asm.ldh_a(high(i)?),
+
+        (Mnemonic::Push, [W(r)]) => asm.push(r16_af(*r)?),
+        (Mnemonic::Pop, [W(r)]) => asm.pop(r16_af(*r)?),
+        (Mnemonic::Inc, [W(r)]) => asm.inc16(r16_sp(*r)?),
+        (Mnemonic::Dec, [W(r)]) => asm.dec16(r16_sp(*r)?),
+        (Mnemonic::Inc, [r]) => asm.inc(r8(r)?),
+        (Mnemonic::Dec, [r]) => asm.dec(r8(r)?),
+
+        (Mnemonic::Add, [W(IrR16::HL), W(r)]) => asm.add_hl(r16_sp(*r)?),
+        (Mnemonic::Add, [W(IrR16::SP), I(i)]) => asm.bytes(&[0xe8, byte(i)?]),
+        (Mnemonic::Add, [B(IrR8::A), src]) => alu(asm, Alu::Add, src)?,
+        (Mnemonic::Adc, [B(IrR8::A), src]) => alu(asm, Alu::Adc, src)?,
+        (Mnemonic::Sub, [B(IrR8::A), src]) => alu(asm, Alu::Sub, src)?,
+        (Mnemonic::Sbc, [B(IrR8::A), src]) => alu(asm, Alu::Sbc, src)?,
+        (Mnemonic::And, [B(IrR8::A), src]) => alu(asm, Alu::And, src)?,
+        (Mnemonic::Xor, [B(IrR8::A), src]) => alu(asm, Alu::Xor, src)?,
+        (Mnemonic::Or, [B(IrR8::A), src]) => alu(asm, Alu::Or, src)?,
+        (Mnemonic::Cp, [B(IrR8::A), src]) => alu(asm, Alu::Cp, src)?,
+
+        (Mnemonic::Jp, [W(IrR16::HL)]) => asm.jp_hl(),
+        (Mnemonic::Jp, [target]) => {
+            let target = address(labels, target)?;
+            asm.byte(0xc3);
+            word(asm, target);
+        }
+        (Mnemonic::Jp, [C(c), target]) => {
+            let target = address(labels, target)?;
+            asm.byte(0xc2 | (cond(*c) as u8) << 3);
+            word(asm, target);
+        }
+        (Mnemonic::Jr, [Operand::Label(l)]) => asm.jr(*labels.get(*l)?),
+        (Mnemonic::Jr, [C(c), Operand::Label(l)]) => asm.jr_cond(cond(*c), *labels.get(*l)?),
+        (Mnemonic::Call, [target]) => {
+            let target = address(labels, target)?;
+            asm.byte(0xcd);
+            word(asm, target);
+        }
+        (Mnemonic::Call, [C(c), target]) => {
+            let target = address(labels, target)?;
+            asm.byte(0xc4 | (cond(*c) as u8) << 3);
+            word(asm, target);
+        }
+        (Mnemonic::Ret, []) => asm.ret(),
+        (Mnemonic::Ret, [C(c)]) => asm.ret_cond(cond(*c)),
+        (Mnemonic::Rst, [I(Immediate::Literal(n))]) if *n <= 0x38 && *n % 8 == 0 => {
+            asm.byte(0xc7 | *n as u8)
+        }
+
+        (Mnemonic::Rlc, [r]) => asm.bytes(&[0xcb, r8(r)? as u8]),
+        (Mnemonic::Rrc, [r]) => asm.bytes(&[0xcb, 0x08 | r8(r)? as u8]),
+        (Mnemonic::Rl, [r]) => asm.rl(r8(r)?),
+        (Mnemonic::Rr, [r]) => asm.rr(r8(r)?),
+        (Mnemonic::Sla, [r]) => asm.sla(r8(r)?),
+        (Mnemonic::Sra, [r]) => asm.bytes(&[0xcb, 0x28 | r8(r)? as u8]),
+        (Mnemonic::Swap, [r]) => asm.bytes(&[0xcb, 0x30 | r8(r)? as u8]),
+        (Mnemonic::Srl, [r]) => asm.srl(r8(r)?),
+        (Mnemonic::Bit, [I(Immediate::Literal(b)), r]) if *b < 8 => {
+            asm.bytes(&[0xcb, 0x40 | (*b as u8) << 3 | r8(r)? as u8])
+        }
+        (Mnemonic::Res, [I(Immediate::Literal(b)), r]) if *b < 8 => {
+            asm.bytes(&[0xcb, 0x80 | (*b as u8) << 3 | r8(r)? as u8])
+        }
+        (Mnemonic::Set, [I(Immediate::Literal(b)), r]) if *b < 8 => {
+            asm.bytes(&[0xcb, 0xc0 | (*b as u8) << 3 | r8(r)? as u8])
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+#[rustfmt::skip]
+fn ld(asm: &mut Asm, dst: &Operand, src: &Operand) -> Option<()> {
+    use Operand::{Immediate as I, Indirect as M, R16 as W, R8 as B};
+    match (dst, src) {
+        (M(Indirect::BC), B(IrR8::A)) => asm.byte(0x02),
+        (M(Indirect::DE), B(IrR8::A)) => asm.byte(0x12),
+        (M(Indirect::HLInc), B(IrR8::A)) => asm.ld_hli_a(),
+        (M(Indirect::HLDec), B(IrR8::A)) => asm.ld_hld_a(),
+        (M(Indirect::C), B(IrR8::A)) => asm.byte(0xe2),
+        (B(IrR8::A), M(Indirect::BC)) => asm.byte(0x0a),
+        (B(IrR8::A), M(Indirect::DE)) => asm.byte(0x1a),
+        (B(IrR8::A), M(Indirect::HLInc)) => asm.ld_a_hli(),
+        (B(IrR8::A), M(Indirect::HLDec)) => asm.byte(0x3a),
+        (B(IrR8::A), M(Indirect::C)) => asm.byte(0xf2),
+        (M(Indirect::Immediate(i)), B(IrR8::A)) => {
+            let address = immediate(i)?;
+            asm.byte(0xea);
+            word(asm, address);
+        }
+        (B(IrR8::A), M(Indirect::Immediate(i))) => {
+            let address = immediate(i)?;
+            asm.byte(0xfa);
+            word(asm, address);
+        }
+        (M(Indirect::Immediate(i)), W(IrR16::SP)) => {
+            let address = immediate(i)?;
+            asm.byte(0x08);
+            word(asm, address);
+        }
+        (W(IrR16::SP), W(IrR16::HL)) => asm.byte(0xf9),
+        (W(IrR16::HL), Operand::SpOffset(e)) => asm.bytes(&[0xf8, *e as u8]),
+        (W(r), I(i)) => {
+            let value = immediate(i)?;
+            asm.byte(0x01 | (r16_sp(*r)? as u8) << 4);
+            word(asm, value);
+        }
+        (dst, I(i)) => asm.ld_n(r8(dst)?, byte(i)?),
+        (dst, src) => {
+            let (dst, src) = (r8(dst)?, r8(src)?);
+            if dst == R8::AtHl && src == R8::AtHl {
+                return None;
+            }
+            asm.ld(dst, src);
+        }
+    }
+    Some(())
+}
+
+fn alu(asm: &mut Asm, op: Alu, src: &Operand) -> Option<()> {
+    match src {
+        Operand::Immediate(i) => asm.alu_n(op, byte(i)?),
+        src => asm.alu(op, r8(src)?),
+    }
+    Some(())
+}
+
+fn word(asm: &mut Asm, word: Word) {
+    match word {
+        Word::Value(nn) => asm.word(nn),
+        Word::Label(label, addend) => asm.word_label(label, addend),
+    }
+}
+
+// Jump & call targets.
+fn address(labels: &[Label], operand: &Operand) -> Option<Word> {
+    match operand {
+        Operand::Label(l) => Some(Word::Label(*labels.get(*l)?, 0)),
+        Operand::Immediate(i) => immediate(i),
+        _ => None,
+    }
+}
+
+fn immediate(immediate: &Immediate) -> Option<Word> {
+    match immediate {
+        Immediate::Literal(nn) => Some(Word::Value(*nn)),
+        Immediate::Pointer(Pointer::Absolute(a)) => Some(Word::Value(*a)),
+        Immediate::Pointer(Pointer::Static(a)) => Some(Word::Value(STATIC + *a)),
+        Immediate::Pointer(Pointer::Const(a)) => Some(Word::Label(Label::Const, *a)),
+        Immediate::Pointer(Pointer::Stack(_))
+        | Immediate::Pointer(Pointer::Return(_))
+        | Immediate::Pointer(Pointer::Indirect(..)) => None,
+    }
+}
+
+fn byte(i: &Immediate) -> Option<u8> {
+    match immediate(i)? {
+        Word::Value(n) if n <= 0xff => Some(n as u8),
+        _ => None,
+    }
+}
+
+// Operand of the `ldh` instructions, either a full HRAM address or the offset
+// relative to `0xff00`.
+fn high(i: &Immediate) -> Option<u8> {
+    match immediate(i)? {
+        Word::Value(n) if n <= 0xff || n >= 0xff00 => Some(n as u8),
+        _ => None,
+    }
+}
+
+fn r8(operand: &Operand) -> Option<R8> {
+    Some(match operand {
+        Operand::R8(IrR8::A) => R8::A,
+        Operand::R8(IrR8::B) => R8::B,
+        Operand::R8(IrR8::C) => R8::C,
+        Operand::R8(IrR8::D) => R8::D,
+        Operand::R8(IrR8::E) => R8::E,
+        Operand::R8(IrR8::H) => R8::H,
+        Operand::R8(IrR8::L) => R8::L,
+        Operand::Indirect(Indirect::HL) => R8::AtHl,
+        _ => return None,
+    })
+}
+
+// 16bit register operands of ld, inc, dec & add.
+fn r16_sp(r: IrR16) -> Option<R16> {
+    match r {
+        IrR16::BC => Some(R16::BC),
+        IrR16::DE => Some(R16::DE),
+        IrR16::HL => Some(R16::HL),
+        IrR16::SP => Some(R16::SPorAF),
+        IrR16::AF => None,
+    }
+}
+
+// 16bit register operands of push & pop.
+fn r16_af(r: IrR16) -> Option<R16> {
+    match r {
+        IrR16::BC => Some(R16::BC),
+        IrR16::DE => Some(R16::DE),
+        IrR16::HL => Some(R16::HL),
+        IrR16::AF => Some(R16::SPorAF),
+        IrR16::SP => None,
+    }
+}
+
+fn cond(c: IrCond) -> Cond {
+    match c {
+        IrCond::NZ => Cond::NZ,
+        IrCond::Z => Cond::Z,
+        IrCond::NC => Cond::NC,
+        IrCond::C => Cond::C,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::emit;
+    use crate::{
+        ir::opcodes::{
+            asm::{Cond, Immediate, Indirect, Item, Mnemonic, Operand, R16, R8},
+            Pointer,
+        },
+        target::lr35902::asm::Asm,
+    };
+
+    fn instruction(mnemonic: Mnemonic, operands: &[Operand]) -> Item {
+        Item::Instruction {
+            mnemonic,
+            operands: operands.to_vec(),
+        }
+    }
+
+    #[test]
+    fn encoding() {
+        let items = vec![
+            instruction(Mnemonic::Ld, &[Operand::R8(R8::A), Operand::R8(R8::B)]),
+            instruction(
+                Mnemonic::Ld,
+                &[
+                    Operand::R8(R8::A),
+                    Operand::Immediate(Immediate::Literal(0x42)),
+                ],
+            ),
+            instruction(
+                Mnemonic::Ld,
+                &[
+                    Operand::R16(R16::HL),
+                    Operand::Immediate(Immediate::Pointer(Pointer::Static(2))),
+                ],
+            ),
+            instruction(
+                Mnemonic::Ld,
+                &[Operand::Indirect(Indirect::HLInc), Operand::R8(R8::A)],
+            ),
+            instruction(
+                Mnemonic::Ldh,
+                &[
+                    Operand::Indirect(Indirect::Immediate(Immediate::Literal(0xff46))),
+                    Operand::R8(R8::A),
+                ],
+            ),
+            instruction(Mnemonic::Swap, &[Operand::Indirect(Indirect::HL)]),
+            instruction(
+                Mnemonic::Bit,
+                &[
+                    Operand::Immediate(Immediate::Literal(7)),
+                    Operand::R8(R8::H),
+                ],
+            ),
+            instruction(Mnemonic::Push, &[Operand::R16(R16::AF)]),
+            instruction(Mnemonic::Ret, &[Operand::Cond(Cond::C)]),
+        ];
+        let mut asm = Asm::new(0);
+        emit(&mut asm, &items).unwrap();
+        assert_eq!(
+            vec![
+                0x78, 0x3e, 0x42, 0x21, 0x02, 0xc0, 0x22, 0xe0, 0x46, 0xcb, 0x36, 0xcb, 0x7c, 0xf5,
+                0xd8
+            ],
+            asm.finish().unwrap()
+        );
+    }
+
+    #[test]
+    fn labels() {
+        let items = vec![
+            Item::Label(0),
+            instruction(Mnemonic::Dec, &[Operand::R8(R8::B)]),
+            instruction(Mnemonic::Jr, &[Operand::Cond(Cond::NZ), Operand::Label(0)]),
+            instruction(Mnemonic::Jp, &[Operand::Label(1)]),
+            Item::Label(1),
+        ];
+        let mut asm = Asm::new(0x200);
+        emit(&mut asm, &items).unwrap();
+        assert_eq!(
+            vec![0x05, 0x20, 0xfd, 0xc3, 0x06, 0x02],
+            asm.finish().unwrap()
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        let items = vec![instruction(
+            Mnemonic::Ld,
+            &[
+                Operand::Indirect(Indirect::HL),
+                Operand::Indirect(Indirect::HL),
+            ],
+        )];
+        assert!(emit(&mut Asm::new(0), &items).is_err());
+        let items = vec![instruction(Mnemonic::Push, &[Operand::R16(R16::SP)])];
+        assert!(emit(&mut Asm::new(0), &items).is_err());
+    }
+}
@@ -0,0 +1,303 @@
+//! Cartridge ROM image.
+//!
+//! # Layout
+//! - `0x0040..0x0068`: Interrupt vectors.
+//! - `0x0100..0x0150`: Entry point & cartridge header.
+//! - `0x0150..`: Const memory, followed by the code.
+//!
+//! The image is padded to 32KB (no memory banking).
+use crate::{
+    byteorder::LittleEndian,
+    ir::Ir,
+    target::lr35902::{
+        asm::{Asm, Label, R16},
+        codegen,
+        runtime::Runtime,
+        Error, ROM_END,
+    },
+    Bytes,
+};
+
+/// Location of the program entry point.
+pub const ENTRY_POINT: u16 = 0x0100;
+
+/// Beginning of the const memory space (right after the cartridge header).
+pub const CONST: u16 = 0x0150;
+
+/// Upper bound (exclusive) of the ROM bank 0.
+pub const ROM0_END: u16 = 0x4000;
+
+const TITLE: usize = 0x134;
+const TITLE_LEN: usize = 15;
+const HEADER_CHECKSUM: usize = 0x14d;
+const GLOBAL_CHECKSUM: usize = 0x14e;
+
+const NINTENDO_LOGO: [u8; 48] = [
+    0xce, 0xed, 0x66, 0x66, 0xcc, 0x0d, 0x00, 0x0b, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0c, 0x00, 0x0d,
+    0x00, 0x08, 0x11, 0x1f, 0x88, 0x89, 0x00, 0x0e, 0xdc, 0xcc, 0x6e, 0xe6, 0xdd, 0xdd, 0xd9, 0x99,
+    0xbb, 0xbb, 0x67, 0x63, 0x6e, 0x0e, 0xec, 0xcc, 0xdd, 0xdc, 0x99, 0x9f, 0xbb, 0xb9, 0x33, 0x3e,
+];
+
+/// Cartridge type (header byte `0x147`).
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum CartridgeType {
+    RomOnly = 0x00,
+    Mbc1 = 0x01,
+    Mbc1Ram = 0x02,
+    Mbc1RamBattery = 0x03,
+    RomRam = 0x08,
+    RomRamBattery = 0x09,
+    Mbc5 = 0x19,
+    Mbc5Ram = 0x1a,
+    Mbc5RamBattery = 0x1b,
+}
+
+/// Size of the external cartridge RAM (header byte `0x149`).
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RamSize {
+    None = 0x00,
+    Kb8 = 0x02,
+    Kb32 = 0x03,
+    Kb128 = 0x04,
+    Kb64 = 0x05,
+}
+
+/// Color Game Boy support (header byte `0x143`).
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Cgb {
+    /// Original Game Boy only.
+    Unsupported = 0x00,
+    /// Works on both the original and the Color Game Boy.
+    Supported = 0x80,
+    /// Color Game Boy only.
+    Only = 0xc0,
+}
+
+/// Destination code (header byte `0x14a`).
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Region {
+    Japanese = 0x00,
+    NonJapanese = 0x01,
+}
+
+/// Builder of cartridge ROM images.
+///
+/// # Example
+/// ```
+/// use ggbc::target::lr35902::{CartridgeType, RomBuilder};
+///
+/// let builder = RomBuilder::default()
+///     .title("HELLO")
+///     .cartridge_type(CartridgeType::RomOnly)
+///     .version(1);
+/// ```
+#[derive(Debug, Clone)]
+pub struct RomBuilder {
+    title: String,
+    licensee: [u8; 2],
+    cgb: Cgb,
+    sgb: bool,
+    cartridge_type: CartridgeType,
+    ram_size: RamSize,
+    region: Region,
+    version: u8,
+}
+
+impl Default for RomBuilder {
+    fn default() -> Self {
+        Self {
+            title: String::new(),
+            licensee: [b'0', b'0'],
+            cgb: Cgb::Unsupported,
+            sgb: false,
+            cartridge_type: CartridgeType::RomOnly,
+            ram_size: RamSize::None,
+            region: Region::NonJapanese,
+            version: 0,
+        }
+    }
+}
+
+impl RomBuilder {
+    /// Game title. Up to 15 ASCII characters.
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = title.to_string();
+        self
+    }
+
+    /// Two-character new licensee code.
+    pub fn licensee(mut self, licensee: [u8; 2]) -> Self {
+        self.licensee = licensee;
+        self
+    }
+
+    /// Color Game Boy support.
+    pub fn cgb(mut self, cgb: Cgb) -> Self {
+        self.cgb = cgb;
+        self
+    }
+
+    /// Super Game Boy support.
+    pub fn sgb(mut self, sgb: bool) -> Self {
+        self.sgb = sgb;
+        self
+    }
+
+    /// Cartridge type.
+    pub fn cartridge_type(mut self, cartridge_type: CartridgeType) -> Self {
+        self.cartridge_type = cartridge_type;
+        self
+    }
+
+    /// Size of the external RAM.
+    pub fn ram_size(mut self, ram_size: RamSize) -> Self {
+        self.ram_size = ram_size;
+        self
+    }
+
+    /// Destination code.
+    pub fn region(mut self, region: Region) -> Self {
+        self.region = region;
+        self
+    }
+
+    /// ROM version number.
+    pub fn version(mut self, version: u8) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Compile the IR into a ROM image.
+    pub fn build(&self, ir: &Ir<LittleEndian>) -> Result<Bytes, Error> {
+        if self.title.len() > TITLE_LEN || !self.title.is_ascii() {
+            return Err(Error::Title(self.title.clone()));
+        }
+        if CONST as usize + ir.const_.len() > ROM0_END as usize {
+            return Err(Error::ConstOverflow(ir.const_.len()));
+        }
+
+        let mut asm = Asm::new(0);
+
+        // interrupt vectors
+        let handlers = &ir.handlers;
+        let vectors = [
+            (0x40, handlers.vblank),
+            (0x48, handlers.lcd_stat),
+            (0x50, handlers.timer),
+            (0x58, handlers.serial),
+            (0x60, handlers.joypad),
+        ];
+        for (vector, handler) in &vectors {
+            asm.org(*vector);
+            match handler {
+                Some(routine) => {
+                    asm.push(R16::SPorAF);
+                    asm.push(R16::HL);
+                    asm.ld_nn_label(R16::HL, Label::Routine(*routine), 0);
+                    asm.jp(Label::Runtime(Runtime::Interrupt));
+                }
+                None => asm.reti(),
+            }
+        }
+
+        // entry point
+        asm.org(ENTRY_POINT);
+        asm.nop();
+        asm.jp(Label::Runtime(Runtime::Entry));
+
+        // header (checksums are computed at the end)
+        asm.bytes(&NINTENDO_LOGO);
+        let mut title = [0; TITLE_LEN];
+        title[..self.title.len()].copy_from_slice(self.title.as_bytes());
+        asm.bytes(&title);
+        asm.bytes(&[self.cgb as u8]);
+        asm.bytes(&self.licensee);
+        asm.bytes(&[
+            if self.sgb { 0x03 } else { 0x00 },
+            self.cartridge_type as u8,
+            // 32KB
+            0x00,
+            self.ram_size as u8,
+            self.region as u8,
+            // use new licensee code
+            0x33,
+            self.version,
+            0x00,
+            0x00,
+            0x00,
+        ]);
+
+        asm.label(Label::Const);
+        asm.bytes(&ir.const_);
+        codegen::codegen(&mut asm, ir)?;
+
+        let code_len = asm.len();
+        if code_len > ROM_END as usize {
+            return Err(Error::RomOverflow(code_len));
+        }
+        asm.org(ROM_END);
+
+        let mut rom = asm.finish()?;
+        rom[HEADER_CHECKSUM] = rom[TITLE..HEADER_CHECKSUM]
+            .iter()
+            .fold(0u8, |x, b| x.wrapping_sub(*b).wrapping_sub(1));
+        let global = rom
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != GLOBAL_CHECKSUM && *i != GLOBAL_CHECKSUM + 1)
+            .fold(0u16, |x, (_, b)| x.wrapping_add(*b as u16));
+        rom[GLOBAL_CHECKSUM..GLOBAL_CHECKSUM + 2].copy_from_slice(&global.to_be_bytes());
+        Ok(rom.into_boxed_slice())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RomBuilder;
+    use crate::{byteorder::LittleEndian, ir::Ir, parser};
+
+    fn build(builder: RomBuilder) -> Box<[u8]> {
+        let (ast, errors) = parser::parse("static FOO:u8 (= FOO 42)");
+        assert!(errors.is_empty(), "{:?}", errors);
+        let ir: Ir<LittleEndian> = Ir::new(&ast).unwrap();
+        builder.build(&ir).unwrap()
+    }
+
+    #[test]
+    fn header() {
+        let rom = build(RomBuilder::default().title("GGBC").version(2));
+        assert_eq!(0x8000, rom.len());
+        assert_eq!(&[0x00, 0xc3], &rom[0x100..0x102]);
+        assert_eq!(&[0xce, 0xed, 0x66, 0x66], &rom[0x104..0x108]);
+        assert_eq!(b"GGBC\0", &rom[0x134..0x139]);
+        assert_eq!(2, rom[0x14c]);
+        // no interrupt handlers
+        assert_eq!(0xd9, rom[0x40]);
+    }
+
+    #[test]
+    fn checksums() {
+        let rom = build(RomBuilder::default().title("CHECKSUM"));
+        let header = rom[0x134..0x14d]
+            .iter()
+            .fold(0u8, |x, b| x.wrapping_sub(*b).wrapping_sub(1));
+        assert_eq!(header, rom[0x14d]);
+        let global = rom
+            .iter()
+            .take(0x14e)
+            .chain(rom.iter().skip(0x150))
+            .fold(0u16, |x, b| x.wrapping_add(*b as u16));
+        assert_eq!(global.to_be_bytes(), [rom[0x14e], rom[0x14f]]);
+    }
+
+    #[test]
+    fn title() {
+        let (ast, errors) = parser::parse("");
+        assert!(errors.is_empty(), "{:?}", errors);
+        let ir: Ir<LittleEndian> = Ir::new(&ast).unwrap();
+        assert!(RomBuilder::default()
+            .title("A VERY LONG GAME TITLE")
+            .build(&ir)
+            .is_err());
+    }
+}
@@ -0,0 +1,449 @@
+//! Runtime support routines.
+//!
+//! Operations that don't map to a short sequence of instructions (multiply,
+//! divide, shifts, ...) are implemented as subroutines shared by the whole
+//! program.
+//!
+//! 8bit routines take the left operand in `A` and the right operand in `C`.
+//! 16bit routines take the left operand in `HL` and the right operand in `DE`
+//! (or `C` in the case of shifts). Results are returned in `A` and `HL`
+//! respectively. Any other register may be clobbered.
+use crate::target::lr35902::{
+    asm::{Alu, Asm, Cond, Label, R16, R8},
+    FRAME_POINTER, INTERRUPT_TARGET, STATIC, WRAM_END,
+};
+
+// Interrupt flag & interrupt enable registers (`0xff00 + n`).
+const IF: u8 = 0x0f;
+const IE: u8 = 0xff;
+
+/// Runtime routines.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Runtime {
+    /// Program entry point.
+    Entry,
+    /// Successful termination of the program.
+    Stop,
+    /// Erroneous termination of the program.
+    Panic,
+    /// Common part of the interrupt handlers.
+    Interrupt,
+    Mul,
+    Div,
+    Rem,
+    DivMod,
+    DivS,
+    RemS,
+    DivModS,
+    LeftShift,
+    RightShift,
+    RightShiftS,
+    MulW,
+    DivW,
+    RemW,
+    DivModW,
+    DivSW,
+    RemSW,
+    DivModSW,
+    LeftShiftW,
+    RightShiftW,
+    RightShiftSW,
+}
+
+/// Emit the runtime routines.
+///
+/// The entry point clears the RAM, initializes both the hardware stack pointer
+/// and the frame pointer (the first stack frame is located at `frame`), and
+/// jumps into the `main` routine.
+///
+/// Interrupt handlers run with their stack frames beginning at
+/// `interrupt_frame`. The entry point enables the `interrupts` in the IE
+/// register mask before jumping into `main`.
+pub fn emit(asm: &mut Asm, frame: u16, interrupt_frame: u16, interrupts: u8, main: usize) {
+    entry(asm, frame, interrupts, main);
+    stop(asm);
+    interrupt(asm, interrupt_frame);
+    mul(asm);
+    div_mod(asm);
+    div_mod_s(asm);
+    shift(asm);
+    mul_w(asm);
+    div_mod_w(asm);
+    div_mod_sw(asm);
+    shift_w(asm);
+}
+
+fn entry(asm: &mut Asm, frame: u16, interrupts: u8, main: usize) {
+    asm.label(Label::Runtime(Runtime::Entry));
+    asm.di();
+    asm.ld_nn(R16::SPorAF, WRAM_END);
+
+    // clear WRAM
+    let clear_wram = asm.local();
+    asm.ld_nn(R16::HL, STATIC);
+    asm.ld_nn(R16::BC, WRAM_END - STATIC);
+    asm.label(clear_wram);
+    asm.alu(Alu::Xor, R8::A);
+    asm.ld_hli_a();
+    asm.dec16(R16::BC);
+    asm.ld(R8::A, R8::B);
+    asm.alu(Alu::Or, R8::C);
+    asm.jr_cond(Cond::NZ, clear_wram);
+
+    // clear HRAM (except the IE register)
+    let clear_hram = asm.local();
+    asm.ld_nn(R16::HL, 0xff80);
+    asm.ld_n(R8::B, 0x7f);
+    asm.label(clear_hram);
+    asm.ld_hli_a();
+    asm.dec(R8::B);
+    asm.jr_cond(Cond::NZ, clear_hram);
+
+    set_frame_pointer(asm, frame);
+
+    // enable the interrupts with a handler (discarding any pending request)
+    if interrupts != 0 {
+        asm.alu(Alu::Xor, R8::A);
+        asm.ldh_at_a(IF);
+        asm.ld_n(R8::A, interrupts);
+        asm.ldh_at_a(IE);
+        asm.ei();
+    }
+    asm.jp(Label::Routine(main));
+}
+
+fn set_frame_pointer(asm: &mut Asm, frame: u16) {
+    let [lo, hi] = frame.to_le_bytes();
+    asm.ld_n(R8::A, lo);
+    asm.ldh_at_a(FRAME_POINTER);
+    asm.ld_n(R8::A, hi);
+    asm.ldh_at_a(FRAME_POINTER + 1);
+}
+
+// The interrupt vectors push AF & HL, load the address of the handler routine
+// into HL, and jump here. The rest of the CPU registers and the HRAM state
+// (frame pointer, virtual registers, and return memory) are saved in the
+// hardware stack before calling the handler, and restored afterwards.
+fn interrupt(asm: &mut Asm, interrupt_frame: u16) {
+    let words = (INTERRUPT_TARGET - FRAME_POINTER) / 2;
+    asm.label(Label::Runtime(Runtime::Interrupt));
+    asm.ld(R8::A, R8::L);
+    asm.ldh_at_a(INTERRUPT_TARGET);
+    asm.ld(R8::A, R8::H);
+    asm.ldh_at_a(INTERRUPT_TARGET + 1);
+    asm.push(R16::BC);
+    asm.push(R16::DE);
+
+    let save = asm.local();
+    asm.ld_nn(R16::HL, 0xff00 | FRAME_POINTER as u16);
+    asm.ld_n(R8::C, words);
+    asm.label(save);
+    asm.ld_a_hli();
+    asm.ld(R8::E, R8::A);
+    asm.ld_a_hli();
+    asm.ld(R8::D, R8::A);
+    asm.push(R16::DE);
+    asm.dec(R8::C);
+    asm.jr_cond(Cond::NZ, save);
+
+    set_frame_pointer(asm, interrupt_frame);
+    let jp_hl = asm.local();
+    asm.ldh_a(INTERRUPT_TARGET);
+    asm.ld(R8::L, R8::A);
+    asm.ldh_a(INTERRUPT_TARGET + 1);
+    asm.ld(R8::H, R8::A);
+    asm.call(jp_hl);
+
+    let restore = asm.local();
+    asm.ld_nn(R16::HL, 0xff00 | (INTERRUPT_TARGET - 1) as u16);
+    asm.ld_n(R8::C, words);
+    asm.label(restore);
+    asm.pop(R16::DE);
+    asm.ld(R8::A, R8::D);
+    asm.ld_hld_a();
+    asm.ld(R8::A, R8::E);
+    asm.ld_hld_a();
+    asm.dec(R8::C);
+    asm.jr_cond(Cond::NZ, restore);
+
+    asm.pop(R16::DE);
+    asm.pop(R16::BC);
+    asm.pop(R16::HL);
+    asm.pop(R16::SPorAF);
+    asm.reti();
+
+    asm.label(jp_hl);
+    asm.jp_hl();
+}
+
+fn stop(asm: &mut Asm) {
+    // the `ld b, b` instruction is used as a software breakpoint by most
+    // debuggers & emulators.
+    asm.label(Label::Runtime(Runtime::Panic));
+    asm.ld(R8::B, R8::B);
+    asm.label(Label::Runtime(Runtime::Stop));
+    asm.di();
+    let halt = asm.local();
+    asm.label(halt);
+    asm.halt();
+    asm.nop();
+    asm.jr(halt);
+}
+
+fn mul(asm: &mut Asm) {
+    let lp = asm.local();
+    asm.label(Label::Runtime(Runtime::Mul));
+    asm.ld(R8::B, R8::A);
+    asm.alu(Alu::Xor, R8::A);
+    asm.inc(R8::C);
+    asm.label(lp);
+    asm.dec(R8::C);
+    asm.ret_cond(Cond::Z);
+    asm.alu(Alu::Add, R8::B);
+    asm.jr(lp);
+}
+
+// A / C -> quotient in B, remainder in A
+fn div_mod(asm: &mut Asm) {
+    asm.label(Label::Runtime(Runtime::Div));
+    asm.call(Label::Runtime(Runtime::DivMod));
+    asm.ld(R8::A, R8::B);
+    asm.ret();
+
+    asm.label(Label::Runtime(Runtime::Rem));
+    asm.label(Label::Runtime(Runtime::DivMod));
+    asm.ld(R8::B, R8::A);
+    asm.ld(R8::A, R8::C);
+    asm.alu(Alu::Or, R8::A);
+    asm.jp_cond(Cond::Z, Label::Runtime(Runtime::Panic));
+    asm.ld(R8::A, R8::B);
+    asm.ld_n(R8::B, 0);
+    let lp = asm.local();
+    asm.label(lp);
+    asm.alu(Alu::Cp, R8::C);
+    asm.ret_cond(Cond::C);
+    asm.alu(Alu::Sub, R8::C);
+    asm.inc(R8::B);
+    asm.jr(lp);
+}
+
+// Signed A / C, rounding towards zero -> quotient in B, remainder in C
+fn div_mod_s(asm: &mut Asm) {
+    asm.label(Label::Runtime(Runtime::DivS));
+    asm.call(Label::Runtime(Runtime::DivModS));
+    asm.ld(R8::A, R8::B);
+    asm.ret();
+
+    asm.label(Label::Runtime(Runtime::RemS));
+    asm.call(Label::Runtime(Runtime::DivModS));
+    asm.ld(R8::A, R8::C);
+    asm.ret();
+
+    // divide the magnitudes, keeping the sign of the dividend in D and the
+    // sign of the quotient in E
+    asm.label(Label::Runtime(Runtime::DivModS));
+    asm.ld(R8::D, R8::A);
+    asm.alu(Alu::Xor, R8::C);
+    asm.ld(R8::E, R8::A);
+    let divisor = asm.local();
+    asm.bit(7, R8::C);
+    asm.jr_cond(Cond::Z, divisor);
+    asm.alu(Alu::Xor, R8::A);
+    asm.alu(Alu::Sub, R8::C);
+    asm.ld(R8::C, R8::A);
+    asm.label(divisor);
+    let dividend = asm.local();
+    asm.ld(R8::A, R8::D);
+    asm.bit(7, R8::A);
+    asm.jr_cond(Cond::Z, dividend);
+    asm.cpl();
+    asm.inc(R8::A);
+    asm.label(dividend);
+    asm.call(Label::Runtime(Runtime::DivMod));
+
+    // the remainder takes the sign of the dividend
+    let remainder = asm.local();
+    asm.bit(7, R8::D);
+    asm.jr_cond(Cond::Z, remainder);
+    asm.cpl();
+    asm.inc(R8::A);
+    asm.label(remainder);
+    asm.ld(R8::C, R8::A);
+    asm.bit(7, R8::E);
+    asm.ret_cond(Cond::Z);
+    asm.alu(Alu::Xor, R8::A);
+    asm.alu(Alu::Sub, R8::B);
+    asm.ld(R8::B, R8::A);
+    asm.ret();
+}
+
+fn shift(asm: &mut Asm) {
+    for (label, op) in &[
+        (Runtime::LeftShift, Asm::sla as fn(&mut Asm, R8)),
+        (Runtime::RightShift, Asm::srl),
+        (Runtime::RightShiftS, Asm::sra),
+    ] {
+        let lp = asm.local();
+        asm.label(Label::Runtime(*label));
+        asm.inc(R8::C);
+        asm.label(lp);
+        asm.dec(R8::C);
+        asm.ret_cond(Cond::Z);
+        op(asm, R8::A);
+        asm.jr(lp);
+    }
+}
+
+fn mul_w(asm: &mut Asm) {
+    // shift-and-add, starting from the most significant bit of DE
+    asm.label(Label::Runtime(Runtime::MulW));
+    asm.ld(R8::B, R8::H);
+    asm.ld(R8::C, R8::L);
+    asm.ld_nn(R16::HL, 0);
+    asm.ld_n(R8::A, 16);
+    let lp = asm.local();
+    let skip = asm.local();
+    asm.label(lp);
+    asm.add_hl(R16::HL);
+    asm.sla(R8::E);
+    asm.rl(R8::D);
+    asm.jr_cond(Cond::NC, skip);
+    asm.add_hl(R16::BC);
+    asm.label(skip);
+    asm.dec(R8::A);
+    asm.jr_cond(Cond::NZ, lp);
+    asm.ret();
+}
+
+// HL / DE -> quotient in HL, remainder in BC
+fn div_mod_w(asm: &mut Asm) {
+    asm.label(Label::Runtime(Runtime::DivW));
+    asm.jp(Label::Runtime(Runtime::DivModW));
+
+    asm.label(Label::Runtime(Runtime::RemW));
+    asm.call(Label::Runtime(Runtime::DivModW));
+    asm.ld(R8::H, R8::B);
+    asm.ld(R8::L, R8::C);
+    asm.ret();
+
+    // restoring division
+    asm.label(Label::Runtime(Runtime::DivModW));
+    asm.ld(R8::A, R8::D);
+    asm.alu(Alu::Or, R8::E);
+    asm.jp_cond(Cond::Z, Label::Runtime(Runtime::Panic));
+    asm.ld_nn(R16::BC, 0);
+    asm.ld_n(R8::A, 16);
+    let lp = asm.local();
+    let sub = asm.local();
+    let next = asm.local();
+    asm.label(lp);
+    asm.push(R16::SPorAF);
+    asm.add_hl(R16::HL);
+    asm.rl(R8::C);
+    asm.rl(R8::B);
+    // a carry out of the remainder means it is larger than any divisor
+    asm.jr_cond(Cond::C, sub);
+    asm.ld(R8::A, R8::C);
+    asm.alu(Alu::Sub, R8::E);
+    asm.ld(R8::A, R8::B);
+    asm.alu(Alu::Sbc, R8::D);
+    asm.jr_cond(Cond::C, next);
+    asm.label(sub);
+    asm.ld(R8::A, R8::C);
+    asm.alu(Alu::Sub, R8::E);
+    asm.ld(R8::C, R8::A);
+    asm.ld(R8::A, R8::B);
+    asm.alu(Alu::Sbc, R8::D);
+    asm.ld(R8::B, R8::A);
+    asm.inc(R8::L);
+    asm.label(next);
+    asm.pop(R16::SPorAF);
+    asm.dec(R8::A);
+    asm.jr_cond(Cond::NZ, lp);
+    asm.ret();
+}
+
+// Signed HL / DE, rounding towards zero -> quotient in HL, remainder in BC
+fn div_mod_sw(asm: &mut Asm) {
+    asm.label(Label::Runtime(Runtime::DivSW));
+    asm.jp(Label::Runtime(Runtime::DivModSW));
+
+    asm.label(Label::Runtime(Runtime::RemSW));
+    asm.call(Label::Runtime(Runtime::DivModSW));
+    asm.ld(R8::H, R8::B);
+    asm.ld(R8::L, R8::C);
+    asm.ret();
+
+    // divide the magnitudes, keeping the sign of the dividend and the sign of
+    // the quotient in the stack
+    asm.label(Label::Runtime(Runtime::DivModSW));
+    asm.ld(R8::B, R8::H);
+    asm.ld(R8::A, R8::H);
+    asm.alu(Alu::Xor, R8::D);
+    asm.ld(R8::C, R8::A);
+    asm.push(R16::BC);
+    let divisor = asm.local();
+    asm.bit(7, R8::D);
+    asm.jr_cond(Cond::Z, divisor);
+    neg_w(asm, R8::D, R8::E);
+    asm.label(divisor);
+    let dividend = asm.local();
+    asm.bit(7, R8::H);
+    asm.jr_cond(Cond::Z, dividend);
+    neg_w(asm, R8::H, R8::L);
+    asm.label(dividend);
+    asm.call(Label::Runtime(Runtime::DivModW));
+    asm.pop(R16::DE);
+
+    // the remainder takes the sign of the dividend
+    let remainder = asm.local();
+    asm.bit(7, R8::D);
+    asm.jr_cond(Cond::Z, remainder);
+    neg_w(asm, R8::B, R8::C);
+    asm.label(remainder);
+    asm.bit(7, R8::E);
+    asm.ret_cond(Cond::Z);
+    neg_w(asm, R8::H, R8::L);
+    asm.ret();
+}
+
+// negate the 16bit value in the `hi` & `lo` registers (clobbers A)
+fn neg_w(asm: &mut Asm, hi: R8, lo: R8) {
+    asm.alu(Alu::Xor, R8::A);
+    asm.alu(Alu::Sub, lo);
+    asm.ld(lo, R8::A);
+    asm.alu(Alu::Sbc, R8::A);
+    asm.alu(Alu::Sub, hi);
+    asm.ld(hi, R8::A);
+}
+
+fn shift_w(asm: &mut Asm) {
+    let lp = asm.local();
+    asm.label(Label::Runtime(Runtime::LeftShiftW));
+    asm.inc(R8::C);
+    asm.label(lp);
+    asm.dec(R8::C);
+    asm.ret_cond(Cond::Z);
+    asm.add_hl(R16::HL);
+    asm.jr(lp);
+
+    let lp = asm.local();
+    asm.label(Label::Runtime(Runtime::RightShiftW));
+    asm.inc(R8::C);
+    asm.label(lp);
+    asm.dec(R8::C);
+    asm.ret_cond(Cond::Z);
+    asm.srl(R8::H);
+    asm.rr(R8::L);
+    asm.jr(lp);
+
+    let lp = asm.local();
+    asm.label(Label::Runtime(Runtime::RightShiftSW));
+    asm.inc(R8::C);
+    asm.label(lp);
+    asm.dec(R8::C);
+    asm.ret_cond(Cond::Z);
+    asm.sra(R8::H);
+    asm.rr(R8::L);
+    asm.jr(lp);
+}
@@ -0,0 +1,450 @@
+use ggbc::{
+    check::{check, TypeError},
+    ir::Layout,
+};
+
+fn errors(input: &str) -> Vec<TypeError> {
+    let (ast, errors) = ggbc::parser::parse(input);
+    assert!(errors.is_empty(), "{:?}", errors);
+    check(&ast).err().unwrap_or_default()
+}
+
+#[test]
+fn valid() {
+    let errors = errors(
+        r#"
+        static FOO:struct { a:u8 b:[i8 2] }
+        const BAR:[u8 3] = [1 2 3]
+        fn add(a:u8 b:u8):u8 { return (+ a b) }
+        fn neg(a:i8):i8 { return (- 0 a) }
+        let a:u8 = (add 1 2)
+        let b:i8 = (neg -128)
+        let c:&u8 = @a
+        (= *c (+ ([1]BAR) 4))
+        (= ([0]FOO::b) b)
+        (+= FOO::a 1)
+        for i:u8 in 0..4 { (= a (* a i)) }
+        if (== a 0) { (= c @FOO::a) }
+        "#,
+    );
+    assert!(errors.is_empty(), "{:?}", errors);
+}
+
+#[test]
+fn mismatch() {
+    let errors = errors(
+        r#"
+        let a:u8 = 0
+        let b:i8 = a
+        let c:&i8 = @a
+        "#,
+    );
+    assert!(matches!(
+        errors.as_slice(),
+        [
+            TypeError::Mismatch {
+                expected: Layout::I8,
+                found: Layout::U8,
+                ..
+            },
+            TypeError::Mismatch { .. },
+        ]
+    ));
+}
+
+#[test]
+fn literal_range() {
+    let errors = errors(
+        r#"
+        let a:u8 = 256
+        let b:i8 = 128
+        let c:i8 = -128
+        "#,
+    );
+    assert!(matches!(
+        errors.as_slice(),
+        [
+            TypeError::LiteralRange {
+                layout: Layout::U8,
+                ..
+            },
+            TypeError::LiteralRange {
+                layout: Layout::I8,
+                ..
+            },
+        ]
+    ));
+}
+
+#[test]
+fn call() {
+    let errors = errors(
+        r#"
+        fn foo(a:u8 b:&u8) {}
+        fn bar:i8 { return 0 }
+        let x:u8 = 0
+        (foo 1)
+        (foo 1 x)
+        let a:u8 = (bar)
+        let b:u8 = (foo 1 @a)
+        "#,
+    );
+    assert!(matches!(
+        errors.as_slice(),
+        [
+            TypeError::Arity {
+                expected: 2,
+                found: 1,
+                ..
+            },
+            TypeError::Mismatch { .. },
+            TypeError::Mismatch {
+                expected: Layout::U8,
+                found: Layout::I8,
+                ..
+            },
+            TypeError::Void { .. },
+        ]
+    ));
+}
+
+#[test]
+fn return_() {
+    let errors = errors(
+        r#"
+        fn foo:u8 { return }
+        fn bar { return 1 }
+        fn baz:&u8 { return 1 }
+        "#,
+    );
+    assert!(matches!(
+        errors.as_slice(),
+        [
+            TypeError::MissingReturn {
+                expected: Layout::U8,
+                ..
+            },
+            TypeError::UnexpectedReturn { .. },
+        ]
+    ));
+}
+
+#[test]
+fn pointers() {
+    let errors = errors(
+        r#"
+        let a:u8 = 0
+        let b:u8 = *a
+        let c:&u8 = @(+ a 1)
+        "#,
+    );
+    assert!(matches!(
+        errors.as_slice(),
+        [
+            TypeError::NotPointer {
+                found: Layout::U8,
+                ..
+            },
+            TypeError::NotAddressable { .. },
+        ]
+    ));
+}
+
+#[test]
+fn pointer_arithmetic() {
+    let errors = errors(
+        r#"
+        let a:[u16 4] = [0 0 0 0]
+        let i:u8 = 1
+        let s:i8 = -1
+        let p:&u16 = @([0]a)
+        let q:&u16 = (+ p i)
+        (+= q 2)
+        (-= q i)
+        (= *(- q 1) 0x1234)
+        if (== q 0) { (= q p) }
+        if (< p q) { (= q p) }
+        let r:&u16 = (+ p s)
+        (*= q 2)
+        let t:&u8 = (+ p 1)
+        if (== p @i) {}
+        "#,
+    );
+    assert!(matches!(
+        errors.as_slice(),
+        [
+            TypeError::NotOffset {
+                found: Layout::I8,
+                ..
+            },
+            TypeError::NotNumeric { .. },
+            TypeError::Mismatch { .. },
+            TypeError::Mismatch { .. },
+        ]
+    ));
+}
+
+#[test]
+fn assign() {
+    let errors = errors(
+        r#"
+        let a:[u8 2] = [0 0]
+        let b:u8 = 0
+        (= (+ b 1) 2)
+        (+= a 1)
+        (= ([b]b) 0)
+        "#,
+    );
+    assert!(matches!(
+        errors.as_slice(),
+        [
+            TypeError::NotAssignable { .. },
+            TypeError::NotNumeric { .. },
+            TypeError::NotArray { .. },
+        ]
+    ));
+}
+
+#[test]
+fn words() {
+    let errors = errors(
+        r#"
+        let a:u16 = 0x1234
+        let b:i16 = -32768
+        let c:u8 = 4
+        (= a (<< a c))
+        (+= b (- 300 b))
+        let d:u16 = 0x10000
+        let e:i16 = 32768
+        let f:u8 = a
+        "#,
+    );
+    assert!(matches!(
+        errors.as_slice(),
+        [
+            TypeError::LiteralRange {
+                layout: Layout::U16,
+                ..
+            },
+            TypeError::LiteralRange {
+                layout: Layout::I16,
+                ..
+            },
+            TypeError::Mismatch {
+                expected: Layout::U8,
+                found: Layout::U16,
+                ..
+            },
+        ]
+    ));
+}
+
+#[test]
+fn structs() {
+    let errors = errors(
+        r#"
+        struct Point { x:u8 y:i8 }
+        static P:Point
+        static L:[Point 2]
+        (= P.x 1)
+        (= ([1]L).y -1)
+        let a:i8 = P.x
+        let b:u8 = P.z
+        let c:u8 = (+ 1 2).x
+        "#,
+    );
+    assert!(matches!(
+        errors.as_slice(),
+        [
+            TypeError::Mismatch {
+                expected: Layout::I8,
+                found: Layout::U8,
+                ..
+            },
+            TypeError::UndefinedField { .. },
+            TypeError::NotStruct { .. },
+        ]
+    ));
+}
+
+#[test]
+fn match_() {
+    let errors = errors(
+        r#"
+        enum State { Title Playing = 4 Paused }
+        static STATE:State
+        static POINT:struct { x:u8 y:u8 }
+        let a:i8 = -1
+        match STATE {
+            State::Title State::Playing { (= STATE State::Paused) }
+            else { }
+        }
+        match a { -1 { } 1 { } }
+        match STATE { 256 { } }
+        match POINT { 0 { } }
+        "#,
+    );
+    assert!(matches!(
+        errors.as_slice(),
+        [
+            TypeError::LiteralRange {
+                layout: Layout::U8,
+                ..
+            },
+            TypeError::NotNumeric { .. },
+        ]
+    ));
+}
+
+#[test]
+fn loops() {
+    let errors = errors(
+        r#"
+        static POINT:struct { x:u8 y:u8 }
+        let n:u8 = 4
+        while n { (-= n 1) }
+        while POINT { }
+        for i:u16 in 0..=0x400 step 0x100 { }
+        for i:u8 in 4..0 step 0x100 { }
+        "#,
+    );
+    assert!(matches!(
+        errors.as_slice(),
+        [
+            TypeError::NotNumeric { .. },
+            TypeError::LiteralRange {
+                layout: Layout::U8,
+                ..
+            },
+        ]
+    ));
+}
+
+#[test]
+fn logical() {
+    let errors = errors(
+        r#"
+        static POINT:struct { x:u8 y:u8 }
+        let a:bool = (and true (or false not 1))
+        let b:u16 = 0x100
+        let c:bool = (and a b)
+        let d:u16 = (or a b)
+        let e:bool = (and a POINT)
+        "#,
+    );
+    assert!(matches!(
+        errors.as_slice(),
+        [
+            TypeError::Mismatch {
+                expected: Layout::U16,
+                found: Layout::U8,
+                ..
+            },
+            TypeError::NotNumeric { .. },
+        ]
+    ));
+}
+
+#[test]
+fn bool_() {
+    let errors = errors(
+        r#"
+        let a:bool = true
+        let b:bool = 1
+        let c:u8 = 2
+        (= b not a)
+        let x:bool = 3
+        let y:u8 = a
+        let z:bool = c
+        (= a 2)
+        (= c b)
+        "#,
+    );
+    assert!(matches!(
+        errors.as_slice(),
+        [
+            TypeError::BoolRange { .. },
+            TypeError::FromBool { .. },
+            TypeError::NotBool { .. },
+            TypeError::BoolRange { .. },
+            TypeError::FromBool { .. },
+        ]
+    ));
+}
+
+#[test]
+fn bool_operators() {
+    let errors = errors(
+        r#"
+        let a:u8 = 1
+        let b:bool = (and a (< a 2))
+        (= b (== a 0))
+        (= b not b)
+        let c:u8 = (or b a)
+        let d:u8 = (>= a 1)
+        (= a not b)
+        (= a (~= a 1))
+        let e:bool = (+ a 1)
+        "#,
+    );
+    assert!(matches!(
+        errors.as_slice(),
+        [
+            TypeError::FromBool { .. },
+            TypeError::FromBool { .. },
+            TypeError::FromBool { .. },
+            TypeError::FromBool { .. },
+            TypeError::NotBool { .. },
+        ]
+    ));
+}
+
+#[test]
+fn type_alias() {
+    let errors = errors(
+        r#"
+        type Tile = [u8 4]
+        type Word = union { word:u16 bytes:[u8 2] }
+        struct Point { x:u8 y:i8 }
+        const TILES:[Tile 2] = [[1 2 3 4] "abcd"]
+        const WORD:Word = { bytes:[0 1] }
+        let p:Point = { y:-1 x:1 }
+        let q:Point = { x:1 }
+        let r:Point = { x:1 y:2 z:3 }
+        let s:Point = { x:1 x:2 y:3 }
+        let t:Word = { word:0 bytes:[0 0] }
+        let u:Tile = { x:0 }
+        let v:Point = { x:1 y:0x80 }
+        "#,
+    );
+    assert!(matches!(
+        errors.as_slice(),
+        [
+            TypeError::MissingField { name, .. },
+            TypeError::UndefinedField { .. },
+            TypeError::DuplicateField { .. },
+            TypeError::UnionFields { .. },
+            TypeError::NotStruct { .. },
+            TypeError::LiteralRange {
+                layout: Layout::I8,
+                ..
+            },
+        ] if name == "y"
+    ));
+}
+
+#[test]
+fn include() {
+    let errors = errors(
+        r#"
+        include FONT:[[u8 16] 2] "font.2bpp"
+        include TILE:[u8 16] 42
+        let a:u8 = ([1]([0]FONT))
+        let b:u16 = ([0]FONT)
+        "#,
+    );
+    assert!(matches!(
+        errors.as_slice(),
+        [TypeError::ExpectedString { .. }, TypeError::Mismatch { .. }]
+    ));
+}
@@ -2,8 +2,9 @@ use ggbc::{byteorder::NativeEndian, ir::Ir, parser::parse};
 use vm::{Machine, Opts};
 
 fn _test_const(input: &str, gt: &[u8]) {
-    let ast = parse(input).unwrap();
-    let ir: Ir<NativeEndian> = Ir::new(&ast);
+    let (ast, errors) = parse(input);
+    assert!(errors.is_empty(), "{:?}", errors);
+    let ir: Ir<NativeEndian> = Ir::new(&ast).unwrap();
     let result = &ir.const_[..gt.len()];
     assert_eq!(gt, result);
 }
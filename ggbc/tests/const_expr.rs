@@ -3,8 +3,9 @@ use ggbc::{byteorder::NativeEndian, ir::Ir, parser::parse};
 use vm::{Machine, Opts};
 
 fn test_const_expr(input: &str) {
-    let ast = parse(input).unwrap();
-    let ir: Ir<NativeEndian> = Ir::new(&ast);
+    let (ast, errors) = parse(input);
+    assert!(errors.is_empty(), "{:?}", errors);
+    let ir: Ir<NativeEndian> = Ir::new(&ast).unwrap();
     let result = Machine::new(&ir, Opts::default()).run().static_[0];
     assert_eq!(0xff, result);
 }
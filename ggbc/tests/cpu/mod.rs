@@ -0,0 +1,490 @@
+//! Minimal LR35902 interpreter used to run the code generated by the LR35902
+//! target. It only models the CPU and a flat 64KB address space (no PPU, no
+//! timers, no memory banking, ...).
+#![allow(dead_code)]
+
+const Z: u8 = 0x80;
+const N: u8 = 0x40;
+const H: u8 = 0x20;
+const C: u8 = 0x10;
+
+/// Reason why the CPU stopped running.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Exit {
+    /// `halt` executed with interrupts disabled.
+    Halt,
+    /// `halt` executed right after a `ld b, b` breakpoint (program panic).
+    Panic,
+    /// Ran out of cycles.
+    Timeout,
+}
+
+pub struct Cpu {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+    pub ime: bool,
+    pub memory: Box<[u8]>,
+    breakpoint: bool,
+}
+
+impl Cpu {
+    /// Create a CPU which memory begins with `rom`.
+    pub fn new(rom: &[u8], pc: u16) -> Self {
+        let mut memory = vec![0; 0x10000].into_boxed_slice();
+        memory[..rom.len()].copy_from_slice(rom);
+        Self {
+            a: 0,
+            f: 0,
+            b: 0,
+            c: 0,
+            d: 0,
+            e: 0,
+            h: 0,
+            l: 0,
+            sp: 0xfffe,
+            pc,
+            ime: false,
+            memory,
+            breakpoint: false,
+        }
+    }
+
+    /// Run until the program halts or `steps` instructions have been executed.
+    pub fn run(&mut self, steps: usize) -> Exit {
+        for _ in 0..steps {
+            let opcode = self.fetch();
+            if opcode == 0x76 {
+                return if self.breakpoint {
+                    Exit::Panic
+                } else {
+                    Exit::Halt
+                };
+            }
+            self.breakpoint |= opcode == 0x40;
+            self.execute(opcode);
+        }
+        Exit::Timeout
+    }
+
+    /// Request an interrupt, if enabled (both IME and its IE bit), by jumping to
+    /// its vector.
+    pub fn interrupt(&mut self, vector: u16) -> bool {
+        let bit = 1 << ((vector - 0x40) / 8);
+        if self.ime && self.read(0xffff) & bit != 0 {
+            self.ime = false;
+            self.push(self.pc);
+            self.pc = vector;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        self.memory[address as usize]
+    }
+
+    pub fn write(&mut self, address: u16, data: u8) {
+        self.memory[address as usize] = data;
+    }
+
+    fn fetch(&mut self) -> u8 {
+        let byte = self.read(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        byte
+    }
+
+    fn fetch_word(&mut self) -> u16 {
+        let lo = self.fetch();
+        let hi = self.fetch();
+        u16::from_le_bytes([lo, hi])
+    }
+
+    fn push(&mut self, word: u16) {
+        let [lo, hi] = word.to_le_bytes();
+        self.sp = self.sp.wrapping_sub(1);
+        self.write(self.sp, hi);
+        self.sp = self.sp.wrapping_sub(1);
+        self.write(self.sp, lo);
+    }
+
+    fn pop(&mut self) -> u16 {
+        let lo = self.read(self.sp);
+        self.sp = self.sp.wrapping_add(1);
+        let hi = self.read(self.sp);
+        self.sp = self.sp.wrapping_add(1);
+        u16::from_le_bytes([lo, hi])
+    }
+
+    fn hl(&self) -> u16 {
+        u16::from_le_bytes([self.l, self.h])
+    }
+
+    fn r16(&self, r: u8) -> u16 {
+        match r {
+            0 => u16::from_le_bytes([self.c, self.b]),
+            1 => u16::from_le_bytes([self.e, self.d]),
+            2 => self.hl(),
+            _ => self.sp,
+        }
+    }
+
+    fn set_r16(&mut self, r: u8, word: u16) {
+        let [lo, hi] = word.to_le_bytes();
+        match r {
+            0 => {
+                self.b = hi;
+                self.c = lo;
+            }
+            1 => {
+                self.d = hi;
+                self.e = lo;
+            }
+            2 => {
+                self.h = hi;
+                self.l = lo;
+            }
+            _ => self.sp = word,
+        }
+    }
+
+    fn r8(&self, r: u8) -> u8 {
+        match r {
+            0 => self.b,
+            1 => self.c,
+            2 => self.d,
+            3 => self.e,
+            4 => self.h,
+            5 => self.l,
+            6 => self.read(self.hl()),
+            _ => self.a,
+        }
+    }
+
+    fn set_r8(&mut self, r: u8, data: u8) {
+        match r {
+            0 => self.b = data,
+            1 => self.c = data,
+            2 => self.d = data,
+            3 => self.e = data,
+            4 => self.h = data,
+            5 => self.l = data,
+            6 => self.write(self.hl(), data),
+            _ => self.a = data,
+        }
+    }
+
+    fn cond(&self, cc: u8) -> bool {
+        match cc {
+            0 => self.f & Z == 0,
+            1 => self.f & Z != 0,
+            2 => self.f & C == 0,
+            _ => self.f & C != 0,
+        }
+    }
+
+    fn flags(&mut self, z: bool, n: bool, h: bool, c: bool) {
+        self.f = (if z { Z } else { 0 })
+            | (if n { N } else { 0 })
+            | (if h { H } else { 0 })
+            | (if c { C } else { 0 });
+    }
+
+    fn alu(&mut self, op: u8, data: u8) {
+        let a = self.a;
+        let carry = (self.f & C != 0) as u8;
+        match op {
+            // add, adc
+            0 | 1 => {
+                let carry = if op == 1 { carry } else { 0 };
+                let res = a as u16 + data as u16 + carry as u16;
+                let h = (a & 0xf) + (data & 0xf) + carry > 0xf;
+                self.a = res as u8;
+                self.flags(self.a == 0, false, h, res > 0xff);
+            }
+            // sub, sbc, cp
+            2 | 3 | 7 => {
+                let carry = if op == 3 { carry } else { 0 };
+                let res = a as i16 - data as i16 - carry as i16;
+                let h = ((a & 0xf) as i16) - ((data & 0xf) as i16) - (carry as i16) < 0;
+                self.flags(res as u8 == 0, true, h, res < 0);
+                if op != 7 {
+                    self.a = res as u8;
+                }
+            }
+            4 => {
+                self.a &= data;
+                self.flags(self.a == 0, false, true, false);
+            }
+            5 => {
+                self.a ^= data;
+                self.flags(self.a == 0, false, false, false);
+            }
+            _ => {
+                self.a |= data;
+                self.flags(self.a == 0, false, false, false);
+            }
+        }
+    }
+
+    fn rot(&mut self, op: u8, data: u8) -> u8 {
+        let carry = self.f & C != 0;
+        let (res, c) = match op {
+            0 => (data.rotate_left(1), data & 0x80 != 0),
+            1 => (data.rotate_right(1), data & 1 != 0),
+            2 => (data << 1 | carry as u8, data & 0x80 != 0),
+            3 => (data >> 1 | (carry as u8) << 7, data & 1 != 0),
+            4 => (data << 1, data & 0x80 != 0),
+            5 => ((data as i8 >> 1) as u8, data & 1 != 0),
+            6 => (data.rotate_left(4), false),
+            _ => (data >> 1, data & 1 != 0),
+        };
+        self.flags(res == 0, false, false, c);
+        res
+    }
+
+    fn execute_cb(&mut self) {
+        let opcode = self.fetch();
+        let r = opcode & 7;
+        let bit = (opcode >> 3) & 7;
+        let data = self.r8(r);
+        match opcode >> 6 {
+            0 => {
+                let res = self.rot(bit, data);
+                self.set_r8(r, res);
+            }
+            1 => {
+                let carry = self.f & C != 0;
+                self.flags(data & (1 << bit) == 0, false, true, carry);
+            }
+            2 => self.set_r8(r, data & !(1 << bit)),
+            _ => self.set_r8(r, data | (1 << bit)),
+        }
+    }
+
+    fn execute(&mut self, opcode: u8) {
+        let x = opcode >> 6;
+        let y = (opcode >> 3) & 7;
+        let z = opcode & 7;
+        let p = y >> 1;
+        match opcode {
+            0x00 | 0x10 => {}
+            0x08 => {
+                let nn = self.fetch_word();
+                let [lo, hi] = self.sp.to_le_bytes();
+                self.write(nn, lo);
+                self.write(nn.wrapping_add(1), hi);
+            }
+            0x18 => {
+                let e = self.fetch() as i8;
+                self.pc = self.pc.wrapping_add(e as u16);
+            }
+            0x20 | 0x28 | 0x30 | 0x38 => {
+                let e = self.fetch() as i8;
+                if self.cond(y - 4) {
+                    self.pc = self.pc.wrapping_add(e as u16);
+                }
+            }
+            0x02 => self.write(self.r16(0), self.a),
+            0x12 => self.write(self.r16(1), self.a),
+            0x22 | 0x32 => {
+                let hl = self.hl();
+                self.write(hl, self.a);
+                let hl = if opcode == 0x22 {
+                    hl.wrapping_add(1)
+                } else {
+                    hl.wrapping_sub(1)
+                };
+                self.set_r16(2, hl);
+            }
+            0x0a => self.a = self.read(self.r16(0)),
+            0x1a => self.a = self.read(self.r16(1)),
+            0x2a | 0x3a => {
+                let hl = self.hl();
+                self.a = self.read(hl);
+                let hl = if opcode == 0x2a {
+                    hl.wrapping_add(1)
+                } else {
+                    hl.wrapping_sub(1)
+                };
+                self.set_r16(2, hl);
+            }
+            0x07 | 0x0f | 0x17 | 0x1f => {
+                self.a = self.rot(y, self.a);
+                self.f &= !Z;
+            }
+            0x27 => {
+                let mut a = self.a;
+                let mut carry = self.f & C != 0;
+                if self.f & N == 0 {
+                    if carry || a > 0x99 {
+                        a = a.wrapping_add(0x60);
+                        carry = true;
+                    }
+                    if self.f & H != 0 || a & 0xf > 0x9 {
+                        a = a.wrapping_add(0x6);
+                    }
+                } else {
+                    if carry {
+                        a = a.wrapping_sub(0x60);
+                    }
+                    if self.f & H != 0 {
+                        a = a.wrapping_sub(0x6);
+                    }
+                }
+                self.a = a;
+                let n = self.f & N != 0;
+                self.flags(a == 0, n, false, carry);
+            }
+            0x2f => {
+                self.a = !self.a;
+                self.f |= N | H;
+            }
+            0x37 => self.f = (self.f & Z) | C,
+            0x3f => self.f = (self.f & Z) | ((self.f & C) ^ C),
+            0xcb => self.execute_cb(),
+            0xe0 => {
+                let n = self.fetch();
+                self.write(0xff00 | n as u16, self.a);
+            }
+            0xf0 => {
+                let n = self.fetch();
+                self.a = self.read(0xff00 | n as u16);
+            }
+            0xe2 => self.write(0xff00 | self.c as u16, self.a),
+            0xf2 => self.a = self.read(0xff00 | self.c as u16),
+            0xea => {
+                let nn = self.fetch_word();
+                self.write(nn, self.a);
+            }
+            0xfa => {
+                let nn = self.fetch_word();
+                self.a = self.read(nn);
+            }
+            0xe8 | 0xf8 => {
+                let e = self.fetch() as i8 as u16;
+                let sp = self.sp;
+                let res = sp.wrapping_add(e);
+                let h = (sp & 0xf) + (e & 0xf) > 0xf;
+                let c = (sp & 0xff) + (e & 0xff) > 0xff;
+                self.flags(false, false, h, c);
+                if opcode == 0xe8 {
+                    self.sp = res;
+                } else {
+                    self.set_r16(2, res);
+                }
+            }
+            0xf9 => self.sp = self.hl(),
+            0xe9 => self.pc = self.hl(),
+            0xc3 => self.pc = self.fetch_word(),
+            0xc2 | 0xca | 0xd2 | 0xda => {
+                let nn = self.fetch_word();
+                if self.cond(y) {
+                    self.pc = nn;
+                }
+            }
+            0xcd => {
+                let nn = self.fetch_word();
+                self.push(self.pc);
+                self.pc = nn;
+            }
+            0xc4 | 0xcc | 0xd4 | 0xdc => {
+                let nn = self.fetch_word();
+                if self.cond(y) {
+                    self.push(self.pc);
+                    self.pc = nn;
+                }
+            }
+            0xc9 => self.pc = self.pop(),
+            0xd9 => {
+                self.pc = self.pop();
+                self.ime = true;
+            }
+            0xc0 | 0xc8 | 0xd0 | 0xd8 => {
+                if self.cond(y) {
+                    self.pc = self.pop();
+                }
+            }
+            0xf3 => self.ime = false,
+            0xfb => self.ime = true,
+            _ => match (x, z) {
+                (0, 1) if y & 1 == 0 => {
+                    let nn = self.fetch_word();
+                    self.set_r16(p, nn);
+                }
+                (0, 1) => {
+                    let hl = self.hl();
+                    let rr = self.r16(p);
+                    let res = hl as u32 + rr as u32;
+                    let h = (hl & 0xfff) + (rr & 0xfff) > 0xfff;
+                    let z = self.f & Z != 0;
+                    self.flags(z, false, h, res > 0xffff);
+                    self.set_r16(2, res as u16);
+                }
+                (0, 3) if y & 1 == 0 => self.set_r16(p, self.r16(p).wrapping_add(1)),
+                (0, 3) => self.set_r16(p, self.r16(p).wrapping_sub(1)),
+                (0, 4) => {
+                    let data = self.r8(y);
+                    let res = data.wrapping_add(1);
+                    let c = self.f & C != 0;
+                    self.flags(res == 0, false, data & 0xf == 0xf, c);
+                    self.set_r8(y, res);
+                }
+                (0, 5) => {
+                    let data = self.r8(y);
+                    let res = data.wrapping_sub(1);
+                    let c = self.f & C != 0;
+                    self.flags(res == 0, true, data & 0xf == 0, c);
+                    self.set_r8(y, res);
+                }
+                (0, 6) => {
+                    let n = self.fetch();
+                    self.set_r8(y, n);
+                }
+                (1, _) => {
+                    let data = self.r8(z);
+                    self.set_r8(y, data);
+                }
+                (2, _) => {
+                    let data = self.r8(z);
+                    self.alu(y, data);
+                }
+                (3, 6) => {
+                    let n = self.fetch();
+                    self.alu(y, n);
+                }
+                (3, 1) => {
+                    let word = self.pop();
+                    if p == 3 {
+                        let [lo, hi] = word.to_le_bytes();
+                        self.a = hi;
+                        self.f = lo & 0xf0;
+                    } else {
+                        self.set_r16(p, word);
+                    }
+                }
+                (3, 5) => {
+                    let word = if p == 3 {
+                        u16::from_le_bytes([self.f, self.a])
+                    } else {
+                        self.r16(p)
+                    };
+                    self.push(word);
+                }
+                (3, 7) => {
+                    self.push(self.pc);
+                    self.pc = (y as u16) << 3;
+                }
+                _ => panic!("Illegal opcode: {:#04x}", opcode),
+            },
+        }
+    }
+}
@@ -1,14 +1,19 @@
-use ggbc::{byteorder::NativeEndian, ir::Ir};
+use ggbc::{
+    byteorder::NativeEndian,
+    ir::{Charmap, Ir, IrBuilder, SemanticError},
+};
 
 fn test(size: u16, input: &str) {
-    let ast = ggbc::parser::parse(input).unwrap();
-    let ir: Ir<NativeEndian> = Ir::new(&ast);
+    let (ast, errors) = ggbc::parser::parse(input);
+    assert!(errors.is_empty(), "{:?}", errors);
+    let ir: Ir<NativeEndian> = Ir::new(&ast).unwrap();
     assert_eq!(size, ir.routines[0].stack_size);
 }
 
 fn test_routine(size: u16, input: &str) {
-    let ast = ggbc::parser::parse(input).unwrap();
-    let ir: Ir<NativeEndian> = Ir::new(&ast);
+    let (ast, errors) = ggbc::parser::parse(input);
+    assert!(errors.is_empty(), "{:?}", errors);
+    let ir: Ir<NativeEndian> = Ir::new(&ast).unwrap();
     assert_eq!(size, ir.routines[1].stack_size);
 }
 
@@ -164,3 +169,409 @@ fn static_usage_10() {
         "#,
     );
 }
+
+#[test]
+fn call_return_layout() {
+    let error = |input: &str| {
+        let (ast, errors) = ggbc::parser::parse(input);
+        assert!(errors.is_empty(), "{:?}", errors);
+        Ir::<NativeEndian>::new(&ast).unwrap_err()
+    };
+    let error_0 = error("fn f:u16 {return 1} let a:u8 = (f)");
+    assert_eq!(
+        "Mismatched types: expected `u8`, found `u16`",
+        error_0.message
+    );
+    let error_1 = error("fn f { } let a:u8 = (f)");
+    assert_eq!("Expected an expression of type `u8`", error_1.message);
+}
+
+#[test]
+fn jump_out_of_range() {
+    let ir = |statements: usize| {
+        let input = format!("let a:u8 = 0 loop {{ {} }}", "(+= a 1) ".repeat(statements));
+        let (ast, errors) = ggbc::parser::parse(&input);
+        assert!(errors.is_empty(), "{:?}", errors);
+        Ir::<NativeEndian>::new(&ast)
+    };
+    assert!(ir(100).is_ok());
+    let error = ir(200).unwrap_err();
+    assert_eq!("Block too large to jump over", error.message);
+}
+
+#[test]
+fn index_offset_range() {
+    let ir = |size: usize| {
+        let input = format!("static A:[[u8 {}] 2] let i:u8 = 1 (= ([0]([i]A)) 1)", size);
+        let (ast, errors) = ggbc::parser::parse(&input);
+        assert!(errors.is_empty(), "{:?}", errors);
+        Ir::<NativeEndian>::new(&ast)
+    };
+    assert!(ir(255).is_ok());
+    let error = ir(256).unwrap_err();
+    assert_eq!("Array index offset out of range", error.message);
+}
+
+#[test]
+fn handlers() {
+    let (ast, errors) = ggbc::parser::parse(
+        r#"
+        fn foo { }
+        fn @vblank bar { }
+        fn @joypad baz { }
+        (foo)
+        "#,
+    );
+    assert!(errors.is_empty(), "{:?}", errors);
+    let ir: Ir<NativeEndian> = Ir::new(&ast).unwrap();
+    assert_eq!(Some("main"), ir.main().debug_name.as_deref());
+    assert_eq!(Some("bar"), ir.vblank().unwrap().debug_name.as_deref());
+    assert_eq!(Some("baz"), ir.joypad().unwrap().debug_name.as_deref());
+    assert!(ir.lcd_stat().is_none());
+    assert!(ir.timer().is_none());
+    assert!(ir.serial().is_none());
+}
+
+#[test]
+fn mod_mangling() {
+    let (ast, errors) = ggbc::parser::parse(
+        r#"
+        mod a {
+            mod b {
+                fn foo { }
+            }
+            fn bar { (b::foo) }
+        }
+        (a::bar)
+        "#,
+    );
+    assert!(errors.is_empty(), "{:?}", errors);
+    let ir: Ir<NativeEndian> = Ir::new(&ast).unwrap();
+    let names: Vec<_> = ir
+        .routines
+        .iter()
+        .map(|r| r.debug_name.as_deref())
+        .collect();
+    assert_eq!(vec![Some("main"), Some("a::b::foo"), Some("a::bar")], names);
+}
+
+#[test]
+fn use_modules() {
+    use ggbc::parser::source::load;
+    use std::collections::HashMap;
+
+    let mut sources = HashMap::new();
+    sources.insert(
+        String::new(),
+        "use a::b::foo use c (foo) (c::baz)".to_string(),
+    );
+    sources.insert("a::b".to_string(), "fn foo { }".to_string());
+    sources.insert(
+        "c".to_string(),
+        "use a::b mod d { } fn baz { (b::foo) }".to_string(),
+    );
+    let sources = load(&sources).unwrap();
+    let modules = sources.parse().unwrap();
+    let ir: Ir<NativeEndian> = Ir::with_modules(&modules).unwrap();
+    let names: Vec<_> = ir
+        .routines
+        .iter()
+        .map(|r| r.debug_name.as_deref())
+        .collect();
+    assert_eq!(vec![Some("main"), Some("a::b::foo"), Some("c::baz")], names);
+}
+
+#[test]
+fn missing_entry_module() {
+    use ggbc::parser::source::load;
+    use std::collections::HashMap;
+
+    let builder = IrBuilder::default();
+    let error = builder.build_modules::<NativeEndian>(&[]).unwrap_err();
+    assert_eq!("Missing entry module", error.message);
+
+    let mut sources = HashMap::new();
+    sources.insert(String::new(), "use a".to_string());
+    sources.insert("a".to_string(), "fn foo { }".to_string());
+    let sources = load(&sources).unwrap();
+    let mut modules = sources.parse().unwrap();
+    // entry module first
+    modules.reverse();
+    let error = builder.build_modules::<NativeEndian>(&modules).unwrap_err();
+    assert_eq!("Missing entry module", error.message);
+}
+
+#[test]
+fn include() {
+    use ggbc::parser::source::load;
+    use std::collections::HashMap;
+
+    let mut sources = HashMap::new();
+    sources.insert(
+        String::new(),
+        r#"use gfx::font include LOGO:[u8 2] "logo.bin""#.to_string(),
+    );
+    sources.insert(
+        "gfx::font".to_string(),
+        r#"include TILES:[[u8 2] 2] "font.2bpp""#.to_string(),
+    );
+    let mut assets = HashMap::new();
+    assets.insert("logo.bin".to_string(), vec![1, 2]);
+    assets.insert("gfx/font.2bpp".to_string(), vec![3, 4, 5, 6]);
+
+    let sources = load(&sources).unwrap();
+    let modules = sources.parse().unwrap();
+    let builder = IrBuilder::default().assets(assets);
+    let ir: Ir<NativeEndian> = builder.build_modules(&modules).unwrap();
+    assert_eq!(&[3, 4, 5, 6, 1, 2], &ir.const_[..]);
+
+    let error = |input: &str| {
+        let (ast, errors) = ggbc::parser::parse(input);
+        assert!(errors.is_empty(), "{:?}", errors);
+        builder.build::<NativeEndian>(&ast).unwrap_err()
+    };
+    let error_0 = error(r#"include LOGO:[u8 4] "logo.bin""#);
+    assert_eq!("Expected a file of 4 bytes, found 2", error_0.message);
+    assert_eq!([0, 20], error_0.span.min);
+    let error_1 = error(r#"include LOGO:[u8 2] "font.2bpp""#);
+    assert_eq!("File not found: `font.2bpp`", error_1.message);
+
+    let (ast, _) = ggbc::parser::parse(r#"include LOGO:[u8 2] "logo.bin""#);
+    let error_2 = Ir::<NativeEndian>::new(&ast).unwrap_err();
+    assert_eq!("Can't include `logo.bin`", error_2.message);
+}
+
+#[test]
+#[cfg(feature = "png")]
+fn include_png() {
+    use png::{BitDepth, ColorType, Encoder};
+    use std::collections::HashMap;
+
+    // encode a (8 bits per pixel) image as PNG
+    fn png(width: u32, color: ColorType, pixels: &[u8]) -> Vec<u8> {
+        let mut png = Vec::new();
+        let height = pixels.len() as u32 / width;
+        let mut encoder = Encoder::new(&mut png, width, height);
+        encoder.set_color(color);
+        encoder.set_depth(BitDepth::Eight);
+        if color == ColorType::Indexed {
+            encoder.set_palette(vec![0xff; 3 * 4]);
+        }
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(pixels).unwrap();
+        writer.finish().unwrap();
+        png
+    }
+
+    // blank, black, blank and gradient tiles
+    let mut pixels = vec![0; 8 * 32];
+    pixels[64..128].copy_from_slice(&[3; 64]);
+    for (i, pixel) in pixels[192..].iter_mut().enumerate() {
+        *pixel = (i % 4) as u8;
+    }
+    let mut gray = vec![0xff; 64];
+    gray[..4].copy_from_slice(&[0x00, 0x55, 0xaa, 0xff]);
+    let mut assets = HashMap::new();
+    assets.insert("bg.png".to_string(), png(8, ColorType::Indexed, &pixels));
+    assets.insert("gray.png".to_string(), png(8, ColorType::Grayscale, &gray));
+    assets.insert("size.png".to_string(), png(4, ColorType::Indexed, &[0; 16]));
+    let builder = IrBuilder::default().assets(assets);
+    let ir = |input: &str| {
+        let (ast, errors) = ggbc::parser::parse(input);
+        assert!(errors.is_empty(), "{:?}", errors);
+        builder.build::<NativeEndian>(&ast)
+    };
+
+    let tiles = ir(r#"include TILES:[[u8 16] 4] "bg.png""#).unwrap();
+    assert_eq!(64, tiles.const_.len());
+    assert_eq!(&[0; 16], &tiles.const_[..16]);
+    assert_eq!(&[0xff; 16], &tiles.const_[16..32]);
+    assert_eq!(&[0x55, 0x33], &tiles.const_[48..50]);
+
+    let map = ir(r#"include TILES:[u8 48] MAP:[u8 4] "bg.png""#).unwrap();
+    assert_eq!(&tiles.const_[..16], &map.const_[..16]);
+    assert_eq!(&tiles.const_[48..64], &map.const_[32..48]);
+    assert_eq!(&[0, 1, 0, 2], &map.const_[48..]);
+
+    let gray = ir(r#"include TILE:[u8 16] "gray.png""#).unwrap();
+    assert_eq!(&[0xa0, 0xc0], &gray.const_[..2]);
+
+    let error_0 = ir(r#"include TILES:[u8 64] MAP:[u8 4] "bg.png""#).unwrap_err();
+    assert_eq!("Expected tile data of 64 bytes, found 48", error_0.message);
+    let error_1 = ir(r#"include TILES:[u8 48] MAP:[u8 2] "bg.png""#).unwrap_err();
+    assert_eq!("Expected a tilemap of 2 bytes, found 4", error_1.message);
+    let error_2 = ir(r#"include TILE:[u8 16] "size.png""#).unwrap_err();
+    assert_eq!("Failed to import `size.png`", error_2.message);
+    assert_eq!(
+        vec!["Image size (4x4) is not a multiple of 8"],
+        error_2.notes
+    );
+    let error_3 = ir(r#"include TILE:[u8 16] MAP:[u8 1] "tile.2bpp""#).unwrap_err();
+    assert_eq!(
+        "Tilemaps can only be generated from `.png` images",
+        error_3.message
+    );
+}
+
+#[test]
+fn inline_asm() {
+    use ggbc::ir::opcodes::{
+        asm::{Cond, Immediate, Indirect, Item, Mnemonic, Operand, R16, R8},
+        Pointer, Statement,
+    };
+
+    let (ast, errors) = ggbc::parser::parse(
+        r#"
+        static FOO:u8
+        const BAR:u8 = 42
+        asm {
+        start:
+            .ld %hl FOO
+            .ld %a BAR
+            .ld [FOO] %a
+            .jp %c start
+        }
+        "#,
+    );
+    assert!(errors.is_empty(), "{:?}", errors);
+    let ir: Ir<NativeEndian> = Ir::new(&ast).unwrap();
+    let expected = Statement::Asm(vec![
+        Item::Label(0),
+        Item::Instruction {
+            mnemonic: Mnemonic::Ld,
+            operands: vec![
+                Operand::R16(R16::HL),
+                Operand::Immediate(Immediate::Pointer(Pointer::Static(0))),
+            ],
+        },
+        Item::Instruction {
+            mnemonic: Mnemonic::Ld,
+            operands: vec![
+                Operand::R8(R8::A),
+                Operand::Immediate(Immediate::Literal(42)),
+            ],
+        },
+        Item::Instruction {
+            mnemonic: Mnemonic::Ld,
+            operands: vec![
+                Operand::Indirect(Indirect::Immediate(Immediate::Pointer(Pointer::Static(0)))),
+                Operand::R8(R8::A),
+            ],
+        },
+        Item::Instruction {
+            mnemonic: Mnemonic::Jp,
+            operands: vec![Operand::Cond(Cond::C), Operand::Label(0)],
+        },
+    ]);
+    assert!(ir.main().statements.contains(&expected));
+}
+
+#[test]
+fn pointer_arithmetic() {
+    use ggbc::ir::opcodes::{Destination, Pointer, Source, Statement};
+
+    let (ast, errors) = ggbc::parser::parse(
+        r#"
+        static FOO:[u16 4]
+        let p:&u16 = @([0]FOO)
+        (= *(+ p 2) 7)
+        "#,
+    );
+    assert!(errors.is_empty(), "{:?}", errors);
+    let ir: Ir<NativeEndian> = Ir::new(&ast).unwrap();
+    let expected = [
+        Statement::AddW {
+            left: Source::Pointer {
+                base: Pointer::Stack(0),
+                offset: None,
+            },
+            right: Source::Literal(4),
+            destination: Destination::Register(0),
+        },
+        Statement::LdW {
+            source: Source::Literal(7),
+            destination: Destination::Pointer {
+                base: Pointer::Indirect(0, 0),
+                offset: None,
+            },
+        },
+    ];
+    let statements = &ir.main().statements;
+    assert!(statements.windows(2).any(|w| w == expected));
+}
+
+#[test]
+fn semantic_errors() {
+    fn error(input: &str) -> SemanticError {
+        let (ast, errors) = ggbc::parser::parse(input);
+        assert!(errors.is_empty(), "{:?}", errors);
+        Ir::<NativeEndian>::new(&ast).unwrap_err()
+    }
+
+    let error_0 = error("fn @vblank foo {} fn @vblank bar {}");
+    assert_eq!("Interrupt handler already defined", error_0.message);
+    assert_eq!(1, error_0.notes.len());
+
+    let error_1 = error("fn foo:u8 { return 1 } let a:u8 = (+ (foo) 1)");
+    assert_eq!(
+        "Function calls in expressions not yet supported",
+        error_1.message
+    );
+    assert_eq!([0, 37], error_1.span.min);
+
+    let error_2 = error("mod a { !! }");
+    assert_eq!("Statement not allowed in a mod", error_2.message);
+
+    let error_3 = error("enum Foo { A = 255 B }");
+    assert_eq!("Enum discriminant overflow", error_3.message);
+    assert_eq!([0, 19], error_3.span.min);
+
+    let error_4 = error("static FOO:u8 let a:u8 = 0 match a { FOO { } }");
+    assert_eq!(
+        "Match patterns must be constant expressions",
+        error_4.message
+    );
+
+    let error_5 = error("for i:u8 in 0..4 step 0 { }");
+    assert_eq!("For range step can't be zero", error_5.message);
+
+    let error_6 = error("let s:u8 = 2 for i:u8 in 0..4 step s { }");
+    assert_eq!(
+        "For range step must be a constant expression",
+        error_6.message
+    );
+
+    let error_7 = error("for i:u8 in 250..+10 { }");
+    assert_eq!("For range end out of bounds", error_7.message);
+    assert_eq!(1, error_7.notes.len());
+
+    let error_8 = error("const A:[u8 2] = [1 2] const B:u8 = ([2]A)");
+    assert_eq!("Index out of bounds", error_8.message);
+    assert_eq!([0, 38], error_8.span.min);
+
+    let error_9 = error("const A:[u8 2] = \"abc\"");
+    assert_eq!("Expected a string of 2 bytes, found 3", error_9.message);
+
+    let error_10 = error("const A:[u8 2] = \"\\q\"");
+    assert_eq!("Invalid escape sequence", error_10.message);
+
+    let error_11 = error("const A:u8 = 'ab'");
+    assert_eq!(
+        "Character literals must contain exactly one character",
+        error_11.message
+    );
+}
+
+#[test]
+fn charmap() {
+    let (ast, errors) = ggbc::parser::parse(r#"const A:[u8 3] = "0\x20:" const B:u8 = '1'"#);
+    assert!(errors.is_empty(), "{:?}", errors);
+    let builder = IrBuilder::default().charmap(Charmap::empty().chars("0123456789:", 0x10));
+    let ir: Ir<NativeEndian> = builder.build(&ast).unwrap();
+    assert_eq!(&[0x10, 0x20, 0x1a, 0x11], &ir.const_[..4]);
+
+    let builder = builder.charmap(Charmap::empty());
+    let error = builder.build::<NativeEndian>(&ast).unwrap_err();
+    assert_eq!("Character '0' is not in the charmap", error.message);
+    assert_eq!([0, 17], error.span.min);
+}
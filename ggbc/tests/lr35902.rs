@@ -0,0 +1,219 @@
+use cpu::{Cpu, Exit};
+use ggbc::target::{
+    lr35902::{Error, ENTRY_POINT, STATIC},
+    LR35902,
+};
+
+mod cpu;
+
+fn run(input: &str) -> (Exit, Cpu) {
+    let rom = ggbc::compile::<LR35902>(input).unwrap();
+    let mut cpu = Cpu::new(&rom, ENTRY_POINT);
+    let exit = cpu.run(10_000_000);
+    (exit, cpu)
+}
+
+fn static_(cpu: &Cpu, len: usize) -> &[u8] {
+    &cpu.memory[STATIC as usize..STATIC as usize + len]
+}
+
+macro_rules! test {
+    ( $(#[$($meta:meta)+])* fn $fn_name:ident, $test:ident, $exit:expr, [$($byte:expr),*]) => {
+        #[test]
+        $(#[$($meta)+])*
+        fn $fn_name() {
+            let input = include_str!(concat!("../../vm/tests/programs/", stringify!($test), ".ggb"));
+            let expected: &[u8] = &[$($byte),*];
+            let (exit, cpu) = run(input);
+            assert_eq!($exit, exit);
+            assert_eq!(expected, static_(&cpu, expected.len()));
+        }
+    };
+    ($fn_name:ident, $exit:expr, [$($byte:expr),*]) => { test!(fn $fn_name, $fn_name, $exit, [$($byte),*]); };
+    ($fn_name:ident, [$($byte:expr),*]) => { test!(fn $fn_name, $fn_name, Exit::Halt, [$($byte),*]); };
+}
+
+test!(array_assign, [1, 2, 3, 4]);
+test!(assign, [4, 8, 2, 0xff, 0xf8, 0x8, 0x10]);
+test!(fn bool, bool, Exit::Halt, [42]);
+test!(fn break_, break, Exit::Halt, []);
+test!(compare, [1, 0, 0, 1, 1, 1]);
+test!(fn const_, const, Exit::Halt, [4, 2]);
+test!(const_data, [7, 3, 4, 111, 0x34, 8, 9, 6, 105]);
+test!(
+    string,
+    [b'o', b'"', b'\n', b'\\', 0x7f, b'!', b'\'', b'\t' + b'A']
+);
+test!(deref, [1, 2, 3]);
+test!(
+    fibonacci,
+    [
+        1, 1, 2, 3, 5, 8, 13, 21, 34, 55, 89, 144, 233, 1, 1, 2, 3, 5, 8, 13, 21, 34, 55, 89, 144,
+        233
+    ]
+);
+test!(fn for_, for, Exit::Halt, [120]);
+test!(
+    for_range,
+    [0, 6, 30, 18, 5, 15, 6, 5, 7, 0xf5, 0x0a, 0x00, 0x01, 0x05, 0x00]
+);
+test!(function, [2]);
+test!(i8, [0xf2, 0xe7, 0xf2, 0xdf, 1, 0, 1, 1]);
+test!(
+    i16,
+    [0xd6, 0xff, 0xb5, 0xff, 0x72, 0xff, 0x2a, 0, 0x9c, 0xff, 1, 0, 1, 1, 1]
+);
+test!(logic, [1, 0, 1, 0, 1, 1, 0, 1, 42, 4]);
+test!(fn loop_, loop, Exit::Halt, [120, 120, 120]);
+test!(fn match_, match, Exit::Halt, [0, 1, 5, 6, 0, 6, 20, 2, 3, 7]);
+test!(
+    memcopy,
+    [
+        2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89,
+        97, 101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181,
+        191, 193, 197, 199, 211, 223, 227, 229, 233, 239, 241, 251
+    ]
+);
+test!(minus, [0xfb, 0xff, 0x81, 0xfe, 0x18, 0xfc, 0xd4, 0xfe]);
+test!(mul, [110, 110]);
+test!(named_struct, [2, 4, 3, 1, 3, 2]);
+test!(
+    pointer,
+    [10, 7, 17, 1, 1, 0, 1, 3, 4, 0x00, 0x04, 0x34, 0x12, 0x00, 0x01]
+);
+test!(recursion, [225, 45, 233, 6]);
+test!(sort, [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]);
+test!(fn struct_, struct, Exit::Halt, [1, 2, 3, 4]);
+test!(union, [3, 4]);
+test!(
+    u16,
+    [0x1c, 0x16, 0xb4, 0xf1, 0xb8, 0x0b, 4, 0, 0x80, 0x3e, 0x58, 0x02, 0, 0, 0, 0, 0, 1, 1, 1]
+);
+test!(fn while_, while, Exit::Halt, [55, 8, 17, 0]);
+test!(not_halt, []);
+test!(halt, Exit::Timeout, []);
+
+#[test]
+fn fibonacci_recursive() {
+    let (exit, cpu) = run(include_str!(
+        "../../vm/tests/programs/fibonacci_recursive.ggb"
+    ));
+    assert_eq!(Exit::Halt, exit);
+    // RESULT is allocated after the 256 bytes of the CACHE
+    assert_eq!(
+        &[1, 1, 2, 3, 5, 8, 13, 21, 34, 55, 89, 144, 233],
+        &static_(&cpu, 256 + 13)[256..]
+    );
+}
+
+#[test]
+fn arithmetic() {
+    let (exit, cpu) = run(r#"
+        static R:[u8 7]
+        let a:u8 = 200
+        let b:u8 = 7
+        (= ([0]R) (* b 9))
+        (= ([1]R) (/ a b))
+        (= ([2]R) (<< b 4))
+        (= ([3]R) (>> a 3))
+        (= ([4]R) (- b a))
+        (= ([5]R) (^ a b))
+        (= ([6]R) (& a 0xf0))
+    "#);
+    assert_eq!(Exit::Halt, exit);
+    assert_eq!(&[63, 28, 112, 25, 63, 207, 192], static_(&cpu, 7));
+}
+
+#[test]
+fn div_by_zero_panics() {
+    let (exit, _) = run(r#"
+        static R:u8
+        let zero:u8 = 0
+        (= R (/ 4 zero))
+    "#);
+    assert_eq!(Exit::Panic, exit);
+}
+
+#[test]
+fn panic() {
+    let (exit, _) = run("!!");
+    assert_eq!(Exit::Panic, exit);
+}
+
+#[test]
+fn interrupt() {
+    let (exit, mut cpu) = run(r#"
+        static COUNT:u8
+        static DONE:u8
+        fn @vblank on_vblank {
+            (+= COUNT 1)
+        }
+        loop {
+            if (== COUNT 3) {
+                break
+            }
+        }
+        (= DONE 1)
+    "#);
+    assert_eq!(Exit::Timeout, exit);
+    // only the vblank interrupt is enabled
+    assert!(!cpu.interrupt(0x50));
+    for count in 1..=2 {
+        assert!(cpu.interrupt(0x40));
+        assert_eq!(Exit::Timeout, cpu.run(1_000));
+        assert_eq!(count, cpu.read(STATIC));
+        assert_eq!(0, cpu.read(STATIC + 1));
+    }
+    assert!(cpu.interrupt(0x40));
+    assert_eq!(Exit::Halt, cpu.run(100_000));
+    assert_eq!(3, cpu.read(STATIC));
+    assert_eq!(1, cpu.read(STATIC + 1));
+}
+
+#[test]
+fn interrupt_stack_overflow() {
+    // both frames fit in the interrupt stack, but not one after the other
+    let input = format!(
+        r#"
+        static COUNT:u8
+        fn fill {{
+            let b:[u8 80] = "{}"
+            (+= COUNT 1)
+        }}
+        fn @vblank on_vblank {{
+            let a:[u8 64] = "{}"
+            (fill)
+        }}
+        "#,
+        "b".repeat(80),
+        "a".repeat(64),
+    );
+    assert!(matches!(
+        ggbc::compile::<LR35902>(&input),
+        Err(ggbc::Error::Codegen(Error::InterruptStackOverflow(_)))
+    ));
+}
+
+#[test]
+fn inline_asm() {
+    let (exit, cpu) = run(r#"
+        static RESULT:[u8 4]
+        const TABLE:[u8 4] = [0x1 0x2 0x3 0x4]
+        const LEN:u8 = 4
+        asm {
+            .ld %hl TABLE
+            .ld %de RESULT
+            .ld %b LEN
+        copy:
+            .ld %a [%hl +]
+            .swap %a
+            .ld [%de] %a
+            .inc %de
+            .dec %b
+            .jr %nz copy
+        }
+        (+= ([3]RESULT) 1)
+    "#);
+    assert_eq!(Exit::Halt, exit);
+    assert_eq!(&[0x10, 0x20, 0x30, 0x41], static_(&cpu, 4));
+}
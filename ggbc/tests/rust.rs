@@ -30,6 +30,7 @@ test!(fn bool, bool);
 test!(fn break_, break);
 test!(compare);
 test!(fn const_, const);
+test!(const_data);
 test!(#[ignore] fn deref, deref);
 test!(fibonacci);
 test!(fibonacci_recursive);
@@ -42,8 +43,19 @@ test!(memcopy);
 test!(mul);
 test!(recursion);
 test!(sort);
-test!(#[ignore] fn string, string);
+test!(string);
 test!(fn struct_, struct);
 test!(union);
+test!(fn while_, while);
 test!(not_halt);
 test!(#[should_panic] fn halt, halt);
+
+// indirect pointers can't be lowered to rust
+#[test]
+fn pointer() {
+    let input = include_str!("../../vm/tests/programs/pointer.ggb");
+    assert!(matches!(
+        ggbc::compile::<Rust>(input),
+        Err(ggbc::Error::Codegen(_))
+    ));
+}
@@ -3,10 +3,13 @@
 //! # Remarks
 //! - Note that the [`Pointer`] type syntax differs compared to Rust, C, or C++.
 //! - Likewise, the [`AddressOf`] operator is also different.
-//! - Expressions are currently lisp-based, but this will change later on.
+//! - Expressions are lisp-based by default. Disabling the `lisp` feature switches
+//!   to an infix grammar with operator precedence (see [`expression`]) which
+//!   produces the same syntax tree.
 //!
 //! [`Pointer`]: ./struct.Pointer.html
 //! [`AddressOf`]: ./expressions/struct.AddressOf.html
+//! [`expression`]: ./expression/index.html
 use crate::{
     lex,
     lex::{
@@ -19,6 +22,7 @@ use crate::{
 use std::iter::Peekable;
 
 // re-exports
+pub use asm::Asm;
 pub use context::{Context, ContextBuilder};
 pub use expression::Expression;
 pub use path::Path;
@@ -27,6 +31,7 @@ pub use types::Type;
 
 #[macro_use]
 mod macros;
+pub mod asm;
 mod context;
 pub mod expression;
 mod path;
@@ -86,12 +91,22 @@ parse! {
         /// Module definition statement.
         Mod(Mod<'a>),
 
+        /// Module import statement.
+        Use(Use<'a>),
+
+        /// Inline assembly statement.
+        Asm(Asm<'a>),
+
         /// Static statement (static symbol definition).
         Static(Static<'a>),
 
         /// Static const statement (const symbol definition).
         Const(Const<'a>),
 
+        /// Include statement (const symbol defined from the contents of a
+        /// file).
+        Include(Include<'a>),
+
         /// Let statement (stack symbol definition).
         Let(Let<'a>),
 
@@ -101,6 +116,9 @@ parse! {
         /// Loop statement.
         Loop(Loop<'a>),
 
+        /// While loop statement.
+        While(While<'a>),
+
         /// Continue statement (flow control).
         Continue(Continue<'a>),
 
@@ -115,47 +133,187 @@ parse! {
 
         /// Return statement.
         Return(Return<'a>),
+
+        /// Named struct type declaration.
+        Struct(types::StructDef<'a>),
+
+        /// Named union type declaration.
+        Union(types::UnionDef<'a>),
+
+        /// Enum type declaration.
+        Enum(types::EnumDef<'a>),
+
+        /// Type alias declaration.
+        Type(types::TypeDef<'a>),
+
+        /// Match statement.
+        Match(Match<'a>),
     }
 }
 
+/// Syntax errors within a statement are recovered from (panic mode): the
+/// error is recorded in the context, and the tokens up to the beginning of
+/// the next statement, or the end of the enclosing block, are skipped.
 impl<'a> Grammar<'a> for Option<Statement<'a>> {
     fn parse(ctx: &mut Context<'a>, tokens: &mut Peekable<Tokens<'a>>) -> Result<Self, Error<'a>> {
-        let statement = match tokens.peek() {
-            Some(Err(_)) => return Err(tokens.next().unwrap().err().unwrap()),
-
-            None | Some(Ok(Token::RightBracket(_))) | Some(Ok(Token::Eof(_))) => return Ok(None),
-
-            Some(Ok(Token::If(_))) => {
-                let if_ = Grammar::parse(ctx, tokens)?;
-
-                if let Some(Ok(Token::Else(_))) = tokens.peek() {
-                    Statement::IfElse(IfElse {
-                        if_,
-                        else_: Grammar::parse(ctx, tokens)?,
-                    })
-                } else {
-                    Statement::If(if_)
+        loop {
+            let start = match tokens.peek() {
+                Some(Ok(token)) => Some(token.span()),
+                _ => None,
+            };
+            match parse_statement(ctx, tokens) {
+                Ok(statement) => return Ok(statement),
+                Err(error) => {
+                    // skip the rest of the block if the error opened one
+                    let depth = match &error {
+                        Error::UnexpectedToken(Token::LeftBracket(_)) => 1,
+                        _ => 0,
+                    };
+                    ctx.error(error);
+                    synchronize(tokens, start, depth);
                 }
             }
-            Some(Ok(Token::LeftBracket(_))) => Statement::Scope(Grammar::parse(ctx, tokens)?),
-            Some(Ok(Token::BangBang(_))) => Statement::Panic(Grammar::parse(ctx, tokens)?),
-            Some(Ok(Token::Mod(_))) => Statement::Mod(Grammar::parse(ctx, tokens)?),
-            #[cfg(todo_asm)]
-            Some(Ok(Token::Asm(_))) => Statement::Asm(Grammar::parse(ctx, tokens)?),
-            Some(Ok(Token::Static(_))) => Statement::Static(Grammar::parse(ctx, tokens)?),
-            Some(Ok(Token::Const(_))) => Statement::Const(Grammar::parse(ctx, tokens)?),
-            Some(Ok(Token::For(_))) => Statement::For(Grammar::parse(ctx, tokens)?),
-            Some(Ok(Token::Loop(_))) => Statement::Loop(Grammar::parse(ctx, tokens)?),
-            Some(Ok(Token::Let(_))) => Statement::Let(Grammar::parse(ctx, tokens)?),
-            Some(Ok(Token::Fn(_))) => Statement::Fn(Grammar::parse(ctx, tokens)?),
-            Some(Ok(Token::Continue(_))) => Statement::Continue(Grammar::parse(ctx, tokens)?),
-            Some(Ok(Token::Break(_))) => Statement::Break(Grammar::parse(ctx, tokens)?),
-            Some(Ok(Token::Return(_))) => Statement::Return(Grammar::parse(ctx, tokens)?),
-            Some(Ok(_)) => Statement::Inline(Grammar::parse(ctx, tokens)?),
-        };
+        }
+    }
+}
 
-        Ok(Some(statement))
+// Skip tokens until the beginning of a statement (other than the one starting
+// at `start`), the end of the enclosing block, or the end of the input.
+// Blocks opened while skipping (or `depth` blocks already open) are skipped
+// entirely.
+fn synchronize<'a>(tokens: &mut Peekable<Tokens<'a>>, start: Option<Span>, mut depth: usize) {
+    loop {
+        match tokens.peek() {
+            None | Some(Ok(Token::Eof(_))) => return,
+            Some(Ok(Token::RightBracket(_))) if depth == 0 => return,
+            Some(Ok(Token::RightBracket(_))) => depth -= 1,
+            Some(Ok(Token::LeftBracket(_))) => depth += 1,
+            Some(Ok(token)) if depth == 0 && Some(token.span()) != start => match token {
+                Token::If(_)
+                | Token::BangBang(_)
+                | Token::Mod(_)
+                | Token::Use(_)
+                | Token::Asm(_)
+                | Token::Static(_)
+                | Token::Const(_)
+                | Token::Include(_)
+                | Token::For(_)
+                | Token::Loop(_)
+                | Token::While(_)
+                | Token::Let(_)
+                | Token::Fn(_)
+                | Token::Struct(_)
+                | Token::Union(_)
+                | Token::Enum(_)
+                | Token::Type(_)
+                | Token::Match(_)
+                | Token::Continue(_)
+                | Token::Break(_)
+                | Token::Return(_) => return,
+                _ => {}
+            },
+            _ => {}
+        }
+        tokens.next();
+    }
+}
+
+fn parse_statement<'a>(
+    ctx: &mut Context<'a>,
+    tokens: &mut Peekable<Tokens<'a>>,
+) -> Result<Option<Statement<'a>>, Error<'a>> {
+    // infix expressions may be separated by `;` to disambiguate statements
+    // like `a = b -c` from `a = b; -c`.
+    #[cfg(not(feature = "lisp"))]
+    while let Some(Ok(Token::SemiColon(_))) = tokens.peek() {
+        tokens.next();
     }
+
+    let statement = match tokens.peek() {
+        Some(Err(_)) => return Err(tokens.next().unwrap().err().unwrap()),
+
+        None | Some(Ok(Token::RightBracket(_))) | Some(Ok(Token::Eof(_))) => return Ok(None),
+
+        Some(Ok(Token::If(_))) => {
+            let if_ = ctx.scoped(|ctx| Grammar::parse(ctx, tokens))?;
+
+            if let Some(Ok(Token::Else(_))) = tokens.peek() {
+                Statement::IfElse(IfElse {
+                    if_,
+                    else_: ctx.scoped(|ctx| Grammar::parse(ctx, tokens))?,
+                })
+            } else {
+                Statement::If(if_)
+            }
+        }
+        Some(Ok(Token::LeftBracket(_))) => {
+            Statement::Scope(ctx.scoped(|ctx| Grammar::parse(ctx, tokens))?)
+        }
+        Some(Ok(Token::BangBang(_))) => Statement::Panic(Grammar::parse(ctx, tokens)?),
+        Some(Ok(Token::Mod(_))) => Statement::Mod(Grammar::parse(ctx, tokens)?),
+        Some(Ok(Token::Use(_))) => {
+            let use_: Use<'a> = Grammar::parse(ctx, tokens)?;
+            ctx.import(&use_.path);
+            Statement::Use(use_)
+        }
+        Some(Ok(Token::Asm(_))) => Statement::Asm(Grammar::parse(ctx, tokens)?),
+        Some(Ok(Token::Static(_))) => {
+            let static_: Static<'a> = Grammar::parse(ctx, tokens)?;
+            ctx.define_static(&static_.field)?;
+            Statement::Static(static_)
+        }
+        Some(Ok(Token::Const(_))) => {
+            let const_: Const<'a> = Grammar::parse(ctx, tokens)?;
+            ctx.define_static(&const_.field)?;
+            Statement::Const(const_)
+        }
+        Some(Ok(Token::Include(_))) => {
+            let include: Include<'a> = Grammar::parse(ctx, tokens)?;
+            ctx.define_static(&include.field)?;
+            if let Some(map) = &include.map {
+                ctx.define_static(map)?;
+            }
+            Statement::Include(include)
+        }
+        Some(Ok(Token::For(_))) => Statement::For(Grammar::parse(ctx, tokens)?),
+        Some(Ok(Token::Loop(_))) => Statement::Loop(ctx.scoped(|ctx| Grammar::parse(ctx, tokens))?),
+        Some(Ok(Token::While(_))) => {
+            Statement::While(ctx.scoped(|ctx| Grammar::parse(ctx, tokens))?)
+        }
+        Some(Ok(Token::Let(_))) => {
+            let let_: Let<'a> = Grammar::parse(ctx, tokens)?;
+            ctx.define_stack(&let_.field)?;
+            Statement::Let(let_)
+        }
+        Some(Ok(Token::Fn(_))) => Statement::Fn(Grammar::parse(ctx, tokens)?),
+        Some(Ok(Token::Struct(_))) => {
+            let struct_: types::StructDef<'a> = Grammar::parse(ctx, tokens)?;
+            ctx.define_type(&struct_.ident)?;
+            Statement::Struct(struct_)
+        }
+        Some(Ok(Token::Union(_))) => {
+            let union: types::UnionDef<'a> = Grammar::parse(ctx, tokens)?;
+            ctx.define_type(&union.ident)?;
+            Statement::Union(union)
+        }
+        Some(Ok(Token::Enum(_))) => {
+            let enum_: types::EnumDef<'a> = Grammar::parse(ctx, tokens)?;
+            ctx.define_enum(&enum_)?;
+            Statement::Enum(enum_)
+        }
+        Some(Ok(Token::Type(_))) => {
+            let type_: types::TypeDef<'a> = Grammar::parse(ctx, tokens)?;
+            ctx.define_type(&type_.ident)?;
+            Statement::Type(type_)
+        }
+        Some(Ok(Token::Match(_))) => Statement::Match(Grammar::parse(ctx, tokens)?),
+        Some(Ok(Token::Continue(_))) => Statement::Continue(Grammar::parse(ctx, tokens)?),
+        Some(Ok(Token::Break(_))) => Statement::Break(Grammar::parse(ctx, tokens)?),
+        Some(Ok(Token::Return(_))) => Statement::Return(Grammar::parse(ctx, tokens)?),
+        Some(Ok(_)) => Statement::Inline(Grammar::parse(ctx, tokens)?),
+    };
+
+    Ok(Some(statement))
 }
 
 impl<'a> Grammar<'a> for Statement<'a> {
@@ -163,8 +321,10 @@ impl<'a> Grammar<'a> for Statement<'a> {
         if let Some(statement) = Grammar::parse(ctx, tokens)? {
             Ok(statement)
         } else {
-            let token = tokens.next().expect("Token please")?;
-            Err(Error::UnexpectedToken(token))
+            match tokens.next() {
+                Some(token) => Err(Error::unexpected(token?)),
+                None => Err(Error::Eof { span: ctx.end }),
+            }
         }
     }
 }
@@ -175,6 +335,10 @@ span!(Else {
     right_bracket
 });
 span!(IfElse { if_, else_ });
+span!(Match {
+    match_,
+    right_bracket
+});
 span!(Scope {
     left_bracket,
     right_bracket
@@ -184,7 +348,9 @@ span!(Mod {
     mod_,
     right_bracket
 });
+span!(Use { use_, path });
 span!(Const { const_, expression });
+span!(Include { include, path });
 span!(Let { let_, expression });
 span!(For {
     for_,
@@ -194,6 +360,10 @@ span!(Loop {
     loop_,
     right_bracket
 });
+span!(While {
+    while_,
+    right_bracket
+});
 span!(Continue { continue_ });
 span!(Break { break_ });
 span!(Inline { inner });
@@ -207,14 +377,38 @@ span!(Return { return_ });
 span!(Field { ident, type_ });
 span!(FieldGroup { head, type_ });
 
-parse! {
-    #[derive(Debug)]
-    pub struct Ast<'a> {
-        /// Inner statements.
-        pub inner: Vec<Statement<'a>>,
+#[derive(Debug)]
+pub struct Ast<'a> {
+    /// Inner statements.
+    pub inner: Vec<Statement<'a>>,
+
+    /// EOF token.
+    pub eof: lex::Eof<'a>,
+}
 
-        /// EOF token.
-        pub eof: lex::Eof<'a>,
+impl<'a> Grammar<'a> for Ast<'a> {
+    fn parse(ctx: &mut Context<'a>, tokens: &mut Peekable<Tokens<'a>>) -> Result<Self, Error<'a>> {
+        let mut inner = Vec::new();
+        loop {
+            inner.extend(Vec::parse(ctx, tokens)?);
+            // unbalanced `}` (statements stop parsing before the first one)
+            match tokens.peek() {
+                Some(Ok(Token::RightBracket(_))) => {
+                    let token = tokens.next().unwrap()?;
+                    ctx.error(Error::UnexpectedToken(token));
+                }
+                _ => break,
+            }
+        }
+        let eof = match tokens.peek() {
+            Some(_) => Grammar::parse(ctx, tokens)?,
+            // consumed by a statement that failed to parse
+            None => {
+                ctx.eof();
+                lex::Eof::implicit(ctx.end)
+            }
+        };
+        Ok(Self { inner, eof })
     }
 }
 
@@ -250,23 +444,50 @@ parse! {
     }
 }
 
-parse! {
-    #[derive(Debug)]
-    pub struct Mod<'a> {
-        /// `mod` token.
-        pub mod_: lex::Mod<'a>,
+#[derive(Debug)]
+pub struct Mod<'a> {
+    /// `mod` token.
+    pub mod_: lex::Mod<'a>,
 
-        /// Identifier token.
-        pub ident: lex::Ident<'a>,
+    /// Identifier token.
+    pub ident: lex::Ident<'a>,
 
-        /// `{` token.
-        pub left_bracket: lex::LeftBracket<'a>,
+    /// `{` token.
+    pub left_bracket: lex::LeftBracket<'a>,
 
-        /// Inner statements.
-        pub inner: Vec<Statement<'a>>,
+    /// Inner statements.
+    pub inner: Vec<Statement<'a>>,
 
-        /// `}` token.
-        pub right_bracket: lex::RightBracket<'a>,
+    /// `}` token.
+    pub right_bracket: lex::RightBracket<'a>,
+}
+
+impl<'a> Grammar<'a> for Mod<'a> {
+    fn parse(ctx: &mut Context<'a>, tokens: &mut Peekable<Tokens<'a>>) -> Result<Self, Error<'a>> {
+        let mod_ = Grammar::parse(ctx, tokens)?;
+        let ident = Grammar::parse(ctx, tokens)?;
+        let left_bracket = Grammar::parse(ctx, tokens)?;
+        ctx.push_mod(&ident);
+        let inner = Grammar::parse(ctx, tokens);
+        ctx.pop_mod();
+        Ok(Self {
+            mod_,
+            ident,
+            left_bracket,
+            inner: inner?,
+            right_bracket: Grammar::parse(ctx, tokens)?,
+        })
+    }
+}
+
+parse! {
+    #[derive(Debug)]
+    pub struct Use<'a> {
+        /// `use` token.
+        pub use_: lex::Use<'a>,
+
+        /// Path of the imported module or item.
+        pub path: Path<'a>,
     }
 }
 
@@ -295,6 +516,23 @@ parse! {
     }
 }
 
+parse! {
+    #[derive(Debug)]
+    pub struct Include<'a> {
+        /// `include` token.
+        pub include: lex::Include<'a>,
+
+        /// [`Field`](Field) tokens.
+        pub field: Field<'a>,
+
+        /// Optional [`Field`](Field) tokens of the tilemap of an image.
+        pub map: Option<Field<'a>>,
+
+        /// Path of the included file, relative to the source file.
+        pub path: lex::Lit<'a>,
+    }
+}
+
 parse! {
     #[derive(Debug)]
     pub struct Let<'a> {
@@ -360,6 +598,82 @@ parse! {
     }
 }
 
+parse! {
+    /// `match <expression> { <arms> [else { <statements> }] }`
+    #[derive(Debug)]
+    pub struct Match<'a> {
+        /// `match` token.
+        pub match_: lex::Match<'a>,
+
+        /// Matched expression tokens.
+        pub expression: Expression<'a>,
+
+        /// `{` token.
+        pub left_bracket: lex::LeftBracket<'a>,
+
+        /// Match arms, tested in order.
+        pub arms: Vec<MatchArm<'a>>,
+
+        /// Optional default arm.
+        pub else_: Option<Else<'a>>,
+
+        /// `}` token.
+        pub right_bracket: lex::RightBracket<'a>,
+    }
+}
+
+parse! {
+    /// `<expressions> { <statements> }`
+    #[derive(Debug)]
+    pub struct MatchArm<'a> {
+        /// Constant expressions compared against the matched expression. The
+        /// arm is taken if any of them is equal.
+        pub patterns: Vec<Expression<'a>>,
+
+        /// `{` token.
+        pub left_bracket: lex::LeftBracket<'a>,
+
+        /// Inner statements.
+        pub inner: Vec<Statement<'a>>,
+
+        /// `}` token.
+        pub right_bracket: lex::RightBracket<'a>,
+    }
+}
+
+impl<'a> Grammar<'a> for Option<MatchArm<'a>> {
+    fn parse(ctx: &mut Context<'a>, tokens: &mut Peekable<Tokens<'a>>) -> Result<Self, Error<'a>> {
+        match tokens.peek() {
+            Some(Ok(Token::Else(_))) | Some(Ok(Token::RightBracket(_))) => Ok(None),
+            // arms need at least one pattern
+            Some(Ok(Token::LeftBracket(_))) => Err(Error::UnexpectedToken(tokens.next().unwrap()?)),
+            _ => Ok(Some(ctx.scoped(|ctx| {
+                // the `{` after the patterns opens the arm (not a struct initializer)
+                let mut patterns = Vec::new();
+                while !matches!(tokens.peek(), Some(Ok(Token::LeftBracket(_)))) {
+                    patterns.push(Grammar::parse(ctx, tokens)?);
+                }
+                Ok::<_, Error<'a>>(MatchArm {
+                    patterns,
+                    left_bracket: Grammar::parse(ctx, tokens)?,
+                    inner: Grammar::parse(ctx, tokens)?,
+                    right_bracket: Grammar::parse(ctx, tokens)?,
+                })
+            })?)),
+        }
+    }
+}
+
+impl<'a> Grammar<'a> for Option<Else<'a>> {
+    fn parse(ctx: &mut Context<'a>, tokens: &mut Peekable<Tokens<'a>>) -> Result<Self, Error<'a>> {
+        if let Some(Ok(Token::Else(_))) = tokens.peek() {
+            Ok(Some(ctx.scoped(|ctx| Grammar::parse(ctx, tokens))?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
 parse! {
     #[derive(Debug)]
     pub struct Range<'a> {
@@ -377,23 +691,97 @@ parse! {
 
         /// Right expression tokens.
         pub right: Expression<'a>,
+
+        /// Optional step.
+        pub step: Option<RangeStep<'a>>,
     }
 }
 
 parse! {
+    /// `step <expression>`
     #[derive(Debug)]
-    pub struct For<'a> {
-        /// `for` token.
-        pub for_: lex::For<'a>,
+    pub struct RangeStep<'a> {
+        /// `step` identifier (not a reserved keyword).
+        pub step: lex::Ident<'a>,
 
-        /// For field tokens.
-        pub field: Field<'a>,
+        /// Step constant expression tokens. Negative steps iterate the range
+        /// in descending order.
+        pub expression: Expression<'a>,
+    }
+}
+
+impl<'a> Grammar<'a> for Option<RangeStep<'a>> {
+    fn parse(ctx: &mut Context<'a>, tokens: &mut Peekable<Tokens<'a>>) -> Result<Self, Error<'a>> {
+        match tokens.peek() {
+            Some(Ok(Token::Ident(ident))) if ident.to_string() == "step" => {
+                Ok(Some(Grammar::parse(ctx, tokens)?))
+            }
+            _ => Ok(None),
+        }
+    }
+}
 
-        /// `in` token.
-        pub in_: lex::In<'a>,
+#[derive(Debug)]
+pub struct For<'a> {
+    /// `for` token.
+    pub for_: lex::For<'a>,
+
+    /// For field tokens.
+    pub field: Field<'a>,
+
+    /// `in` token.
+    pub in_: lex::In<'a>,
+
+    /// Range token.
+    pub range: Range<'a>,
+
+    /// `{` token.
+    pub left_bracket: lex::LeftBracket<'a>,
+
+    /// Inner statements.
+    pub inner: Vec<Statement<'a>>,
+
+    /// `}` token.
+    pub right_bracket: lex::RightBracket<'a>,
+}
+
+impl<'a> Grammar<'a> for For<'a> {
+    fn parse(ctx: &mut Context<'a>, tokens: &mut Peekable<Tokens<'a>>) -> Result<Self, Error<'a>> {
+        let for_ = Grammar::parse(ctx, tokens)?;
+        let field: Field<'a> = Grammar::parse(ctx, tokens)?;
+        let in_ = Grammar::parse(ctx, tokens)?;
+        let range = Grammar::parse(ctx, tokens)?;
+        let left_bracket = Grammar::parse(ctx, tokens)?;
+
+        // the field is only defined within the body of the loop.
+        // (the body is parsed regardless of whether the field is valid)
+        let inner = ctx.scoped(|ctx| {
+            if let Err(error) = ctx.define_stack(&field) {
+                ctx.error(error);
+            }
+            Grammar::parse(ctx, tokens)
+        })?;
+        Ok(Self {
+            for_,
+            field,
+            in_,
+            range,
+            left_bracket,
+            inner,
+            right_bracket: Grammar::parse(ctx, tokens)?,
+        })
+    }
+}
+
+parse! {
+    /// `while <expression> { <statements> }`
+    #[derive(Debug)]
+    pub struct While<'a> {
+        /// `while` token.
+        pub while_: lex::While<'a>,
 
-        /// Range token.
-        pub range: Range<'a>,
+        /// Loop condition expression tokens.
+        pub expression: Expression<'a>,
 
         /// `{` token.
         pub left_bracket: lex::LeftBracket<'a>,
@@ -444,29 +832,150 @@ impl<'a> Grammar<'a> for Option<FnReturn<'a>> {
     }
 }
 
-parse! {
-    #[derive(Debug)]
-    pub struct Fn<'a> {
-        /// `fn` token.
-        pub fn_: lex::Fn<'a>,
+/// Interrupts that can be serviced by a function.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Interrupt {
+    /// `@vblank`
+    VBlank,
 
-        /// Function identifier token.
-        pub ident: lex::Ident<'a>,
+    /// `@lcd_stat`
+    LcdStat,
 
-        /// Function argument tokens.
-        pub fn_arg: Option<FnArg<'a>>,
+    /// `@timer`
+    Timer,
 
-        /// Function return tokens.
-        pub fn_return: Option<FnReturn<'a>>,
+    /// `@serial`
+    Serial,
 
-        /// `{` token.
-        pub left_bracket: lex::LeftBracket<'a>,
+    /// `@joypad`
+    Joypad,
+}
 
-        /// Inner statements.
-        pub inner: Vec<Statement<'a>>,
+#[derive(Debug)]
+pub struct FnHandler<'a> {
+    /// `@` token.
+    pub at: lex::At<'a>,
 
-        /// `}` token.
-        pub right_bracket: lex::RightBracket<'a>,
+    /// Interrupt identifier token.
+    pub ident: lex::Ident<'a>,
+
+    /// The interrupt serviced by the function.
+    pub interrupt: Interrupt,
+}
+
+impl<'a> Grammar<'a> for FnHandler<'a> {
+    fn parse(ctx: &mut Context<'a>, tokens: &mut Peekable<Tokens<'a>>) -> Result<Self, Error<'a>> {
+        let at = Grammar::parse(ctx, tokens)?;
+        let ident: lex::Ident<'a> = Grammar::parse(ctx, tokens)?;
+        let interrupt = match ident.to_string().as_str() {
+            "vblank" => Interrupt::VBlank,
+            "lcd_stat" => Interrupt::LcdStat,
+            "timer" => Interrupt::Timer,
+            "serial" => Interrupt::Serial,
+            "joypad" => Interrupt::Joypad,
+            _ => return Err(Error::InvalidInterrupt(ident)),
+        };
+        Ok(Self {
+            at,
+            ident,
+            interrupt,
+        })
+    }
+}
+
+impl<'a> Grammar<'a> for Option<FnHandler<'a>> {
+    fn parse(ctx: &mut Context<'a>, tokens: &mut Peekable<Tokens<'a>>) -> Result<Self, Error<'a>> {
+        if let Some(Ok(Token::At(_))) = tokens.peek() {
+            Ok(Some(Grammar::parse(ctx, tokens)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Fn<'a> {
+    /// `fn` token.
+    pub fn_: lex::Fn<'a>,
+
+    /// Optional [`FnHandler`](FnHandler) tokens, for interrupt handlers.
+    pub handler: Option<FnHandler<'a>>,
+
+    /// Function identifier token.
+    pub ident: lex::Ident<'a>,
+
+    /// Function argument tokens.
+    pub fn_arg: Option<FnArg<'a>>,
+
+    /// Function return tokens.
+    pub fn_return: Option<FnReturn<'a>>,
+
+    /// `{` token.
+    pub left_bracket: lex::LeftBracket<'a>,
+
+    /// Inner statements.
+    pub inner: Vec<Statement<'a>>,
+
+    /// `}` token.
+    pub right_bracket: lex::RightBracket<'a>,
+}
+
+impl<'a> Grammar<'a> for Fn<'a> {
+    fn parse(ctx: &mut Context<'a>, tokens: &mut Peekable<Tokens<'a>>) -> Result<Self, Error<'a>> {
+        let fn_ = Grammar::parse(ctx, tokens)?;
+        let handler: Option<FnHandler<'a>> = Grammar::parse(ctx, tokens)?;
+        let ident = Grammar::parse(ctx, tokens)?;
+        // defined before the body, so functions can be recursive.
+        ctx.define_fn(&ident)?;
+
+        ctx.push_fn_scope();
+        let fn_ = Self::parse_signature(fn_, handler, ident, ctx, tokens);
+        ctx.pop_scope();
+        fn_
+    }
+}
+
+impl<'a> Fn<'a> {
+    // parse the rest of the function within its own scope.
+    fn parse_signature(
+        fn_: lex::Fn<'a>,
+        handler: Option<FnHandler<'a>>,
+        ident: lex::Ident<'a>,
+        ctx: &mut Context<'a>,
+        tokens: &mut Peekable<Tokens<'a>>,
+    ) -> Result<Self, Error<'a>> {
+        let fn_arg: Option<FnArg<'a>> = Grammar::parse(ctx, tokens)?;
+        let fn_return: Option<FnReturn<'a>> = Grammar::parse(ctx, tokens)?;
+
+        // interrupt handlers can't take arguments nor return values
+        if handler.is_some() {
+            if let Some(arg) = fn_arg.as_ref().filter(|arg| !arg.inner.is_empty()) {
+                return Err(Error::InvalidHandler {
+                    ident,
+                    span: arg.span(),
+                });
+            }
+            if let Some(ret) = &fn_return {
+                return Err(Error::InvalidHandler {
+                    ident,
+                    span: ret.span(),
+                });
+            }
+        }
+        for field in fn_arg.iter().flat_map(|arg| &arg.inner) {
+            ctx.define_stack(field)?;
+        }
+
+        Ok(Self {
+            fn_,
+            handler,
+            ident,
+            fn_arg,
+            fn_return,
+            left_bracket: Grammar::parse(ctx, tokens)?,
+            inner: Grammar::parse(ctx, tokens)?,
+            right_bracket: Grammar::parse(ctx, tokens)?,
+        })
     }
 }
 
@@ -573,10 +1082,18 @@ parse! {
 
 #[cfg(test)]
 mod test {
-    use crate::ast::Ast;
+    use crate::{ast::Ast, Error};
 
     fn parse_program(input: &str) -> Ast<'_> {
-        crate::parse(input).unwrap()
+        let (ast, errors) = crate::parse(input);
+        assert!(errors.is_empty(), "{:?}", errors);
+        ast
+    }
+
+    // returns the first syntax error of the program
+    fn parse_error(input: &str) -> Error<'_> {
+        let (_, errors) = crate::parse(input);
+        errors.into_iter().next().expect("Expected a syntax error")
     }
 
     #[test]
@@ -628,9 +1145,89 @@ mod test {
     }
 
     #[test]
-    #[ignore]
+    #[cfg(feature = "lisp")]
+    fn enum_() {
+        parse_program("enum Foo { }");
+        parse_program("enum Foo { A B C }");
+        parse_program("enum Foo { A = 1 B C = (+ 2 2) }");
+        parse_program("enum Foo { A } const FOO:Foo = Foo::A");
+        parse_program("mod a { enum Foo { A } } const FOO:a::Foo = a::Foo::A");
+        assert!(matches!(
+            parse_error("enum Foo { A A }"),
+            Error::ShadowIdent { .. }
+        ));
+        assert!(matches!(
+            parse_error("enum Foo { A } Foo::B"),
+            Error::InvalidPath(_)
+        ));
+    }
+
+    #[test]
+    fn type_() {
+        parse_program("type Tile = [u8 16]");
+        parse_program("type Tile = [u8 16] type Map = [Tile 4] static MAP:Map");
+        parse_program("mod a { type Byte = u8 } static B:a::Byte");
+        assert!(matches!(
+            parse_error("type Tile = [u8 16] type Tile = u8"),
+            Error::ShadowIdent { .. }
+        ));
+        assert!(matches!(
+            parse_error("type Tile = Foo"),
+            Error::InvalidPath(_)
+        ));
+    }
+
+    #[test]
+    fn include() {
+        parse_program(r#"include FONT:[u8 16] "font.2bpp""#);
+        parse_program(r#"include TILES:[u8 32] MAP:[u8 4] "bg.png" use MAP"#);
+        parse_program(r#"mod gfx { include TILES:[[u8 16] 4] "gfx/tiles.2bpp" } use gfx::TILES"#);
+        assert!(matches!(
+            parse_error(r#"include FONT "font.2bpp""#),
+            Error::UnexpectedToken(_)
+        ));
+        assert!(matches!(
+            parse_error(r#"include A:[u8 16] A:[u8 1] "a.png""#),
+            Error::ShadowIdent { .. }
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "lisp")]
+    fn struct_init() {
+        parse_program("struct P { x:u8 y:u8 } const A:P = { x:1 y:(+ 1 2) }");
+        parse_program("const A:struct { } = { }");
+        parse_program("const A:[struct { x:u8 } 2] = [{ x:1 } { x:2 }]");
+        assert!(matches!(
+            parse_error("const A:struct { x:u8 } = { x }"),
+            Error::UnexpectedToken(_)
+        ));
+    }
+
+    #[test]
+    fn match_() {
+        parse_program("match 0 { }");
+        parse_program("match 0 { 0 { } 1 2 { } }");
+        parse_program("match 0 { 0 { } else { } }");
+        parse_program("enum Foo { A B } match Foo::A { Foo::A { } Foo::B { } }");
+        parse_program("match 0 { 0 { let a:u8 = 0 } 1 { let a:u8 = 1 } else { let a:u8 = 2 } }");
+        assert!(matches!(
+            parse_error("match 0 { { } }"),
+            Error::UnexpectedToken(_)
+        ));
+        assert!(matches!(
+            parse_error("match 0 { 0 { let a:u8 = 0 } 1 { a } }"),
+            Error::InvalidPath(_)
+        ));
+    }
+
+    #[test]
     fn asm() {
         parse_program("asm { }");
+        parse_program("asm { .nop .halt }");
+        parse_program("asm { .ld %a 0x42 .ld [%hl +] %a .ldh [0x40] %a }");
+        parse_program("static FOO:u8 asm { .ld %hl FOO .inc [%hl] }");
+        parse_program("asm { loop_: .dec %b .jr %nz loop_ .ret %c .reti }");
     }
 
     #[test]
@@ -666,10 +1263,33 @@ mod test {
     }
 
     #[test]
+    #[cfg(feature = "lisp")]
     fn for_() {
         parse_program("for i:u8 in 0.. 42 { }");
         parse_program("for i:u8 in 0..=42 { }");
         parse_program("for i:u8 in 0..=+42 { }");
+        parse_program("for i:u8 in 42..=0 step -1 { }");
+        parse_program("for i:u8 in 0..42 step (+ 1 1) { }");
+        parse_program("let step:u8 = 2 for i:u8 in 0..42 step step { }");
+    }
+
+    #[test]
+    #[cfg(feature = "lisp")]
+    fn logical() {
+        parse_program("let a:bool = (and true (or false not 1))");
+        parse_program("let a:u8 = 1 if (and a not (== a 2)) { }");
+        parse_program("let a:[bool 2] = [true false]");
+    }
+
+    #[test]
+    #[cfg(feature = "lisp")]
+    fn while_() {
+        parse_program("while 1 { }");
+        parse_program("let a:u8 = 0 while (< a 4) { (+= a 1) continue }");
+        assert!(matches!(
+            parse_error("while 1 { let a:u8 = 0 } a"),
+            Error::InvalidPath(_)
+        ));
     }
 
     #[test]
@@ -698,17 +1318,38 @@ mod test {
         parse_program("fn foo(bar:u8) { }");
         parse_program("fn foo(bar:u8):u8 { }");
         parse_program("fn foo(bar:u8 baz:u8):u8 { }");
+        parse_program("fn @vblank foo { }");
+        parse_program("fn @lcd_stat foo() { }");
+        parse_program("fn @timer foo { }");
+        parse_program("fn @serial foo { }");
+        parse_program("fn @joypad foo { }");
     }
 
     #[test]
-    #[ignore]
+    fn fn_handler_panic() {
+        assert!(matches!(
+            parse_error("fn @reset foo { }"),
+            Error::InvalidInterrupt(_)
+        ));
+        assert!(matches!(
+            parse_error("fn @vblank foo(bar:u8) { }"),
+            Error::InvalidHandler { .. }
+        ));
+        assert!(matches!(
+            parse_error("fn @vblank foo:u8 { }"),
+            Error::InvalidHandler { .. }
+        ));
+    }
+
+    #[test]
+    #[cfg(not(feature = "lisp"))]
     fn inline_non_lisp() {
         parse_program("1");
-        parse_program("foo");
-        parse_program("foo()");
-        parse_program("foo::bar(0, 1, 2)");
-        parse_program("foo = 42");
-        parse_program("foo += 42");
+        parse_program("static foo:u8 foo");
+        parse_program("fn foo { } foo()");
+        parse_program("mod foo { fn bar(a:u8 b:u8 c:u8) { } } foo::bar(0, 1, 2)");
+        parse_program("static foo:u8 foo = 42");
+        parse_program("static foo:u8 foo += 42");
     }
 
     #[test]
@@ -734,6 +1375,84 @@ mod test {
         );
     }
 
+    #[test]
+    fn use_() {
+        parse_program("use foo::bar bar::baz (bar)");
+        parse_program("mod a { use b::c fn foo { c::d } }");
+        assert!(matches!(
+            parse_error("mod a { use b::c } c::d"),
+            Error::InvalidPath(_)
+        ));
+    }
+
+    #[test]
+    fn undefined_path() {
+        for input in &[
+            "foo",
+            "(foo)",
+            "let foo:u8 = foo",
+            "{ let foo:u8 = 0 } foo",
+            "let foo:u8 = 0 fn bar { foo }",
+            "for i:u8 in 0..i { }",
+            "for i:u8 in 0..1 { } i",
+            "fn foo(bar:u8) { } bar",
+            "mod a { static foo:u8 } foo",
+            "static foo:struct { bar:u8 } foo::baz",
+            "static foo:Bar",
+        ] {
+            let error = parse_error(input);
+            assert!(matches!(error, Error::InvalidPath(_)), "{}", input);
+        }
+    }
+
+    #[test]
+    fn shadow_ident() {
+        parse_program("{ let foo:u8 = 0 } let foo:u8 = 0");
+        parse_program("let foo:u8 = 0 fn bar { let foo:u8 = 1 }");
+        parse_program("mod a { static foo:u8 } static foo:u8 fn foo { }");
+        parse_program("let a:u8 = 0 asm { a: .jr a }");
+        for input in &[
+            "let foo:u8 = 0 let foo:u8 = 0",
+            "static foo:u8 { let foo:u8 = 0 }",
+            "let foo:u8 = 0 for foo:u8 in 0..1 { }",
+            "fn foo(a:u8 a:u8) { }",
+            "fn foo { } fn foo { }",
+            "mod a { fn foo { } } mod a { fn foo { } }",
+            "mod a { const foo:u8 = 0 static foo:u8 }",
+            "static foo:struct { bar:u8 bar:u8 }",
+            "asm { a: a: }",
+        ] {
+            let error = parse_error(input);
+            assert!(matches!(error, Error::ShadowIdent { .. }), "{}", input);
+        }
+    }
+
+    #[test]
+    fn truncated_input() {
+        let expression = if cfg!(feature = "lisp") {
+            "let x:u8 = (+ 1"
+        } else {
+            "let x:u8 = (1 +"
+        };
+        for input in &[
+            "(",
+            "fn f {",
+            "static A:[u8",
+            "asm {",
+            "match 1 {",
+            expression,
+        ] {
+            // a single error, at the end of the input
+            let (_, errors) = crate::parse(input);
+            assert!(
+                matches!(errors.as_slice(), [Error::Eof { span }] if span.min == [0, input.len()]),
+                "{} {:?}",
+                input,
+                errors
+            );
+        }
+    }
+
     #[test]
     fn mod_scopes() {
         parse_program(
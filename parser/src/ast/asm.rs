@@ -0,0 +1,572 @@
+//! Inline assembly grammars.
+//!
+//! # Syntax
+//! Instructions are written with the mnemonic first, followed by a fixed number
+//! of operands (destination first), without commas:
+//!
+//! ```no_rust
+//! asm {
+//!     .ld %hl OAM_BUFFER
+//!     .ld %b 40
+//! wait:
+//!     .ld [%hl +] %a
+//!     .dec %b
+//!     .jr %nz wait
+//!     .ldh [0xff46] %a
+//! }
+//! ```
+//!
+//! Memory operands are enclosed in square brackets. Immediate operands are
+//! either constant expressions, or paths to symbols (which evaluate to the
+//! address of the symbol).
+use crate::{
+    ast::{Context, Expression, Grammar},
+    lex,
+    lex::{
+        span,
+        span::{Span, Spanned},
+        Token, Tokens,
+    },
+    Error,
+};
+use std::iter::Peekable;
+
+span!(Asm { asm, right_bracket });
+span!(Label { ident, colon });
+span!(SpOffset { sp, expression });
+span!(Indirect {
+    left_square,
+    right_square
+});
+
+/// `asm { ... }`
+#[derive(Debug)]
+pub struct Asm<'a> {
+    /// `asm` token.
+    pub asm: lex::Asm<'a>,
+
+    /// `{` token.
+    pub left_bracket: lex::LeftBracket<'a>,
+
+    /// Inner assembly statements.
+    pub inner: Vec<AsmStatement<'a>>,
+
+    /// `}` token.
+    pub right_bracket: lex::RightBracket<'a>,
+}
+
+impl<'a> Grammar<'a> for Asm<'a> {
+    fn parse(ctx: &mut Context<'a>, tokens: &mut Peekable<Tokens<'a>>) -> Result<Self, Error<'a>> {
+        let asm = Grammar::parse(ctx, tokens)?;
+        let left_bracket = Grammar::parse(ctx, tokens)?;
+
+        // labels may be referenced before they are defined
+        ctx.begin_asm();
+        let inner = Grammar::parse(ctx, tokens);
+        let labels = ctx.end_asm();
+        let inner = inner?;
+        let right_bracket = Grammar::parse(ctx, tokens)?;
+        labels?;
+        Ok(Self {
+            asm,
+            left_bracket,
+            inner,
+            right_bracket,
+        })
+    }
+}
+
+parse! {
+    /// Statements of an inline assembly block.
+    #[derive(Debug)]
+    pub enum AsmStatement<'a> {
+        /// Label definition.
+        Label(Label<'a>),
+
+        /// Instruction.
+        Instruction(Instruction<'a>),
+    }
+}
+
+impl<'a> Grammar<'a> for Option<AsmStatement<'a>> {
+    fn parse(ctx: &mut Context<'a>, tokens: &mut Peekable<Tokens<'a>>) -> Result<Self, Error<'a>> {
+        match tokens.peek() {
+            Some(Err(_)) => Err(tokens.next().unwrap().err().unwrap()),
+            Some(Ok(Token::Ident(_))) => {
+                let label: Label<'a> = Grammar::parse(ctx, tokens)?;
+                ctx.define_label(&label.ident)?;
+                Ok(Some(AsmStatement::Label(label)))
+            }
+            Some(Ok(Token::RightBracket(_))) => Ok(None),
+            _ => Ok(Some(AsmStatement::Instruction(Grammar::parse(
+                ctx, tokens,
+            )?))),
+        }
+    }
+}
+
+parse! {
+    /// `<ident> :`
+    #[derive(Debug)]
+    pub struct Label<'a> {
+        /// Label identifier.
+        pub ident: lex::Ident<'a>,
+
+        /// `:` token.
+        pub colon: lex::Colon<'a>,
+    }
+}
+
+/// Assembly instruction.
+#[derive(Debug)]
+pub struct Instruction<'a> {
+    /// Mnemonic token.
+    pub mnemonic: Mnemonic<'a>,
+
+    /// Instruction operands (the destination comes first).
+    pub operands: Vec<Operand<'a>>,
+}
+
+impl Spanned for Instruction<'_> {
+    fn span(&self) -> Span {
+        let mut span = self.mnemonic.span();
+        if let Some(operand) = self.operands.last() {
+            span = span::union(&span, &operand.span());
+        }
+        span
+    }
+}
+
+impl<'a> Grammar<'a> for Instruction<'a> {
+    fn parse(ctx: &mut Context<'a>, tokens: &mut Peekable<Tokens<'a>>) -> Result<Self, Error<'a>> {
+        let mnemonic: Mnemonic<'_> = Grammar::parse(ctx, tokens)?;
+        let condition = matches!(
+            tokens.peek(),
+            Some(Ok(Token::NZ(_)))
+                | Some(Ok(Token::Z(_)))
+                | Some(Ok(Token::NC(_)))
+                | Some(Ok(Token::C(_)))
+        );
+        let arity = match mnemonic {
+            Mnemonic::Nop(_)
+            | Mnemonic::Stop(_)
+            | Mnemonic::Halt(_)
+            | Mnemonic::Di(_)
+            | Mnemonic::Ei(_)
+            | Mnemonic::Daa(_)
+            | Mnemonic::Scf(_)
+            | Mnemonic::Cpl(_)
+            | Mnemonic::Ccf(_)
+            | Mnemonic::Reti(_)
+            | Mnemonic::Rlca(_)
+            | Mnemonic::Rla(_)
+            | Mnemonic::Rrca(_)
+            | Mnemonic::Rra(_) => 0,
+            Mnemonic::Push(_)
+            | Mnemonic::Pop(_)
+            | Mnemonic::Inc(_)
+            | Mnemonic::Dec(_)
+            | Mnemonic::Rst(_)
+            | Mnemonic::Rlc(_)
+            | Mnemonic::Rrc(_)
+            | Mnemonic::Rl(_)
+            | Mnemonic::Rr(_)
+            | Mnemonic::Sla(_)
+            | Mnemonic::Sra(_)
+            | Mnemonic::Srl(_)
+            | Mnemonic::Swap(_) => 1,
+            Mnemonic::Ld(_)
+            | Mnemonic::Ldh(_)
+            | Mnemonic::Add(_)
+            | Mnemonic::Adc(_)
+            | Mnemonic::Sub(_)
+            | Mnemonic::Sbc(_)
+            | Mnemonic::And(_)
+            | Mnemonic::Xor(_)
+            | Mnemonic::Or(_)
+            | Mnemonic::Cp(_)
+            | Mnemonic::Bit(_)
+            | Mnemonic::Res(_)
+            | Mnemonic::Set(_) => 2,
+            // optional condition operand
+            Mnemonic::Jp(_) | Mnemonic::Jr(_) | Mnemonic::Call(_) => {
+                if condition {
+                    2
+                } else {
+                    1
+                }
+            }
+            Mnemonic::Ret(_) => {
+                if condition {
+                    1
+                } else {
+                    0
+                }
+            }
+        };
+        let mut operands = Vec::with_capacity(arity);
+        for _ in 0..arity {
+            operands.push(Grammar::parse(ctx, tokens)?);
+        }
+        Ok(Self { mnemonic, operands })
+    }
+}
+
+// Generates an enum where each variant wraps a token, along with the Spanned,
+// Display, and Grammar implementations.
+macro_rules! token_enum {
+    ($(#[$meta:meta])* pub enum $enum_name:ident<'a> { $($var:ident,)+ }) => {
+        $(#[$meta])*
+        #[derive(Debug)]
+        pub enum $enum_name<'a> {
+            $(
+                #[allow(missing_docs)]
+                $var(lex::$var<'a>),
+            )+
+        }
+
+        impl Spanned for $enum_name<'_> {
+            fn span(&self) -> Span {
+                match self {
+                    $($enum_name::$var(t) => t.span(),)+
+                }
+            }
+        }
+
+        impl std::fmt::Display for $enum_name<'_> {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                match self {
+                    $($enum_name::$var(t) => t.fmt(f),)+
+                }
+            }
+        }
+
+        impl<'a> Grammar<'a> for Option<$enum_name<'a>> {
+            fn parse(
+                ctx: &mut Context<'a>,
+                tokens: &mut Peekable<Tokens<'a>>,
+            ) -> Result<Self, Error<'a>> {
+                match tokens.peek() {
+                    $(Some(Ok(Token::$var(_))) => {
+                        Ok(Some($enum_name::$var(Grammar::parse(ctx, tokens)?)))
+                    })+
+                    _ => Ok(None),
+                }
+            }
+        }
+
+        impl<'a> Grammar<'a> for $enum_name<'a> {
+            fn parse(
+                ctx: &mut Context<'a>,
+                tokens: &mut Peekable<Tokens<'a>>,
+            ) -> Result<Self, Error<'a>> {
+                match Grammar::parse(ctx, tokens)? {
+                    Some(t) => Ok(t),
+                    None => match tokens.next() {
+                        Some(Ok(token)) => Err(Error::unexpected(token)),
+                        Some(Err(err)) => Err(err),
+                        None => Err(Error::Eof { span: ctx.end }),
+                    },
+                }
+            }
+        }
+    };
+}
+
+token_enum! {
+    /// Instruction mnemonics.
+    pub enum Mnemonic<'a> {
+        Nop,
+        Stop,
+        Halt,
+        Di,
+        Ei,
+        Ld,
+        Ldh,
+        Push,
+        Pop,
+        Inc,
+        Dec,
+        Daa,
+        Scf,
+        Cpl,
+        Ccf,
+        Add,
+        Adc,
+        Sub,
+        Sbc,
+        And,
+        Xor,
+        Or,
+        Cp,
+        Jp,
+        Jr,
+        Call,
+        Ret,
+        Reti,
+        Rst,
+        Rlca,
+        Rla,
+        Rrca,
+        Rra,
+        Rlc,
+        Rrc,
+        Rl,
+        Rr,
+        Sla,
+        Sra,
+        Srl,
+        Swap,
+        Bit,
+        Res,
+        Set,
+    }
+}
+
+token_enum! {
+    /// CPU registers.
+    pub enum Register<'a> {
+        A,
+        F,
+        AF,
+        B,
+        C,
+        BC,
+        D,
+        E,
+        DE,
+        H,
+        L,
+        HL,
+        SP,
+        PC,
+    }
+}
+
+token_enum! {
+    /// Jump conditions.
+    ///
+    /// The carry condition shares the `%c` token with the `C` register, and is
+    /// therefore parsed as a [`Register`](Register).
+    pub enum Condition<'a> {
+        NZ,
+        Z,
+        NC,
+    }
+}
+
+/// Instruction operands.
+#[derive(Debug)]
+pub enum Operand<'a> {
+    /// Register (`%a`, `%hl`, ...).
+    Register(Register<'a>),
+
+    /// Jump condition (`%nz`, `%z`, `%nc`).
+    Condition(Condition<'a>),
+
+    /// Stack pointer plus a signed offset (`%sp + n`, `%sp - n`).
+    SpOffset(SpOffset<'a>),
+
+    /// Memory operand (`[%hl]`, `[0xff40]`, `[SYMBOL]`, ...).
+    Indirect(Indirect<'a>),
+
+    /// Immediate operand.
+    Expression(Expression<'a>),
+}
+
+impl Spanned for Operand<'_> {
+    fn span(&self) -> Span {
+        match self {
+            Operand::Register(r) => r.span(),
+            Operand::Condition(c) => c.span(),
+            Operand::SpOffset(o) => o.span(),
+            Operand::Indirect(i) => i.span(),
+            Operand::Expression(e) => e.span(),
+        }
+    }
+}
+
+impl<'a> Grammar<'a> for Operand<'a> {
+    fn parse(ctx: &mut Context<'a>, tokens: &mut Peekable<Tokens<'a>>) -> Result<Self, Error<'a>> {
+        match tokens.peek() {
+            Some(Err(_)) => Err(tokens.next().unwrap().err().unwrap()),
+            Some(Ok(Token::LeftSquare(_))) => Ok(Operand::Indirect(Grammar::parse(ctx, tokens)?)),
+            Some(Ok(Token::SP(_))) => {
+                let sp = Grammar::parse(ctx, tokens)?;
+                match tokens.peek() {
+                    Some(Ok(Token::Plus(_))) | Some(Ok(Token::Minus(_))) => {
+                        Ok(Operand::SpOffset(SpOffset {
+                            sp,
+                            sign: Grammar::parse(ctx, tokens)?,
+                            expression: Grammar::parse(ctx, tokens)?,
+                        }))
+                    }
+                    _ => Ok(Operand::Register(Register::SP(sp))),
+                }
+            }
+            _ => {
+                if let Some(register) = Grammar::parse(ctx, tokens)? {
+                    Ok(Operand::Register(register))
+                } else if let Some(condition) = Grammar::parse(ctx, tokens)? {
+                    Ok(Operand::Condition(condition))
+                } else {
+                    Ok(Operand::Expression(Grammar::parse(ctx, tokens)?))
+                }
+            }
+        }
+    }
+}
+
+token_enum! {
+    /// Sign of an offset.
+    pub enum Sign<'a> {
+        Plus,
+        Minus,
+    }
+}
+
+/// `%sp + <expression>`
+#[derive(Debug)]
+pub struct SpOffset<'a> {
+    /// `%sp` token.
+    pub sp: lex::SP<'a>,
+
+    /// `+` or `-` token.
+    pub sign: Sign<'a>,
+
+    /// Offset expression.
+    pub expression: Expression<'a>,
+}
+
+/// `[ <address> ]`
+#[derive(Debug)]
+pub struct Indirect<'a> {
+    /// `[` token.
+    pub left_square: lex::LeftSquare<'a>,
+
+    /// Memory address.
+    pub address: Address<'a>,
+
+    /// `]` token.
+    pub right_square: lex::RightSquare<'a>,
+}
+
+impl<'a> Grammar<'a> for Indirect<'a> {
+    fn parse(ctx: &mut Context<'a>, tokens: &mut Peekable<Tokens<'a>>) -> Result<Self, Error<'a>> {
+        let left_square = Grammar::parse(ctx, tokens)?;
+        let address = match tokens.peek() {
+            Some(Ok(Token::HL(_))) => {
+                let hl = Grammar::parse(ctx, tokens)?;
+                match Grammar::parse(ctx, tokens)? {
+                    Some(sign) => Address::HlOffset(hl, sign),
+                    None => Address::Register(Register::HL(hl)),
+                }
+            }
+            _ => match Grammar::parse(ctx, tokens)? {
+                Some(register) => Address::Register(register),
+                None => Address::Expression(Grammar::parse(ctx, tokens)?),
+            },
+        };
+        Ok(Self {
+            left_square,
+            address,
+            right_square: Grammar::parse(ctx, tokens)?,
+        })
+    }
+}
+
+/// Address of a memory operand.
+#[derive(Debug)]
+pub enum Address<'a> {
+    /// Address held in a register (`[%hl]`, `[%c]`, ...).
+    Register(Register<'a>),
+
+    /// Address held in `HL`, incremented or decremented after the access
+    /// (`[%hl +]`, `[%hl -]`).
+    HlOffset(lex::HL<'a>, Sign<'a>),
+
+    /// Address given by an immediate operand.
+    Expression(Expression<'a>),
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Address, Asm, AsmStatement, Mnemonic, Operand, Register, Sign};
+    use crate::{ast::Statement, Error};
+
+    // parses the asm block at the end of the program
+    fn parse_asm(input: &str) -> Asm<'_> {
+        let (mut ast, errors) = crate::parse(input);
+        assert!(errors.is_empty(), "{:?}", errors);
+        match ast.inner.pop() {
+            Some(Statement::Asm(asm)) => asm,
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn instructions() {
+        let asm = parse_asm(
+            r#"
+            static FOO:u8
+            mod foo { fn bar {} }
+            asm {
+                .nop
+                .ld %a 42
+                .ld [%hl +] %a
+                .ldh [%c] %a
+                .ld %hl %sp + 2
+                .jr %nz wait
+                .jp %c foo::bar
+                .ret %c
+                .ret
+                .bit 7 %a
+                .add %a [FOO]
+            wait:
+            }"#,
+        );
+        let arity: Vec<_> = asm
+            .inner
+            .iter()
+            .filter_map(|s| match s {
+                AsmStatement::Instruction(i) => Some(i.operands.len()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(vec![0, 2, 2, 2, 2, 2, 2, 1, 0, 2, 2], arity);
+
+        match &asm.inner[2] {
+            AsmStatement::Instruction(i) => {
+                assert!(matches!(i.mnemonic, Mnemonic::Ld(_)));
+                assert!(matches!(
+                    i.operands[0],
+                    Operand::Indirect(super::Indirect {
+                        address: Address::HlOffset(_, Sign::Plus(_)),
+                        ..
+                    })
+                ));
+                assert!(matches!(i.operands[1], Operand::Register(Register::A(_))));
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn labels() {
+        let asm = parse_asm("asm { start: .nop .jr start end: }");
+        assert!(matches!(asm.inner[0], AsmStatement::Label(_)));
+        assert!(matches!(asm.inner[1], AsmStatement::Instruction(_)));
+        assert!(matches!(asm.inner[2], AsmStatement::Instruction(_)));
+        assert!(matches!(asm.inner[3], AsmStatement::Label(_)));
+    }
+
+    #[test]
+    fn undefined_label() {
+        let (_, errors) = crate::parse("asm { start: .jr end }");
+        assert!(
+            matches!(errors.as_slice(), [Error::InvalidPath(path)] if path.head.to_string() == "end")
+        );
+        let (_, errors) = crate::parse("asm { .jr foo::start start: }");
+        assert!(matches!(errors.as_slice(), [Error::InvalidPath(_)]));
+    }
+}
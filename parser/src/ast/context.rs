@@ -1,34 +1,388 @@
-use crate::ast::Path;
-use std::collections::HashSet;
+use crate::{
+    ast::{types::EnumDef, Field, Path, Type},
+    lex::{self, span::Span},
+    Error,
+};
+use std::collections::HashMap;
 
 #[derive(Default, Debug)]
 pub struct ContextBuilder {
-    _phantom: std::marker::PhantomData<()>,
+    module: Vec<String>,
 }
 
 impl ContextBuilder {
+    /// Path of the module being parsed (defaults to the root module).
+    pub fn module(mut self, module: &[String]) -> Self {
+        self.module = module.to_vec();
+        self
+    }
+
     pub fn build<'a>(self) -> Context<'a> {
         Context {
-            paths: HashSet::new(),
-            _phantom: std::marker::PhantomData,
+            module: self.module,
+            scopes: vec![Scope::default()],
+            asm: None,
+            errors: Vec::new(),
+            end: Span {
+                min: [0, 0],
+                max: [0, 0],
+            },
         }
     }
 }
 
-#[allow(unused)]
+/// Kinds of names.
+///
+/// Names of different kinds don't conflict with each other.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Kind {
+    /// Stack symbol (lets, function arguments, for loop fields). Stack symbols
+    /// are not visible from within nested functions.
+    Stack,
+
+    /// Static, const, or absolute symbol.
+    Static,
+
+    /// Function.
+    Fn,
+
+    /// Named type.
+    Type,
+
+    /// Inline assembly label. Only visible within the block.
+    Label,
+}
+
+#[derive(Debug)]
+struct Symbol<'a> {
+    ident: lex::Ident<'a>,
+    kind: Kind,
+}
+
+#[derive(Debug, Default)]
+struct Scope<'a> {
+    /// Function scopes hide the stack symbols of the enclosing scopes.
+    fn_: bool,
+
+    /// Defined symbols. Static symbols and functions are keyed by their name
+    /// prefixed with the module path (`a::b::name`).
+    symbols: HashMap<String, Symbol<'a>>,
+
+    /// Names imported with `use` (and the depth of the module importing them).
+    imports: Vec<(usize, String)>,
+}
+
+/// Symbol tables used to validate paths while parsing.
+///
+/// Mirrors the name resolution rules of the compiler:
+/// - Stack symbols shadow any other symbol, and are looked up first.
+/// - Paths starting with an imported name refer to the imported item. As
+///   imports may refer to other source files, these paths are not validated.
+/// - Otherwise paths are relative to the current module, then to each of its
+///   ancestors, all the way up to the root module.
+#[derive(Debug)]
 pub struct Context<'a> {
-    paths: HashSet<String>,
-    _phantom: std::marker::PhantomData<&'a ()>,
+    module: Vec<String>,
+    scopes: Vec<Scope<'a>>,
+
+    /// Paths referenced from an inline assembly block before being defined
+    /// (they might be labels declared later on in the block).
+    asm: Option<Vec<Path<'a>>>,
+
+    /// Errors the parser has recovered from.
+    errors: Vec<Error<'a>>,
+
+    /// Span of the end of the input, where the EOF token is synthesized if a
+    /// statement that failed to parse consumed it.
+    pub(crate) end: Span,
 }
 
 impl<'a> Context<'a> {
-    #[allow(unused)]
+    /// Returns whether the path is a type.
     pub(crate) fn is_type(&self, path: &Path<'a>) -> bool {
-        true
+        self.is_imported(path) || self.lookup(&path_name(path), |k| k == Kind::Type).is_some()
     }
 
-    #[allow(unused)]
+    /// Returns whether the path is a symbol or a function.
     pub(crate) fn is_defined(&self, path: &Path<'a>) -> bool {
-        true
+        self.is_imported(path) || self.lookup(&path_name(path), |k| k != Kind::Type).is_some()
+    }
+
+    /// Record an error the parser has recovered from.
+    pub(crate) fn error(&mut self, error: Error<'a>) {
+        self.errors.push(error);
+    }
+
+    /// Record an early EOF, unless the statement that consumed the end of the
+    /// input has reported it already.
+    pub(crate) fn eof(&mut self) {
+        if !matches!(self.errors.last(), Some(Error::Eof { .. })) {
+            self.errors.push(Error::Eof { span: self.end });
+        }
+    }
+
+    /// Take the errors recorded so far.
+    pub(crate) fn take_errors(&mut self) -> Vec<Error<'a>> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Parse within a new block scope.
+    pub(crate) fn scoped<T, F>(&mut self, fun: F) -> Result<T, Error<'a>>
+    where
+        F: FnOnce(&mut Self) -> Result<T, Error<'a>>,
+    {
+        self.push_scope();
+        let result = fun(self);
+        self.pop_scope();
+        result
+    }
+
+    /// Enter a block scope.
+    pub(crate) fn push_scope(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+
+    /// Enter the scope of a function body.
+    pub(crate) fn push_fn_scope(&mut self) {
+        self.scopes.push(Scope {
+            fn_: true,
+            ..Default::default()
+        });
+    }
+
+    /// Exit the current scope, dropping all the names defined within.
+    pub(crate) fn pop_scope(&mut self) {
+        assert!(self.scopes.len() > 1);
+        self.scopes.pop();
+    }
+
+    /// Enter a child module.
+    pub(crate) fn push_mod(&mut self, ident: &lex::Ident<'a>) {
+        self.module.push(ident.to_string());
+    }
+
+    /// Return to the parent module. Imports made from within the child module
+    /// are dropped.
+    pub(crate) fn pop_mod(&mut self) {
+        assert!(self.module.pop().is_some());
+        let depth = self.module.len();
+        for scope in &mut self.scopes {
+            scope.imports.retain(|(d, _)| *d <= depth);
+        }
+    }
+
+    /// Import a path into the current scope.
+    pub(crate) fn import(&mut self, path: &Path<'a>) {
+        let name = path.iter().last().unwrap().to_string();
+        let depth = self.module.len();
+        self.scopes.last_mut().unwrap().imports.push((depth, name));
+    }
+
+    /// Define the symbols of a `let` field.
+    pub(crate) fn define_stack(&mut self, field: &Field<'a>) -> Result<(), Error<'a>> {
+        self.define_field(String::new(), field, Kind::Stack)
+    }
+
+    /// Define the symbols of a `static` or `const` field.
+    pub(crate) fn define_static(&mut self, field: &Field<'a>) -> Result<(), Error<'a>> {
+        self.define_field(self.module.join("::"), field, Kind::Static)
+    }
+
+    /// Define a function.
+    pub(crate) fn define_fn(&mut self, ident: &lex::Ident<'a>) -> Result<(), Error<'a>> {
+        // functions remain defined beyond the scope they are declared in.
+        let name = mangle(&self.module, &ident.to_string());
+        Self::define_in(&mut self.scopes[0], name, ident, Kind::Fn)
+    }
+
+    /// Define a named type.
+    pub(crate) fn define_type(&mut self, ident: &lex::Ident<'a>) -> Result<(), Error<'a>> {
+        let name = mangle(&self.module, &ident.to_string());
+        let scope = self.scopes.last_mut().unwrap();
+        Self::define_in(scope, name, ident, Kind::Type)
+    }
+
+    /// Define an enum type, along with its variants (`Name::Variant`).
+    pub(crate) fn define_enum(&mut self, enum_: &EnumDef<'a>) -> Result<(), Error<'a>> {
+        self.define_type(&enum_.ident)?;
+        for variant in &enum_.variants {
+            let name = format!("{}::{}", enum_.ident, variant.ident);
+            let name = mangle(&self.module, &name);
+            let scope = self.scopes.last_mut().unwrap();
+            Self::define_in(scope, name, &variant.ident, Kind::Static)?;
+        }
+        Ok(())
+    }
+
+    /// Define an inline assembly label.
+    pub(crate) fn define_label(&mut self, ident: &lex::Ident<'a>) -> Result<(), Error<'a>> {
+        let scope = self.scopes.last_mut().unwrap();
+        Self::define_in(scope, ident.to_string(), ident, Kind::Label)
+    }
+
+    /// Begin parsing an inline assembly block.
+    /// Undefined paths are accepted until [`end_asm`] is called.
+    ///
+    /// [`end_asm`]: #method.end_asm
+    pub(crate) fn begin_asm(&mut self) {
+        self.push_scope();
+        self.asm = Some(Vec::new());
+    }
+
+    /// End parsing an inline assembly block. Fails if any path referenced
+    /// before being defined is not a label of the block.
+    pub(crate) fn end_asm(&mut self) -> Result<(), Error<'a>> {
+        let paths = self.asm.take().unwrap();
+        let labels = &self.scopes.last().unwrap().symbols;
+        let result = match paths
+            .into_iter()
+            .find(|p| p.len() != 1 || !labels.contains_key(&p.head.to_string()))
+        {
+            Some(path) => Err(Error::InvalidPath(path)),
+            None => Ok(()),
+        };
+        self.pop_scope();
+        result
+    }
+
+    /// Accept an undefined path if it might be an inline assembly label that
+    /// has not been defined yet.
+    pub(crate) fn defer_label(&mut self, path: &Path<'a>) -> bool {
+        match &mut self.asm {
+            Some(paths) => {
+                paths.push(path.clone());
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn define_field(
+        &mut self,
+        prefix: String,
+        field: &Field<'a>,
+        kind: Kind,
+    ) -> Result<(), Error<'a>> {
+        let name = if prefix.is_empty() {
+            field.ident.to_string()
+        } else {
+            format!("{}::{}", prefix, field.ident)
+        };
+
+        // a symbol can't be defined if the name is already visible.
+        if let Some(symbol) = self.lookup_exact(&name, kind) {
+            return Err(Error::ShadowIdent {
+                ident: symbol.ident.clone(),
+                shadow: field.ident.clone(),
+            });
+        }
+        let scope = self.scopes.last_mut().unwrap();
+        Self::define_in(scope, name.clone(), &field.ident, kind)?;
+
+        // struct & union fields
+        if let Type::Struct(struct_) = &field.type_ {
+            for field in &struct_.fields {
+                self.define_field(name.clone(), field, kind)?;
+            }
+        } else if let Type::Union(union) = &field.type_ {
+            for field in &union.fields {
+                self.define_field(name.clone(), field, kind)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn define_in(
+        scope: &mut Scope<'a>,
+        name: String,
+        ident: &lex::Ident<'a>,
+        kind: Kind,
+    ) -> Result<(), Error<'a>> {
+        if let Some(symbol) = scope.symbols.get(&name).filter(|s| conflict(s.kind, kind)) {
+            return Err(Error::ShadowIdent {
+                ident: symbol.ident.clone(),
+                shadow: ident.clone(),
+            });
+        }
+        let ident = ident.clone();
+        scope.symbols.insert(name, Symbol { ident, kind });
+        Ok(())
+    }
+
+    fn is_imported(&self, path: &Path<'a>) -> bool {
+        let head = path.head.to_string();
+        self.scopes
+            .iter()
+            .flat_map(|s| &s.imports)
+            .any(|(_, name)| *name == head)
+    }
+
+    // Symbol visible from the current scope, by exact (mangled) name.
+    fn lookup_exact(&self, name: &str, kind: Kind) -> Option<&Symbol<'a>> {
+        let mut stack = true;
+        for scope in self.scopes.iter().rev() {
+            if let Some(symbol) = scope.symbols.get(name) {
+                let visible = stack || !matches!(symbol.kind, Kind::Stack | Kind::Label);
+                if visible && conflict(symbol.kind, kind) {
+                    return Some(symbol);
+                }
+            }
+            stack &= !scope.fn_;
+        }
+        None
+    }
+
+    // Resolve a path from the current scope & module.
+    fn lookup<F>(&self, name: &str, filter: F) -> Option<&Symbol<'a>>
+    where
+        F: Fn(Kind) -> bool,
+    {
+        let mut stack = true;
+        for scope in self.scopes.iter().rev() {
+            if let Some(symbol) = scope.symbols.get(name) {
+                if stack && matches!(symbol.kind, Kind::Stack | Kind::Label) && filter(symbol.kind)
+                {
+                    return Some(symbol);
+                }
+            }
+            stack &= !scope.fn_;
+        }
+        (0..=self.module.len()).rev().find_map(|len| {
+            let name = mangle(&self.module[..len], name);
+            self.scopes
+                .iter()
+                .rev()
+                .filter_map(|scope| scope.symbols.get(&name))
+                .find(|s| !matches!(s.kind, Kind::Stack | Kind::Label) && filter(s.kind))
+        })
+    }
+}
+
+// Whether a name of kind `new` can't be defined if one of kind `old` exists.
+fn conflict(old: Kind, new: Kind) -> bool {
+    use Kind::{Fn, Label, Stack, Static, Type};
+    matches!(
+        (old, new),
+        (Stack, Stack)
+            | (Stack, Static)
+            | (Static, Stack)
+            | (Static, Static)
+            | (Fn, Fn)
+            | (Type, Type)
+            | (Label, Label)
+    )
+}
+
+fn path_name(path: &Path<'_>) -> String {
+    let name: Vec<_> = path.iter().map(|i| i.to_string()).collect();
+    name.join("::")
+}
+
+fn mangle(module: &[String], name: &str) -> String {
+    let mut mangled = module.join("::");
+    if !mangled.is_empty() {
+        mangled.push_str("::");
     }
+    mangled.push_str(name);
+    mangled
 }
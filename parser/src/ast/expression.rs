@@ -1,22 +1,30 @@
 //! Expression grammars.
+#[cfg(feature = "lisp")]
+use crate::lex::Token;
 use crate::{
     ast::{Context, Grammar, Path},
     lex,
-    lex::{Token, Tokens},
+    lex::Tokens,
     Error,
 };
 use std::iter::Peekable;
 
+#[cfg(not(feature = "lisp"))]
+mod infix;
+
 parse! {
     #[derive(Debug)]
     pub enum Expression<'a> {
         Path(Path<'a>),
         Lit(lex::Lit<'a>),
         Array(Array<'a>),
+        Struct(Struct<'a>),
         Minus(Box<Minus<'a>>),
         AddressOf(Box<AddressOf<'a>>),
         Deref(Box<Deref<'a>>),
         Not(Box<Not<'a>>),
+        LogicalNot(Box<LogicalNot<'a>>),
+        Field(Box<Field<'a>>),
         Add(Box<LispNode<'a, Add<'a>>>),
         Sub(Box<LispNode<'a, Sub<'a>>>),
         Mul(Box<LispNode<'a, Mul<'a>>>),
@@ -24,6 +32,8 @@ parse! {
         And(Box<LispNode<'a, And<'a>>>),
         Or(Box<LispNode<'a, Or<'a>>>),
         Xor(Box<LispNode<'a, Xor<'a>>>),
+        LogicalAnd(Box<LispNode<'a, LogicalAnd<'a>>>),
+        LogicalOr(Box<LispNode<'a, LogicalOr<'a>>>),
         Assign(Box<LispNode<'a, Assign<'a>>>),
         PlusAssign(Box<LispNode<'a, PlusAssign<'a>>>),
         MinusAssign(Box<LispNode<'a, MinusAssign<'a>>>),
@@ -45,6 +55,7 @@ parse! {
     }
 }
 
+#[cfg(feature = "lisp")]
 impl<'a> Grammar<'a> for Option<Expression<'a>> {
     fn parse(
         context: &mut Context<'a>,
@@ -60,28 +71,33 @@ impl<'a> Grammar<'a> for Option<Expression<'a>> {
             }};
         }
 
-        let expression = match tokens.peek() {
+        let mut expression = match tokens.peek() {
             None => {
                 let _ = tokens.next();
-                return Err(Error::Eof);
+                return Err(Error::Eof { span: context.end });
             }
             Some(Err(_)) => return Err(tokens.next().unwrap().err().unwrap()),
 
             Some(Ok(Token::Lit(_))) => Expression::Lit(Grammar::parse(context, tokens)?),
             Some(Ok(Token::Ident(_))) => {
                 let path = Grammar::parse(context, tokens)?;
-                if !context.is_defined(&path) {
+                if !context.is_defined(&path) && !context.defer_label(&path) {
                     return Err(Error::InvalidPath(path));
                 }
                 Expression::Path(path)
             }
             // array
             Some(Ok(Token::LeftSquare(_))) => Expression::Array(Grammar::parse(context, tokens)?),
+            // struct
+            Some(Ok(Token::LeftBracket(_))) => Expression::Struct(Grammar::parse(context, tokens)?),
             // unary ops
             Some(Ok(Token::Minus(_))) => Expression::Minus(Grammar::parse(context, tokens)?),
             Some(Ok(Token::At(_))) => Expression::AddressOf(Grammar::parse(context, tokens)?),
             Some(Ok(Token::Star(_))) => Expression::Deref(Grammar::parse(context, tokens)?),
             Some(Ok(Token::Tilde(_))) => Expression::Not(Grammar::parse(context, tokens)?),
+            Some(Ok(Token::LogicalNot(_))) => {
+                Expression::LogicalNot(Grammar::parse(context, tokens)?)
+            }
 
             // others
             Some(Ok(Token::LeftPar(_))) => {
@@ -95,6 +111,9 @@ impl<'a> Grammar<'a> for Option<Expression<'a>> {
                     Some(Ok(Token::Ampersand(_))) => prefix_match_arm!(And, left_par),
                     Some(Ok(Token::Pipe(_))) => prefix_match_arm!(Or, left_par),
                     Some(Ok(Token::Caret(_))) => prefix_match_arm!(Xor, left_par),
+                    // logic
+                    Some(Ok(Token::LogicalAnd(_))) => prefix_match_arm!(LogicalAnd, left_par),
+                    Some(Ok(Token::LogicalOr(_))) => prefix_match_arm!(LogicalOr, left_par),
                     // assignment
                     Some(Ok(Token::Assign(_))) => prefix_match_arm!(Assign, left_par),
                     Some(Ok(Token::PlusAssign(_))) => prefix_match_arm!(PlusAssign, left_par),
@@ -119,13 +138,22 @@ impl<'a> Grammar<'a> for Option<Expression<'a>> {
                     Some(Ok(_)) => prefix_match_arm!(Call, left_par),
                     // fallbacks
                     // errors
-                    None => unimplemented!(),
+                    None => return Err(Error::Eof { span: context.end }),
                     Some(Err(_)) => return Err(tokens.next().unwrap().err().unwrap()),
                 }
             }
             Some(Ok(_)) => return Ok(None),
         };
 
+        // field projections (`.<ident>`) bind tighter than any prefix operator
+        while let Some(Ok(Token::Dot(_))) = tokens.peek() {
+            expression = Expression::Field(Box::new(Field {
+                inner: expression,
+                dot: Grammar::parse(context, tokens)?,
+                ident: Grammar::parse(context, tokens)?,
+            }));
+        }
+
         Ok(Some(expression))
     }
 }
@@ -138,8 +166,10 @@ impl<'a> Grammar<'a> for Expression<'a> {
         if let Some(statement) = Grammar::parse(context, tokens)? {
             Ok(statement)
         } else {
-            let token = tokens.next().unwrap()?;
-            Err(Error::UnexpectedToken(token))
+            match tokens.next() {
+                Some(token) => Err(Error::unexpected(token?)),
+                None => Err(Error::Eof { span: context.end }),
+            }
         }
     }
 }
@@ -148,6 +178,11 @@ span!(Array {
     left_square,
     right_square
 });
+span!(Struct {
+    left_bracket,
+    right_bracket
+});
+span!(StructField { ident, expression });
 span!(LispNode<I> {
     left_par,
     right_par
@@ -159,10 +194,14 @@ span!(Div { slash, right });
 span!(And { ampersand, right });
 span!(Or { pipe, right });
 span!(Xor { caret, right });
+span!(LogicalAnd { and, right });
+span!(LogicalOr { or, right });
 span!(Minus { minus, inner });
 span!(AddressOf { at, inner });
 span!(Deref { star, inner });
 span!(Not { tilde, inner });
+span!(LogicalNot { not, inner });
+span!(Field { inner, ident });
 span!(Assign { assign, right });
 span!(PlusAssign { plus_assign, right });
 span!(MinusAssign {
@@ -216,6 +255,48 @@ parse! {
     }
 }
 
+parse! {
+    /// `{ <ident>:<expression> ... }`
+    #[derive(Debug)]
+    pub struct Struct<'a> {
+        /// `{` token.
+        pub left_bracket: lex::LeftBracket<'a>,
+
+        /// Field initializers.
+        pub fields: Vec<StructField<'a>>,
+
+        /// `}` token.
+        pub right_bracket: lex::RightBracket<'a>,
+    }
+}
+
+parse! {
+    /// `<ident>:<expression>`
+    #[derive(Debug)]
+    pub struct StructField<'a> {
+        /// Field identifier.
+        pub ident: lex::Ident<'a>,
+
+        /// `:` token.
+        pub colon: lex::Colon<'a>,
+
+        /// Field value.
+        pub expression: Expression<'a>,
+    }
+}
+
+impl<'a> Grammar<'a> for Option<StructField<'a>> {
+    fn parse(
+        context: &mut Context<'a>,
+        tokens: &mut Peekable<Tokens<'a>>,
+    ) -> Result<Self, Error<'a>> {
+        match tokens.peek() {
+            Some(Ok(lex::Token::Ident(_))) => Ok(Some(Grammar::parse(context, tokens)?)),
+            _ => Ok(None),
+        }
+    }
+}
+
 parse! {
     #[derive(Debug)]
     pub struct Add<'a> {
@@ -286,6 +367,36 @@ parse! {
     }
 }
 
+parse! {
+    /// `(and <left> <right>)`
+    #[derive(Debug)]
+    pub struct LogicalAnd<'a> {
+        /// `and` token.
+        pub and: lex::LogicalAnd<'a>,
+
+        /// lhs expression tokens.
+        pub left: Expression<'a>,
+
+        /// rhs expression tokens (only evaluated when lhs is true).
+        pub right: Expression<'a>,
+    }
+}
+
+parse! {
+    /// `(or <left> <right>)`
+    #[derive(Debug)]
+    pub struct LogicalOr<'a> {
+        /// `or` token.
+        pub or: lex::LogicalOr<'a>,
+
+        /// lhs expression tokens.
+        pub left: Expression<'a>,
+
+        /// rhs expression tokens (only evaluated when lhs is false).
+        pub right: Expression<'a>,
+    }
+}
+
 parse! {
     #[derive(Debug)]
     pub struct Or<'a> {
@@ -322,6 +433,19 @@ parse! {
     }
 }
 
+/// `<expression>.<ident>`
+#[derive(Debug)]
+pub struct Field<'a> {
+    /// Struct or union expression tokens.
+    pub inner: Expression<'a>,
+
+    /// `.` token.
+    pub dot: lex::Dot<'a>,
+
+    /// Field identifier.
+    pub ident: lex::Ident<'a>,
+}
+
 parse! {
     #[derive(Debug)]
     pub struct AddressOf<'a> {
@@ -346,6 +470,18 @@ parse! {
     }
 }
 
+parse! {
+    /// `not <expression>`
+    #[derive(Debug)]
+    pub struct LogicalNot<'a> {
+        /// `not` token.
+        pub not: lex::LogicalNot<'a>,
+
+        /// Negated expression tokens.
+        pub inner: Expression<'a>,
+    }
+}
+
 parse! {
     #[derive(Debug)]
     pub struct Assign<'a> {
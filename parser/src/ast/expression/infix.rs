@@ -0,0 +1,482 @@
+//! Infix (Rust/C-like) expression grammar.
+//!
+//! Produces the same [`Expression`] nodes as the lisp grammar, with the
+//! parentheses of the lisp nodes implied by the operands of each operation.
+//!
+//! Operators, from the lowest to the highest precedence:
+//!
+//! | Operators                               | Associativity |
+//! |-----------------------------------------|---------------|
+//! | `=` `+=` `-=` `*=` `/=` `&=` `\|=` `^=` | right         |
+//! | `or`                                    | left          |
+//! | `and`                                   | left          |
+//! | `==` `~=` `<` `<=` `>` `>=`             | left          |
+//! | `\|`                                    | left          |
+//! | `^`                                     | left          |
+//! | `&`                                     | left          |
+//! | `<<` `>>`                               | left          |
+//! | `+` `-`                                 | left          |
+//! | `*` `/`                                 | left          |
+//! | `-` `~` `not` `@` `*` (unary)           | prefix        |
+//! | `f(a, b)` `a[i]` `a.b`                  | postfix       |
+//!
+//! [`Expression`]: ../enum.Expression.html
+use crate::{
+    ast::{
+        expression::{
+            Add, AddressOf, And, AndAssign, Array, Assign, Call, Deref, Div, DivAssign, Eq,
+            Expression, Field, Greater, GreaterEq, Index, LeftShift, Less, LessEq, LispNode,
+            LogicalAnd, LogicalNot, LogicalOr, Minus, MinusAssign, Mul, MulAssign, Not, NotEq, Or,
+            OrAssign, PlusAssign, RightShift, Struct, Sub, Xor, XorAssign,
+        },
+        Context, Grammar,
+    },
+    lex,
+    lex::{
+        span::{Span, Spanned},
+        Token, Tokens,
+    },
+    Error,
+};
+use std::iter::Peekable;
+
+// precedence of assignments (the lowest)
+const ASSIGN: u8 = 1;
+
+// precedence of a binary operator token, or `None` if the token isn't one.
+fn precedence(token: &Token<'_>) -> Option<u8> {
+    Some(match token {
+        Token::Assign(_)
+        | Token::PlusAssign(_)
+        | Token::MinusAssign(_)
+        | Token::StarAssign(_)
+        | Token::SlashAssign(_)
+        | Token::AmpersandAssign(_)
+        | Token::PipeAssign(_)
+        | Token::CaretAssign(_) => ASSIGN,
+        Token::LogicalOr(_) => 2,
+        Token::LogicalAnd(_) => 3,
+        Token::Eq(_)
+        | Token::TildeEq(_)
+        | Token::Less(_)
+        | Token::LessEq(_)
+        | Token::Greater(_)
+        | Token::GreaterEq(_) => 4,
+        Token::Pipe(_) => 5,
+        Token::Caret(_) => 6,
+        Token::Ampersand(_) => 7,
+        Token::LessLess(_) | Token::GreatGreat(_) => 8,
+        Token::Plus(_) | Token::Minus(_) => 9,
+        Token::Star(_) | Token::Slash(_) => 10,
+        _ => return None,
+    })
+}
+
+// lisp node with implicit parentheses, spanning from `min` to `max`.
+fn node<'a, I>(min: Span, inner: I, max: Span) -> Box<LispNode<'a, I>> {
+    Box::new(LispNode {
+        left_par: lex::LeftPar::implicit(Span {
+            min: min.min,
+            max: min.min,
+        }),
+        inner,
+        right_par: lex::RightPar::implicit(Span {
+            min: max.max,
+            max: max.max,
+        }),
+    })
+}
+
+// build the expression of a binary operator.
+fn binary<'a>(op: Token<'a>, left: Expression<'a>, right: Expression<'a>) -> Expression<'a> {
+    let (min, max) = (left.span(), right.span());
+    macro_rules! binary {
+        ($var:ident, $field:ident, $token:expr) => {
+            Expression::$var(node(
+                min,
+                $var {
+                    $field: $token,
+                    left,
+                    right,
+                },
+                max,
+            ))
+        };
+    }
+    match op {
+        Token::Assign(t) => binary!(Assign, assign, t),
+        Token::PlusAssign(t) => binary!(PlusAssign, plus_assign, t),
+        Token::MinusAssign(t) => binary!(MinusAssign, minus_assign, t),
+        Token::StarAssign(t) => binary!(MulAssign, star_assign, t),
+        Token::SlashAssign(t) => binary!(DivAssign, slash_assign, t),
+        Token::AmpersandAssign(t) => binary!(AndAssign, ampersand_assign, t),
+        Token::PipeAssign(t) => binary!(OrAssign, pipe_assign, t),
+        Token::CaretAssign(t) => binary!(XorAssign, caret_assign, t),
+        Token::LogicalOr(t) => binary!(LogicalOr, or, t),
+        Token::LogicalAnd(t) => binary!(LogicalAnd, and, t),
+        Token::Eq(t) => binary!(Eq, eq, t),
+        Token::TildeEq(t) => binary!(NotEq, tilde_eq, t),
+        Token::Less(t) => binary!(Less, less, t),
+        Token::LessEq(t) => binary!(LessEq, less_eq, t),
+        Token::Greater(t) => binary!(Greater, greater, t),
+        Token::GreaterEq(t) => binary!(GreaterEq, greater_eq, t),
+        Token::Pipe(t) => binary!(Or, pipe, t),
+        Token::Caret(t) => binary!(Xor, caret, t),
+        Token::Ampersand(t) => binary!(And, ampersand, t),
+        Token::LessLess(t) => binary!(LeftShift, less_less, t),
+        Token::GreatGreat(t) => binary!(RightShift, great_great, t),
+        Token::Plus(t) => binary!(Add, plus, t),
+        Token::Minus(t) => binary!(Sub, minus, t),
+        Token::Star(t) => binary!(Mul, star, t),
+        Token::Slash(t) => binary!(Div, slash, t),
+        _ => unreachable!(),
+    }
+}
+
+impl<'a> Grammar<'a> for Option<Expression<'a>> {
+    fn parse(
+        context: &mut Context<'a>,
+        tokens: &mut Peekable<Tokens<'a>>,
+    ) -> Result<Self, Error<'a>> {
+        parse_binary(context, tokens, ASSIGN)
+    }
+}
+
+// error for an unexpected token. The end of the input is left in the token
+// stream so that the parsing of the program can terminate.
+fn unexpected<'a>(context: &Context<'a>, tokens: &mut Peekable<Tokens<'a>>) -> Error<'a> {
+    match tokens.peek() {
+        None => Error::Eof { span: context.end },
+        Some(Ok(Token::Eof(eof))) => Error::Eof { span: eof.span() },
+        _ => match tokens.next().unwrap() {
+            Ok(token) => Error::UnexpectedToken(token),
+            Err(err) => err,
+        },
+    }
+}
+
+// parse a closing token, if it's there.
+fn close<'a, T: Grammar<'a>>(
+    context: &mut Context<'a>,
+    tokens: &mut Peekable<Tokens<'a>>,
+) -> Result<T, Error<'a>> {
+    match tokens.peek() {
+        None | Some(Ok(Token::Eof(_))) => Err(unexpected(context, tokens)),
+        _ => Grammar::parse(context, tokens),
+    }
+}
+
+// parse an expression, which is required to be there.
+fn expect<'a>(
+    context: &mut Context<'a>,
+    tokens: &mut Peekable<Tokens<'a>>,
+    parse: fn(
+        &mut Context<'a>,
+        &mut Peekable<Tokens<'a>>,
+    ) -> Result<Option<Expression<'a>>, Error<'a>>,
+) -> Result<Expression<'a>, Error<'a>> {
+    match parse(context, tokens)? {
+        Some(expression) => Ok(expression),
+        None => Err(unexpected(context, tokens)),
+    }
+}
+
+// precedence climbing over the binary operators of (at least) `min` precedence.
+fn parse_binary<'a>(
+    context: &mut Context<'a>,
+    tokens: &mut Peekable<Tokens<'a>>,
+    min: u8,
+) -> Result<Option<Expression<'a>>, Error<'a>> {
+    let mut left = match parse_unary(context, tokens)? {
+        Some(left) => left,
+        None => return Ok(None),
+    };
+    while let Some(Ok(token)) = tokens.peek() {
+        let precedence = match precedence(token) {
+            Some(precedence) if precedence >= min => precedence,
+            _ => break,
+        };
+        let op = tokens.next().unwrap()?;
+        // assignments are right associative
+        let min = if precedence == ASSIGN {
+            precedence
+        } else {
+            precedence + 1
+        };
+        let right = match parse_binary(context, tokens, min)? {
+            Some(right) => right,
+            None => return Err(unexpected(context, tokens)),
+        };
+        left = binary(op, left, right);
+    }
+    Ok(Some(left))
+}
+
+fn parse_unary<'a>(
+    context: &mut Context<'a>,
+    tokens: &mut Peekable<Tokens<'a>>,
+) -> Result<Option<Expression<'a>>, Error<'a>> {
+    Ok(Some(match tokens.peek() {
+        Some(Ok(Token::Minus(_))) => Expression::Minus(Box::new(Minus {
+            minus: Grammar::parse(context, tokens)?,
+            inner: expect(context, tokens, parse_unary)?,
+        })),
+        Some(Ok(Token::At(_))) => Expression::AddressOf(Box::new(AddressOf {
+            at: Grammar::parse(context, tokens)?,
+            inner: expect(context, tokens, parse_unary)?,
+        })),
+        Some(Ok(Token::Star(_))) => Expression::Deref(Box::new(Deref {
+            star: Grammar::parse(context, tokens)?,
+            inner: expect(context, tokens, parse_unary)?,
+        })),
+        Some(Ok(Token::Tilde(_))) => Expression::Not(Box::new(Not {
+            tilde: Grammar::parse(context, tokens)?,
+            inner: expect(context, tokens, parse_unary)?,
+        })),
+        Some(Ok(Token::LogicalNot(_))) => Expression::LogicalNot(Box::new(LogicalNot {
+            not: Grammar::parse(context, tokens)?,
+            inner: expect(context, tokens, parse_unary)?,
+        })),
+        _ => return parse_postfix(context, tokens),
+    }))
+}
+
+fn parse_postfix<'a>(
+    context: &mut Context<'a>,
+    tokens: &mut Peekable<Tokens<'a>>,
+) -> Result<Option<Expression<'a>>, Error<'a>> {
+    let mut expression = match tokens.peek() {
+        None => {
+            let _ = tokens.next();
+            return Err(Error::Eof { span: context.end });
+        }
+        Some(Err(_)) => return Err(tokens.next().unwrap().err().unwrap()),
+
+        Some(Ok(Token::Lit(_))) => Expression::Lit(Grammar::parse(context, tokens)?),
+        Some(Ok(Token::Ident(_))) => {
+            let path = Grammar::parse(context, tokens)?;
+            if !context.is_defined(&path) && !context.defer_label(&path) {
+                return Err(Error::InvalidPath(path));
+            }
+            Expression::Path(path)
+        }
+        // array
+        Some(Ok(Token::LeftSquare(_))) => Expression::Array(parse_array(context, tokens)?),
+        // struct
+        Some(Ok(Token::LeftBracket(_))) => Expression::Struct(parse_struct(context, tokens)?),
+        // grouping
+        Some(Ok(Token::LeftPar(_))) => {
+            let _: lex::LeftPar<'a> = Grammar::parse(context, tokens)?;
+            let inner = expect(context, tokens, Grammar::parse)?;
+            let _: lex::RightPar<'a> = close(context, tokens)?;
+            inner
+        }
+        Some(Ok(_)) => return Ok(None),
+    };
+
+    loop {
+        expression = match tokens.peek() {
+            // field projection
+            Some(Ok(Token::Dot(_))) => Expression::Field(Box::new(Field {
+                inner: expression,
+                dot: Grammar::parse(context, tokens)?,
+                ident: Grammar::parse(context, tokens)?,
+            })),
+            // indexing
+            Some(Ok(Token::LeftSquare(_))) => {
+                let min = expression.span();
+                let left_square = Grammar::parse(context, tokens)?;
+                let left = expect(context, tokens, Grammar::parse)?;
+                let right_square: lex::RightSquare<'a> = close(context, tokens)?;
+                let max = right_square.span();
+                let index = Index {
+                    left_square,
+                    left,
+                    right_square,
+                    right: expression,
+                };
+                Expression::Index(node(min, index, max))
+            }
+            // only named functions can be called
+            Some(Ok(Token::LeftPar(_))) if matches!(expression, Expression::Path(_)) => {
+                let _: lex::LeftPar<'a> = Grammar::parse(context, tokens)?;
+                let args = parse_list(context, tokens, |token| {
+                    matches!(token, Some(Ok(Token::RightPar(_))))
+                })?;
+                let left_par = lex::LeftPar::implicit(Span {
+                    min: expression.span().min,
+                    max: expression.span().min,
+                });
+                Expression::Call(Box::new(LispNode {
+                    left_par,
+                    inner: Call {
+                        left: expression,
+                        args,
+                    },
+                    right_par: close(context, tokens)?,
+                }))
+            }
+            _ => break,
+        };
+    }
+
+    Ok(Some(expression))
+}
+
+// `[a, b, c]` (commas are optional).
+fn parse_array<'a>(
+    context: &mut Context<'a>,
+    tokens: &mut Peekable<Tokens<'a>>,
+) -> Result<Array<'a>, Error<'a>> {
+    let left_square = Grammar::parse(context, tokens)?;
+    let inner = parse_list(context, tokens, |token| {
+        matches!(token, Some(Ok(Token::RightSquare(_))))
+    })?;
+    Ok(Array {
+        left_square,
+        inner,
+        right_square: close(context, tokens)?,
+    })
+}
+
+// `{ a:1, b:2 }` (commas are optional).
+fn parse_struct<'a>(
+    context: &mut Context<'a>,
+    tokens: &mut Peekable<Tokens<'a>>,
+) -> Result<Struct<'a>, Error<'a>> {
+    let left_bracket = Grammar::parse(context, tokens)?;
+    let mut fields = Vec::new();
+    while let Some(field) = Grammar::parse(context, tokens)? {
+        fields.push(field);
+        if let Some(Ok(Token::Comma(_))) = tokens.peek() {
+            tokens.next();
+        }
+    }
+    Ok(Struct {
+        left_bracket,
+        fields,
+        right_bracket: close(context, tokens)?,
+    })
+}
+
+// comma-separated expressions up to (but excluding) the closing token. A
+// trailing comma is allowed.
+fn parse_list<'a>(
+    context: &mut Context<'a>,
+    tokens: &mut Peekable<Tokens<'a>>,
+    end: fn(Option<&Result<Token<'a>, Error<'a>>>) -> bool,
+) -> Result<Vec<Expression<'a>>, Error<'a>> {
+    let mut list = Vec::new();
+    while !end(tokens.peek()) {
+        list.push(expect(context, tokens, Grammar::parse)?);
+        if let Some(Ok(Token::Comma(_))) = tokens.peek() {
+            tokens.next();
+        }
+    }
+    Ok(list)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        ast::{Ast, Expression, Statement},
+        Error,
+    };
+
+    fn parse_program(input: &str) -> Ast<'_> {
+        let (ast, errors) = crate::parse(input);
+        assert!(errors.is_empty(), "{:?}", errors);
+        ast
+    }
+
+    // expression of the last statement of the program
+    fn parse_expression(input: &str) -> Expression<'_> {
+        match parse_program(input).inner.pop() {
+            Some(Statement::Inline(inline)) => inline.inner,
+            statement => panic!("Expected an expression, found {:?}", statement),
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        let expression = parse_expression("static a:u8 a = 1 + 2 * 3 - 4");
+        let assign = match expression {
+            Expression::Assign(assign) => assign,
+            e => panic!("{:?}", e),
+        };
+        let sub = match &assign.inner.right {
+            Expression::Sub(sub) => sub,
+            e => panic!("{:?}", e),
+        };
+        assert!(matches!(sub.inner.right, Expression::Lit(_)));
+        let add = match &sub.inner.left {
+            Expression::Add(add) => add,
+            e => panic!("{:?}", e),
+        };
+        assert!(matches!(add.inner.left, Expression::Lit(_)));
+        assert!(matches!(add.inner.right, Expression::Mul(_)));
+
+        assert!(matches!(
+            parse_expression("(1 + 2) * 3"),
+            Expression::Mul(_)
+        ));
+        assert!(matches!(
+            parse_expression("1 < 2 and 3 < 4 or not 0"),
+            Expression::LogicalOr(_)
+        ));
+        assert!(matches!(
+            parse_expression("static a:u8 -a.b"),
+            Expression::Minus(_)
+        ));
+    }
+
+    #[test]
+    fn assign_right_associative() {
+        let expression = parse_expression("static a:u8 static b:u8 a = b = 1");
+        match expression {
+            Expression::Assign(assign) => {
+                assert!(matches!(assign.inner.left, Expression::Path(_)));
+                assert!(matches!(assign.inner.right, Expression::Assign(_)));
+            }
+            e => panic!("{:?}", e),
+        }
+    }
+
+    #[test]
+    fn postfix() {
+        parse_program("static a:[u8 4] a[1 + 2] = a[0]");
+        parse_program("static a:[[u8 4] 4] a[0][1] = 2");
+        parse_program("static p:struct { x:u8 y:[u8 2] } p.y[1] = p.x");
+        parse_program("fn f(a:u8 b:u8):u8 { return a + b } let x:u8 = f(1, f(2, 3),)");
+        parse_program("let a:[u8 3] = [1, -2, 3]");
+        parse_program("let a:[u8 3] = [1 2 3]");
+        parse_program(
+            "struct P { x:u8 y:u8 } const A:P = { x:1 + 2, y:3 } const B:P = { x:1 y:2 }",
+        );
+        parse_program("static a:&u8 *a = *a + 1");
+        parse_program("static a:u8 let p:&u8 = @a");
+    }
+
+    #[test]
+    fn spans() {
+        let expression = parse_expression("static a:u8\n  a + 1");
+        assert_eq!([1, 2], expression.span().min);
+        let expression = parse_expression("fn foo(a:u8) { }\nfoo(42)");
+        assert_eq!([1, 0], expression.span().min);
+    }
+
+    #[test]
+    fn errors() {
+        let error = |input| {
+            let (_, errors) = crate::parse(input);
+            errors.into_iter().next().expect("Expected a syntax error")
+        };
+        assert!(matches!(error("1 +"), Error::Eof { .. }));
+        assert!(matches!(error("(1 + 2"), Error::Eof { .. }));
+        assert!(matches!(error("1 + }"), Error::UnexpectedToken(_)));
+        assert!(matches!(error("[1, 2"), Error::Eof { .. }));
+        assert!(matches!(error("foo(1)"), Error::InvalidPath(_)));
+    }
+
+    use crate::lex::span::Spanned;
+}
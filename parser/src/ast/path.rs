@@ -9,7 +9,7 @@ use crate::{
 };
 use std::iter::Peekable;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Path<'a> {
     /// Head identifier token.
     pub head: lex::Ident<'a>,
@@ -2,7 +2,11 @@
 use crate::{
     ast::{expression::Expression, Context, Field, Grammar, Path},
     lex,
-    lex::{Token, Tokens},
+    lex::{
+        span,
+        span::{Span, Spanned},
+        Token, Tokens,
+    },
     Error,
 };
 use std::iter::Peekable;
@@ -16,6 +20,15 @@ parse! {
         /// i8 type.
         I8(lex::I8<'a>),
 
+        /// u16 type.
+        U16(lex::U16<'a>),
+
+        /// i16 type.
+        I16(lex::I16<'a>),
+
+        /// bool type.
+        Bool(lex::Bool<'a>),
+
         /// Array type.
         Array(Box<Array<'a>>),
 
@@ -39,6 +52,9 @@ impl<'a> Grammar<'a> for Option<Type<'a>> {
             Some(Err(_)) => return Err(tokens.next().unwrap().err().unwrap()),
             Some(Ok(Token::U8(_))) => Type::U8(Grammar::parse(ctx, tokens)?),
             Some(Ok(Token::I8(_))) => Type::I8(Grammar::parse(ctx, tokens)?),
+            Some(Ok(Token::U16(_))) => Type::U16(Grammar::parse(ctx, tokens)?),
+            Some(Ok(Token::I16(_))) => Type::I16(Grammar::parse(ctx, tokens)?),
+            Some(Ok(Token::Bool(_))) => Type::Bool(Grammar::parse(ctx, tokens)?),
             Some(Ok(Token::LeftSquare(_))) => Type::Array(Grammar::parse(ctx, tokens)?),
             Some(Ok(Token::Struct(_))) => Type::Struct(Grammar::parse(ctx, tokens)?),
             Some(Ok(Token::Union(_))) => Type::Union(Grammar::parse(ctx, tokens)?),
@@ -62,7 +78,10 @@ impl<'a> Grammar<'a> for Type<'a> {
         if let Some(statement) = Grammar::parse(ctx, tokens)? {
             Ok(statement)
         } else {
-            Err(Error::UnexpectedToken(tokens.next().unwrap()?))
+            match tokens.next() {
+                Some(token) => Err(Error::unexpected(token?)),
+                None => Err(Error::Eof { span: ctx.end }),
+            }
         }
     }
 }
@@ -79,8 +98,31 @@ span!(Array {
     left_square,
     right_square
 });
+span!(StructDef {
+    struct_,
+    right_bracket
+});
+span!(UnionDef {
+    union,
+    right_bracket
+});
+span!(EnumDef {
+    enum_,
+    right_bracket
+});
+span!(TypeDef { type_, inner });
+span!(Discriminant { assign, expression });
 span!(Pointer { ampersand, type_ });
 
+impl Spanned for Variant<'_> {
+    fn span(&self) -> Span {
+        match &self.discriminant {
+            Some(discriminant) => span::union(&self.ident.span(), &discriminant.span()),
+            None => self.ident.span(),
+        }
+    }
+}
+
 parse! {
     #[derive(Debug)]
     pub struct Struct<'a> {
@@ -115,6 +157,132 @@ parse! {
     }
 }
 
+parse! {
+    /// `struct <ident> { <fields> }`
+    #[derive(Debug)]
+    pub struct StructDef<'a> {
+        /// `struct` token.
+        pub struct_: lex::Struct<'a>,
+
+        /// Type identifier.
+        pub ident: lex::Ident<'a>,
+
+        /// `{` token.
+        pub left_bracket: lex::LeftBracket<'a>,
+
+        /// Inner fields.
+        pub fields: Vec<Field<'a>>,
+
+        /// `}` token.
+        pub right_bracket: lex::RightBracket<'a>,
+    }
+}
+
+parse! {
+    /// `union <ident> { <fields> }`
+    #[derive(Debug)]
+    pub struct UnionDef<'a> {
+        /// `union` token.
+        pub union: lex::Union<'a>,
+
+        /// Type identifier.
+        pub ident: lex::Ident<'a>,
+
+        /// `{` token.
+        pub left_bracket: lex::LeftBracket<'a>,
+
+        /// Inner fields.
+        pub fields: Vec<Field<'a>>,
+
+        /// `}` token.
+        pub right_bracket: lex::RightBracket<'a>,
+    }
+}
+
+parse! {
+    /// `type <ident> = <type>`
+    #[derive(Debug)]
+    pub struct TypeDef<'a> {
+        /// `type` token.
+        pub type_: lex::Type<'a>,
+
+        /// Alias identifier.
+        pub ident: lex::Ident<'a>,
+
+        /// `=` token.
+        pub assign: lex::Assign<'a>,
+
+        /// Aliased type.
+        pub inner: Type<'a>,
+    }
+}
+
+parse! {
+    /// `enum <ident> { <variants> }`
+    #[derive(Debug)]
+    pub struct EnumDef<'a> {
+        /// `enum` token.
+        pub enum_: lex::Enum<'a>,
+
+        /// Type identifier.
+        pub ident: lex::Ident<'a>,
+
+        /// `{` token.
+        pub left_bracket: lex::LeftBracket<'a>,
+
+        /// Enum variants.
+        pub variants: Vec<Variant<'a>>,
+
+        /// `}` token.
+        pub right_bracket: lex::RightBracket<'a>,
+    }
+}
+
+parse! {
+    /// `<ident> [= <expression>]`
+    #[derive(Debug)]
+    pub struct Variant<'a> {
+        /// Variant identifier.
+        pub ident: lex::Ident<'a>,
+
+        /// Optional explicit discriminant. Defaults to the discriminant of the
+        /// previous variant plus one (or zero for the first variant).
+        pub discriminant: Option<Discriminant<'a>>,
+    }
+}
+
+impl<'a> Grammar<'a> for Option<Variant<'a>> {
+    fn parse(ctx: &mut Context<'a>, tokens: &mut Peekable<Tokens<'a>>) -> Result<Self, Error<'a>> {
+        if let Some(Ok(Token::Ident(_))) = tokens.peek() {
+            Ok(Some(Grammar::parse(ctx, tokens)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+parse! {
+    /// `= <expression>`
+    #[derive(Debug)]
+    pub struct Discriminant<'a> {
+        /// `=` token.
+        pub assign: lex::Assign<'a>,
+
+        /// Discriminant constant expression tokens.
+        pub expression: Expression<'a>,
+    }
+}
+
+impl<'a> Grammar<'a> for Option<Discriminant<'a>> {
+    fn parse(ctx: &mut Context<'a>, tokens: &mut Peekable<Tokens<'a>>) -> Result<Self, Error<'a>> {
+        if let Some(Ok(Token::Assign(_))) = tokens.peek() {
+            Ok(Some(Grammar::parse(ctx, tokens)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
 parse! {
     /// `& <type>`
     #[derive(Debug)]
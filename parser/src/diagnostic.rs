@@ -0,0 +1,294 @@
+//! Source-annotated error reporting.
+//!
+//! A [`Diagnostic`] is rendered next to the line of code it refers to, with
+//! the offending part of the line underlined:
+//!
+//! ```text
+//! error: Unexpected token: `}`
+//!  --> main.ggb:3:10
+//!   |
+//! 3 |     (foo }
+//!   |          ^ unexpected token
+//!   |
+//!   = help: Expressions are closed with `)`
+//! ```
+//!
+//! [`Diagnostic`]: ./struct.Diagnostic.html
+use crate::{
+    lex::span::{Span, Spanned},
+    Error,
+};
+use std::fmt;
+
+const RESET: &str = "\x1b[0m";
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+
+/// An error message, optionally pointing to a location in the source code.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Diagnostic {
+    /// Error message.
+    pub message: String,
+
+    /// Location of the error in the source code. Errors without a location
+    /// (such as an early EOF) point to the end of the source code.
+    pub span: Option<Span>,
+
+    /// Short label printed next to the underlined code.
+    pub label: Option<String>,
+
+    /// Help notes printed after the source code.
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    /// Create diagnostic with the given error message.
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            span: None,
+            label: None,
+            notes: Vec::new(),
+        }
+    }
+
+    /// Set the location of the error.
+    pub fn span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    /// Set the label of the underlined code.
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// Append a help note.
+    pub fn note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Render the diagnostic against the source code it refers to.
+    ///
+    /// The returned value implements `Display`. Output is plain text unless
+    /// colors are enabled with [`Render::color`].
+    ///
+    /// [`Render::color`]: ./struct.Render.html#method.color
+    pub fn render<'a>(&'a self, source: &'a str) -> Render<'a> {
+        Render {
+            diagnostic: self,
+            source,
+            path: None,
+            color: false,
+        }
+    }
+}
+
+impl From<&Error<'_>> for Diagnostic {
+    fn from(error: &Error<'_>) -> Self {
+        let diagnostic = Self::new(error.to_string());
+        match error {
+            Error::Eof { span } => diagnostic.span(*span).label("unexpected end of file"),
+            Error::UnexpectedToken(token) => {
+                diagnostic.span(token.span()).label("unexpected token")
+            }
+            Error::InvalidPath(path) => diagnostic.span(path.span()).label("invalid path"),
+            Error::ReservedKeyword { span, .. } => diagnostic
+                .span(*span)
+                .label("reserved keyword")
+                .note("Reserved keywords can't be used as identifiers"),
+            Error::UnexpectedByte { span, .. } => {
+                diagnostic.span(*span).label("unexpected character")
+            }
+            Error::UnterminatedLit { span, .. } => {
+                diagnostic.span(*span).label("unterminated literal")
+            }
+            Error::InvalidInterrupt(ident) => diagnostic
+                .span(ident.span())
+                .label("unknown interrupt")
+                .note("Valid interrupts are `vblank`, `lcd_stat`, `timer`, `serial` and `joypad`"),
+            Error::InvalidHandler { span, .. } => diagnostic
+                .span(*span)
+                .label("not allowed in an interrupt handler"),
+            Error::ShadowIdent { ident, shadow } => {
+                let [line, col] = ident.span().min;
+                diagnostic
+                    .span(shadow.span())
+                    .label(format!("shadows `{}`", ident))
+                    .note(format!(
+                        "`{}` is first defined at {}:{}",
+                        ident,
+                        line + 1,
+                        col + 1
+                    ))
+            }
+        }
+    }
+}
+
+/// Displayable rendering of a [`Diagnostic`].
+///
+/// [`Diagnostic`]: ./struct.Diagnostic.html
+#[derive(Debug, Clone, Copy)]
+pub struct Render<'a> {
+    diagnostic: &'a Diagnostic,
+    source: &'a str,
+    path: Option<&'a str>,
+    color: bool,
+}
+
+impl<'a> Render<'a> {
+    /// Set the path of the source file, printed before the line number.
+    pub fn path(mut self, path: &'a str) -> Self {
+        self.path = Some(path);
+        self
+    }
+
+    /// Enable or disable ANSI terminal colors.
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    fn paint(&self, style: &'static str) -> &'static str {
+        if self.color {
+            style
+        } else {
+            ""
+        }
+    }
+
+    // Span of the diagnostic, or an empty span at the end of the source.
+    fn span(&self) -> Span {
+        self.diagnostic.span.unwrap_or_else(|| {
+            let lines = self.source.lines().count().max(1);
+            let last = self.source.lines().last().map(str::len).unwrap_or(0);
+            Span {
+                min: [lines - 1, last],
+                max: [lines - 1, last],
+            }
+        })
+    }
+}
+
+impl fmt::Display for Render<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (red, blue, bold, reset) = (
+            self.paint(RED),
+            self.paint(BLUE),
+            self.paint(BOLD),
+            self.paint(RESET),
+        );
+        let Diagnostic {
+            message,
+            label,
+            notes,
+            ..
+        } = self.diagnostic;
+        let Span { min, max } = self.span();
+        let line = self.source.lines().nth(min[0]).unwrap_or("");
+        let number = (min[0] + 1).to_string();
+        let pad = " ".repeat(number.len());
+
+        writeln!(f, "{}error{}: {}{}{}", red, reset, bold, message, reset)?;
+        write!(f, "{}{}-->{} ", pad, blue, reset)?;
+        if let Some(path) = self.path {
+            write!(f, "{}:", path)?;
+        }
+        writeln!(f, "{}:{}", number, min[1] + 1)?;
+        writeln!(f, "{} {}|{}", pad, blue, reset)?;
+        writeln!(f, "{}{} |{} {}", blue, number, reset, line)?;
+
+        // spans over multiple lines are underlined up to the end of the first
+        let start = min[1].min(line.len());
+        let end = if max[0] == min[0] { max[1] } else { line.len() };
+        let carets = end.saturating_sub(start).max(1);
+        // keep tabs in the indentation so the carets line up with the code
+        let indent: String = line
+            .bytes()
+            .take(start)
+            .map(|b| if b == b'\t' { '\t' } else { ' ' })
+            .collect();
+        write!(f, "{} {}|{} {}", pad, blue, reset, indent)?;
+        write!(f, "{}{}", red, "^".repeat(carets))?;
+        if let Some(label) = label {
+            write!(f, " {}", label)?;
+        }
+        writeln!(f, "{}", reset)?;
+
+        if !notes.is_empty() {
+            writeln!(f, "{} {}|{}", pad, blue, reset)?;
+        }
+        for note in notes {
+            writeln!(
+                f,
+                "{} {}={} {}help{}: {}",
+                pad, blue, reset, bold, reset, note
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Diagnostic;
+    use crate::lex::span::Span;
+
+    fn span(min: [usize; 2], max: [usize; 2]) -> Span {
+        Span { min, max }
+    }
+
+    #[test]
+    fn plain() {
+        let diagnostic = Diagnostic::new("Unexpected token: `}`")
+            .span(span([2, 9], [2, 10]))
+            .label("unexpected token")
+            .note("Expressions are closed with `)`");
+        let source = "let a:u8 = 0\n\n    (foo }\n";
+        let expected = "\
+error: Unexpected token: `}`
+ --> main.ggb:3:10
+  |
+3 |     (foo }
+  |          ^ unexpected token
+  |
+  = help: Expressions are closed with `)`
+";
+        let render = diagnostic.render(source).path("main.ggb").to_string();
+        assert_eq!(expected, render);
+    }
+
+    #[test]
+    fn color() {
+        let diagnostic = Diagnostic::new("Oops").span(span([0, 0], [0, 3]));
+        let render = diagnostic.render("foo").color(true).to_string();
+        assert!(render.contains("\x1b[1;31m^^^\x1b[0m"));
+        assert!(!diagnostic.render("foo").to_string().contains('\x1b'));
+    }
+
+    #[test]
+    fn tabs() {
+        let diagnostic = Diagnostic::new("Oops").span(span([0, 2], [0, 5]));
+        let render = diagnostic.render("\t\tfoo").to_string();
+        assert!(render.ends_with("  | \t\t^^^\n"));
+    }
+
+    #[test]
+    fn multi_line() {
+        let diagnostic = Diagnostic::new("Oops").span(span([0, 4], [2, 1]));
+        let render = diagnostic.render("let foo\n{\n}").to_string();
+        assert!(render.ends_with("  |     ^^^\n"));
+    }
+
+    #[test]
+    fn end_of_file() {
+        let diagnostic = Diagnostic::new("Early EOF").label("unexpected end of file");
+        let render = diagnostic.render("let a:u8 = 0\n(foo").to_string();
+        assert!(render.contains(" --> 2:5\n"));
+        assert!(render.ends_with("  |     ^ unexpected end of file\n"));
+    }
+}
@@ -150,6 +150,9 @@ tokens! {
     /// `enum`
     "enum" => Enum,
 
+    /// `type`
+    "type" => Type,
+
     /// `use`
     "use" => Use,
 
@@ -162,6 +165,9 @@ tokens! {
     /// `const`
     "const" => Const,
 
+    /// `include`
+    "include" => Include,
+
     /// `pub`
     "pub" => Pub,
 
@@ -171,6 +177,9 @@ tokens! {
     /// `loop`
     "loop" => Loop,
 
+    /// `while`
+    "while" => While,
+
     /// `let`
     "let" => Let,
 
@@ -183,6 +192,9 @@ tokens! {
     /// `else`
     "else" => Else,
 
+    /// `match`
+    "match" => Match,
+
     /// `continue`
     "continue" => Continue,
 
@@ -192,6 +204,15 @@ tokens! {
     /// `return`
     "return" => Return,
 
+    /// `and`
+    "and" => LogicalAnd,
+
+    /// `or`
+    "or" => LogicalOr,
+
+    /// `not`
+    "not" => LogicalNot,
+
     /// `in`
     "read" => Read,
 
@@ -206,6 +227,15 @@ tokens! {
     /// `i8`
     "i8" => I8,
 
+    /// `u16`
+    "u16" => U16,
+
+    /// `i16`
+    "i16" => I16,
+
+    /// `bool`
+    "bool" => Bool,
+
     // asm registers
 
     /// `%a`
@@ -250,6 +280,17 @@ tokens! {
     /// `%pc`
     "%pc" => PC,
 
+    // asm conditions (`%c` doubles as the carry condition)
+
+    /// `%nz`
+    "%nz" => NZ,
+
+    /// `%z`
+    "%z" => Z,
+
+    /// `%nc`
+    "%nc" => NC,
+
     // asm misc/control
 
     /// `.nop`
@@ -321,6 +362,69 @@ tokens! {
     /// `.cp`
     ".cp" => Cp,
 
+    /// `.jp`
+    ".jp" => Jp,
+
+    /// `.jr`
+    ".jr" => Jr,
+
+    /// `.call`
+    ".call" => Call,
+
+    /// `.ret`
+    ".ret" => Ret,
+
+    /// `.reti`
+    ".reti" => Reti,
+
+    /// `.rst`
+    ".rst" => Rst,
+
+    /// `.rlca`
+    ".rlca" => Rlca,
+
+    /// `.rla`
+    ".rla" => Rla,
+
+    /// `.rrca`
+    ".rrca" => Rrca,
+
+    /// `.rra`
+    ".rra" => Rra,
+
+    /// `.rlc`
+    ".rlc" => Rlc,
+
+    /// `.rrc`
+    ".rrc" => Rrc,
+
+    /// `.rl`
+    ".rl" => Rl,
+
+    /// `.rr`
+    ".rr" => Rr,
+
+    /// `.sla`
+    ".sla" => Sla,
+
+    /// `.sra`
+    ".sra" => Sra,
+
+    /// `.srl`
+    ".srl" => Srl,
+
+    /// `.swap`
+    ".swap" => Swap,
+
+    /// `.bit`
+    ".bit" => Bit,
+
+    /// `.res`
+    ".res" => Res,
+
+    /// `.set`
+    ".set" => Set,
+
     // variables
 
     /// Identifier
@@ -335,6 +439,28 @@ tokens! {
     "" => Eof,
 }
 
+// parentheses implied by the infix expression syntax, so that infix expressions
+// can be represented by the same (lisp) nodes as their prefix counterparts.
+#[cfg(not(feature = "lisp"))]
+impl LeftPar<'_> {
+    pub(crate) fn implicit(span: Span) -> Self {
+        Self((raw::RawToken::Keyword("("), span))
+    }
+}
+
+#[cfg(not(feature = "lisp"))]
+impl RightPar<'_> {
+    pub(crate) fn implicit(span: Span) -> Self {
+        Self((raw::RawToken::Keyword(")"), span))
+    }
+}
+
+impl Eof<'_> {
+    pub(crate) fn implicit(span: Span) -> Self {
+        Self((raw::RawToken::Eof, span))
+    }
+}
+
 impl<'a> Tokens<'a> {
     /// Create new Tokens.
     pub fn new(input: &'a str) -> Self {
@@ -349,28 +475,23 @@ impl<'a> Tokens<'a> {
         if self.ended {
             return None;
         }
-        loop {
-            match self.raw.next() {
-                Some(ts) if ts.0.is_unexpected() => {
-                    // TODO
-                    self.ended = true;
-                    return Some(Err(Error::UnexpectedByte {
-                        byte: 0,
-                        span: ts.1,
-                    }));
-                }
-                Some(ts) if ts.0.is_ident() => return Some(Ok(Token::Ident(Ident(ts)))),
-                Some(ts) if ts.0.is_lit() => return Some(Ok(Token::Lit(Lit(ts)))),
-                Some(ts) if ts.0.is_eof() => {
-                    self.ended = true;
-                    return Some(Ok(Token::Eof(Eof(ts))));
-                }
-                Some((raw::RawToken::Keyword(keyword), span)) => {
-                    return match_token(self, keyword, span)
-                }
-                None => return None,
-                _ => unreachable!(),
+        match self.raw.next() {
+            Some((raw::RawToken::Unexpected(byte), span)) => {
+                Some(Err(Error::UnexpectedByte { byte, span }))
+            }
+            Some((raw::RawToken::Unterminated(quote), span)) => {
+                let quote = quote as char;
+                Some(Err(Error::UnterminatedLit { quote, span }))
+            }
+            Some(ts) if ts.0.is_ident() => Some(Ok(Token::Ident(Ident(ts)))),
+            Some(ts) if ts.0.is_lit() => Some(Ok(Token::Lit(Lit(ts)))),
+            Some(ts) if ts.0.is_eof() => {
+                self.ended = true;
+                Some(Ok(Token::Eof(Eof(ts))))
             }
+            Some((raw::RawToken::Keyword(keyword), span)) => match_token(self, keyword, span),
+            None => None,
+            _ => unreachable!(),
         }
     }
 }
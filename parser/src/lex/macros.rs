@@ -36,14 +36,14 @@ macro_rules! tokens {
 
             impl<'a> crate::ast::Grammar<'a> for $token<'a> {
                 fn parse(
-                    _: &mut crate::ast::Context<'a>,
+                    context: &mut crate::ast::Context<'a>,
                     tokens: &mut std::iter::Peekable<crate::lex::Tokens<'a>>,
                 ) -> Result<Self, crate::Error<'a>> {
                     match tokens.next() {
                         Some(Ok(Token::$token(token))) => Ok(token),
-                        Some(Ok(token)) => Err(crate::Error::UnexpectedToken(token)),
+                        Some(Ok(token)) => Err(crate::Error::unexpected(token)),
                         Some(Err(err)) => Err(err),
-                        None => unimplemented!(),
+                        None => Err(crate::Error::Eof { span: context.end }),
                     }
                 }
             }
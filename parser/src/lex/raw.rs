@@ -22,6 +22,8 @@ pub enum RawToken<'a> {
     Lit(&'a str),
     /// Unexpected byte.
     Unexpected(u8),
+    /// Quoted literal missing its closing quote.
+    Unterminated(u8),
     /// End of file.
     Eof,
 }
@@ -33,6 +35,7 @@ impl std::fmt::Display for RawToken<'_> {
             RawToken::Ident(s) => s.fmt(f),
             RawToken::Lit(s) => s.fmt(f),
             RawToken::Unexpected(s) => s.fmt(f),
+            RawToken::Unterminated(s) => (*s as char).fmt(f),
             RawToken::Eof => Ok(()),
         }
     }
@@ -180,12 +183,20 @@ impl<'a> Tokens<'a> {
                     },
                 ))
             }
-            /* str lit */
-            Some(b'"') => {
+            /* str lit | char lit */
+            Some(&quote) if quote == b'"' || quote == b'\'' => {
                 let min = self.cursor();
-                let lit = self.next_str_lit();
-                let max = self.cursor();
-                Some((Lit(lit), Span { min, max }))
+                match self.next_quoted_lit(quote) {
+                    Some(lit) => {
+                        let max = self.cursor();
+                        Some((Lit(lit), Span { min, max }))
+                    }
+                    // the span of the opening quote
+                    None => {
+                        let max = [min[0], min[1] + 1];
+                        Some((RawToken::Unterminated(quote), Span { min, max }))
+                    }
+                }
             }
             /* num lit (decimal) */
             Some(b) if b.is_ascii_digit() && *b != b'0' => {
@@ -198,14 +209,19 @@ impl<'a> Tokens<'a> {
         }
     }
 
-    fn next_str_lit(&mut self) -> &'a str {
+    // literal enclosed in `quote` characters. Escape sequences (`\"`) are kept
+    // as they are, and decoded by the compiler. Returns `None` if the input
+    // ends before the closing quote.
+    fn next_quoted_lit(&mut self, quote: u8) -> Option<&'a str> {
         let cursor = self.offset;
-        assert_eq!(Some(b'"'), self.next_char());
+        assert_eq!(Some(quote), self.next_char());
         loop {
-            let c = self.next_char();
-            match c {
-                Some(b'"') => return &self.input[cursor..self.offset],
-                None => panic!("EOF"),
+            match self.next_char() {
+                Some(c) if c == quote => return Some(&self.input[cursor..self.offset]),
+                Some(b'\\') => {
+                    let _ = self.next_char();
+                }
+                None => return None,
                 _ => {}
             }
         }
@@ -264,6 +280,8 @@ impl<'a> Tokens<'a> {
             if token_str.bytes().all(|b| b.is_ascii_digit())
                 || token_str.starts_with("0x")
                     && token_str[2..].bytes().all(|b| b.is_ascii_hexdigit())
+                || token_str == "true"
+                || token_str == "false"
             {
                 RawToken::Lit(token)
             } else {
@@ -272,8 +290,9 @@ impl<'a> Tokens<'a> {
         }
     }
 
-    // keyword with non-alphanumeric nor _ characters
-    // FIXME cursor bug
+    // keyword beginning with a non-alphanumeric nor _ character.
+    // the keyword may continue with alphanumeric characters (`%hl`, `.nop`), in
+    // which case it must not be immediately followed by one.
     fn next_kword(&mut self) -> RawToken<'a> {
         let mut offset = self.offset;
         let mut line = self.line;
@@ -286,13 +305,13 @@ impl<'a> Tokens<'a> {
         loop {
             match self.peek_char() {
                 None => break,
-                Some(b) if b.is_ascii_alphanumeric() | b.is_ascii_whitespace() => break,
+                Some(b) if b.is_ascii_whitespace() => break,
                 Some(_) => {
                     self.next_char().unwrap();
 
                     let prefix = &self.input[cursor..self.offset];
                     // keep the longest possible keyword
-                    if self.has_kword(prefix) {
+                    if self.has_kword(prefix) && !self.word_continues(prefix) {
                         chars = self.chars.clone();
                         offset = self.offset;
                         line = self.line;
@@ -305,18 +324,31 @@ impl<'a> Tokens<'a> {
             }
         }
 
+        self.chars = chars;
+        self.offset = offset;
+        self.line = line;
+        self.line_offset = line_offset;
         if keyword.is_empty() {
-            let byte = keyword.as_bytes()[self.offset];
+            let byte = self.next_char().unwrap();
             RawToken::Unexpected(byte)
         } else {
-            self.chars = chars;
-            self.offset = offset;
-            self.line = line;
-            self.line_offset = line_offset;
             RawToken::Keyword(keyword)
         }
     }
 
+    // whether the given keyword ends with an alphanumeric character that is
+    // followed by yet another one (i.e. the keyword is a prefix of a word).
+    fn word_continues(&self, kword: &str) -> bool {
+        let is_word = |b: &u8| b.is_ascii_alphanumeric() || *b == b'_';
+        kword.as_bytes().last().map(is_word).unwrap_or(false)
+            && self
+                .input
+                .as_bytes()
+                .get(self.offset)
+                .map(is_word)
+                .unwrap_or(false)
+    }
+
     // trie operations.
     // maybe use an actual trie later.
     fn has_prefix(&self, pre: &str) -> bool {
@@ -364,6 +396,20 @@ mod test {
         assert_eq!(None, tokens.next().map(|t| t.0));
     }
 
+    #[test]
+    fn lit_escape() {
+        use RawToken::{Eof, Lit};
+
+        let input = r#""say \"hi\"\\" 'a' '\'' '"'"#;
+        let mut tokens = Tokens::new(input, HashSet::new());
+
+        assert_eq!(Some(Lit(r#""say \"hi\"\\""#)), tokens.next().map(|t| t.0));
+        assert_eq!(Some(Lit("'a'")), tokens.next().map(|t| t.0));
+        assert_eq!(Some(Lit(r"'\''")), tokens.next().map(|t| t.0));
+        assert_eq!(Some(Lit("'\"'")), tokens.next().map(|t| t.0));
+        assert_eq!(Some(Eof), tokens.next().map(|t| t.0));
+    }
+
     #[test]
     fn lit_numeric_hex() {
         use RawToken::{Eof, Lit};
@@ -409,6 +455,29 @@ mod test {
         assert_eq!(None, tokens.next().map(|t| t.0));
     }
 
+    #[test]
+    fn tokens_mixed() {
+        use RawToken::{Eof, Ident, Keyword, Unexpected};
+
+        let kwords = [".", ".nop", ".nope", "%a", "%af"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let input = ".nop %af %a.nope .nopx ? .";
+        let mut tokens = Tokens::new(input, kwords);
+
+        assert_eq!(Some(Keyword(".nop")), tokens.next().map(|t| t.0));
+        assert_eq!(Some(Keyword("%af")), tokens.next().map(|t| t.0));
+        assert_eq!(Some(Keyword("%a")), tokens.next().map(|t| t.0));
+        assert_eq!(Some(Keyword(".nope")), tokens.next().map(|t| t.0));
+        assert_eq!(Some(Keyword(".")), tokens.next().map(|t| t.0));
+        assert_eq!(Some(Ident("nopx")), tokens.next().map(|t| t.0));
+        assert_eq!(Some(Unexpected(b'?')), tokens.next().map(|t| t.0));
+        assert_eq!(Some(Keyword(".")), tokens.next().map(|t| t.0));
+        assert_eq!(Some(Eof), tokens.next().map(|t| t.0));
+        assert_eq!(None, tokens.next().map(|t| t.0));
+    }
+
     #[test]
     fn tokens_alphanumeric() {
         use RawToken::{Eof, Ident, Keyword};
@@ -11,9 +11,8 @@
     clippy::doc_markdown,
     clippy::dbg_macro,
     clippy::todo,
-    clippy::empty_enum,
+    clippy::empty_enums,
     clippy::enum_glob_use,
-    clippy::pub_enum_variant_names,
     clippy::mem_forget,
     clippy::use_self,
     clippy::filter_map_next,
@@ -26,18 +25,26 @@
 )]
 
 pub mod ast;
+pub mod diagnostic;
 pub mod lex;
+pub mod source;
 
 use ast::{Context, Grammar};
-use lex::span::Span;
+use lex::span::{Span, Spanned};
 use thiserror::Error;
 
 // re-exports
 pub use ast::{Ast, ContextBuilder};
+pub use diagnostic::Diagnostic;
 pub use lex::Tokens;
 
 /// Parse input source code.
-pub fn parse(input: &str) -> Result<Ast<'_>, Error<'_>> {
+///
+/// The parser recovers from syntax errors, so that all of them can be reported
+/// at once: the syntax tree is returned along with the errors, leaving out the
+/// statements that failed to parse. The tree is only complete when there are
+/// no errors.
+pub fn parse(input: &str) -> (Ast<'_>, Vec<Error<'_>>) {
     let mut context = ContextBuilder::default().build();
     parse_with_context(input, &mut context)
 }
@@ -46,15 +53,31 @@ pub fn parse(input: &str) -> Result<Ast<'_>, Error<'_>> {
 pub fn parse_with_context<'a>(
     input: &'a str,
     context: &mut Context<'a>,
-) -> Result<Ast<'a>, Error<'a>> {
+) -> (Ast<'a>, Vec<Error<'a>>) {
+    let line = input.matches('\n').count();
+    let offset = input.len() - input.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    context.end = Span {
+        min: [line, offset],
+        max: [line, offset],
+    };
     let mut tokens = Tokens::new(input).peekable();
-    Grammar::parse(context, &mut tokens)
+    let ast = Grammar::parse(context, &mut tokens).unwrap_or_else(|error| {
+        context.error(error);
+        Ast {
+            inner: Vec::new(),
+            eof: lex::Eof::implicit(context.end),
+        }
+    });
+    (ast, context.take_errors())
 }
 
 #[derive(Error, Debug)]
 pub enum Error<'a> {
     #[error("Early EOF")]
-    Eof,
+    Eof {
+        /// Location of the end of the input.
+        span: Span,
+    },
 
     #[error("Unexpected token: `{0}`")]
     UnexpectedToken(lex::Token<'a>),
@@ -80,6 +103,27 @@ pub enum Error<'a> {
         span: Span,
     },
 
+    #[error("Unterminated literal: missing closing `{quote}`")]
+    UnterminatedLit {
+        /// The opening quote.
+        quote: char,
+
+        /// Location of the opening quote in the programs source code.
+        span: Span,
+    },
+
+    #[error("Invalid interrupt: `{0}`")]
+    InvalidInterrupt(lex::Ident<'a>),
+
+    #[error("Interrupt handlers can't take arguments or return values")]
+    InvalidHandler {
+        /// Identifier of the handler function.
+        ident: lex::Ident<'a>,
+
+        /// Location of the arguments or return type.
+        span: Span,
+    },
+
     #[error("Shadowed identifier")]
     ShadowIdent {
         /// An already defined and previously validated identifier.
@@ -89,3 +133,13 @@ pub enum Error<'a> {
         shadow: lex::Ident<'a>,
     },
 }
+
+impl<'a> Error<'a> {
+    /// Error for an unexpected token (an early EOF if the input has ended).
+    pub(crate) fn unexpected(token: lex::Token<'a>) -> Self {
+        match token {
+            lex::Token::Eof(eof) => Self::Eof { span: eof.span() },
+            token => Self::UnexpectedToken(token),
+        }
+    }
+}
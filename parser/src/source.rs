@@ -0,0 +1,381 @@
+//! Programs made of multiple source files.
+//!
+//! Every source file defines a module, named after its path relative to the
+//! entry module (the source of module `a::b` is expected at `a/b.ggb`).
+//! Modules are loaded on demand by following the `use` statements, starting
+//! from the entry module:
+//!
+//! ```ggb
+//! use gfx::tiles
+//!
+//! (tiles::load)
+//! ```
+//!
+//! Import paths are absolute. The longest prefix of the path that names an
+//! existing source file is the imported module (so `use gfx::tiles::load`
+//! also imports the module `gfx::tiles`, if `gfx/tiles/load.ggb` doesn't
+//! exist). Paths that don't name any source file are assumed to refer to
+//! modules declared in-line with `mod`.
+//!
+//! Items of other modules are referred to through the imported names (`tiles`
+//! in the example above), since the parser can't validate the paths of items
+//! defined in other source files.
+//!
+//! Modules must be imported in a hierarchy: a module can't (directly or
+//! indirectly) import itself.
+//!
+//! Files embedded with `include` statements are also read relative to the
+//! source file of the module that includes them (see [`AssetProvider`]).
+//!
+//! [`AssetProvider`]: ./trait.AssetProvider.html
+use crate::{lex::Token, Ast, ContextBuilder, Tokens};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// Source code provider for the modules of a program.
+pub trait SourceProvider {
+    /// Returns the source code of the module with the given path, or `None`
+    /// if the module doesn't exist. The entry module has an empty path.
+    fn source(&self, module: &[String]) -> io::Result<Option<String>>;
+}
+
+/// In-memory sources, keyed by module path (`"a::b"`). The entry module is
+/// keyed by the empty string.
+impl SourceProvider for HashMap<String, String> {
+    fn source(&self, module: &[String]) -> io::Result<Option<String>> {
+        Ok(self.get(&module.join("::")).cloned())
+    }
+}
+
+/// Provider of the files embedded into a program with `include` statements.
+pub trait AssetProvider {
+    /// Returns the contents of the file at `path` (relative to the source file
+    /// of the module with the given path), or `None` if the file doesn't
+    /// exist.
+    fn asset(&self, module: &[String], path: &str) -> io::Result<Option<Vec<u8>>>;
+}
+
+/// In-memory assets, keyed by their path relative to the entry module
+/// (`"gfx/font.2bpp"`), using `/` as the path separator.
+impl AssetProvider for HashMap<String, Vec<u8>> {
+    fn asset(&self, module: &[String], path: &str) -> io::Result<Option<Vec<u8>>> {
+        let dir = &module[..module.len().saturating_sub(1)];
+        let path: Vec<_> = dir.iter().map(String::as_str).chain(Some(path)).collect();
+        Ok(self.get(&path.join("/")).cloned())
+    }
+}
+
+/// Sources read from the file system.
+#[derive(Debug, Clone)]
+pub struct FileSystem {
+    entry: PathBuf,
+}
+
+impl FileSystem {
+    /// Create provider from the path to the source file of the entry module.
+    /// The rest of the modules are read relative to its parent directory.
+    pub fn new<P: Into<PathBuf>>(entry: P) -> Self {
+        Self {
+            entry: entry.into(),
+        }
+    }
+
+    /// Returns the file path of the given module.
+    pub fn path(&self, module: &[String]) -> PathBuf {
+        if module.is_empty() {
+            return self.entry.clone();
+        }
+        let mut path = self
+            .entry
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        path.extend(module);
+        path.set_extension("ggb");
+        path
+    }
+}
+
+impl SourceProvider for FileSystem {
+    fn source(&self, module: &[String]) -> io::Result<Option<String>> {
+        match fs::read_to_string(self.path(module)) {
+            Ok(source) => Ok(Some(source)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl AssetProvider for FileSystem {
+    fn asset(&self, module: &[String], path: &str) -> io::Result<Option<Vec<u8>>> {
+        let mut file = self.path(module);
+        file.set_file_name(path);
+        match fs::read(file) {
+            Ok(asset) => Ok(Some(asset)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Errors when loading the sources of a program.
+#[derive(Error, Debug)]
+pub enum LoadError {
+    #[error("Entry module not found")]
+    NoEntry,
+
+    #[error("Failed to read module `{module}`: {source}")]
+    Io {
+        /// Path of the module.
+        module: String,
+
+        /// Underlying IO error.
+        source: io::Error,
+    },
+
+    #[error("Import cycle: {}", .0.join(" -> "))]
+    Cycle(Vec<String>),
+}
+
+/// Source code of a module.
+#[derive(Debug, Clone)]
+pub struct Source {
+    /// Path of the module (empty for the entry module).
+    pub module: Vec<String>,
+
+    /// Source code.
+    pub code: String,
+}
+
+/// Parsed module.
+#[derive(Debug)]
+pub struct Module<'a> {
+    /// Path of the module (empty for the entry module).
+    pub module: &'a [String],
+
+    /// Syntax tree.
+    pub ast: Ast<'a>,
+}
+
+/// Sources of all the modules of a program.
+///
+/// Modules are sorted so that every module comes after the ones it imports,
+/// which means the entry module is always the last one.
+#[derive(Debug, Clone)]
+pub struct Sources {
+    sources: Vec<Source>,
+}
+
+impl Sources {
+    /// Returns an iterator over the module sources, in import order.
+    pub fn iter(&self) -> impl Iterator<Item = &Source> {
+        self.sources.iter()
+    }
+
+    /// Parse all modules, in import order.
+    ///
+    /// Parsing stops at the first module with syntax errors, returning all of
+    /// the errors found in its source code.
+    pub fn parse(&self) -> Result<Vec<Module<'_>>, Vec<crate::Error<'_>>> {
+        self.sources
+            .iter()
+            .map(|source| {
+                let mut context = ContextBuilder::default().module(&source.module).build();
+                match crate::parse_with_context(&source.code, &mut context) {
+                    (ast, errors) if errors.is_empty() => Ok(Module {
+                        module: &source.module,
+                        ast,
+                    }),
+                    (_, errors) => Err(errors),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Load the sources of the entry module, and of all the modules it (directly
+/// or indirectly) imports.
+///
+/// Syntax errors don't prevent loading, as imports are found without parsing
+/// the source code. The errors themselves are reported by
+/// [`Sources::parse`].
+///
+/// [`Sources::parse`]: ./struct.Sources.html#method.parse
+pub fn load<P: SourceProvider>(provider: &P) -> Result<Sources, LoadError> {
+    let entry = source(provider, &[])?.ok_or(LoadError::NoEntry)?;
+    let mut loader = Loader {
+        provider,
+        sources: Vec::new(),
+        stack: Vec::new(),
+    };
+    loader.visit(Vec::new(), entry)?;
+    Ok(Sources {
+        sources: loader.sources,
+    })
+}
+
+fn source<P: SourceProvider>(provider: &P, module: &[String]) -> Result<Option<String>, LoadError> {
+    provider.source(module).map_err(|source| LoadError::Io {
+        module: module.join("::"),
+        source,
+    })
+}
+
+struct Loader<'p, P> {
+    provider: &'p P,
+    sources: Vec<Source>,
+    // modules currently being loaded (for cycle detection).
+    stack: Vec<Vec<String>>,
+}
+
+impl<P: SourceProvider> Loader<'_, P> {
+    fn visit(&mut self, module: Vec<String>, code: String) -> Result<(), LoadError> {
+        self.stack.push(module);
+        for path in imports(&code) {
+            // find longest prefix that is a module source file
+            for len in (1..=path.len()).rev() {
+                let import = &path[..len];
+                if import == self.stack.last().unwrap().as_slice()
+                    || self.sources.iter().any(|s| s.module == import)
+                {
+                    break;
+                }
+                if let Some(pos) = self.stack.iter().position(|m| m == import) {
+                    let cycle = self.stack[pos..]
+                        .iter()
+                        .chain(Some(&import.to_vec()))
+                        .map(|m| m.join("::"))
+                        .collect();
+                    return Err(LoadError::Cycle(cycle));
+                }
+                if let Some(code) = source(self.provider, import)? {
+                    self.visit(import.to_vec(), code)?;
+                    break;
+                }
+            }
+        }
+        let module = self.stack.pop().unwrap();
+        self.sources.push(Source { module, code });
+        Ok(())
+    }
+}
+
+// Returns the paths of all the `use` statements in the given source code.
+//
+// Imports are found by scanning the tokens rather than parsing the source
+// code, which only happens once all the modules are loaded (and within the
+// context of each module, see `Sources::parse`).
+fn imports(code: &str) -> Vec<Vec<String>> {
+    let mut tokens = Tokens::new(code).filter_map(Result::ok).peekable();
+    let mut imports = Vec::new();
+    while let Some(token) = tokens.next() {
+        if let Token::Use(_) = token {
+            let mut path = Vec::new();
+            while let Some(Token::Ident(ident)) = tokens.next() {
+                path.push(ident.to_string());
+                if let Some(Token::Square(_)) = tokens.peek() {
+                    tokens.next();
+                } else {
+                    break;
+                }
+            }
+            if !path.is_empty() {
+                imports.push(path);
+            }
+        }
+    }
+    imports
+}
+
+#[cfg(test)]
+mod test {
+    use super::{load, AssetProvider, FileSystem, LoadError};
+    use std::{collections::HashMap, path::PathBuf};
+
+    fn provider(sources: &[(&str, &str)]) -> HashMap<String, String> {
+        sources
+            .iter()
+            .map(|(m, s)| (m.to_string(), s.to_string()))
+            .collect()
+    }
+
+    fn modules(sources: &[(&str, &str)]) -> Result<Vec<String>, LoadError> {
+        Ok(load(&provider(sources))?
+            .iter()
+            .map(|s| s.module.join("::"))
+            .collect())
+    }
+
+    #[test]
+    fn import_order() {
+        let modules = modules(&[
+            ("", "use a use b::c::foo use d"),
+            ("a", "use b::c"),
+            ("b::c", "fn foo {}"),
+        ])
+        .unwrap();
+        assert_eq!(vec!["b::c", "a", ""], modules);
+    }
+
+    #[test]
+    fn self_import() {
+        let modules = modules(&[("", "use a"), ("a", "mod b {} use a::b")]).unwrap();
+        assert_eq!(vec!["a", ""], modules);
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let modules = modules(&[
+            ("", "fn foo { use a::b::c } let x:u8 = ( use b"),
+            ("a::b", ""),
+            ("b", ""),
+        ])
+        .unwrap();
+        assert_eq!(vec!["a::b", "b", ""], modules);
+    }
+
+    #[test]
+    fn no_entry() {
+        assert!(matches!(modules(&[("a", "")]), Err(LoadError::NoEntry)));
+    }
+
+    #[test]
+    fn cycle() {
+        let err = modules(&[
+            ("", "use a"),
+            ("a", "use b"),
+            ("b", "use c::d"),
+            ("c::d", "use a"),
+        ]);
+        match err {
+            Err(LoadError::Cycle(cycle)) => assert_eq!(vec!["a", "b", "c::d", "a"], cycle),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn file_system_path() {
+        let fs = FileSystem::new("src/main.ggb");
+        let module = |m: &[&str]| m.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(PathBuf::from("src/main.ggb"), fs.path(&module(&[])));
+        assert_eq!(PathBuf::from("src/a/b.ggb"), fs.path(&module(&["a", "b"])));
+    }
+
+    #[test]
+    fn asset_path() {
+        let mut assets = HashMap::new();
+        assets.insert("font.2bpp".to_string(), vec![1]);
+        assets.insert("gfx/tiles.2bpp".to_string(), vec![2]);
+        let module = |m: &[&str]| m.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let asset = |m: &[&str], path| assets.asset(&module(m), path).unwrap();
+        assert_eq!(Some(vec![1]), asset(&[], "font.2bpp"));
+        assert_eq!(Some(vec![1]), asset(&["a"], "font.2bpp"));
+        assert_eq!(Some(vec![2]), asset(&["gfx", "a"], "tiles.2bpp"));
+        assert_eq!(Some(vec![2]), asset(&["a"], "gfx/tiles.2bpp"));
+        assert_eq!(None, asset(&["gfx", "a"], "font.2bpp"));
+    }
+}
@@ -36,6 +36,17 @@ fn lit_numeric_hex() {
     assert_eq_token!(Eof, tokens);
 }
 
+#[test]
+fn lit_bool() {
+    let input = "true false truely";
+    let mut tokens = Tokens::new(input);
+
+    assert_eq_token!(Lit("true"), tokens);
+    assert_eq_token!(Lit("false"), tokens);
+    assert_eq_token!(Ident("truely"), tokens);
+    assert_eq_token!(Eof, tokens);
+}
+
 #[test]
 fn match_longest_token() {
     let input = "=====";
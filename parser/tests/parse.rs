@@ -1,4 +1,113 @@
+use parser::{ast::Statement, lex::span::Spanned, Error};
+
 #[test]
+#[cfg(feature = "lisp")]
 fn parse() {
-    parser::parse(include_str!("programs/parse.ggb")).unwrap();
+    let (_, errors) = parser::parse(include_str!("programs/parse.ggb"));
+    assert!(errors.is_empty(), "{:?}", errors);
+}
+
+#[test]
+#[cfg(not(feature = "lisp"))]
+fn parse_infix() {
+    let (_, errors) = parser::parse(include_str!("programs/parse_infix.ggb"));
+    assert!(errors.is_empty(), "{:?}", errors);
+}
+
+#[test]
+fn recover() {
+    let input = r#"
+        let a:u8 = 0
+        let b:u8 = ]
+        fn foo(x:u8 {
+            (= a x)
+        }
+        let c:u8 = 1
+        fn bar {
+            let d:u8 = ]
+            let e:u8 = 1
+        }
+        let a:u8 = 2
+        "#;
+    let (ast, errors) = parser::parse(input);
+
+    let lines: Vec<_> = errors
+        .iter()
+        .map(|error| match error {
+            Error::UnexpectedToken(token) => token.span().min[0],
+            Error::ShadowIdent { shadow, .. } => shadow.span().min[0],
+            _ => panic!("{:?}", error),
+        })
+        .collect();
+    assert_eq!(vec![2, 3, 8, 11], lines);
+
+    // statements that failed to parse are left out
+    let names: Vec<_> = ast
+        .inner
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::Let(let_) => Some(let_.field.ident.to_string()),
+            Statement::Fn(fn_) => Some(fn_.ident.to_string()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(vec!["a", "c", "bar"], names);
+    match &ast.inner[2] {
+        Statement::Fn(fn_) => assert_eq!(1, fn_.inner.len()),
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn recover_unbalanced() {
+    let (ast, errors) = parser::parse("let a:u8 = 0 } let b:u8 = 1 }");
+    assert_eq!(2, errors.len());
+    assert_eq!(2, ast.inner.len());
+}
+
+#[test]
+fn recover_unexpected_byte() {
+    let (ast, errors) = parser::parse("let a:u8 = 0 $ let b:u8 = 1");
+    assert!(matches!(
+        errors.as_slice(),
+        [Error::UnexpectedByte { byte: b'$', .. }]
+    ));
+    assert_eq!(2, ast.inner.len());
+}
+
+#[test]
+fn unterminated_literal() {
+    for (input, quote) in [(r#"let a:u8 = 0 "abc"#, '"'), ("let a:u8 = 0 '", '\'')] {
+        let (_, errors) = parser::parse(input);
+        match errors.as_slice() {
+            [Error::UnterminatedLit { quote: q, span }] => {
+                assert_eq!(quote, *q);
+                assert_eq!([0, 13], span.min);
+                assert_eq!([0, 14], span.max);
+            }
+            errors => panic!("{:?}", errors),
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "lisp")]
+fn field() {
+    use parser::ast::Expression;
+
+    let (ast, errors) = parser::parse("struct A { b:struct { c:u8 } } static X:A (= X.b.c 1)");
+    assert!(errors.is_empty(), "{:?}", errors);
+    let assign = match &ast.inner[2] {
+        Statement::Inline(inline) => match &inline.inner {
+            Expression::Assign(assign) => assign,
+            _ => panic!(),
+        },
+        _ => panic!(),
+    };
+    let field = match &assign.inner.left {
+        Expression::Field(field) => field,
+        _ => panic!(),
+    };
+    assert_eq!("c", field.ident.to_string());
+    assert!(matches!(&field.inner, Expression::Field(inner) if inner.ident.to_string() == "b"));
 }
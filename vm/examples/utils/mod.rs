@@ -4,10 +4,11 @@ use vm::{memory::Memory, Machine, Opts};
 
 pub fn run(program: &str, range: Option<Range<usize>>) {
     print_input(program);
-    let ast = ggbc::parser::parse(program).unwrap();
+    let (ast, errors) = ggbc::parser::parse(program);
+    assert!(errors.is_empty(), "{:?}", errors);
     #[cfg(nope)]
     print_ast(&ast);
-    let ir = Ir::new(&ast);
+    let ir = Ir::new(&ast).unwrap();
     print_ir(&ir);
     let vm: Machine<NativeEndian> = Machine::new(&ir, Opts::default());
     print_result(&vm.run(), range);
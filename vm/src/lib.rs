@@ -3,9 +3,8 @@
     clippy::doc_markdown,
     clippy::dbg_macro,
     clippy::todo,
-    clippy::empty_enum,
+    clippy::empty_enums,
     clippy::enum_glob_use,
-    clippy::pub_enum_variant_names,
     clippy::mem_forget,
     clippy::use_self,
     clippy::filter_map_next,
@@ -24,7 +23,7 @@ use ggbc::{
         Ir,
     },
 };
-use memory::Memory;
+use memory::{Memory, CONST_RANGE, STACK_RANGE};
 use registers::Registers;
 use std::ops::RangeFrom;
 
@@ -129,6 +128,9 @@ impl<'a, B: ByteOrder> Machine<'a, B> {
         match statement {
             Statement::Nop(_) => {}
 
+            // inline assembly targets the LR35902 only, the VM skips it.
+            Statement::Asm(_) => {}
+
             Statement::Stop(StopStatus::Success) => self.running = false,
             Statement::Stop(StopStatus::Error) => {
                 self.running = false;
@@ -144,6 +146,10 @@ impl<'a, B: ByteOrder> Machine<'a, B> {
                 source,
                 destination,
             } => self.ld16(source, destination),
+            Statement::LdAddr {
+                source,
+                destination,
+            } => self.ld_addr(source, destination),
 
             // arithmetic unary operators
             Statement::Inc {
@@ -214,31 +220,81 @@ impl<'a, B: ByteOrder> Machine<'a, B> {
                 right,
                 destination,
             } => self.right_shift(left, right, destination),
+            Statement::DivS {
+                left,
+                right,
+                destination,
+            } => self.div_s(left, right, destination),
+            Statement::RemS {
+                left,
+                right,
+                destination,
+            } => self.rem_s(left, right, destination),
+            Statement::RightShiftS {
+                left,
+                right,
+                destination,
+            } => self.right_shift_s(left, right, destination),
+            Statement::GreaterS {
+                left,
+                right,
+                destination,
+            } => self.greater_s(left, right, destination),
+            Statement::GreaterEqS {
+                left,
+                right,
+                destination,
+            } => self.greater_eq_s(left, right, destination),
+            Statement::LessS {
+                left,
+                right,
+                destination,
+            } => self.less_s(left, right, destination),
+            Statement::LessEqS {
+                left,
+                right,
+                destination,
+            } => self.less_eq_s(left, right, destination),
             Statement::MulW {
                 left,
                 right,
                 destination,
-            } => todo!(),
+            } => self.mul16(left, right, destination),
             Statement::DivW {
                 left,
                 right,
                 destination,
-            } => todo!(),
+            } => self.div16(left, right, destination),
             Statement::RemW {
                 left,
                 right,
                 destination,
-            } => todo!(),
+            } => self.rem16(left, right, destination),
             Statement::LeftShiftW {
                 left,
                 right,
                 destination,
-            } => todo!(),
+            } => self.left_shift16(left, right, destination),
             Statement::RightShiftW {
                 left,
                 right,
                 destination,
-            } => todo!(),
+            } => self.right_shift16(left, right, destination),
+            Statement::DivSW {
+                left,
+                right,
+                destination,
+            } => self.div_s16(left, right, destination),
+            Statement::RemSW {
+                left,
+                right,
+                destination,
+            } => self.rem_s16(left, right, destination),
+            Statement::RightShiftSW {
+                left,
+                right,
+                destination,
+            } => self.right_shift_s16(left, right, destination),
 
             // comparator
             Statement::Eq {
@@ -271,6 +327,36 @@ impl<'a, B: ByteOrder> Machine<'a, B> {
                 right,
                 destination,
             } => self.less_eq(left, right, destination),
+            Statement::EqW {
+                left,
+                right,
+                destination,
+            } => self.eq16(left, right, destination),
+            Statement::NotEqW {
+                left,
+                right,
+                destination,
+            } => self.not_eq16(left, right, destination),
+            Statement::GreaterW {
+                left,
+                right,
+                destination,
+            } => self.greater16(left, right, destination),
+            Statement::GreaterEqW {
+                left,
+                right,
+                destination,
+            } => self.greater_eq16(left, right, destination),
+            Statement::LessW {
+                left,
+                right,
+                destination,
+            } => self.less16(left, right, destination),
+            Statement::LessEqW {
+                left,
+                right,
+                destination,
+            } => self.less_eq16(left, right, destination),
 
             // 16bit alu
             Statement::AddW {
@@ -323,12 +409,9 @@ impl<'a, B: ByteOrder> Machine<'a, B> {
         self.program_counter.push(0);
         self.routine.push(routine);
 
-        // initialize new stack frame
-        let current_stack = self.memory.stack.clone();
+        // new stack frame (overlaps with the end of the current one, where the
+        // caller has placed the arguments)
         self.memory.stack.push(range.start as usize);
-        for i in range.clone() {
-            self.memory.stack[(i - range.start) as usize] = current_stack[i as usize];
-        }
     }
 
     fn ret(&mut self) {
@@ -409,6 +492,66 @@ impl<'a, B: ByteOrder> Machine<'a, B> {
         self.ld(&Source::Literal(left >> right), destination);
     }
 
+    fn div_s(&mut self, left: &Source<u8>, right: &Source<u8>, destination: &Destination) {
+        let left = self.read(left);
+        let right = self.read(right);
+        self.ld(
+            &Source::Literal(((left as i8).wrapping_div(right as i8)) as u8),
+            destination,
+        );
+    }
+
+    fn rem_s(&mut self, left: &Source<u8>, right: &Source<u8>, destination: &Destination) {
+        let left = self.read(left);
+        let right = self.read(right);
+        self.ld(
+            &Source::Literal(((left as i8).wrapping_rem(right as i8)) as u8),
+            destination,
+        );
+    }
+
+    fn right_shift_s(&mut self, left: &Source<u8>, right: &Source<u8>, destination: &Destination) {
+        let left = self.read(left);
+        let right = self.read(right);
+        self.ld(&Source::Literal(((left as i8) >> right) as u8), destination);
+    }
+
+    fn greater_s(&mut self, left: &Source<u8>, right: &Source<u8>, destination: &Destination) {
+        let left = self.read(left) as i8;
+        let right = self.read(right) as i8;
+        self.ld(
+            &Source::Literal(if left > right { 1 } else { 0 }),
+            destination,
+        );
+    }
+
+    fn greater_eq_s(&mut self, left: &Source<u8>, right: &Source<u8>, destination: &Destination) {
+        let left = self.read(left) as i8;
+        let right = self.read(right) as i8;
+        self.ld(
+            &Source::Literal(if left >= right { 1 } else { 0 }),
+            destination,
+        );
+    }
+
+    fn less_s(&mut self, left: &Source<u8>, right: &Source<u8>, destination: &Destination) {
+        let left = self.read(left) as i8;
+        let right = self.read(right) as i8;
+        self.ld(
+            &Source::Literal(if left < right { 1 } else { 0 }),
+            destination,
+        );
+    }
+
+    fn less_eq_s(&mut self, left: &Source<u8>, right: &Source<u8>, destination: &Destination) {
+        let left = self.read(left) as i8;
+        let right = self.read(right) as i8;
+        self.ld(
+            &Source::Literal(if left <= right { 1 } else { 0 }),
+            destination,
+        );
+    }
+
     fn eq(&mut self, left: &Source<u8>, right: &Source<u8>, destination: &Destination) {
         let left = self.read(left);
         let right = self.read(right);
@@ -463,6 +606,60 @@ impl<'a, B: ByteOrder> Machine<'a, B> {
         );
     }
 
+    fn eq16(&mut self, left: &Source<u16>, right: &Source<u16>, destination: &Destination) {
+        let left = self.read_u16(left);
+        let right = self.read_u16(right);
+        self.ld(
+            &Source::Literal(if left == right { 1 } else { 0 }),
+            destination,
+        );
+    }
+
+    fn not_eq16(&mut self, left: &Source<u16>, right: &Source<u16>, destination: &Destination) {
+        let left = self.read_u16(left);
+        let right = self.read_u16(right);
+        self.ld(
+            &Source::Literal(if left != right { 1 } else { 0 }),
+            destination,
+        );
+    }
+
+    fn greater16(&mut self, left: &Source<u16>, right: &Source<u16>, destination: &Destination) {
+        let left = self.read_u16(left);
+        let right = self.read_u16(right);
+        self.ld(
+            &Source::Literal(if left > right { 1 } else { 0 }),
+            destination,
+        );
+    }
+
+    fn greater_eq16(&mut self, left: &Source<u16>, right: &Source<u16>, destination: &Destination) {
+        let left = self.read_u16(left);
+        let right = self.read_u16(right);
+        self.ld(
+            &Source::Literal(if left >= right { 1 } else { 0 }),
+            destination,
+        );
+    }
+
+    fn less16(&mut self, left: &Source<u16>, right: &Source<u16>, destination: &Destination) {
+        let left = self.read_u16(left);
+        let right = self.read_u16(right);
+        self.ld(
+            &Source::Literal(if left < right { 1 } else { 0 }),
+            destination,
+        );
+    }
+
+    fn less_eq16(&mut self, left: &Source<u16>, right: &Source<u16>, destination: &Destination) {
+        let left = self.read_u16(left);
+        let right = self.read_u16(right);
+        self.ld(
+            &Source::Literal(if left <= right { 1 } else { 0 }),
+            destination,
+        );
+    }
+
     fn add(&mut self, left: &Source<u8>, right: &Source<u8>, destination: &Destination) {
         let left = self.read(left);
         let right = self.read(right);
@@ -506,6 +703,68 @@ impl<'a, B: ByteOrder> Machine<'a, B> {
         self.ld16(&Source::Literal(left.wrapping_sub(right)), destination);
     }
 
+    fn mul16(&mut self, left: &Source<u16>, right: &Source<u16>, destination: &Destination) {
+        let left = self.read_u16(left);
+        let right = self.read_u16(right);
+        self.ld16(&Source::Literal(left.wrapping_mul(right)), destination);
+    }
+
+    fn div16(&mut self, left: &Source<u16>, right: &Source<u16>, destination: &Destination) {
+        let left = self.read_u16(left);
+        let right = self.read_u16(right);
+        self.ld16(&Source::Literal(left / right), destination);
+    }
+
+    fn rem16(&mut self, left: &Source<u16>, right: &Source<u16>, destination: &Destination) {
+        let left = self.read_u16(left);
+        let right = self.read_u16(right);
+        self.ld16(&Source::Literal(left % right), destination);
+    }
+
+    fn left_shift16(&mut self, left: &Source<u16>, right: &Source<u8>, destination: &Destination) {
+        let left = self.read_u16(left);
+        let right = self.read(right);
+        let data = left.checked_shl(right.into()).unwrap_or(0);
+        self.ld16(&Source::Literal(data), destination);
+    }
+
+    fn right_shift16(&mut self, left: &Source<u16>, right: &Source<u8>, destination: &Destination) {
+        let left = self.read_u16(left);
+        let right = self.read(right);
+        let data = left.checked_shr(right.into()).unwrap_or(0);
+        self.ld16(&Source::Literal(data), destination);
+    }
+
+    fn div_s16(&mut self, left: &Source<u16>, right: &Source<u16>, destination: &Destination) {
+        let left = self.read_u16(left) as i16;
+        let right = self.read_u16(right) as i16;
+        self.ld16(
+            &Source::Literal(left.wrapping_div(right) as u16),
+            destination,
+        );
+    }
+
+    fn rem_s16(&mut self, left: &Source<u16>, right: &Source<u16>, destination: &Destination) {
+        let left = self.read_u16(left) as i16;
+        let right = self.read_u16(right) as i16;
+        self.ld16(
+            &Source::Literal(left.wrapping_rem(right) as u16),
+            destination,
+        );
+    }
+
+    // shifting by 15 or more leaves only copies of the sign bit
+    fn right_shift_s16(
+        &mut self,
+        left: &Source<u16>,
+        right: &Source<u8>,
+        destination: &Destination,
+    ) {
+        let left = self.read_u16(left) as i16;
+        let right = self.read(right).min(15);
+        self.ld16(&Source::Literal((left >> right) as u16), destination);
+    }
+
     fn inc(&mut self, source: &Source<u8>, destination: &Destination) {
         let data = self.read(source).wrapping_add(1);
         self.ld(&Source::Literal(data), destination);
@@ -527,7 +786,7 @@ impl<'a, B: ByteOrder> Machine<'a, B> {
     }
 
     fn ld(&mut self, source: &Source<u8>, destination: &Destination) {
-        use Pointer::{Absolute, Const, Return, Stack, Static};
+        use Pointer::{Absolute, Const, Indirect, Return, Stack, Static};
         let data = self.read(source);
         match destination {
             Destination::Pointer { base, offset } => {
@@ -539,6 +798,10 @@ impl<'a, B: ByteOrder> Machine<'a, B> {
                     // TODO don't panic, rather stop the VM and log the error
                     Const(_) => panic!("Attempted to write to ROM memory!"),
                     Stack(addr) => self.memory.stack[(*addr + offset) as usize] = data,
+                    Indirect(..) => {
+                        let address = self.address(base, offset);
+                        self.at_mut(address)[0] = data
+                    }
                 }
             }
             Destination::Register(reg) => self.reg8.last_mut().unwrap().set(*reg, data),
@@ -547,7 +810,7 @@ impl<'a, B: ByteOrder> Machine<'a, B> {
 
     // FIXME code repetition with Self::ld (use traits instead)
     fn ld16(&mut self, source: &Source<u16>, destination: &Destination) {
-        use Pointer::{Absolute, Const, Return, Stack, Static};
+        use Pointer::{Absolute, Const, Indirect, Return, Stack, Static};
         // load data from source
         let data = self.read_u16(source);
         // store byte on the destination
@@ -569,14 +832,70 @@ impl<'a, B: ByteOrder> Machine<'a, B> {
                     Stack(addr) => {
                         B::write_u16(&mut self.memory.stack[(*addr + offset) as usize..], data)
                     }
+                    Indirect(..) => {
+                        let address = self.address(base, offset);
+                        B::write_u16(self.at_mut(address), data)
+                    }
                 }
             }
             Destination::Register(reg) => self.reg16.last_mut().unwrap().set(*reg, data),
         }
     }
 
+    fn ld_addr(&mut self, source: &Source<u16>, destination: &Destination) {
+        let address = match source {
+            Source::Pointer { base, offset } => {
+                let offset = offset.as_ref().map(|o| self.read(o)).unwrap_or(0) as u16;
+                self.address(base, offset)
+            }
+            source => self.read_u16(source),
+        };
+        self.ld16(&Source::Literal(address), destination);
+    }
+
+    /// Address of the memory a pointer refers to.
+    ///
+    /// Static memory is addressed in the same way as absolute pointers, while
+    /// const and stack memory are mapped to [`CONST_RANGE`] and
+    /// [`STACK_RANGE`] respectively. Return memory isn't addressable.
+    fn address(&self, base: &Pointer, offset: u16) -> u16 {
+        use Pointer::{Absolute, Const, Indirect, Return, Stack, Static};
+        let address = match base {
+            Absolute(addr) | Static(addr) => *addr,
+            Const(addr) => CONST_RANGE.start + *addr,
+            Stack(addr) => STACK_RANGE.start + self.memory.stack.stack_pointer() as u16 + *addr,
+            // TODO don't panic, rather stop the VM and log the error
+            Return(_) => panic!("Attempted to take the address of return memory!"),
+            Indirect(reg, addr) => self.reg16.last().unwrap().get(*reg).wrapping_add(*addr),
+        };
+        address.wrapping_add(offset)
+    }
+
+    /// Memory starting at the given address (see [`Self::address`]).
+    fn at(&self, address: u16) -> &[u8] {
+        if CONST_RANGE.contains(&address) {
+            &self.ir.const_[(address - CONST_RANGE.start) as usize..]
+        } else if STACK_RANGE.contains(&address) {
+            &self.memory.stack.data()[(address - STACK_RANGE.start) as usize..]
+        } else {
+            &self.memory.static_[address as usize..]
+        }
+    }
+
+    /// Mutable version of [`Self::at`].
+    fn at_mut(&mut self, address: u16) -> &mut [u8] {
+        if CONST_RANGE.contains(&address) {
+            // TODO don't panic, rather stop the VM and log the error
+            panic!("Attempted to write to ROM memory!")
+        } else if STACK_RANGE.contains(&address) {
+            &mut self.memory.stack.data_mut()[(address - STACK_RANGE.start) as usize..]
+        } else {
+            &mut self.memory.static_[address as usize..]
+        }
+    }
+
     fn read(&self, source: &Source<u8>) -> u8 {
-        use Pointer::{Absolute, Const, Return, Stack, Static};
+        use Pointer::{Absolute, Const, Indirect, Return, Stack, Static};
         match source {
             Source::Pointer { base, offset } => {
                 let offset = offset.as_ref().map(|o| self.read(o)).unwrap_or(0) as u16;
@@ -586,6 +905,7 @@ impl<'a, B: ByteOrder> Machine<'a, B> {
                     Return(addr) => self.memory.return_[(*addr + offset) as usize],
                     Const(addr) => self.ir.const_[(*addr + offset) as usize],
                     Stack(addr) => self.memory.stack[(*addr + offset) as usize],
+                    Indirect(..) => self.at(self.address(base, offset))[0],
                 }
             }
             Source::Register(reg) => self.reg8.last().unwrap().get(*reg),
@@ -594,7 +914,7 @@ impl<'a, B: ByteOrder> Machine<'a, B> {
     }
 
     fn read_u16(&self, source: &Source<u16>) -> u16 {
-        use Pointer::{Absolute, Const, Return, Stack, Static};
+        use Pointer::{Absolute, Const, Indirect, Return, Stack, Static};
         match source {
             Source::Pointer { base: ptr, offset } => {
                 let offset = offset.as_ref().map(|o| self.read(o)).unwrap_or(0) as u16;
@@ -606,6 +926,7 @@ impl<'a, B: ByteOrder> Machine<'a, B> {
                     Return(addr) => B::read_u16(&self.memory.return_[(*addr + offset) as usize..]),
                     Const(addr) => B::read_u16(&self.ir.const_[(*addr + offset) as usize..]),
                     Stack(addr) => B::read_u16(&self.memory.stack[(*addr + offset) as usize..]),
+                    Indirect(..) => B::read_u16(self.at(self.address(ptr, offset))),
                 }
             }
             Source::Register(reg) => self.reg16.last().unwrap().get(*reg),
@@ -1,5 +1,14 @@
 use crate::{Opts, Stack};
-use std::ops::{Deref, DerefMut};
+use std::ops::{Deref, DerefMut, Range};
+
+/// Addresses of const memory, as seen by pointers held in registers.
+pub const CONST_RANGE: Range<u16> = 0x4000..0x8000;
+
+/// Addresses of stack memory, as seen by pointers held in registers.
+///
+/// Unlike stack pointers, these are relative to the bottom of the stack rather
+/// than to the current stack frame.
+pub const STACK_RANGE: Range<u16> = 0xd000..0xe000;
 
 /// Memory space of static memory.
 pub type StaticMemory = Box<[u8]>;
@@ -60,6 +69,16 @@ impl StackMemory {
     pub fn pop(&mut self) {
         self.stack_pointer.pop().unwrap();
     }
+
+    /// Return the whole stack memory, regardless of the current stack pointer.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Mutable version of [`Self::data`].
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
 }
 
 impl Deref for StackMemory {
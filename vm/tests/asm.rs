@@ -0,0 +1,7 @@
+mod utils;
+
+#[test]
+fn asm() {
+    let memory = utils::run(include_str!("programs/asm.ggb"));
+    assert_eq!(&[1, 2], &memory.static_[..2])
+}
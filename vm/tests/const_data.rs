@@ -0,0 +1,7 @@
+mod utils;
+
+#[test]
+fn const_data() {
+    let memory = utils::run(include_str!("programs/const_data.ggb"));
+    assert_eq!(&[7, 3, 4, 111, 0x34, 8, 9, 6, 105], &memory.static_[..9])
+}
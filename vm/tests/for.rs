@@ -5,3 +5,14 @@ fn for_() {
     let memory = utils::run(include_str!("programs/for.ggb"));
     assert_eq!(&[120], &memory.static_[..1])
 }
+
+#[test]
+fn range() {
+    let memory = utils::run(include_str!("programs/for_range.ggb"));
+    assert_eq!(&[0, 6, 30, 18, 5, 15, 6, 5, 7], &memory.static_[..9]);
+    let words: Vec<_> = memory.static_[9..15]
+        .chunks(2)
+        .map(|w| u16::from_ne_bytes([w[0], w[1]]))
+        .collect();
+    assert_eq!(&[2805, 256, 5], &words[..]);
+}
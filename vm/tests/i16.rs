@@ -0,0 +1,12 @@
+mod utils;
+
+#[test]
+fn i16() {
+    let memory = utils::run(include_str!("programs/i16.ggb"));
+    let words: Vec<_> = memory.static_[..10]
+        .chunks(2)
+        .map(|w| i16::from_ne_bytes([w[0], w[1]]))
+        .collect();
+    assert_eq!(&[-42, -75, -142, 42, -100], &words[..]);
+    assert_eq!(&[1, 0, 1, 1, 1], &memory.static_[10..15]);
+}
@@ -0,0 +1,9 @@
+mod utils;
+
+#[test]
+fn i8() {
+    let memory = utils::run(include_str!("programs/i8.ggb"));
+    let bytes: Vec<_> = memory.static_[..4].iter().map(|b| *b as i8).collect();
+    assert_eq!(&[-14, -25, -14, -33], &bytes[..]);
+    assert_eq!(&[1, 0, 1, 1], &memory.static_[4..8]);
+}
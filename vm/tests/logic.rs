@@ -0,0 +1,7 @@
+mod utils;
+
+#[test]
+fn logic() {
+    let memory = utils::run(include_str!("programs/logic.ggb"));
+    assert_eq!(&[1, 0, 1, 0, 1, 1, 0, 1, 42, 4], &memory.static_[..10])
+}
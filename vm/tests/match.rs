@@ -0,0 +1,7 @@
+mod utils;
+
+#[test]
+fn match_() {
+    let memory = utils::run(include_str!("programs/match.ggb"));
+    assert_eq!(&[0, 1, 5, 6, 0, 6, 20, 2, 3, 7], &memory.static_[..10])
+}
@@ -0,0 +1,13 @@
+mod utils;
+
+#[test]
+fn minus() {
+    let memory = utils::run(include_str!("programs/minus.ggb"));
+    let bytes: Vec<_> = memory.static_[..4].iter().map(|b| *b as i8).collect();
+    assert_eq!(&[-5, -1, -127, -2], &bytes[..]);
+    let words: Vec<_> = memory.static_[4..8]
+        .chunks(2)
+        .map(|w| i16::from_ne_bytes([w[0], w[1]]))
+        .collect();
+    assert_eq!(&[-1000, -300], &words[..]);
+}
@@ -0,0 +1,7 @@
+mod utils;
+
+#[test]
+fn mod_() {
+    let memory = utils::run(include_str!("programs/mod.ggb"));
+    assert_eq!(&[4, 6, 2, 4, 5, 2, 5, 4], &memory.static_[..8])
+}
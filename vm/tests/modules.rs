@@ -0,0 +1,22 @@
+use ggbc::{
+    byteorder::NativeEndian,
+    ir::Ir,
+    parser::source::{load, FileSystem},
+};
+use vm::{Machine, Opts};
+
+#[test]
+fn modules() {
+    let entry = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/programs/modules/main.ggb"
+    );
+    let sources = load(&FileSystem::new(entry)).unwrap();
+    let modules = sources.parse().unwrap();
+    ggbc::check::check_modules(&modules).unwrap();
+    let ir: Ir<NativeEndian> = Ir::with_modules(&modules).unwrap();
+    let memory = Machine::new(&ir, Opts::default()).run();
+    // gfx::tiles::LOADED is allocated first, as imported modules are compiled
+    // before the entry module.
+    assert_eq!(&[6, 4, 6, 3, 6, 7, 8, 3, 2], &memory.static_[..9])
+}
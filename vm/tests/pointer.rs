@@ -0,0 +1,12 @@
+mod utils;
+
+#[test]
+fn pointer() {
+    let memory = utils::run(include_str!("programs/pointer.ggb"));
+    assert_eq!(&[10, 7, 17, 1, 1, 0, 1, 3, 4], &memory.static_[..9]);
+    let words: Vec<_> = memory.static_[9..15]
+        .chunks(2)
+        .map(|w| u16::from_ne_bytes([w[0], w[1]]))
+        .collect();
+    assert_eq!(&[0x400, 0x1234, 0x100], &words[..]);
+}
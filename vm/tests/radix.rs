@@ -0,0 +1,7 @@
+mod utils;
+
+#[test]
+fn radix() {
+    let memory = utils::run(include_str!("programs/radix.ggb"));
+    assert_eq!(&[10, 15, 0xff, 0xef], &memory.static_[..4])
+}
@@ -1,7 +1,10 @@
 mod utils;
 
 #[test]
-#[ignore]
 fn string() {
-    utils::run(include_str!("programs/string.ggb"));
+    let memory = utils::run(include_str!("programs/string.ggb"));
+    assert_eq!(
+        &[b'o', b'"', b'\n', b'\\', 0x7f, b'!', b'\'', b'\t' + b'A'],
+        &memory.static_[..8]
+    )
 }
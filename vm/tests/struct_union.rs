@@ -11,3 +11,9 @@ fn union() {
     let memory = utils::run(include_str!("programs/union.ggb"));
     assert_eq!(&[3, 4], &memory.static_[..2])
 }
+
+#[test]
+fn named() {
+    let memory = utils::run(include_str!("programs/named_struct.ggb"));
+    assert_eq!(&[2, 4, 3, 1, 3, 2], &memory.static_[..6])
+}
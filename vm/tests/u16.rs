@@ -0,0 +1,12 @@
+mod utils;
+
+#[test]
+fn u16() {
+    let memory = utils::run(include_str!("programs/u16.ggb"));
+    let words: Vec<_> = memory.static_[..16]
+        .chunks(2)
+        .map(|w| u16::from_ne_bytes([w[0], w[1]]))
+        .collect();
+    assert_eq!(&[0x161c, 0xf1b4, 3000, 4, 16000, 600, 0, 0], &words[..]);
+    assert_eq!(&[0, 1, 1, 1], &memory.static_[16..20]);
+}
@@ -2,7 +2,9 @@ use ggbc::{byteorder::NativeEndian, ir::Ir};
 use vm::{memory::Memory, Machine, Opts};
 
 pub fn run(input: &str) -> Memory {
-    let ast = ggbc::parser::parse(input).unwrap();
-    let ir: Ir<NativeEndian> = Ir::new(&ast);
+    let (ast, errors) = ggbc::parser::parse(input);
+    assert!(errors.is_empty(), "{:?}", errors);
+    ggbc::check::check(&ast).unwrap();
+    let ir: Ir<NativeEndian> = Ir::new(&ast).unwrap();
     Machine::new(&ir, Opts::default()).run()
 }
@@ -0,0 +1,7 @@
+mod utils;
+
+#[test]
+fn while_() {
+    let memory = utils::run(include_str!("programs/while.ggb"));
+    assert_eq!(&[55, 8, 17, 0], &memory.static_[..4])
+}